@@ -0,0 +1,19 @@
+// With the ffi feature, generates rust_virtual_memory.h from src/ffi.rs into
+// OUT_DIR. The committed include/rust_virtual_memory.h is refreshed by hand
+// with the cbindgen CLI (see the README).
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).expect("invalid cbindgen.toml");
+        cbindgen::Builder::new()
+            .with_crate(&crate_dir)
+            .with_config(config)
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(format!("{}/rust_virtual_memory.h", out_dir));
+    }
+}
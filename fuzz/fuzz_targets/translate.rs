@@ -0,0 +1,40 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_virtual_memory::constants::{MAX_SEGMENTS, S_SHIFT};
+use rust_virtual_memory::testing::{check_translation, random_init, random_valid_va, InitParams};
+use rust_virtual_memory::translation::{Access, TranslationResult, VirtualAddress};
+use rust_virtual_memory::VMManager;
+
+// The first 8 bytes seed a random init. Every 4 bytes after them are one
+// access: with an even first byte to a valid address, which must translate,
+// and otherwise to the 4 bytes as a raw address in the segment space. Odd
+// second bytes write.
+// Every successful translation must keep `check_translation`'s invariants.
+fuzz_target!(|data: &[u8]| {
+    let (seed, accesses) = data.split_at(data.len().min(8));
+    let mut rng = seed.iter().fold(0u64, |state, &byte| state << 8 | byte as u64);
+    let init = random_init(&mut rng, &InitParams::default()).unwrap();
+    let mut vm = VMManager::from_init_data(&init);
+    for chunk in accesses.chunks_exact(4) {
+        let valid = chunk[0] % 2 == 0;
+        let va = match valid {
+            true => random_valid_va(vm.memory(), &mut rng).unwrap(),
+            false => {
+                let raw = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                VirtualAddress::from_raw(raw % (MAX_SEGMENTS << S_SHIFT) as u32)
+            }
+        };
+        let access = match chunk[1] % 2 {
+            0 => Access::read(va.to_raw()),
+            _ => Access::write(va.to_raw()),
+        };
+        let result = vm.access(&access);
+        if valid && !matches!(result, TranslationResult::Success(_)) {
+            panic!("valid va {} failed: {}", va.to_raw(), result);
+        }
+        if let Err(violation) = check_translation(vm.memory(), va, &result) {
+            panic!("{}", violation);
+        }
+    }
+});
@@ -0,0 +1,72 @@
+/* Generated by cbindgen from src/ffi.rs; regenerate with `cbindgen --config cbindgen.toml --output include/rust_virtual_memory.h`. Do not edit. */
+
+#ifndef RUST_VIRTUAL_MEMORY_H
+#define RUST_VIRTUAL_MEMORY_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef struct VMManager VMManager;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a simulator with empty memory. Free it with `vm_destroy`.
+struct VMManager *vm_create(void);
+
+// Loads a text or binary init file, replacing the simulator's memory.
+// Returns 0, or -1 with the reason available from `vm_last_error`.
+//
+// # Safety
+// `vm` must come from `vm_create` and `path` must be a NUL-terminated string.
+int32_t vm_init_from_file(struct VMManager *vm, const char *path);
+
+// Like `vm_init_from_file`, with the init file's contents in memory.
+//
+// # Safety
+// `vm` must come from `vm_create` and `content` must be a NUL-terminated string.
+int32_t vm_init_from_string(struct VMManager *vm, const char *content);
+
+// Selects how `vm_translate` and `vm_translate_batch` report failures: with
+// `detailed` false every failure is -1; with it true a boundary violation is
+// -1, an invalid segment -2, an invalid page -3 and a protection fault -4.
+// Returns 0, or -1 if `vm` is NULL.
+//
+// # Safety
+// `vm` must come from `vm_create`.
+int32_t vm_set_error_codes(struct VMManager *vm, bool detailed);
+
+// Translates a virtual address as a read, demand paging as needed. Returns
+// the physical address, or a negative code as the command-line output does.
+//
+// # Safety
+// `vm` must come from `vm_create`.
+int32_t vm_translate(struct VMManager *vm, uint32_t va);
+
+// Translates `len` addresses from `vas` into `out`. Returns 0, or -1 if a
+// pointer is NULL.
+//
+// # Safety
+// `vm` must come from `vm_create`, and `vas` and `out` must each point to
+// `len` elements.
+int32_t vm_translate_batch(struct VMManager *vm, const uint32_t *vas, size_t len, int32_t *out);
+
+// The message for the last call on this thread that returned -1, or NULL.
+// The string stays valid until the next failing call on this thread.
+const char *vm_last_error(void);
+
+// Frees a simulator from `vm_create`. NULL is ignored.
+//
+// # Safety
+// `vm` must come from `vm_create` and not be used afterwards.
+void vm_destroy(struct VMManager *vm);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RUST_VIRTUAL_MEMORY_H */
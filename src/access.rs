@@ -0,0 +1,129 @@
+use crate::cost::TlbOutcome;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::replacement::PageId;
+use crate::tlb::TlbCache;
+use crate::translation::{
+    check_protection, copy_on_write, record_tlb_hit, translate_with_demand_paging_faults, Access, FaultFlags,
+    TranslationResult, VirtualAddress,
+};
+
+// Services a TLB miss: walks the tables and handles whatever faults the walk
+// takes. The hooks let a walker keep its own state in step with the access.
+pub trait Walker {
+    fn walk(
+        &mut self,
+        va: &VirtualAddress,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        ffl: &mut FreeFrameList,
+    ) -> Result<(TranslationResult, FaultFlags), String>;
+
+    // Pages the last walk evicted, whose TLB entries are dropped before the fill.
+    fn take_evicted(&mut self) -> Vec<PageId> {
+        Vec::new()
+    }
+
+    fn on_hit(&mut self, _va: &VirtualAddress) {}
+
+    // Runs after every access, denied or not, before copy-on-write.
+    fn after_translate(
+        &mut self,
+        _va: &VirtualAddress,
+        _result: &TranslationResult,
+        _faults: FaultFlags,
+        _pm: &mut PhysicalMemory,
+        _disk: &Disk,
+        _ffl: &mut FreeFrameList,
+    ) {
+    }
+
+    // A write replaced the shared frame with a private copy.
+    fn after_copy(&mut self, _va: &VirtualAddress, _shared_frame: u32, _private_frame: u32) {}
+}
+
+// Plain demand paging from the free list, as VMManager walks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DemandPaging;
+
+impl Walker for DemandPaging {
+    fn walk(
+        &mut self,
+        va: &VirtualAddress,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        ffl: &mut FreeFrameList,
+    ) -> Result<(TranslationResult, FaultFlags), String> {
+        Ok(translate_with_demand_paging_faults(va, pm, disk, ffl))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessOutcome {
+    pub result: TranslationResult,
+    pub faults: FaultFlags,
+    pub tlb: TlbOutcome,
+    // The frame the TLB or walk resolved to, before any copy-on-write.
+    pub page_frame: u32,
+}
+
+// One access as VMManager and the CLI handle it: the protection check, the TLB
+// lookup (a hit touches and references the frame), the walk on a miss, and
+// copy-on-write for a write to a shared page. The caller emits the access's
+// events and then calls record_access.
+pub fn handle_access<T: TlbCache + ?Sized, W: Walker + ?Sized>(
+    access: &Access,
+    va: &VirtualAddress,
+    pm: &mut PhysicalMemory,
+    disk: &mut Disk,
+    ffl: &mut FreeFrameList,
+    mut tlb: Option<&mut T>,
+    walker: &mut W,
+) -> Result<AccessOutcome, String> {
+    let violation = check_protection(access.kind, va, pm);
+    let cached = match violation {
+        Some(_) => None,
+        None => tlb.as_mut().and_then(|tlb| tlb.lookup(va)),
+    };
+    let outcome = match (&tlb, violation, cached) {
+        (None, _, _) | (Some(_), Some(_), _) => TlbOutcome::NotPresent,
+        (Some(_), None, Some(_)) => TlbOutcome::Hit,
+        (Some(_), None, None) => TlbOutcome::Miss,
+    };
+    #[cfg(feature = "tracing")]
+    if outcome != TlbOutcome::NotPresent {
+        tracing::trace!(hit = outcome == TlbOutcome::Hit, "TLB lookup");
+    }
+    if let Some(hit @ TranslationResult::Success(_)) = cached {
+        record_tlb_hit(&hit, pm);
+        walker.on_hit(va);
+    }
+    let (result, faults) = match violation.or(cached) {
+        Some(result) => (result, FaultFlags::default()),
+        None => {
+            let (result, faults) = walker.walk(va, pm, disk, ffl)?;
+            let evicted = walker.take_evicted();
+            if let Some(tlb) = tlb.as_mut() {
+                for (s, p) in evicted {
+                    tlb.invalidate(s, p);
+                }
+                tlb.fill(va, pm, &result);
+            }
+            (result, faults)
+        }
+    };
+    walker.after_translate(va, &result, faults, pm, disk, ffl);
+    let page_frame = match result {
+        TranslationResult::Success(pa) => (pa as usize / pm.layout().page_size()) as u32,
+        _ => 0,
+    };
+    let (result, cow_fault) = copy_on_write(access.kind, va, result, pm, ffl);
+    if cow_fault {
+        if let Some(tlb) = tlb.as_mut() {
+            tlb.fill(va, pm, &result);
+        }
+        if let TranslationResult::Success(pa) = result {
+            walker.after_copy(va, page_frame, (pa as usize / pm.layout().page_size()) as u32);
+        }
+    }
+    Ok(AccessOutcome { result, faults: FaultFlags { cow_fault, ..faults }, tlb: outcome, page_frame })
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::process::Pid;
+use crate::replacement::{frames_needed, PageId, Replacer};
+use crate::translation::{FaultFlags, TranslationResult, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationPolicy {
+    Global,
+    FixedEqual,
+    Proportional,
+}
+
+impl AllocationPolicy {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "global" => Ok(AllocationPolicy::Global),
+            "equal" => Ok(AllocationPolicy::FixedEqual),
+            "proportional" => Ok(AllocationPolicy::Proportional),
+            _ => Err(format!("Unknown allocation policy: {} (expected global, equal or proportional)", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AllocationPolicy::Global => "global",
+            AllocationPolicy::FixedEqual => "equal",
+            AllocationPolicy::Proportional => "proportional",
+        }
+    }
+}
+
+// Pages spanned by the segments of the active segment table.
+pub fn virtual_pages(pm: &PhysicalMemory) -> usize {
+    let layout = pm.layout();
+    (0..layout.max_segments() as u32)
+        .map(|s| (pm.get_segment_size(s).max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size()))
+        .sum()
+}
+
+struct AllocatedProcess {
+    st_frame: u32,
+    pages: usize,
+    quota: usize,
+    replacer: Replacer,
+}
+
+// Each process replaces its own pages with its own Replacer, all drawing on one
+// free frame list. Under the local policies a process that would exceed its
+// quota evicts one of its own pages first; under Global a process short of free
+// frames takes one from whichever other process holds the most.
+pub struct ProcessAllocator {
+    policy: AllocationPolicy,
+    frames: usize,
+    processes: BTreeMap<Pid, AllocatedProcess>,
+}
+
+impl ProcessAllocator {
+    pub fn new(policy: AllocationPolicy, frames: usize) -> Self {
+        ProcessAllocator { policy, frames, processes: BTreeMap::new() }
+    }
+
+    pub fn policy(&self) -> AllocationPolicy {
+        self.policy
+    }
+
+    pub fn add_process(&mut self, pid: Pid, st_frame: u32, pages: usize, replacer: Replacer) {
+        self.processes.insert(pid, AllocatedProcess { st_frame, pages, quota: 0, replacer });
+        self.assign_quotas();
+    }
+
+    fn assign_quotas(&mut self) {
+        let count = self.processes.len().max(1);
+        let total_pages: usize = self.processes.values().map(|process| process.pages).sum();
+        for process in self.processes.values_mut() {
+            process.quota = match self.policy {
+                AllocationPolicy::Global => self.frames,
+                AllocationPolicy::Proportional if total_pages > 0 => self.frames * process.pages / total_pages,
+                AllocationPolicy::FixedEqual | AllocationPolicy::Proportional => self.frames / count,
+            }
+            .max(1);
+        }
+    }
+
+    pub fn quota(&self, pid: Pid) -> Option<usize> {
+        self.processes.get(&pid).map(|process| process.quota)
+    }
+
+    // Frames a process holds through its replacer: faulted-in pages and page tables.
+    pub fn held(&self, pid: Pid) -> usize {
+        self.processes.get(&pid).map_or(0, |process| held(&process.replacer))
+    }
+
+    pub fn replacer(&self, pid: Pid) -> Option<&Replacer> {
+        self.processes.get(&pid).map(|process| &process.replacer)
+    }
+
+    pub fn replacer_mut(&mut self, pid: Pid) -> Option<&mut Replacer> {
+        self.processes.get_mut(&pid).map(|process| &mut process.replacer)
+    }
+
+    pub fn take_evicted(&mut self, pid: Pid) -> Vec<PageId> {
+        self.processes.get_mut(&pid).map_or_else(Vec::new, |process| process.replacer.take_evicted())
+    }
+
+    pub fn pids(&self) -> Vec<Pid> {
+        self.processes.keys().copied().collect()
+    }
+
+    pub fn translate(
+        &mut self,
+        pid: Pid,
+        va: &VirtualAddress,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        ffl: &mut FreeFrameList,
+    ) -> Result<(TranslationResult, FaultFlags), String> {
+        let st_frame = self.processes.get(&pid).ok_or_else(|| format!("No such process: {}", pid))?.st_frame;
+        let active = pm.st_frame();
+        switch_st(pm, st_frame);
+
+        if self.policy == AllocationPolicy::Global {
+            while ffl.free_count() < frames_needed(va, pm, disk) && self.evict_elsewhere(pid, pm, disk, ffl) {}
+        } else {
+            let process = self.processes.get_mut(&pid).unwrap();
+            while held(&process.replacer) + frames_needed(va, pm, disk) > process.quota
+                && process.replacer.evict_sparing(va.s, pm, disk, ffl)
+            {}
+        }
+
+        let result = self.processes.get_mut(&pid).unwrap().replacer.translate(va, pm, disk, ffl);
+        switch_st(pm, active);
+        Ok(result)
+    }
+
+    fn evict_elsewhere(&mut self, pid: Pid, pm: &mut PhysicalMemory, disk: &mut Disk, ffl: &mut FreeFrameList) -> bool {
+        let active = pm.st_frame();
+        let victim = self
+            .processes
+            .iter_mut()
+            .filter(|(&other, process)| other != pid && held(&process.replacer) > 0)
+            .max_by_key(|(&other, process)| (held(&process.replacer), std::cmp::Reverse(other)));
+        let Some((_, process)) = victim else {
+            return false;
+        };
+        switch_st(pm, process.st_frame);
+        let evicted = process.replacer.evict_one(pm, disk, ffl);
+        switch_st(pm, active);
+        evicted
+    }
+}
+
+fn held(replacer: &Replacer) -> usize {
+    replacer.resident().len() + replacer.page_tables().len()
+}
+
+// Leaves a single-process run's (possibly paged) segment table alone.
+fn switch_st(pm: &mut PhysicalMemory, st_frame: u32) {
+    if pm.st_frame() != st_frame {
+        pm.set_st_frame(st_frame);
+    }
+}
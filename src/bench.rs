@@ -0,0 +1,195 @@
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::constants::*;
+use crate::generator::{Distribution, SyntheticTrace};
+use crate::io::InitData;
+use crate::memory::{Disk, FreeFrameList, FreeListKind, PhysicalMemory};
+use crate::tlb::{Tlb, TlbCache, TlbEviction};
+use crate::translation::{translate, translate_with_demand_paging_faults, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchConfig {
+    pub addresses: usize,
+    pub segments: u32,
+    pub pages: u32,
+    pub locality: Distribution,
+    pub tlb_entries: usize,
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            addresses: 1_000_000,
+            segments: 16,
+            pages: 32,
+            locality: Distribution::Zipf(1.0),
+            tlb_entries: 16,
+            seed: 1,
+        }
+    }
+}
+
+impl BenchConfig {
+    // Each segment gets a resident PT and all of its pages on disk, so the first
+    // pass faults every touched page in; frames and blocks must both suffice.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.addresses == 0 || self.segments == 0 || self.pages == 0 {
+            return Err("Benchmark needs at least one address, segment and page".to_string());
+        }
+        if self.segments as usize > MAX_SEGMENTS || self.pages as usize > PT_SIZE {
+            return Err(format!("At most {} segments of {} pages", MAX_SEGMENTS, PT_SIZE));
+        }
+        let pages = self.segments as usize * self.pages as usize;
+        if ST_FRAMES + self.segments as usize + pages > NUM_FRAMES || pages >= DISK_BLOCKS {
+            return Err(format!("{} segments of {} pages do not fit in memory and on disk", self.segments, self.pages));
+        }
+        Ok(())
+    }
+
+    pub fn init_data(&self) -> InitData {
+        let mut init_data = InitData::default();
+        for s in 0..self.segments {
+            init_data.st_entries.push((s, (self.pages as usize * PAGE_SIZE) as i32, (ST_FRAMES as u32 + s) as i32));
+            for p in 0..self.pages {
+                init_data.pt_entries.push((s, p, -((1 + s * self.pages + p) as i32)));
+            }
+        }
+        init_data
+    }
+
+    pub fn trace(&self) -> Vec<u32> {
+        SyntheticTrace::new(self.seed, self.addresses)
+            .segments(self.locality, self.segments)
+            .pages(self.locality, self.pages)
+            .generate()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub operations: usize,
+    pub elapsed: Duration,
+    pub faults: u64,
+    pub tlb_hit_rate: Option<f64>,
+}
+
+impl BenchResult {
+    pub fn per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.operations as f64 / seconds
+    }
+
+    pub fn fault_rate(&self) -> f64 {
+        if self.operations == 0 {
+            return 0.0;
+        }
+        self.faults as f64 / self.operations as f64
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} {:>10} ops in {:>9.3} ms  {:>14.0} ops/s  fault rate {:>6.2}%",
+            self.name,
+            self.operations,
+            self.elapsed.as_secs_f64() * 1000.0,
+            self.per_second(),
+            self.fault_rate() * 100.0
+        )?;
+        if let Some(rate) = self.tlb_hit_rate {
+            write!(f, "  TLB hit rate {:.2}%", rate * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn translate_batch_uncached(vas: &[u32], pm: &PhysicalMemory) -> Vec<i32> {
+    vas.iter().map(|&va| translate(&VirtualAddress::decompose(pm.layout(), va), pm, None).to_output()).collect()
+}
+
+pub fn translate_batch_cached(vas: &[u32], pm: &PhysicalMemory, tlb: &mut Tlb) -> Vec<i32> {
+    vas.iter().map(|&va| translate(&VirtualAddress::decompose(pm.layout(), va), pm, Some(&mut *tlb)).to_output()).collect()
+}
+
+fn timed<T, F: FnOnce() -> T>(run: F) -> (T, Duration) {
+    let start = Instant::now();
+    let value = run();
+    (value, start.elapsed())
+}
+
+pub fn bench_demand_paging(vas: &[u32], pm: &mut PhysicalMemory, disk: &Disk, ffl: &mut FreeFrameList) -> BenchResult {
+    let (faults, elapsed) = timed(|| {
+        let mut faults = 0;
+        for &va in vas {
+            let (_, flags) = translate_with_demand_paging_faults(&VirtualAddress::decompose(pm.layout(), va), pm, disk, ffl);
+            faults += flags.hard_faults();
+        }
+        faults
+    });
+    BenchResult { name: "demand paging", operations: vas.len(), elapsed, faults, tlb_hit_rate: None }
+}
+
+pub fn bench_uncached(vas: &[u32], pm: &PhysicalMemory) -> BenchResult {
+    let (_, elapsed) = timed(|| black_box(translate_batch_uncached(vas, pm)));
+    BenchResult { name: "uncached", operations: vas.len(), elapsed, faults: 0, tlb_hit_rate: None }
+}
+
+pub fn bench_tlb(vas: &[u32], pm: &PhysicalMemory, entries: usize) -> BenchResult {
+    let mut tlb = Tlb::new(entries, TlbEviction::Lru);
+    let (_, elapsed) = timed(|| black_box(translate_batch_cached(vas, pm, &mut tlb)));
+    BenchResult { name: "tlb", operations: vas.len(), elapsed, faults: 0, tlb_hit_rate: Some(tlb.hit_rate()) }
+}
+
+// Allocates every frame, then repeatedly releases and re-occupies frames spread
+// over the whole range, which is the access pattern of init loading.
+pub fn bench_free_list(kind: FreeListKind, rounds: usize) -> BenchResult {
+    let (operations, elapsed) = timed(|| {
+        let mut ffl = FreeFrameList::with_kind(kind);
+        let mut operations = 0;
+        for round in 0..rounds {
+            while ffl.allocate().is_some() {
+                operations += 1;
+            }
+            for i in 0..NUM_FRAMES - ST_FRAMES {
+                ffl.release((ST_FRAMES + (i * 5 + round) % (NUM_FRAMES - ST_FRAMES)) as u32);
+                operations += 1;
+            }
+            for frame in (ST_FRAMES as u32..NUM_FRAMES as u32).step_by(3) {
+                ffl.mark_occupied(frame);
+                operations += 1;
+            }
+        }
+        operations
+    });
+    let name = match kind {
+        FreeListKind::Sorted => "free list sorted",
+        FreeListKind::Bitmap => "free list bitmap",
+    };
+    BenchResult { name, operations, elapsed, faults: 0, tlb_hit_rate: None }
+}
+
+pub fn run_benchmarks(config: &BenchConfig) -> Result<Vec<BenchResult>, String> {
+    config.validate()?;
+    let vas = config.trace();
+    let mut pm = PhysicalMemory::new();
+    let mut disk = Disk::new();
+    let mut ffl = config.init_data().apply(&mut pm, &mut disk);
+
+    let mut results = vec![bench_demand_paging(&vas, &mut pm, &disk, &mut ffl)];
+    results.push(bench_uncached(&vas, &pm));
+    if config.tlb_entries > 0 {
+        results.push(bench_tlb(&vas, &pm, config.tlb_entries));
+    }
+    results.push(bench_free_list(FreeListKind::Sorted, 100));
+    results.push(bench_free_list(FreeListKind::Bitmap, 100));
+    Ok(results)
+}
@@ -0,0 +1,182 @@
+use crate::constants::*;
+use crate::io::{check_huge_page, InitData};
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::translation::Protection;
+use crate::vm_manager::VMManager;
+
+// Sets up memory without an init file, e.g.
+// `VmBuilder::new().segment(8, 4000).pt_in_frame(3).page(0, 10).page_on_disk(1, 20).build()`.
+// Page methods apply to the segment most recently started with `segment`.
+// Mistakes are collected and reported by `build`, so calls can be chained.
+#[derive(Debug, Default)]
+pub struct VmBuilder {
+    init_data: InitData,
+    current: Option<u32>,
+    errors: Vec<String>,
+}
+
+impl VmBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn segment(mut self, s: u32, size: i32) -> Self {
+        if s as usize >= MAX_SEGMENTS {
+            self.errors.push(format!("Segment {} is out of range", s));
+        } else if !(0..=(PT_SIZE * PAGE_SIZE) as i32).contains(&size) {
+            self.errors.push(format!("Segment {} size {} is out of range", s, size));
+        } else if self.init_data.st_entries.iter().any(|&(existing, _, _)| existing == s) {
+            self.errors.push(format!("Segment {} is defined twice", s));
+        } else {
+            self.init_data.st_entries.push((s, size, 0));
+        }
+        self.current = Some(s);
+        self
+    }
+
+    // A segment mapped by one huge page from frame on, with no page table.
+    pub fn huge_segment(mut self, s: u32, size: i32, frame: u32) -> Self {
+        if let Err(error) = check_huge_page(s, size, frame as i32, PAGE_SIZE) {
+            self.errors.push(error);
+        }
+        self = self.segment(s, size).set_pt(frame as i32);
+        self.init_data.huge_segments.push(s);
+        self
+    }
+
+    pub fn protection(mut self, protection: Protection) -> Self {
+        if let Some(s) = self.current_segment("Protection") {
+            self.init_data.protections.retain(|&(existing, _)| existing != s);
+            self.init_data.protections.push((s, protection));
+        }
+        self
+    }
+
+    pub fn pt_in_frame(mut self, frame: u32) -> Self {
+        match self.check_frame(frame, "page table") {
+            true => self.set_pt(frame as i32),
+            false => self,
+        }
+    }
+
+    pub fn pt_on_disk(mut self, block: usize) -> Self {
+        match self.check_block(block, "page table") {
+            true => self.set_pt(-(block as i32)),
+            false => self,
+        }
+    }
+
+    pub fn page(mut self, p: u32, frame: u32) -> Self {
+        match self.check_frame(frame, "page") {
+            true => self.add_page(p, frame as i32, false),
+            false => self,
+        }
+    }
+
+    // Resident and shared copy-on-write with any other page in the same frame.
+    pub fn cow_page(mut self, p: u32, frame: u32) -> Self {
+        match self.check_frame(frame, "page") {
+            true => self.add_page(p, frame as i32, true),
+            false => self,
+        }
+    }
+
+    pub fn page_on_disk(mut self, p: u32, block: usize) -> Self {
+        match self.check_block(block, "page") {
+            true => self.add_page(p, -(block as i32), false),
+            false => self,
+        }
+    }
+
+    pub fn demand_zero(self, p: u32) -> Self {
+        self.add_page(p, PTE_DEMAND_ZERO, false)
+    }
+
+    // Preloads consecutive words of a disk block starting at offset.
+    pub fn disk_words(mut self, block: usize, offset: usize, values: &[i32]) -> Self {
+        if block >= DISK_BLOCKS || offset + values.len() > BLOCK_SIZE {
+            self.errors.push(format!("Disk words {}..{} of block {} are outside the disk", offset, offset + values.len(), block));
+            return self;
+        }
+        for (i, &value) in values.iter().enumerate() {
+            self.init_data.disk_words.push((block, offset + i, value));
+        }
+        self
+    }
+
+    // The equivalent init data, after the same conflict checks as `--strict`.
+    pub fn into_init_data(self) -> Result<InitData, String> {
+        let mut errors = self.errors;
+        for &(s, _, pt) in &self.init_data.st_entries {
+            if pt == 0 && self.init_data.pt_entries.iter().any(|&(owner, _, _)| owner == s) {
+                errors.push(format!("Segment {} has pages but no page table", s));
+            }
+        }
+        if let Err(conflicts) = self.init_data.validate() {
+            errors.extend(conflicts);
+        }
+        match errors.is_empty() {
+            true => Ok(self.init_data),
+            false => Err(errors.join("; ")),
+        }
+    }
+
+    pub fn build(self) -> Result<(PhysicalMemory, Disk, FreeFrameList), String> {
+        let init_data = self.into_init_data()?;
+        let mut pm = PhysicalMemory::new();
+        let mut disk = Disk::new();
+        let ffl = init_data.apply(&mut pm, &mut disk);
+        Ok((pm, disk, ffl))
+    }
+
+    pub fn build_manager(self) -> Result<VMManager, String> {
+        Ok(VMManager::from_init_data(&self.into_init_data()?))
+    }
+
+    fn current_segment(&mut self, what: &str) -> Option<u32> {
+        if self.current.is_none() {
+            self.errors.push(format!("{} set before any segment", what));
+        }
+        self.current
+    }
+
+    fn check_frame(&mut self, frame: u32, what: &str) -> bool {
+        let valid = (ST_FRAMES as u32..NUM_FRAMES as u32).contains(&frame);
+        if !valid {
+            self.errors.push(format!("Frame {} cannot hold a {}", frame, what));
+        }
+        valid
+    }
+
+    fn check_block(&mut self, block: usize, what: &str) -> bool {
+        let valid = (1..DISK_BLOCKS).contains(&block);
+        if !valid {
+            self.errors.push(format!("Disk block {} cannot hold a {}", block, what));
+        }
+        valid
+    }
+
+    fn set_pt(mut self, location: i32) -> Self {
+        if let Some(s) = self.current_segment("Page table") {
+            if let Some(entry) = self.init_data.st_entries.iter_mut().find(|(existing, _, _)| *existing == s) {
+                entry.2 = location;
+            }
+        }
+        self
+    }
+
+    fn add_page(mut self, p: u32, location: i32, cow: bool) -> Self {
+        let Some(s) = self.current_segment("Page") else { return self };
+        if p as usize >= PT_SIZE {
+            self.errors.push(format!("Page {} of segment {} is out of range", p, s));
+        } else if self.init_data.pt_entries.iter().any(|&(owner, page, _)| (owner, page) == (s, p)) {
+            self.errors.push(format!("Page {} of segment {} is defined twice", p, s));
+        } else {
+            self.init_data.pt_entries.push((s, p, location));
+            if cow {
+                self.init_data.cow_pages.push((s, p));
+            }
+        }
+        self
+    }
+}
@@ -0,0 +1,92 @@
+use crate::constants::*;
+use crate::memory::{FrameOwner, FreeFrameList, PageTableEntry, PhysicalMemory};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    pub segments_compacted: usize,
+    pub pt_frames_before: usize,
+    pub pt_frames_after: usize,
+}
+
+impl CompactionReport {
+    pub fn frames_saved(&self) -> usize {
+        self.pt_frames_before - self.pt_frames_after
+    }
+}
+
+
+pub fn compact_page_tables(
+    pm: &mut PhysicalMemory,
+    ffl: &mut FreeFrameList,
+    slot_size: usize,
+) -> Result<CompactionReport, String> {
+    let layout = *pm.layout();
+    let page_size = layout.page_size();
+    if slot_size == 0 || !slot_size.is_power_of_two() || slot_size > page_size {
+        return Err(format!("PT slot size {} must be a power of two no larger than {}", slot_size, page_size));
+    }
+
+    let mut pt_users = vec![0usize; NUM_FRAMES];
+    for s in 0..layout.max_segments() as u32 {
+        let pt_location = pm.get_segment_pt_location(s);
+        if pt_location > 0 {
+            pt_users[pm.pt_frame(pt_location) as usize] += 1;
+        }
+    }
+
+    let mut candidates: Vec<(u32, u32, Vec<PageTableEntry>)> = Vec::new();
+    for s in 0..layout.max_segments() as u32 {
+        let size = pm.get_segment_size(s);
+        let pt_location = pm.get_segment_pt_location(s);
+        if size <= 0 || pt_location <= 0 || PhysicalMemory::is_compact_pt(pt_location) || pm.is_huge_segment(s) {
+            continue;
+        }
+        let pages = (size as usize).div_ceil(page_size);
+        if pages > slot_size || pt_users[pt_location as usize] != 1 {
+            continue;
+        }
+        let entries = (0..pages as u32).map(|p| pm.page_entry(pt_location, p)).collect();
+        candidates.push((s, pt_location as u32, entries));
+    }
+
+    let slots_per_frame = page_size / slot_size;
+    let pt_frames_before = candidates.len();
+    let pt_frames_after = candidates.len().div_ceil(slots_per_frame);
+    if pt_frames_after == pt_frames_before {
+        return Ok(CompactionReport { segments_compacted: 0, pt_frames_before, pt_frames_after });
+    }
+
+    let mut frames: Vec<u32> = candidates.iter().map(|&(_, f, _)| f).collect();
+    frames.sort_unstable();
+    for &frame in &frames {
+        let start = pm.frame_to_address(frame as i32);
+        for i in 0..page_size {
+            pm.write(start + i, 0);
+        }
+    }
+
+    for (i, (s, _, entries)) in candidates.iter().enumerate() {
+        let frame = frames[i / slots_per_frame];
+        let address = pm.frame_to_address(frame as i32) + (i % slots_per_frame) * slot_size;
+        let location = PhysicalMemory::compact_pt_location(address);
+        for (p, &entry) in entries.iter().enumerate() {
+            pm.set_page_table_entry(location, p as u32, entry);
+        }
+        let size = pm.get_segment_size(*s);
+        pm.set_segment_entry(*s, size, location, pm.segment_protection(*s));
+        if i % slots_per_frame == 0 {
+            pm.frame_table_mut().set_owner(frame, FrameOwner::PageTable(*s));
+        }
+    }
+
+    for &frame in &frames[pt_frames_after..] {
+        pm.frame_table_mut().release(frame);
+        ffl.release(frame);
+    }
+
+    Ok(CompactionReport {
+        segments_compacted: candidates.len(),
+        pt_frames_before,
+        pt_frames_after,
+    })
+}
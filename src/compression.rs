@@ -0,0 +1,195 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use crate::memory::{Disk, PhysicalMemory};
+use crate::replacement::PageId;
+
+// A page's words as (value, count) runs.
+pub fn compress(words: &[i32]) -> Vec<(i32, u32)> {
+    let mut runs: Vec<(i32, u32)> = Vec::new();
+    for &word in words {
+        match runs.last_mut() {
+            Some((value, count)) if *value == word => *count += 1,
+            _ => runs.push((word, 1)),
+        }
+    }
+    runs
+}
+
+pub fn decompress(runs: &[(i32, u32)]) -> impl Iterator<Item = i32> + '_ {
+    runs.iter().flat_map(|&(value, count)| std::iter::repeat_n(value, count as usize))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedPage {
+    pub block: usize,
+    pub runs: Vec<(i32, u32)>,
+    // Newer than the copy in the page's disk block.
+    pub dirty: bool,
+}
+
+impl CompressedPage {
+    // Each run costs two words of pool space.
+    pub fn words(&self) -> usize {
+        self.runs.len() * 2
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub stored: u64,
+    // Pages that would not have shrunk, sent straight to disk.
+    pub rejected: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub written_back: u64,
+    pub original_words: u64,
+    pub compressed_words: u64,
+}
+
+impl PoolStats {
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_words == 0 {
+            return 0.0;
+        }
+        self.original_words as f64 / self.compressed_words as f64
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        let faults = self.hits + self.misses;
+        if faults == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / faults as f64
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pages stored ({} rejected), ratio {:.2}:1, {} hits / {} misses ({:.1}% hit rate), {} written back",
+            self.stored,
+            self.rejected,
+            self.ratio(),
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0,
+            self.written_back
+        )
+    }
+}
+
+// A zswap-style pool of evicted pages kept compressed in memory, within a
+// budget of words. Faults on a pooled page decompress it instead of reading
+// its block; when the pool is full the oldest pages are written back to disk.
+pub struct CompressedPool {
+    budget: usize,
+    used: usize,
+    pages: HashMap<PageId, CompressedPage>,
+    order: VecDeque<PageId>,
+    stats: PoolStats,
+}
+
+impl CompressedPool {
+    pub fn new(budget: usize) -> Self {
+        CompressedPool { budget, used: 0, pages: HashMap::new(), order: VecDeque::new(), stats: PoolStats::default() }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    pub fn contains(&self, page: PageId) -> bool {
+        self.pages.contains_key(&page)
+    }
+
+    pub fn stats(&self) -> &PoolStats {
+        &self.stats
+    }
+
+    // Compresses the frame's page into the pool, writing back older pages to make
+    // room. Returns false, leaving the page to go to disk, if it does not shrink.
+    pub fn store(&mut self, page: PageId, block: usize, frame: u32, pm: &PhysicalMemory, disk: &mut Disk) -> bool {
+        let (start, page_size) = (pm.frame_to_address(frame as i32), pm.layout().page_size());
+        let words: Vec<i32> = (start..start + page_size).map(|address| pm.read(address)).collect();
+        let compressed = CompressedPage { block, runs: compress(&words), dirty: pm.is_dirty(frame) };
+        if compressed.words() >= page_size || compressed.words() > self.budget {
+            self.stats.rejected += 1;
+            return false;
+        }
+        self.remove(page);
+        while self.used + compressed.words() > self.budget {
+            let oldest = self.order.pop_front().expect("a non-empty pool holds at least one page");
+            self.write_back(oldest, disk);
+        }
+        self.used += compressed.words();
+        self.stats.stored += 1;
+        self.stats.original_words += page_size as u64;
+        self.stats.compressed_words += compressed.words() as u64;
+        self.pages.insert(page, compressed);
+        self.order.push_back(page);
+        true
+    }
+
+    // Decompresses a pooled page into the frame, counting a hit.
+    pub fn load(&mut self, page: PageId, frame: u32, pm: &mut PhysicalMemory) -> Option<CompressedPage> {
+        let compressed = self.take(page)?;
+        let start = pm.frame_to_address(frame as i32);
+        for (i, word) in decompress(&compressed.runs).enumerate() {
+            pm.write(start + i, word);
+        }
+        self.stats.hits += 1;
+        Some(compressed)
+    }
+
+    pub fn record_miss(&mut self) {
+        self.stats.misses += 1;
+    }
+
+    // Drops a page whose pooled copy is no longer needed, e.g. because it was
+    // freed or its old frame was reclaimed intact.
+    pub fn remove(&mut self, page: PageId) {
+        self.take(page);
+    }
+
+    // Writes back and drops every pooled page of segment s, before its page
+    // table goes to disk and the pool could no longer be consulted for it.
+    pub fn flush_segment(&mut self, s: u32, disk: &mut Disk) {
+        let pages: Vec<PageId> = self.order.iter().copied().filter(|&(owner, _)| owner == s).collect();
+        for page in pages {
+            self.order.retain(|&p| p != page);
+            self.write_back(page, disk);
+        }
+    }
+
+    fn take(&mut self, page: PageId) -> Option<CompressedPage> {
+        let compressed = self.pages.remove(&page)?;
+        self.order.retain(|&p| p != page);
+        self.used -= compressed.words();
+        Some(compressed)
+    }
+
+    fn write_back(&mut self, page: PageId, disk: &mut Disk) {
+        let Some(compressed) = self.pages.remove(&page) else { return };
+        self.used -= compressed.words();
+        if compressed.dirty {
+            for (offset, word) in decompress(&compressed.runs).enumerate() {
+                disk.write(compressed.block, offset, word);
+            }
+            self.stats.written_back += 1;
+        }
+    }
+}
@@ -22,3 +22,32 @@ pub const P_SHIFT: u32 = W_BITS;
 pub const S_SHIFT: u32 = P_BITS + W_BITS;
 
 pub const INVALID_ADDRESS: i32 = -1;
+
+// Output codes per failure kind under ErrorCodes::Detailed.
+pub const ERROR_BOUNDARY_VIOLATION: i32 = -1;
+pub const ERROR_INVALID_SEGMENT: i32 = -2;
+pub const ERROR_INVALID_PAGE: i32 = -3;
+pub const ERROR_PROTECTION: i32 = -4;
+
+pub const PROTECTION_SHIFT: u32 = 28;
+pub const SEGMENT_SIZE_MASK: i32 = (1 << PROTECTION_SHIFT) - 1;
+
+pub const PTE_COW: i32 = 1 << 30;
+
+// A PT entry for a page that gets a zeroed frame on first touch, with no disk
+// read. Real disk blocks are all far smaller, so -PTE_DEMAND_ZERO is never one.
+pub const PTE_DEMAND_ZERO: i32 = -(1 << 30);
+
+// Set in a resident ST entry's PT location when the PT is a slot of a frame
+// shared by compacted PTs; the low bits are then the slot's word address.
+// Without it a positive PT location is always a frame number.
+pub const PT_COMPACT: i32 = 1 << 30;
+
+pub const DEFAULT_PT_SLOT_SIZE: usize = 32;
+pub const DEFAULT_SYNC_INTERVAL: usize = 4096;
+
+// A huge-page segment's ST entry maps one aligned run of HUGE_PAGE_FRAMES
+// frames directly, with no page table; the flag sits in the size word's sign bit.
+pub const HUGE_PAGE_FRAMES: usize = 8;
+pub const HUGE_PAGE_SIZE: usize = HUGE_PAGE_FRAMES * PAGE_SIZE;
+pub const SEGMENT_HUGE: i32 = 1 << 31;
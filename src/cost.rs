@@ -0,0 +1,131 @@
+use std::ops::AddAssign;
+
+use crate::stats::TranslationStats;
+use crate::translation::{FaultFlags, TranslationResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CostModel {
+    pub tlb_lookup: u64,
+    pub memory_access: u64,
+    pub fault_overhead: u64,
+    pub disk_transfer: u64,
+    // Replaces disk_transfer for page faults served by the slow swap tier.
+    pub slow_disk_transfer: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlbOutcome {
+    NotPresent,
+    Hit,
+    Miss,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyBreakdown {
+    pub tlb: u64,
+    pub table_walk: u64,
+    pub fault_service: u64,
+    pub disk: u64,
+    pub data: u64,
+}
+
+impl LatencyBreakdown {
+    pub fn total(&self) -> u64 {
+        self.tlb + self.table_walk + self.fault_service + self.disk + self.data
+    }
+}
+
+impl AddAssign for LatencyBreakdown {
+    fn add_assign(&mut self, other: Self) {
+        self.tlb += other.tlb;
+        self.table_walk += other.table_walk;
+        self.fault_service += other.fault_service;
+        self.disk += other.disk;
+        self.data += other.data;
+    }
+}
+
+impl CostModel {
+    // `tlb=1,memory=100,fault=1000,disk=100000,slow-disk=1000000`; omitted costs
+    // keep their defaults.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut model = CostModel::default();
+        for part in spec.split([',', ' ']).filter(|part| !part.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| format!("Invalid cost (expected key=cycles): {}", part))?;
+            let cycles = value.parse().map_err(|_| format!("Invalid cycle count for {}: {}", key, value))?;
+            match key {
+                "tlb" => model.tlb_lookup = cycles,
+                "memory" => model.memory_access = cycles,
+                "fault" => model.fault_overhead = cycles,
+                "disk" => model.disk_transfer = cycles,
+                "slow-disk" => model.slow_disk_transfer = cycles,
+                _ => return Err(format!("Unknown cost: {} (expected tlb, memory, fault, disk or slow-disk)", key)),
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn memory_references(result: &TranslationResult) -> u64 {
+        match result {
+            TranslationResult::Success(_) => 3,
+            TranslationResult::InvalidPage => 2,
+            TranslationResult::SegmentBoundaryViolation
+            | TranslationResult::InvalidSegment
+            | TranslationResult::AccessDenied
+            | TranslationResult::ProtectionViolation => 1,
+        }
+    }
+
+    pub fn breakdown(&self, result: &TranslationResult, faults: FaultFlags, tlb: TlbOutcome) -> LatencyBreakdown {
+        let data_refs = matches!(result, TranslationResult::Success(_)) as u64;
+        let walk_refs = match tlb {
+            TlbOutcome::Hit => 0,
+            TlbOutcome::NotPresent | TlbOutcome::Miss => Self::memory_references(result) - data_refs,
+        };
+        let hard_faults = faults.hard_faults();
+        LatencyBreakdown {
+            tlb: if tlb == TlbOutcome::NotPresent { 0 } else { self.tlb_lookup },
+            table_walk: walk_refs * self.memory_access,
+            fault_service: (hard_faults
+                + faults.soft_fault as u64
+                + faults.zero_fault as u64
+                + faults.compressed_fault as u64
+                + faults.cow_fault as u64)
+                * self.fault_overhead,
+            disk: self.disk_cycles(hard_faults, faults.slow_tier as u64),
+            data: data_refs * self.memory_access,
+        }
+    }
+
+    pub fn access_latency(&self, result: &TranslationResult, faults: FaultFlags) -> u64 {
+        self.breakdown(result, faults, TlbOutcome::NotPresent).total()
+    }
+
+    // The total of access_latency over a TLB-less two-level run, from its
+    // counters alone: every cost is linear in the result kinds and fault counts.
+    pub fn simulated_cycles(&self, stats: &TranslationStats) -> u64 {
+        let others = stats.translations - stats.successes - stats.invalid_pages;
+        let references = stats.successes * 3 + stats.invalid_pages * 2 + others;
+        let hard_faults = stats.st_faults + stats.pd_faults + stats.pt_faults + stats.page_faults;
+        references * self.memory_access
+            + (hard_faults + stats.soft_faults + stats.zero_fill_faults + stats.compressed_faults + stats.cow_faults)
+                * self.fault_overhead
+            + self.disk_cycles(hard_faults, stats.slow_tier_faults)
+    }
+
+    fn disk_cycles(&self, transfers: u64, slow_transfers: u64) -> u64 {
+        (transfers - slow_transfers) * self.disk_transfer + slow_transfers * self.slow_disk_transfer
+    }
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            tlb_lookup: 1,
+            memory_access: 100,
+            fault_overhead: 1_000,
+            disk_transfer: 100_000,
+            slow_disk_transfer: 1_000_000,
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::memory::{PhysicalMemory, StSlice};
+use crate::translation::{FaultFlags, TranslationResult, VirtualAddress};
+
+pub const EVENTS_MAGIC: &[u8; 4] = b"VMEV";
+pub const EVENTS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FaultKind {
+    SegmentTable,
+    Directory,
+    PageTable,
+    Page,
+    Soft,
+    CopyOnWrite,
+    ZeroFill,
+    Compressed,
+}
+
+impl FaultKind {
+    const ALL: [FaultKind; 8] = [
+        FaultKind::SegmentTable,
+        FaultKind::Directory,
+        FaultKind::PageTable,
+        FaultKind::Page,
+        FaultKind::Soft,
+        FaultKind::CopyOnWrite,
+        FaultKind::ZeroFill,
+        FaultKind::Compressed,
+    ];
+
+    // The faults an access took, in the order they are logged.
+    pub fn taken(faults: FaultFlags) -> impl Iterator<Item = FaultKind> {
+        let flags = [
+            faults.st_fault,
+            faults.pd_fault,
+            faults.pt_fault,
+            faults.page_fault,
+            faults.soft_fault,
+            faults.cow_fault,
+            faults.zero_fault,
+            faults.compressed_fault,
+        ];
+        Self::ALL.into_iter().zip(flags).filter(|&(_, set)| set).map(|(kind, _)| kind)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaultKind::SegmentTable => "st",
+            FaultKind::Directory => "pd",
+            FaultKind::PageTable => "pt",
+            FaultKind::Page => "page",
+            FaultKind::Soft => "soft",
+            FaultKind::CopyOnWrite => "cow",
+            FaultKind::ZeroFill => "zero",
+            FaultKind::Compressed => "compressed",
+        }
+    }
+}
+
+// What the simulator reports to observers as each access is handled, tagged
+// with the index of the access. Statistics, the event log and --verbose are
+// all built from these, so the translation path itself records nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VmEvent {
+    TlbHit { access: u32, va: u32 },
+    TlbMiss { access: u32, va: u32 },
+    FaultStarted { access: u32, kind: FaultKind },
+    FrameAllocated { access: u32, frame: u32 },
+    Evicted { access: u32, frames: u32 },
+    DiskRead { access: u32, blocks: u32 },
+    DiskWrite { access: u32, blocks: u32 },
+    Translated { access: u32, va: u32, result: TranslationResult, faults: FaultFlags },
+}
+
+impl VmEvent {
+    // Faults, the frames they filled and the blocks they read. `page_frame` is
+    // the frame a page fault filled, before any copy-on-write moved the result.
+    pub fn for_translation(
+        access: u32,
+        va: &VirtualAddress,
+        result: &TranslationResult,
+        faults: FaultFlags,
+        page_frame: u32,
+        pm: &PhysicalMemory,
+        mut emit: impl FnMut(VmEvent),
+    ) {
+        for kind in FaultKind::taken(faults) {
+            emit(VmEvent::FaultStarted { access, kind });
+        }
+        if faults.st_fault {
+            if let StSlice::Resident(frame) = pm.st_slices()[pm.st_slice_of(va.s)] {
+                emit(VmEvent::FrameAllocated { access, frame });
+            }
+        }
+        if faults.pt_fault {
+            let frame = pm.pt_frame(pm.get_segment_pt_location(va.s));
+            emit(VmEvent::FrameAllocated { access, frame });
+        }
+        if faults.page_fault || faults.zero_fault || faults.compressed_fault {
+            emit(VmEvent::FrameAllocated { access, frame: page_frame });
+        }
+        if let (true, TranslationResult::Success(pa)) = (faults.cow_fault, result) {
+            emit(VmEvent::FrameAllocated { access, frame: (*pa as usize / pm.layout().page_size()) as u32 });
+        }
+        if faults.hard_faults() > 0 {
+            emit(VmEvent::DiskRead { access, blocks: faults.hard_faults() as u32 });
+        }
+    }
+
+    pub fn for_replacement(access: u32, evicted: u32, written: u32, mut emit: impl FnMut(VmEvent)) {
+        if evicted > 0 {
+            emit(VmEvent::Evicted { access, frames: evicted });
+        }
+        if written > 0 {
+            emit(VmEvent::DiskWrite { access, blocks: written });
+        }
+    }
+}
+
+impl fmt::Display for VmEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmEvent::TlbHit { access, va } => write!(f, "access {}: TLB hit for {}", access, va),
+            VmEvent::TlbMiss { access, va } => write!(f, "access {}: TLB miss for {}", access, va),
+            VmEvent::FaultStarted { access, kind } => write!(f, "access {}: {} fault", access, kind.name()),
+            VmEvent::FrameAllocated { access, frame } => write!(f, "access {}: frame {} allocated", access, frame),
+            VmEvent::Evicted { access, frames } => write!(f, "access {}: {} frames evicted", access, frames),
+            VmEvent::DiskRead { access, blocks } => write!(f, "access {}: {} disk reads", access, blocks),
+            VmEvent::DiskWrite { access, blocks } => write!(f, "access {}: {} disk writes", access, blocks),
+            VmEvent::Translated { access, va, result, .. } => write!(f, "access {}: {} -> {}", access, va, result),
+        }
+    }
+}
+
+// Anything that consumes VmEvents; closures taking &VmEvent are observers too.
+pub trait Observer {
+    fn on_event(&mut self, event: &VmEvent);
+}
+
+impl<F: FnMut(&VmEvent)> Observer for F {
+    fn on_event(&mut self, event: &VmEvent) {
+        self(event)
+    }
+}
+
+// Observers registered on a VMManager, each with an id for removing it.
+#[derive(Default)]
+pub struct Observers {
+    observers: Vec<(usize, Box<dyn Observer + Send>)>,
+    next_id: usize,
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<O: Observer + Send + 'static>(&mut self, observer: O) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.observers.push((id, Box::new(observer)));
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let before = self.observers.len();
+        self.observers.retain(|(observer, _)| *observer != id);
+        self.observers.len() < before
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    pub fn emit(&mut self, event: &VmEvent) {
+        for (_, observer) in &mut self.observers {
+            observer.on_event(event);
+        }
+    }
+}
+
+// The replayable part of a run: every event except TLB lookups, in order,
+// tagged with the index of the access that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Fault { access: u32, kind: FaultKind },
+    FrameAllocated { access: u32, frame: u32 },
+    Evicted { access: u32, frames: u32 },
+    DiskRead { access: u32, blocks: u32 },
+    DiskWrite { access: u32, blocks: u32 },
+    Translated { access: u32, pa: i32 },
+}
+
+impl Event {
+    // Each event is encoded as a tag byte followed by two little-endian words.
+    fn encode(&self, out: &mut Vec<u8>) {
+        let (tag, access, value) = match *self {
+            Event::Fault { access, kind } => (0, access, FaultKind::ALL.iter().position(|&k| k == kind).unwrap() as i32),
+            Event::FrameAllocated { access, frame } => (1, access, frame as i32),
+            Event::Evicted { access, frames } => (2, access, frames as i32),
+            Event::DiskRead { access, blocks } => (3, access, blocks as i32),
+            Event::DiskWrite { access, blocks } => (4, access, blocks as i32),
+            Event::Translated { access, pa } => (5, access, pa),
+        };
+        out.push(tag);
+        out.extend_from_slice(&access.to_le_bytes());
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let access = u32::from_le_bytes(bytes[1..5].try_into().unwrap());
+        let value = i32::from_le_bytes(bytes[5..9].try_into().unwrap());
+        match bytes[0] {
+            0 => {
+                let kind = FaultKind::ALL.get(value as usize).ok_or_else(|| format!("Unknown fault kind {}", value))?;
+                Ok(Event::Fault { access, kind: *kind })
+            }
+            1 => Ok(Event::FrameAllocated { access, frame: value as u32 }),
+            2 => Ok(Event::Evicted { access, frames: value as u32 }),
+            3 => Ok(Event::DiskRead { access, blocks: value as u32 }),
+            4 => Ok(Event::DiskWrite { access, blocks: value as u32 }),
+            5 => Ok(Event::Translated { access, pa: value }),
+            tag => Err(format!("Unknown event tag {}", tag)),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Fault { access, kind } => write!(f, "access {}: {} fault", access, kind.name()),
+            Event::FrameAllocated { access, frame } => write!(f, "access {}: frame {} allocated", access, frame),
+            Event::Evicted { access, frames } => write!(f, "access {}: {} frames evicted", access, frames),
+            Event::DiskRead { access, blocks } => write!(f, "access {}: {} disk reads", access, blocks),
+            Event::DiskWrite { access, blocks } => write!(f, "access {}: {} disk writes", access, blocks),
+            Event::Translated { access, pa } => write!(f, "access {}: result {}", access, pa),
+        }
+    }
+}
+
+const EVENT_SIZE: usize = 9;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventLog {
+    events: Vec<Event>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn push(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    // The first index at which the logs differ, with the event each has there.
+    pub fn first_divergence(&self, other: &EventLog) -> Option<(usize, Option<Event>, Option<Event>)> {
+        let len = self.events.len().max(other.events.len());
+        (0..len)
+            .map(|i| (i, self.events.get(i).copied(), other.events.get(i).copied()))
+            .find(|(_, a, b)| a != b)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(12 + self.events.len() * EVENT_SIZE);
+        out.extend_from_slice(EVENTS_MAGIC);
+        out.extend_from_slice(&EVENTS_VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.events.len() as u32).to_le_bytes());
+        for event in &self.events {
+            event.encode(&mut out);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 12 || &bytes[..4] != EVENTS_MAGIC {
+            return Err("Not an event log file (bad magic)".to_string());
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != EVENTS_VERSION {
+            return Err(format!("Unsupported event log version {}", version));
+        }
+        let count = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let body = &bytes[12..];
+        if body.len() != count * EVENT_SIZE {
+            return Err(format!("Event log should hold {} events but has {} bytes of them", count, body.len()));
+        }
+        let events = body.chunks_exact(EVENT_SIZE).map(Event::decode).collect::<Result<_, _>>()?;
+        Ok(EventLog { events })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        fs::write(path.as_ref(), self.to_bytes()).map_err(|e| format!("Failed to write event log: {}", e))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let bytes = fs::read(path.as_ref()).map_err(|e| format!("Failed to read event log: {}", e))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl Observer for EventLog {
+    fn on_event(&mut self, event: &VmEvent) {
+        let event = match *event {
+            VmEvent::TlbHit { .. } | VmEvent::TlbMiss { .. } => return,
+            VmEvent::FaultStarted { access, kind } => Event::Fault { access, kind },
+            VmEvent::FrameAllocated { access, frame } => Event::FrameAllocated { access, frame },
+            VmEvent::Evicted { access, frames } => Event::Evicted { access, frames },
+            VmEvent::DiskRead { access, blocks } => Event::DiskRead { access, blocks },
+            VmEvent::DiskWrite { access, blocks } => Event::DiskWrite { access, blocks },
+            VmEvent::Translated { access, result, .. } => Event::Translated { access, pa: result.to_output() },
+        };
+        self.push(event);
+    }
+}
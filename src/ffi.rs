@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+use std::slice;
+
+use crate::constants::INVALID_ADDRESS;
+use crate::io::InitData;
+use crate::translation::ErrorCodes;
+use crate::vm_manager::VMManager;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message).unwrap_or_else(|_| c"error message contained a NUL byte".to_owned());
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// Maps a Rust result onto the C convention: 0 on success, -1 with the message
+// left for vm_last_error.
+fn status(result: Result<(), String>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            set_last_error(message);
+            -1
+        }
+    }
+}
+
+unsafe fn c_str<'a>(s: *const c_char, what: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is NULL", what));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| format!("{} is not valid UTF-8", what))
+}
+
+/// Creates a simulator with empty memory. Free it with `vm_destroy`.
+#[no_mangle]
+pub extern "C" fn vm_create() -> *mut VMManager {
+    Box::into_raw(Box::new(VMManager::new()))
+}
+
+/// Loads a text or binary init file, replacing the simulator's memory.
+/// Returns 0, or -1 with the reason available from `vm_last_error`.
+///
+/// # Safety
+/// `vm` must come from `vm_create` and `path` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vm_init_from_file(vm: *mut VMManager, path: *const c_char) -> i32 {
+    let Some(vm) = vm.as_mut() else {
+        return status(Err("vm is NULL".to_string()));
+    };
+    status(c_str(path, "path").and_then(InitData::from_file).map(|init_data| vm.init_from_data(&init_data)))
+}
+
+/// Like `vm_init_from_file`, with the init file's contents in memory.
+///
+/// # Safety
+/// `vm` must come from `vm_create` and `content` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vm_init_from_string(vm: *mut VMManager, content: *const c_char) -> i32 {
+    let Some(vm) = vm.as_mut() else {
+        return status(Err("vm is NULL".to_string()));
+    };
+    status(c_str(content, "content").and_then(|content| vm.init_from_str(content)))
+}
+
+/// Selects how `vm_translate` and `vm_translate_batch` report failures: with
+/// `detailed` false every failure is -1; with it true a boundary violation is
+/// -1, an invalid segment -2, an invalid page -3 and a protection fault -4.
+/// Returns 0, or -1 if `vm` is NULL.
+///
+/// # Safety
+/// `vm` must come from `vm_create`.
+#[no_mangle]
+pub unsafe extern "C" fn vm_set_error_codes(vm: *mut VMManager, detailed: bool) -> i32 {
+    let Some(vm) = vm.as_mut() else {
+        return status(Err("vm is NULL".to_string()));
+    };
+    vm.set_error_codes(if detailed { ErrorCodes::Detailed } else { ErrorCodes::Collapsed });
+    0
+}
+
+/// Translates a virtual address as a read, demand paging as needed. Returns
+/// the physical address, or a negative code as the command-line output does.
+///
+/// # Safety
+/// `vm` must come from `vm_create`.
+#[no_mangle]
+pub unsafe extern "C" fn vm_translate(vm: *mut VMManager, va: u32) -> i32 {
+    match vm.as_mut() {
+        Some(vm) => {
+            let result = vm.translate(va);
+            vm.output(&result)
+        }
+        None => INVALID_ADDRESS,
+    }
+}
+
+/// Translates `len` addresses from `vas` into `out`. Returns 0, or -1 if a
+/// pointer is NULL.
+///
+/// # Safety
+/// `vm` must come from `vm_create`, and `vas` and `out` must each point to
+/// `len` elements.
+#[no_mangle]
+pub unsafe extern "C" fn vm_translate_batch(vm: *mut VMManager, vas: *const u32, len: usize, out: *mut i32) -> i32 {
+    let Some(vm) = vm.as_mut() else {
+        return status(Err("vm is NULL".to_string()));
+    };
+    if vas.is_null() || out.is_null() {
+        return status(Err("vas or out is NULL".to_string()));
+    }
+    let results = vm.translate_batch(slice::from_raw_parts(vas, len));
+    slice::from_raw_parts_mut(out, len).copy_from_slice(&results);
+    0
+}
+
+/// The message for the last call on this thread that returned -1, or NULL.
+/// The string stays valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn vm_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Frees a simulator from `vm_create`. NULL is ignored.
+///
+/// # Safety
+/// `vm` must come from `vm_create` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn vm_destroy(vm: *mut VMManager) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
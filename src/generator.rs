@@ -0,0 +1,346 @@
+use crate::constants::*;
+use crate::io::InitData;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::translation::{compose, translate_with_demand_paging, TranslationResult, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessOutcome {
+    pub va: u32,
+    pub result: TranslationResult,
+    pub value: Option<i32>,
+}
+
+pub trait AddressGenerator {
+    fn next_address(&mut self, previous: Option<&AccessOutcome>) -> Option<u32>;
+}
+
+impl<F> AddressGenerator for F
+where
+    F: FnMut(Option<&AccessOutcome>) -> Option<u32>,
+{
+    fn next_address(&mut self, previous: Option<&AccessOutcome>) -> Option<u32> {
+        self(previous)
+    }
+}
+
+pub struct PointerChase {
+    start: u32,
+}
+
+impl PointerChase {
+    pub fn new(start: u32) -> Self {
+        PointerChase { start }
+    }
+}
+
+impl AddressGenerator for PointerChase {
+    fn next_address(&mut self, previous: Option<&AccessOutcome>) -> Option<u32> {
+        match previous {
+            None => Some(self.start),
+            Some(outcome) => match outcome.value {
+                Some(value) if value >= 0 => Some(value as u32),
+                _ => None,
+            },
+        }
+    }
+}
+
+pub fn run_closed_loop<G: AddressGenerator + ?Sized>(
+    generator: &mut G,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    max_steps: usize,
+) -> Vec<AccessOutcome> {
+    let mut outcomes: Vec<AccessOutcome> = Vec::new();
+
+    while outcomes.len() < max_steps {
+        let va = match generator.next_address(outcomes.last()) {
+            Some(va) => va,
+            None => break,
+        };
+        let result = translate_with_demand_paging(&VirtualAddress::decompose(pm.layout(), va), pm, disk, ffl, None);
+        let value = match result {
+            TranslationResult::Success(pa) => Some(pm.read(pa as usize)),
+            _ => None,
+        };
+        outcomes.push(AccessOutcome { va, result, value });
+    }
+
+    outcomes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Uniform,
+    Zipf(f64),
+    Fixed(u32),
+}
+
+impl Distribution {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once(':') {
+            None if spec == "uniform" => Ok(Distribution::Uniform),
+            Some(("zipf", s)) => match s.parse::<f64>() {
+                Ok(s) if s.is_finite() && s >= 0.0 => Ok(Distribution::Zipf(s)),
+                _ => Err(format!("Invalid Zipf exponent: {}", s)),
+            },
+            Some(("fixed", value)) => {
+                value.parse().map(Distribution::Fixed).map_err(|_| format!("Invalid fixed value: {}", value))
+            }
+            _ => Err(format!("Unknown distribution: {} (expected uniform, zipf:<s> or fixed:<n>)", spec)),
+        }
+    }
+}
+
+// Draws values in 0..count. Zipf ranks value 0 as the most popular, so a skewed
+// segment distribution makes the low-numbered segments hot.
+#[derive(Debug, Clone)]
+struct Sampler {
+    distribution: Distribution,
+    count: u32,
+    cdf: Vec<f64>,
+}
+
+impl Sampler {
+    fn new(distribution: Distribution, count: u32) -> Self {
+        let cdf = match distribution {
+            Distribution::Zipf(s) => {
+                let weights: Vec<f64> = (1..=count).map(|rank| 1.0 / (rank as f64).powf(s)).collect();
+                let total: f64 = weights.iter().sum();
+                weights
+                    .iter()
+                    .scan(0.0, |sum, w| {
+                        *sum += w / total;
+                        Some(*sum)
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        Sampler { distribution, count: count.max(1), cdf }
+    }
+
+    fn sample(&self, rng: &mut u64) -> u32 {
+        match self.distribution {
+            Distribution::Uniform => (next_random(rng) % self.count as u64) as u32,
+            Distribution::Fixed(value) => value,
+            Distribution::Zipf(_) => {
+                let u = unit_random(rng);
+                (self.cdf.partition_point(|&p| p < u) as u32).min(self.count - 1)
+            }
+        }
+    }
+}
+
+// splitmix64, so generated traces are reproducible from the seed alone.
+pub(crate) fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub(crate) fn unit_random(state: &mut u64) -> f64 {
+    (next_random(state) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Open-loop synthetic workload: segment, page and offset are drawn independently,
+// each from its own distribution over a configurable range.
+#[derive(Debug, Clone)]
+pub struct SyntheticTrace {
+    rng: u64,
+    remaining: usize,
+    segment: Sampler,
+    page: Sampler,
+    offset: Sampler,
+}
+
+impl SyntheticTrace {
+    pub fn new(seed: u64, length: usize) -> Self {
+        SyntheticTrace {
+            rng: seed,
+            remaining: length,
+            segment: Sampler::new(Distribution::Uniform, MAX_SEGMENTS as u32),
+            page: Sampler::new(Distribution::Uniform, PT_SIZE as u32),
+            offset: Sampler::new(Distribution::Uniform, PAGE_SIZE as u32),
+        }
+    }
+
+    pub fn segments(mut self, distribution: Distribution, count: u32) -> Self {
+        self.segment = Sampler::new(distribution, count.min(MAX_SEGMENTS as u32));
+        self
+    }
+
+    pub fn pages(mut self, distribution: Distribution, count: u32) -> Self {
+        self.page = Sampler::new(distribution, count.min(PT_SIZE as u32));
+        self
+    }
+
+    pub fn offsets(mut self, distribution: Distribution, count: u32) -> Self {
+        self.offset = Sampler::new(distribution, count.min(PAGE_SIZE as u32));
+        self
+    }
+
+    pub fn generate(mut self) -> Vec<u32> {
+        let mut vas = Vec::with_capacity(self.remaining);
+        while let Some(va) = self.next_address(None) {
+            vas.push(va);
+        }
+        vas
+    }
+}
+
+impl AddressGenerator for SyntheticTrace {
+    fn next_address(&mut self, _previous: Option<&AccessOutcome>) -> Option<u32> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let s = self.segment.sample(&mut self.rng);
+        let p = self.page.sample(&mut self.rng);
+        let w = self.offset.sample(&mut self.rng);
+        Some(compose(s & (MAX_SEGMENTS as u32 - 1), p & P_MASK, w & W_MASK))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Locality {
+    Uniform,
+    // hot_share of the accesses go to the first hot_fraction of the pages.
+    HotSet { hot_fraction: f64, hot_share: f64 },
+    Sequential,
+    Zipf(f64),
+}
+
+impl Locality {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("Unknown locality: {} (expected uniform, hotset[:a/b], sequential or zipf:<s>)", spec);
+        match spec.split_once(':') {
+            None => match spec {
+                "uniform" => Ok(Locality::Uniform),
+                "hotset" => Ok(Locality::HotSet { hot_fraction: 0.1, hot_share: 0.9 }),
+                "sequential" => Ok(Locality::Sequential),
+                _ => Err(invalid()),
+            },
+            Some(("hotset", split)) => {
+                let (share, fraction) = split.split_once('/').ok_or_else(invalid)?;
+                let share: f64 = share.parse().map_err(|_| invalid())?;
+                let fraction: f64 = fraction.parse().map_err(|_| invalid())?;
+                if !(0.0..=100.0).contains(&share) || !(0.0..=100.0).contains(&fraction) || fraction == 0.0 {
+                    return Err(invalid());
+                }
+                Ok(Locality::HotSet { hot_fraction: fraction / 100.0, hot_share: share / 100.0 })
+            }
+            Some(("zipf", _)) => match Distribution::parse(spec)? {
+                Distribution::Zipf(s) => Ok(Locality::Zipf(s)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Parameters for `gen`: a fresh init file with `segments` segments of `pages`
+// pages each, every PT resident and each page resident with probability
+// `resident` (on disk otherwise), plus a trace drawn over all of those pages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkloadConfig {
+    pub segments: u32,
+    pub pages: u32,
+    pub resident: f64,
+    pub length: usize,
+    pub locality: Locality,
+    pub seed: u64,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        WorkloadConfig {
+            segments: 8,
+            pages: 64,
+            resident: 0.25,
+            length: 10_000,
+            locality: Locality::HotSet { hot_fraction: 0.1, hot_share: 0.9 },
+            seed: 1,
+        }
+    }
+}
+
+impl WorkloadConfig {
+    pub fn init_data(&self) -> Result<InitData, String> {
+        if self.segments == 0 || self.pages == 0 {
+            return Err("Workload needs at least one segment and page".to_string());
+        }
+        if self.segments as usize > MAX_SEGMENTS || self.pages as usize > PT_SIZE {
+            return Err(format!("At most {} segments of {} pages", MAX_SEGMENTS, PT_SIZE));
+        }
+        if !(0.0..=1.0).contains(&self.resident) {
+            return Err(format!("Resident fraction must be between 0 and 1, got {}", self.resident));
+        }
+
+        let mut rng = self.seed ^ 0x5eed;
+        let mut init_data = InitData::default();
+        let mut next_frame = ST_FRAMES + self.segments as usize;
+        let mut next_block = 1;
+        for s in 0..self.segments {
+            init_data.st_entries.push((s, (self.pages as usize * PAGE_SIZE) as i32, (ST_FRAMES as u32 + s) as i32));
+            for p in 0..self.pages {
+                let resident = unit_random(&mut rng) < self.resident;
+                let location = if resident {
+                    next_frame += 1;
+                    (next_frame - 1) as i32
+                } else {
+                    next_block += 1;
+                    -((next_block - 1) as i32)
+                };
+                init_data.pt_entries.push((s, p, location));
+            }
+        }
+        if next_frame > NUM_FRAMES || next_block > DISK_BLOCKS {
+            return Err(format!(
+                "{} segments of {} pages do not fit: {} frames and {} disk blocks needed",
+                self.segments,
+                self.pages,
+                next_frame,
+                next_block - 1
+            ));
+        }
+        Ok(init_data)
+    }
+
+    pub fn trace(&self) -> Vec<u32> {
+        let total = self.segments.max(1) * self.pages.max(1);
+        let mut rng = self.seed;
+        let zipf = match self.locality {
+            Locality::Zipf(s) => Some(Sampler::new(Distribution::Zipf(s), total)),
+            _ => None,
+        };
+        let hot = match self.locality {
+            Locality::HotSet { hot_fraction, .. } => ((total as f64 * hot_fraction).ceil() as u32).clamp(1, total),
+            _ => total,
+        };
+
+        (0..self.length)
+            .map(|i| {
+                let page = match self.locality {
+                    Locality::Uniform => (next_random(&mut rng) % total as u64) as u32,
+                    Locality::Sequential => (i % total as usize) as u32,
+                    Locality::Zipf(_) => zipf.as_ref().unwrap().sample(&mut rng),
+                    Locality::HotSet { hot_share, .. } => {
+                        let roll = unit_random(&mut rng);
+                        if roll < hot_share || hot == total {
+                            (next_random(&mut rng) % hot as u64) as u32
+                        } else {
+                            hot + (next_random(&mut rng) % (total - hot) as u64) as u32
+                        }
+                    }
+                };
+                let (s, p) = (page / self.pages, page % self.pages);
+                let w = (next_random(&mut rng) % PAGE_SIZE as u64) as u32;
+                compose(s, p, w)
+            })
+            .collect()
+    }
+}
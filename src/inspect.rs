@@ -0,0 +1,294 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::constants::*;
+use crate::memory::{Disk, FrameOwner, FreeFrameList, PhysicalMemory, StSlice};
+use crate::translation::Protection;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtLocation {
+    None,
+    Frame(u32),
+    DiskBlock(usize),
+    // A huge-page segment has no PT; this is the first frame of its page.
+    HugePage(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentDump {
+    pub s: u32,
+    pub size: i32,
+    pub protection: Protection,
+    pub pt: PtLocation,
+    // Non-zero PT entries with their copy-on-write flag.
+    pub pages: Vec<(u32, i32, bool)>,
+}
+
+// What an init file (or any other state) looks like once applied: the ST, every
+// PT wherever it lives, and the frames left on the free list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDump {
+    pub st_slices: Vec<StSlice>,
+    pub segments: Vec<SegmentDump>,
+    pub free_frames: Vec<u32>,
+}
+
+const DOT_RESIDENT: &str = "palegreen";
+const DOT_ON_DISK: &str = "lightgray";
+
+impl MemoryDump {
+    pub fn capture(pm: &PhysicalMemory, disk: &Disk, ffl: &FreeFrameList) -> Self {
+        let mut segments = Vec::new();
+        let layout = pm.layout();
+        for s in 0..layout.max_segments() as u32 {
+            let (size, pt_location, protection) = pm.read_segment_entry(disk, s);
+            if size == 0 && pt_location == 0 {
+                continue;
+            }
+            let pt = match pt_location {
+                0 => PtLocation::None,
+                l if l < 0 => PtLocation::DiskBlock((-l) as usize),
+                l if pm.is_huge_segment(s) => PtLocation::HugePage(l as u32),
+                l => PtLocation::Frame(pm.pt_frame(l)),
+            };
+            let page_count = (size.max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size()) as u32;
+            let pages = (0..page_count)
+                .filter_map(|p| {
+                    let (entry, cow) = match pt {
+                        PtLocation::None | PtLocation::HugePage(_) => (0, false),
+                        PtLocation::Frame(_) => (pm.get_page_frame(pt_location, p), pm.is_cow(pt_location, p)),
+                        PtLocation::DiskBlock(_) => (pm.read_page_entry(disk, pt_location, p), false),
+                    };
+                    (entry != 0).then_some((p, entry, cow))
+                })
+                .collect();
+            segments.push(SegmentDump { s, size, protection, pt, pages });
+        }
+        let mut free_frames = ffl.frames();
+        free_frames.sort_unstable();
+        MemoryDump { st_slices: pm.st_slices().to_vec(), segments, free_frames }
+    }
+
+    // Consecutive free frames as inclusive (first, last) runs.
+    pub fn free_runs(&self) -> Vec<(u32, u32)> {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for &frame in &self.free_frames {
+            match runs.last_mut() {
+                Some((_, last)) if *last + 1 == frame => *last = frame,
+                _ => runs.push((frame, frame)),
+            }
+        }
+        runs
+    }
+
+    pub fn to_json(&self) -> String {
+        let slices: Vec<String> = self.st_slices.iter().map(|&slice| slice_location(slice).to_string()).collect();
+        let segments: Vec<String> = self
+            .segments
+            .iter()
+            .map(|segment| {
+                let pt = match segment.pt {
+                    PtLocation::None => "null".to_string(),
+                    PtLocation::Frame(frame) => format!("{{\"frame\": {}}}", frame),
+                    PtLocation::DiskBlock(block) => format!("{{\"disk_block\": {}}}", block),
+                    PtLocation::HugePage(frame) => format!("{{\"huge_page\": {}}}", frame),
+                };
+                let pages: Vec<String> = segment
+                    .pages
+                    .iter()
+                    .map(|&(p, entry, cow)| match entry {
+                        PTE_DEMAND_ZERO => format!("{{\"page\": {}, \"demand_zero\": true}}", p),
+                        e if e < 0 => format!("{{\"page\": {}, \"disk_block\": {}}}", p, -e),
+                        e => format!("{{\"page\": {}, \"frame\": {}, \"cow\": {}}}", p, e, cow),
+                    })
+                    .collect();
+                format!(
+                    "{{\"segment\": {}, \"size\": {}, \"protection\": \"{}\", \"page_table\": {}, \"pages\": [{}]}}",
+                    segment.s,
+                    segment.size,
+                    segment.protection,
+                    pt,
+                    pages.join(", ")
+                )
+            })
+            .collect();
+        let runs: Vec<String> = self.free_runs().iter().map(|(first, last)| format!("[{}, {}]", first, last)).collect();
+        format!(
+            "{{\"st_slices\": [{}], \"segments\": [{}], \"free_frame_count\": {}, \"free_frames\": [{}]}}",
+            slices.join(", "),
+            segments.join(", "),
+            self.free_frames.len(),
+            runs.join(", ")
+        )
+    }
+
+    // A Graphviz digraph of the paging structures: the ST points at each PT
+    // (or huge page), each PT at the frames and disk blocks of its pages.
+    // Resident nodes are green and disk blocks grey, with edges to disk dashed;
+    // a frame or block mapped twice, as by copy-on-write, is a single node.
+    pub fn to_dot(&self) -> String {
+        let mut lines: Vec<String> =
+            ["digraph paging {", "  rankdir=LR;", "  node [shape=box, style=filled];"].map(String::from).to_vec();
+        let slices: Vec<String> = self
+            .st_slices
+            .iter()
+            .map(|&slice| match slice {
+                StSlice::Resident(frame) => format!("frame {}", frame),
+                StSlice::OnDisk(block) => format!("disk block {}", block),
+            })
+            .collect();
+        lines.push(format!("  st [label=\"Segment table\\n{}\", fillcolor=lightblue];", slices.join("\\n")));
+
+        let mut targets = BTreeSet::new();
+        let mut edges = Vec::new();
+        for segment in &self.segments {
+            let s = segment.s;
+            let label = format!("s {} (size {}, {})", s, segment.size, segment.protection);
+            let (pt, color, style) = match segment.pt {
+                PtLocation::None => (format!("PT {}\\nnone", s), "white", "solid"),
+                PtLocation::Frame(frame) => (format!("PT {}\\nframe {}", s, frame), DOT_RESIDENT, "solid"),
+                PtLocation::DiskBlock(block) => (format!("PT {}\\ndisk block {}", s, block), DOT_ON_DISK, "dashed"),
+                PtLocation::HugePage(frame) => {
+                    let last = frame + HUGE_PAGE_FRAMES as u32 - 1;
+                    let node = format!("  huge{} [label=\"huge page\\nframes {}-{}\", fillcolor={}];", s, frame, last, DOT_RESIDENT);
+                    lines.push(node);
+                    edges.push(format!("  st -> huge{} [label=\"{}\"];", s, label));
+                    continue;
+                }
+            };
+            lines.push(format!("  pt{} [label=\"{}\", fillcolor={}];", s, pt, color));
+            edges.push(format!("  st -> pt{} [label=\"{}\", style={}];", s, label, style));
+            for &(p, entry, cow) in &segment.pages {
+                let cow = if cow { " (cow)" } else { "" };
+                let (target, style) = match entry {
+                    PTE_DEMAND_ZERO => ("zero".to_string(), "dotted"),
+                    e if e < 0 => (format!("block{}", -e), "dashed"),
+                    e => (format!("frame{}", e), "solid"),
+                };
+                edges.push(format!("  pt{} -> {} [label=\"p {}{}\", style={}];", s, target, p, cow, style));
+                if targets.insert(entry) {
+                    lines.push(match entry {
+                        PTE_DEMAND_ZERO => "  zero [label=\"demand-zero\", shape=note, fillcolor=white];".to_string(),
+                        e if e < 0 => format!("  block{} [label=\"disk block {}\", fillcolor={}];", -e, -e, DOT_ON_DISK),
+                        e => format!("  frame{} [label=\"frame {}\", fillcolor={}];", e, e, DOT_RESIDENT),
+                    });
+                }
+            }
+        }
+        lines.extend(edges);
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+}
+
+const MAP_COLUMNS: usize = 64;
+
+// Every frame's owner as one character, for watching memory fill up and
+// fragment. Frames taken since an earlier map (`since`) are marked apart from
+// what they now hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameMap {
+    owners: Vec<FrameOwner>,
+    recent: Vec<bool>,
+}
+
+impl FrameMap {
+    pub fn capture(pm: &PhysicalMemory) -> Self {
+        let owners: Vec<FrameOwner> = (0..NUM_FRAMES as u32).map(|frame| pm.who_owns(frame)).collect();
+        FrameMap { recent: vec![false; owners.len()], owners }
+    }
+
+    // Marks the frames that were free in `earlier` and are in use now.
+    pub fn since(mut self, earlier: &FrameMap) -> Self {
+        for (frame, owner) in self.owners.iter().enumerate() {
+            self.recent[frame] = *owner != FrameOwner::Free && earlier.owners[frame] == FrameOwner::Free;
+        }
+        self
+    }
+
+    fn symbol(&self, frame: usize) -> char {
+        if self.recent[frame] {
+            return '+';
+        }
+        match self.owners[frame] {
+            FrameOwner::Free => '.',
+            FrameOwner::SegmentTable => 'S',
+            FrameOwner::PageTable(_) => 'P',
+            FrameOwner::Page(..) => '#',
+        }
+    }
+
+    fn free_runs(&self) -> usize {
+        (0..self.owners.len())
+            .filter(|&frame| self.owners[frame] == FrameOwner::Free && (frame == 0 || self.owners[frame - 1] != FrameOwner::Free))
+            .count()
+    }
+}
+
+impl fmt::Display for FrameMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = |wanted: fn(&FrameOwner) -> bool| self.owners.iter().filter(|owner| wanted(owner)).count();
+        let free = count(|owner| *owner == FrameOwner::Free);
+        writeln!(
+            f,
+            "Frames: {} ST, {} PT, {} page, {} free in {} run(s)",
+            count(|owner| *owner == FrameOwner::SegmentTable),
+            count(|owner| matches!(owner, FrameOwner::PageTable(_))),
+            count(|owner| matches!(owner, FrameOwner::Page(..))),
+            free,
+            self.free_runs()
+        )?;
+        for row in (0..self.owners.len()).step_by(MAP_COLUMNS) {
+            let line: String = (row..(row + MAP_COLUMNS).min(self.owners.len())).map(|frame| self.symbol(frame)).collect();
+            writeln!(f, "{:>5} {}", row, line)?;
+        }
+        write!(f, "S segment table  P page table  # resident page  . free  + newly allocated")
+    }
+}
+
+fn slice_location(slice: StSlice) -> i32 {
+    match slice {
+        StSlice::Resident(frame) => frame as i32,
+        StSlice::OnDisk(block) => -(block as i32),
+    }
+}
+
+impl fmt::Display for MemoryDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Segment table:")?;
+        for (i, &slice) in self.st_slices.iter().enumerate() {
+            match slice {
+                StSlice::Resident(frame) => writeln!(f, "  slice {}: frame {}", i, frame)?,
+                StSlice::OnDisk(block) => writeln!(f, "  slice {}: disk block {}", i, block)?,
+            }
+        }
+        for segment in &self.segments {
+            let pt = match segment.pt {
+                PtLocation::None => "no page table".to_string(),
+                PtLocation::Frame(frame) => format!("PT in frame {}", frame),
+                PtLocation::DiskBlock(block) => format!("PT in disk block {}", block),
+                PtLocation::HugePage(frame) => {
+                    format!("huge page in frames {}-{}", frame, frame + HUGE_PAGE_FRAMES as u32 - 1)
+                }
+            };
+            writeln!(f, "Segment {}: size {} ({}), {}", segment.s, segment.size, segment.protection, pt)?;
+            for &(p, entry, cow) in &segment.pages {
+                match entry {
+                    PTE_DEMAND_ZERO => writeln!(f, "  page {}: demand-zero", p)?,
+                    e if e < 0 => writeln!(f, "  page {}: disk block {}", p, -e)?,
+                    e if cow => writeln!(f, "  page {}: frame {} (copy-on-write)", p, e)?,
+                    e => writeln!(f, "  page {}: frame {}", p, e)?,
+                }
+            }
+        }
+        let runs: Vec<String> = self
+            .free_runs()
+            .iter()
+            .map(|&(first, last)| match first == last {
+                true => first.to_string(),
+                false => format!("{}-{}", first, last),
+            })
+            .collect();
+        write!(f, "Free frames ({}): {}", self.free_frames.len(), runs.join(", "))
+    }
+}
@@ -0,0 +1,190 @@
+use crate::constants::*;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::process::Pid;
+use crate::translation::{translate_with_demand_paging_faults, FaultFlags, TranslationResult, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableKind {
+    Forward,
+    Inverted,
+}
+
+impl TableKind {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "forward" => Ok(TableKind::Forward),
+            "inverted" => Ok(TableKind::Inverted),
+            _ => Err(format!("Unknown page table organization: {}", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TableKind::Forward => "forward",
+            TableKind::Inverted => "inverted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IptEntry {
+    pub pid: Pid,
+    pub s: u32,
+    pub p: u32,
+    next: Option<u32>,
+}
+
+// One entry per frame, tagged with the (pid, s, p) mapped there. A hash anchor
+// table points at the first frame of each chain; colliding tags are chained
+// through the entries' next links, newest first.
+#[derive(Debug, Clone)]
+pub struct InvertedPageTable {
+    anchors: Vec<Option<u32>>,
+    entries: Vec<Option<IptEntry>>,
+    pub lookups: u64,
+    pub misses: u64,
+    pub probes: u64,
+}
+
+impl InvertedPageTable {
+    pub fn new() -> Self {
+        InvertedPageTable {
+            anchors: vec![None; NUM_FRAMES],
+            entries: vec![None; NUM_FRAMES],
+            lookups: 0,
+            misses: 0,
+            probes: 0,
+        }
+    }
+
+    // Tags every page the frame table records as resident with the given pid.
+    pub fn from_memory(pm: &PhysicalMemory, pid: Pid) -> Self {
+        let mut ipt = Self::new();
+        for (frame, (s, p)) in pm.frame_table().pages() {
+            ipt.insert(frame, pid, s, p);
+        }
+        ipt
+    }
+
+    fn hash(&self, pid: Pid, s: u32, p: u32) -> usize {
+        let key = (pid as u64) << 40 ^ (s as u64) << 20 ^ p as u64;
+        (key.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as usize % self.anchors.len()
+    }
+
+    pub fn insert(&mut self, frame: u32, pid: Pid, s: u32, p: u32) {
+        self.remove(frame);
+        let bucket = self.hash(pid, s, p);
+        let next = self.anchors[bucket].replace(frame);
+        self.entries[frame as usize] = Some(IptEntry { pid, s, p, next });
+    }
+
+    pub fn remove(&mut self, frame: u32) -> Option<IptEntry> {
+        let entry = self.entries[frame as usize].take()?;
+        let bucket = self.hash(entry.pid, entry.s, entry.p);
+        if self.anchors[bucket] == Some(frame) {
+            self.anchors[bucket] = entry.next;
+            return Some(entry);
+        }
+        let mut current = self.anchors[bucket];
+        while let Some(f) = current {
+            let link = self.entries[f as usize].as_mut().unwrap();
+            if link.next == Some(frame) {
+                link.next = entry.next;
+                break;
+            }
+            current = link.next;
+        }
+        Some(entry)
+    }
+
+    pub fn lookup(&mut self, pid: Pid, s: u32, p: u32) -> Option<u32> {
+        self.lookups += 1;
+        let mut current = self.anchors[self.hash(pid, s, p)];
+        while let Some(frame) = current {
+            self.probes += 1;
+            let entry = self.entries[frame as usize].unwrap();
+            if (entry.pid, entry.s, entry.p) == (pid, s, p) {
+                return Some(frame);
+            }
+            current = entry.next;
+        }
+        self.misses += 1;
+        None
+    }
+
+    pub fn entry(&self, frame: u32) -> Option<IptEntry> {
+        self.entries[frame as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn probes_per_lookup(&self) -> f64 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        self.probes as f64 / self.lookups as f64
+    }
+
+    pub fn longest_chain(&self) -> usize {
+        self.anchors
+            .iter()
+            .map(|&anchor| {
+                let mut length = 0;
+                let mut current = anchor;
+                while let Some(frame) = current {
+                    length += 1;
+                    current = self.entries[frame as usize].and_then(|entry| entry.next);
+                }
+                length
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Default for InvertedPageTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Segment bounds still come from the ST; the page lookup goes through the IPT.
+// On a miss the forward tables act as the external page table: they are walked
+// (faulting the page in if needed) and the resulting frame is entered in the IPT.
+pub fn translate_inverted(
+    va: &VirtualAddress,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    ipt: &mut InvertedPageTable,
+    pid: Pid,
+) -> (TranslationResult, FaultFlags) {
+    let segment_size = pm.get_segment_size(va.s);
+    let pt_location = pm.get_segment_pt_location(va.s);
+
+    if segment_size == 0 && pt_location == 0 {
+        return (TranslationResult::InvalidSegment, FaultFlags::default());
+    }
+
+    if va.pw >= segment_size as u32 {
+        return (TranslationResult::SegmentBoundaryViolation, FaultFlags::default());
+    }
+
+    if let Some(frame) = ipt.lookup(pid, va.s, va.p) {
+        pm.frame_table_mut().touch(frame);
+        let pa = frame as i32 * pm.layout().page_size() as i32 + va.w as i32;
+        return (TranslationResult::Success(pa), FaultFlags::default());
+    }
+
+    let (result, faults) = translate_with_demand_paging_faults(va, pm, disk, ffl);
+    if let TranslationResult::Success(pa) = result {
+        ipt.insert((pa as usize / pm.layout().page_size()) as u32, pid, va.s, va.p);
+    }
+    (result, faults)
+}
@@ -1,128 +1,1395 @@
-use std::fs;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
 use std::path::Path;
 
 use crate::constants::*;
-use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::layout::AddressLayout;
+use crate::memory::{Disk, FrameOwner, FreeFrameList, PhysicalMemory, StSlice};
+use crate::sink::Radix;
+use crate::translation::{Access, AccessType, DataOp, FaultFlags, Protection, TranslationResult, VirtualAddress};
 
-#[derive(Debug, Default)]
+pub const TRACE_MAGIC: &[u8; 4] = b"VMTR";
+pub const TRACE_VERSION: u32 = 1;
+pub const INIT_MAGIC: &[u8; 4] = b"VMIN";
+pub const INIT_VERSION: u32 = 1;
+
+const TAG_READ: u8 = 0;
+const TAG_WRITE: u8 = 1;
+const TAG_EXECUTE: u8 = 2;
+const TAG_LOAD: u8 = 3;
+const TAG_STORE: u8 = 4;
+const TAG_RESIZE: u8 = 5;
+const TAG_FREE: u8 = 6;
+const TAG_REPEATED: u8 = 0x80;
+const ST_RECORD_SIZE: usize = 13;
+const PT_RECORD_SIZE: usize = 13;
+const DISK_RECORD_SIZE: usize = 12;
+
+type StLine = (Vec<(u32, i32, i32)>, Vec<(u32, Protection)>, Vec<u32>);
+type PtLine = (Vec<(u32, u32, i32)>, Vec<(u32, u32)>);
+
+// Everything from a '#' to the end of its line is a comment.
+fn strip_comment(line: &str) -> &str {
+    line.split_once('#').map_or(line, |(content, _)| content)
+}
+
+fn is_comment_line(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+fn is_directive_line(line: &str) -> bool {
+    line.trim_start().starts_with('@')
+}
+
+// Files named `*.bin` are written in the binary formats; reading goes by the
+// magic at the start of the file instead, whatever the name.
+pub fn is_binary_path<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().extension().is_some_and(|ext| ext.eq_ignore_ascii_case("bin"))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_i32(bytes: &[u8], at: usize) -> i32 {
+    i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn check_header(bytes: &[u8], magic: &[u8; 4], version: u32, header: usize, what: &str) -> Result<(), String> {
+    if bytes.len() < header || &bytes[..4] != magic {
+        return Err(format!("Not a binary {} file (bad magic)", what));
+    }
+    match read_u32(bytes, 4) {
+        v if v == version => Ok(()),
+        v => Err(format!("Unsupported binary {} version {}", what, v)),
+    }
+}
+
+// A huge page must start on a frame that is a multiple of its size and lie
+// wholly in memory, and its segment must fit in it.
+pub(crate) fn check_huge_page(s: u32, size: i32, frame: i32, page_size: usize) -> Result<(), String> {
+    if size > (HUGE_PAGE_FRAMES * page_size) as i32 {
+        return Err(format!("Huge-page segment {} size {} exceeds {}", s, size, HUGE_PAGE_FRAMES * page_size));
+    }
+    if frame <= 0 || !(frame as usize).is_multiple_of(HUGE_PAGE_FRAMES) || frame as usize + HUGE_PAGE_FRAMES > NUM_FRAMES {
+        return Err(format!("Huge page of segment {} must start on a resident frame aligned to {}, got {}", s, HUGE_PAGE_FRAMES, frame));
+    }
+    Ok(())
+}
+
+// What to do when an init file defines the same segment, or the same page of a
+// segment, more than once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    ErrorOnDuplicate,
+    #[default]
+    LastWins,
+    FirstWins,
+}
+
+impl DuplicatePolicy {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "error" => Ok(DuplicatePolicy::ErrorOnDuplicate),
+            "last" => Ok(DuplicatePolicy::LastWins),
+            "first" => Ok(DuplicatePolicy::FirstWins),
+            _ => Err(format!("Unknown duplicate policy: {} (expected error, first or last)", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::ErrorOnDuplicate => "error",
+            DuplicatePolicy::LastWins => "last",
+            DuplicatePolicy::FirstWins => "first",
+        }
+    }
+}
+
+// An init entry dropped in favour of another definition of the same segment or page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overridden {
+    Segment { s: u32, size: i32, location: i32 },
+    Page { s: u32, p: u32, location: i32 },
+}
+
+impl fmt::Display for Overridden {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Overridden::Segment { s, size, location } => {
+                write!(f, "segment {} (size {}, PT at {}) was overridden", s, size, location)
+            }
+            Overridden::Page { s, p, location } => write!(f, "page {} of segment {} (at {}) was overridden", p, s, location),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InitData {
     pub st_entries: Vec<(u32, i32, i32)>,
     pub pt_entries: Vec<(u32, u32, i32)>,
+    pub protections: Vec<(u32, Protection)>,
+    pub cow_pages: Vec<(u32, u32)>,
+    // Segments mapped by a huge page, whose ST entry holds its first frame.
+    pub huge_segments: Vec<u32>,
+    pub st_root: Vec<i32>,
+    // Initial disk contents as (block, offset, value) words.
+    pub disk_words: Vec<(usize, usize, i32)>,
 }
 
 impl InitData {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
-        let content = fs::read_to_string(path.as_ref())
-            .map_err(|e| format!("Failed to read init file: {}", e))?;
-        Self::parse(&content)
+        Self::from_file_with_layout(path, &AddressLayout::DEFAULT)
+    }
+
+    // Binary init files hold raw entries and need no layout to read.
+    pub fn from_file_with_layout<P: AsRef<Path>>(path: P, layout: &AddressLayout) -> Result<Self, String> {
+        let bytes = fs::read(path.as_ref()).map_err(|e| format!("Failed to read init file: {}", e))?;
+        if bytes.starts_with(INIT_MAGIC) {
+            return Self::from_bytes(&bytes);
+        }
+        let content = String::from_utf8(bytes).map_err(|_| "Init file is neither text nor binary init data".to_string())?;
+        Self::parse_with_layout(&content, layout)
     }
 
     pub fn parse(content: &str) -> Result<Self, String> {
-        let lines: Vec<&str> = content.lines().collect();
+        Self::parse_with_layout(content, &AddressLayout::DEFAULT)
+    }
+
+    pub fn parse_with_layout(content: &str, layout: &AddressLayout) -> Result<Self, String> {
+        let (directives, lines): (Vec<&str>, Vec<&str>) =
+            content.lines().filter(|line| !is_comment_line(line)).map(strip_comment).partition(|line| is_directive_line(line));
 
         if lines.is_empty() {
             return Err("Init file is empty".to_string());
         }
 
-        let st_entries = Self::parse_st_line(lines[0])?;
-        let pt_entries = if lines.len() > 1 {
-            Self::parse_pt_line(lines[1])?
+        let mut st_root = Vec::new();
+        for directive in directives {
+            let mut tokens = directive.split_whitespace();
+            match tokens.next() {
+                Some("@st-root") => st_root = Self::parse_st_root(tokens, layout)?,
+                Some(other) => return Err(format!("Unknown init directive: {}", other)),
+                None => {}
+            }
+        }
+
+        let (st_entries, protections, huge_segments) = Self::parse_st_line(lines[0], layout)?;
+        let (pt_entries, cow_pages) = if lines.len() > 1 {
+            Self::parse_pt_line(lines[1], layout)?
         } else {
-            Vec::new()
+            (Vec::new(), Vec::new())
         };
+        let mut disk_words = Vec::new();
+        for line in lines.iter().skip(2) {
+            disk_words.extend(Self::parse_disk_line(line)?);
+        }
 
-        Ok(InitData { st_entries, pt_entries })
+        Ok(InitData { st_entries, pt_entries, protections, cow_pages, huge_segments, st_root, disk_words })
     }
 
-    fn parse_st_line(line: &str) -> Result<Vec<(u32, i32, i32)>, String> {
+    // Lines after the PT line preload disk blocks: each `D b off v1 v2 ...`
+    // record writes its values to block b from word off on.
+    fn parse_disk_line(line: &str) -> Result<Vec<(usize, usize, i32)>, String> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.first().is_some_and(|&token| token != "D") {
+            return Err(format!("Disk data records start with D, got {}", tokens[0]));
+        }
+        let mut words = Vec::new();
+        for record in tokens.split(|&token| token == "D").skip(1) {
+            let [block, offset, values @ ..] = record else {
+                return Err(format!("Disk data record D {} needs a block, an offset and values", record.join(" ")));
+            };
+            let block: usize = block.parse().map_err(|_| format!("Invalid disk block: {}", block))?;
+            let offset: usize = offset.parse().map_err(|_| format!("Invalid block offset: {}", offset))?;
+            if block == 0 || block >= DISK_BLOCKS {
+                return Err(format!("Disk block {} out of range", block));
+            }
+            if values.is_empty() || offset + values.len() > BLOCK_SIZE {
+                return Err(format!("Disk data for block {} must hold 1 to {} words from offset {}", block, BLOCK_SIZE, offset));
+            }
+            for (i, value) in values.iter().enumerate() {
+                let value: i32 = value.parse().map_err(|_| format!("Invalid disk word: {}", value))?;
+                words.push((block, offset + i, value));
+            }
+        }
+        Ok(words)
+    }
+
+    // `@st-root` lists where each ST slice starts out: a frame, or a negative
+    // disk block for a slice that is only loaded on its first ST fault.
+    fn parse_st_root<'a, I: Iterator<Item = &'a str>>(tokens: I, layout: &AddressLayout) -> Result<Vec<i32>, String> {
+        let mut root = Vec::new();
+        for token in tokens {
+            let location: i32 = token.parse().map_err(|_| format!("Invalid ST slice location: {}", token))?;
+            if location >= NUM_FRAMES as i32 || -location >= DISK_BLOCKS as i32 {
+                return Err(format!("ST slice location {} out of range", location));
+            }
+            root.push(location);
+        }
+        if root.len() != layout.st_frames() {
+            return Err(format!("ST root has {} slices, expected {}", root.len(), layout.st_frames()));
+        }
+        Ok(root)
+    }
+
+    // A size may be suffixed with protection flags, `h` among them marking a
+    // huge-page segment whose frame is the first of its huge page.
+    fn parse_st_line(line: &str, layout: &AddressLayout) -> Result<StLine, String> {
         let tokens: Vec<&str> = line.split_whitespace().collect();
         if tokens.is_empty() {
-            return Ok(Vec::new());
+            return Ok((Vec::new(), Vec::new(), Vec::new()));
         }
-        if tokens.len() % 3 != 0 {
+        if !tokens.len().is_multiple_of(3) {
             return Err(format!("ST line has {} tokens, expected multiple of 3", tokens.len()));
         }
 
         let mut entries = Vec::new();
+        let mut protections = Vec::new();
+        let mut huge_segments = Vec::new();
         for chunk in tokens.chunks(3) {
             let s: u32 = chunk[0].parse().map_err(|_| format!("Invalid segment number: {}", chunk[0]))?;
-            let z: i32 = chunk[1].parse().map_err(|_| format!("Invalid segment size: {}", chunk[1]))?;
+            let (size, flags) = match chunk[1].split_once(':') {
+                Some((size, flags)) => (size, Some(flags)),
+                None => (chunk[1], None),
+            };
+            let z: i32 = size.parse().map_err(|_| format!("Invalid segment size: {}", chunk[1]))?;
             let f: i32 = chunk[2].parse().map_err(|_| format!("Invalid frame/block: {}", chunk[2]))?;
 
-            if s >= MAX_SEGMENTS as u32 {
-                return Err(format!("Segment number {} exceeds max {}", s, MAX_SEGMENTS - 1));
+            if s >= layout.max_segments() as u32 {
+                return Err(format!("Segment number {} exceeds max {}", s, layout.max_segments() - 1));
+            }
+            if !(0..=SEGMENT_SIZE_MASK).contains(&z) {
+                return Err(format!("Segment size {} out of range", z));
+            }
+            let huge = flags.is_some_and(|flags| flags.contains('h'));
+            if huge {
+                check_huge_page(s, z, f, layout.page_size())?;
+                huge_segments.push(s);
+            }
+            match flags.map(|flags| flags.replace('h', "")) {
+                Some(flags) if !flags.is_empty() => protections.push((s, Protection::parse(&flags)?)),
+                _ => {}
             }
             entries.push((s, z, f));
         }
-        Ok(entries)
+        Ok((entries, protections, huge_segments))
     }
 
-    fn parse_pt_line(line: &str) -> Result<Vec<(u32, u32, i32)>, String> {
+    // A resident frame may be suffixed with `:c` to map it copy-on-write; `z` in
+    // place of a frame or block marks a demand-zero page.
+    fn parse_pt_line(line: &str, layout: &AddressLayout) -> Result<PtLine, String> {
         let tokens: Vec<&str> = line.split_whitespace().collect();
         if tokens.is_empty() {
-            return Ok(Vec::new());
+            return Ok((Vec::new(), Vec::new()));
         }
-        if tokens.len() % 3 != 0 {
+        if !tokens.len().is_multiple_of(3) {
             return Err(format!("PT line has {} tokens, expected multiple of 3", tokens.len()));
         }
 
         let mut entries = Vec::new();
+        let mut cow_pages = Vec::new();
         for chunk in tokens.chunks(3) {
             let s: u32 = chunk[0].parse().map_err(|_| format!("Invalid segment number: {}", chunk[0]))?;
             let p: u32 = chunk[1].parse().map_err(|_| format!("Invalid page number: {}", chunk[1]))?;
-            let f: i32 = chunk[2].parse().map_err(|_| format!("Invalid frame/block: {}", chunk[2]))?;
+            let (frame, flags) = match chunk[2].split_once(':') {
+                Some((frame, flags)) => (frame, Some(flags)),
+                None => (chunk[2], None),
+            };
+            let f: i32 = match frame {
+                "z" => PTE_DEMAND_ZERO,
+                _ => frame.parse().map_err(|_| format!("Invalid frame/block: {}", chunk[2]))?,
+            };
+            match flags {
+                Some("c") if f > 0 => cow_pages.push((s, p)),
+                Some("c") => return Err(format!("Only resident pages can be copy-on-write: {}", chunk[2])),
+                Some(_) => return Err(format!("Invalid PT entry flags: {}", chunk[2])),
+                None => {}
+            }
 
-            if s >= MAX_SEGMENTS as u32 {
-                return Err(format!("Segment number {} exceeds max {}", s, MAX_SEGMENTS - 1));
+            if s >= layout.max_segments() as u32 {
+                return Err(format!("Segment number {} exceeds max {}", s, layout.max_segments() - 1));
             }
-            if p >= PT_SIZE as u32 {
-                return Err(format!("Page number {} exceeds max {}", p, PT_SIZE - 1));
+            if p >= layout.pt_size() as u32 {
+                return Err(format!("Page number {} exceeds max {}", p, layout.pt_size() - 1));
             }
             entries.push((s, p, f));
         }
-        Ok(entries)
+        Ok((entries, cow_pages))
+    }
+
+    pub fn is_huge(&self, segment: u32) -> bool {
+        self.huge_segments.contains(&segment)
+    }
+
+    pub fn protection(&self, segment: u32) -> Protection {
+        self.protections.iter().rev().find(|(s, _)| *s == segment).map(|&(_, p)| p).unwrap_or_default()
+    }
+
+    // The disk words as `D` records, one per run of consecutive words in a block.
+    fn disk_records(&self) -> Vec<String> {
+        let mut records: Vec<(usize, usize, Vec<String>)> = Vec::new();
+        for &(block, offset, value) in &self.disk_words {
+            match records.last_mut() {
+                Some((b, start, values)) if *b == block && *start + values.len() == offset => values.push(value.to_string()),
+                _ => records.push((block, offset, vec![value.to_string()])),
+            }
+        }
+        records.into_iter().map(|(block, offset, values)| format!("D {} {} {}", block, offset, values.join(" "))).collect()
+    }
+
+    // Conflicts apply() would silently paper over: a segment defined twice, a
+    // frame mapped by two pages (unless all of them are copy-on-write), a PT
+    // frame that is also a page frame, a PT or page in a segment table frame, and
+    // a disk block holding both a PT and a page. Page tables may share a frame or
+    // block, as compacted ones do.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        self.validate_with_layout(&AddressLayout::DEFAULT)
+    }
+
+    pub fn validate_with_layout(&self, layout: &AddressLayout) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let st_frames: Vec<u32> = match self.st_root.is_empty() {
+            true => (0..layout.st_frames() as u32).collect(),
+            false => self.st_root.iter().filter(|&&l| l >= 0).map(|&l| l as u32).collect(),
+        };
+        let st_blocks: Vec<usize> = self.st_root.iter().filter(|&&l| l < 0).map(|&l| (-l) as usize).collect();
+
+        let mut segments: Vec<u32> = Vec::new();
+        let mut pt_frames: Vec<(u32, u32)> = Vec::new();
+        let mut pt_blocks: Vec<(usize, u32)> = Vec::new();
+        let mut huge_frames: Vec<(u32, u32)> = Vec::new();
+        for &(s, _, location) in &self.st_entries {
+            if segments.contains(&s) {
+                errors.push(format!("segment {} is defined more than once", s));
+            }
+            segments.push(s);
+            if self.is_huge(s) {
+                for frame in location as u32..location as u32 + HUGE_PAGE_FRAMES as u32 {
+                    if st_frames.contains(&frame) {
+                        errors.push(format!("the huge page of segment {} covers segment table frame {}", s, frame));
+                    }
+                    if let Some(&(_, other)) = huge_frames.iter().find(|&&(f, _)| f == frame) {
+                        errors.push(format!("frame {} is in the huge pages of segments {} and {}", frame, other, s));
+                    }
+                    huge_frames.push((frame, s));
+                }
+                if self.pt_entries.iter().any(|&(owner, _, _)| owner == s) {
+                    errors.push(format!("segment {} is mapped by a huge page but has page table entries", s));
+                }
+            } else if location > 0 {
+                let frame = match PhysicalMemory::is_compact_pt(location) {
+                    true => ((location & !PT_COMPACT) as usize / layout.page_size()) as u32,
+                    false => location as u32,
+                };
+                if st_frames.contains(&frame) {
+                    errors.push(format!("the page table of segment {} is in segment table frame {}", s, frame));
+                }
+                pt_frames.push((frame, s));
+            } else if location < 0 {
+                let block = (-location) as usize;
+                if st_blocks.contains(&block) {
+                    errors.push(format!("the page table of segment {} is in segment table block {}", s, block));
+                }
+                pt_blocks.push((block, s));
+            }
+        }
+
+        for &(frame, s) in &pt_frames {
+            if let Some(&(_, huge)) = huge_frames.iter().find(|&&(f, _)| f == frame) {
+                errors.push(format!("frame {} holds the page table of segment {} and is in the huge page of segment {}", frame, s, huge));
+            }
+        }
+
+        let mut page_frames: Vec<(u32, (u32, u32))> = Vec::new();
+        for &(s, p, location) in &self.pt_entries {
+            if location > 0 {
+                let frame = location as u32;
+                let cow = self.cow_pages.contains(&(s, p));
+                if st_frames.contains(&frame) {
+                    errors.push(format!("page {} of segment {} is in segment table frame {}", p, s, frame));
+                }
+                if let Some(&(_, pt_segment)) = pt_frames.iter().find(|&&(f, _)| f == frame) {
+                    errors.push(format!(
+                        "frame {} holds both the page table of segment {} and page {} of segment {}",
+                        frame, pt_segment, p, s
+                    ));
+                }
+                if let Some(&(_, huge)) = huge_frames.iter().find(|&&(f, _)| f == frame) {
+                    errors.push(format!("frame {} holds page {} of segment {} and is in the huge page of segment {}", frame, p, s, huge));
+                }
+                let other = page_frames
+                    .iter()
+                    .find(|&&(f, page)| f == frame && page != (s, p) && !(cow && self.cow_pages.contains(&page)));
+                if let Some(&(_, (other_s, other_p))) = other {
+                    errors.push(format!(
+                        "frame {} is mapped by page {} of segment {} and page {} of segment {}",
+                        frame, other_p, other_s, p, s
+                    ));
+                }
+                page_frames.push((frame, (s, p)));
+            } else if location < 0 && location != PTE_DEMAND_ZERO {
+                let block = (-location) as usize;
+                if let Some(&(_, pt_segment)) = pt_blocks.iter().find(|&&(b, _)| b == block) {
+                    errors.push(format!(
+                        "disk block {} holds both the page table of segment {} and page {} of segment {}",
+                        block, pt_segment, p, s
+                    ));
+                }
+                if st_blocks.contains(&block) {
+                    errors.push(format!("page {} of segment {} is in segment table block {}", p, s, block));
+                }
+            }
+        }
+        let mut preloaded: Vec<usize> = self.disk_words.iter().map(|&(block, _, _)| block).collect();
+        preloaded.dedup();
+        for block in preloaded {
+            if let Some(&(_, pt_segment)) = pt_blocks.iter().find(|&&(b, _)| b == block) {
+                errors.push(format!("disk block {} holds both the page table of segment {} and preloaded data", block, pt_segment));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    // Keeps one definition of every segment and page as the policy says and
+    // reports the entries it dropped, in file order.
+    pub fn deduplicate(&self, policy: DuplicatePolicy) -> Result<(InitData, Vec<Overridden>), String> {
+        let keep = |indices: Vec<usize>| match policy {
+            DuplicatePolicy::FirstWins => indices[0],
+            _ => indices[indices.len() - 1],
+        };
+        let mut segments: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for (i, &(s, _, _)) in self.st_entries.iter().enumerate() {
+            segments.entry(s).or_default().push(i);
+        }
+        let mut pages: BTreeMap<(u32, u32), Vec<usize>> = BTreeMap::new();
+        for (i, &(s, p, _)) in self.pt_entries.iter().enumerate() {
+            pages.entry((s, p)).or_default().push(i);
+        }
+        if policy == DuplicatePolicy::ErrorOnDuplicate {
+            if let Some((s, _)) = segments.iter().find(|(_, indices)| indices.len() > 1) {
+                return Err(format!("Segment {} is defined more than once", s));
+            }
+            if let Some(((s, p), _)) = pages.iter().find(|(_, indices)| indices.len() > 1) {
+                return Err(format!("Page {} of segment {} is defined more than once", p, s));
+            }
+        }
+
+        let kept_segments: BTreeSet<usize> = segments.into_values().map(keep).collect();
+        let kept_pages: BTreeSet<usize> = pages.into_values().map(keep).collect();
+        let mut overridden = Vec::new();
+        let mut st_entries = Vec::new();
+        for (i, &(s, size, location)) in self.st_entries.iter().enumerate() {
+            match kept_segments.contains(&i) {
+                true => st_entries.push((s, size, location)),
+                false => overridden.push(Overridden::Segment { s, size, location }),
+            }
+        }
+        let mut pt_entries = Vec::new();
+        for (i, &(s, p, location)) in self.pt_entries.iter().enumerate() {
+            match kept_pages.contains(&i) {
+                true => pt_entries.push((s, p, location)),
+                false => overridden.push(Overridden::Page { s, p, location }),
+            }
+        }
+        let mut protections = self.protections.clone();
+        if policy == DuplicatePolicy::FirstWins {
+            protections.reverse();
+        }
+        let init_data = InitData {
+            st_entries,
+            pt_entries,
+            protections,
+            cow_pages: self.cow_pages.clone(),
+            huge_segments: self.huge_segments.clone(),
+            st_root: self.st_root.clone(),
+            disk_words: self.disk_words.clone(),
+        };
+        Ok((init_data, overridden))
+    }
+
+    pub fn apply_with_policy(
+        &self,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        policy: DuplicatePolicy,
+    ) -> Result<(FreeFrameList, Vec<Overridden>), String> {
+        let (init_data, overridden) = self.deduplicate(policy)?;
+        Ok((init_data.apply(pm, disk), overridden))
     }
 
     pub fn apply(&self, pm: &mut PhysicalMemory, disk: &mut Disk) -> FreeFrameList {
         let mut ffl = FreeFrameList::new();
+        self.apply_to(pm, disk, &mut ffl);
+        ffl
+    }
 
+    pub fn apply_to(&self, pm: &mut PhysicalMemory, disk: &mut Disk, ffl: &mut FreeFrameList) {
+        for &(block, offset, value) in &self.disk_words {
+            disk.write(block, offset, value);
+        }
+        for frame in 0..pm.layout().st_frames() as u32 {
+            ffl.mark_occupied(frame);
+        }
+        if !self.st_root.is_empty() {
+            self.apply_st_root(pm, ffl);
+        }
         for &(segment, size, pt_location) in &self.st_entries {
-            pm.set_segment_entry(segment, size, pt_location);
-            if pt_location > 0 {
-                ffl.mark_occupied(pt_location as u32);
+            pm.write_segment_entry(disk, segment, size, pt_location, self.protection(segment));
+            if self.is_huge(segment) {
+                pm.set_segment_huge(disk, segment, true);
+                let pages = (size.max(0) as usize).div_ceil(pm.layout().page_size()).min(HUGE_PAGE_FRAMES);
+                for p in 0..HUGE_PAGE_FRAMES as u32 {
+                    let frame = pt_location as u32 + p;
+                    ffl.mark_occupied(frame);
+                    if (p as usize) < pages && pm.who_owns(frame) == FrameOwner::Free {
+                        pm.frame_table_mut().assign(frame, segment, p);
+                    }
+                }
+            } else if pt_location > 0 {
+                let pt_frame = pm.pt_frame(pt_location);
+                ffl.mark_occupied(pt_frame);
+                if (pt_frame as usize) < NUM_FRAMES && pm.who_owns(pt_frame) == FrameOwner::Free {
+                    pm.frame_table_mut().set_owner(pt_frame, FrameOwner::PageTable(segment));
+                }
             }
         }
 
         for &(segment, page, frame_location) in &self.pt_entries {
-            let pt_location = pm.get_segment_pt_location(segment);
+            let (_, pt_location, _) = pm.read_segment_entry(disk, segment);
+            let entry = match self.cow_pages.contains(&(segment, page)) {
+                true => frame_location | PTE_COW,
+                false => frame_location,
+            };
             if pt_location >= 0 {
-                pm.set_page_entry(pt_location, page, frame_location);
+                pm.set_page_entry(pt_location, page, entry);
             } else {
-                let block = (-pt_location) as usize;
-                disk.write(block, page as usize, frame_location);
+                pm.write_page_entry(disk, pt_location, page, entry);
             }
             if frame_location > 0 {
-                ffl.mark_occupied(frame_location as u32);
+                let frame = frame_location as u32;
+                ffl.mark_occupied(frame);
+                if (frame as usize) < NUM_FRAMES && pm.who_owns(frame) == FrameOwner::Free {
+                    pm.frame_table_mut().assign(frame, segment, page);
+                }
             }
         }
+    }
 
-        ffl
+    // Moves the ST slices to the root's locations. Frame 0 is not freed when its
+    // slice moves, since a zero entry means "no frame" and it is never handed out.
+    fn apply_st_root(&self, pm: &mut PhysicalMemory, ffl: &mut FreeFrameList) {
+        for slice in pm.st_slices().to_vec() {
+            if let StSlice::Resident(frame) = slice {
+                pm.frame_table_mut().release(frame);
+                if frame > 0 {
+                    ffl.release(frame);
+                }
+            }
+        }
+        for (slice, &location) in self.st_root.iter().enumerate() {
+            let location = if location < 0 {
+                StSlice::OnDisk((-location) as usize)
+            } else {
+                ffl.mark_occupied(location as u32);
+                pm.frame_table_mut().set_owner(location as u32, FrameOwner::SegmentTable);
+                StSlice::Resident(location as u32)
+            };
+            pm.set_st_slice(slice, location);
+        }
+    }
+
+    pub fn from_memory(pm: &PhysicalMemory, disk: &Disk) -> Self {
+        let mut init_data = InitData::default();
+        if pm.st_is_paged() {
+            init_data.st_root = pm
+                .st_slices()
+                .iter()
+                .map(|&slice| match slice {
+                    StSlice::Resident(frame) => frame as i32,
+                    StSlice::OnDisk(block) => -(block as i32),
+                })
+                .collect();
+        }
+
+        let layout = pm.layout();
+        for s in 0..layout.max_segments() as u32 {
+            let (size, pt_location, protection) = pm.read_segment_entry(disk, s);
+            if size == 0 && pt_location == 0 {
+                continue;
+            }
+            init_data.st_entries.push((s, size, pt_location));
+            if protection != Protection::ALL {
+                init_data.protections.push((s, protection));
+            }
+            if pm.is_huge_segment(s) {
+                init_data.huge_segments.push(s);
+                continue;
+            }
+
+            let pages = (size.max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size()) as u32;
+            for p in 0..pages {
+                let entry = pm.read_page_entry(disk, pt_location, p);
+                if entry != 0 {
+                    init_data.pt_entries.push((s, p, entry));
+                }
+                if pt_location > 0 && pm.is_cow(pt_location, p) {
+                    init_data.cow_pages.push((s, p));
+                }
+                if entry < 0 && entry != PTE_DEMAND_ZERO {
+                    let block = (-entry) as usize;
+                    for offset in 0..BLOCK_SIZE {
+                        let value = disk.read(block, offset);
+                        if value != 0 {
+                            init_data.disk_words.push((block, offset, value));
+                        }
+                    }
+                }
+            }
+        }
+
+        init_data
+    }
+
+    pub fn to_init_string(&self) -> String {
+        let st: Vec<String> = self
+            .st_entries
+            .iter()
+            .map(|&(s, z, f)| match (self.protection(s), self.is_huge(s)) {
+                (Protection::ALL, false) => format!("{} {} {}", s, z, f),
+                (Protection::ALL, true) => format!("{} {}:h {}", s, z, f),
+                (protection, false) => format!("{} {}:{} {}", s, z, protection, f),
+                (protection, true) => format!("{} {}:{}h {}", s, z, protection, f),
+            })
+            .collect();
+        let pt: Vec<String> = self
+            .pt_entries
+            .iter()
+            .map(|&(s, p, f)| match (self.cow_pages.contains(&(s, p)), f) {
+                (true, _) => format!("{} {} {}:c", s, p, f),
+                (false, PTE_DEMAND_ZERO) => format!("{} {} z", s, p),
+                (false, _) => format!("{} {} {}", s, p, f),
+            })
+            .collect();
+        let mut init = format!("{}\n{}\n", st.join(" "), pt.join(" "));
+        if !self.disk_words.is_empty() {
+            init += &format!("{}\n", self.disk_records().join(" "));
+        }
+        if self.st_root.is_empty() {
+            return init;
+        }
+        let root: Vec<String> = self.st_root.iter().map(|location| location.to_string()).collect();
+        format!("@st-root {}\n{}", root.join(" "), init)
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let content = match is_binary_path(&path) {
+            true => self.to_bytes(),
+            false => self.to_init_string().into_bytes(),
+        };
+        fs::write(path.as_ref(), content).map_err(|e| format!("Failed to write init file: {}", e))
+    }
+
+    // Magic, version and four record counts (ST entries, PT entries, ST root
+    // locations, disk words), then the records in that order. ST records carry
+    // the denied protection bits (and 8 for a huge-page segment) and PT records a
+    // copy-on-write byte.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(
+            24 + self.st_entries.len() * ST_RECORD_SIZE
+                + self.pt_entries.len() * PT_RECORD_SIZE
+                + self.st_root.len() * 4
+                + self.disk_words.len() * DISK_RECORD_SIZE,
+        );
+        out.extend_from_slice(INIT_MAGIC);
+        out.extend_from_slice(&INIT_VERSION.to_le_bytes());
+        for count in [self.st_entries.len(), self.pt_entries.len(), self.st_root.len(), self.disk_words.len()] {
+            out.extend_from_slice(&(count as u32).to_le_bytes());
+        }
+        for &(s, z, f) in &self.st_entries {
+            out.extend_from_slice(&s.to_le_bytes());
+            out.extend_from_slice(&z.to_le_bytes());
+            out.extend_from_slice(&f.to_le_bytes());
+            out.push(self.protection(s).denied_bits() as u8 | (self.is_huge(s) as u8) << 3);
+        }
+        for &(s, p, f) in &self.pt_entries {
+            out.extend_from_slice(&s.to_le_bytes());
+            out.extend_from_slice(&p.to_le_bytes());
+            out.extend_from_slice(&f.to_le_bytes());
+            out.push(self.cow_pages.contains(&(s, p)) as u8);
+        }
+        for &location in &self.st_root {
+            out.extend_from_slice(&location.to_le_bytes());
+        }
+        for &(block, offset, value) in &self.disk_words {
+            out.extend_from_slice(&(block as u32).to_le_bytes());
+            out.extend_from_slice(&(offset as u32).to_le_bytes());
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        check_header(bytes, INIT_MAGIC, INIT_VERSION, 24, "init")?;
+        let counts: Vec<usize> = (0..4).map(|i| read_u32(bytes, 8 + i * 4) as usize).collect();
+        let expected = 24
+            + counts[0] * ST_RECORD_SIZE
+            + counts[1] * PT_RECORD_SIZE
+            + counts[2] * 4
+            + counts[3] * DISK_RECORD_SIZE;
+        if bytes.len() != expected {
+            return Err(format!("Binary init file should be {} bytes but is {}", expected, bytes.len()));
+        }
+        let mut init_data = InitData::default();
+        let mut at = 24;
+        for _ in 0..counts[0] {
+            let (s, z, f) = (read_u32(bytes, at), read_i32(bytes, at + 4), read_i32(bytes, at + 8));
+            init_data.st_entries.push((s, z, f));
+            let flags = bytes[at + 12];
+            let protection = Protection::from_denied_bits(flags as i32 & 7);
+            if protection != Protection::ALL {
+                init_data.protections.push((s, protection));
+            }
+            if flags & 8 != 0 {
+                check_huge_page(s, z, f, PAGE_SIZE)?;
+                init_data.huge_segments.push(s);
+            }
+            at += ST_RECORD_SIZE;
+        }
+        for _ in 0..counts[1] {
+            let (s, p) = (read_u32(bytes, at), read_u32(bytes, at + 4));
+            init_data.pt_entries.push((s, p, read_i32(bytes, at + 8)));
+            match bytes[at + 12] {
+                0 => {}
+                1 => init_data.cow_pages.push((s, p)),
+                flag => return Err(format!("Invalid copy-on-write flag {} for page {} of segment {}", flag, p, s)),
+            }
+            at += PT_RECORD_SIZE;
+        }
+        for _ in 0..counts[2] {
+            init_data.st_root.push(read_i32(bytes, at));
+            at += 4;
+        }
+        for _ in 0..counts[3] {
+            let (block, offset) = (read_u32(bytes, at) as usize, read_u32(bytes, at + 4) as usize);
+            if block >= DISK_BLOCKS || offset >= BLOCK_SIZE {
+                return Err(format!("Disk word {} of block {} is outside the disk", offset, block));
+            }
+            init_data.disk_words.push((block, offset, read_i32(bytes, at + 8)));
+            at += DISK_RECORD_SIZE;
+        }
+        Ok(init_data)
     }
 }
 
+// Accepts decimal, 0x-prefixed hexadecimal and 0b-prefixed binary addresses.
+// Init data for three-level layouts. The ST line is as in two-level files but
+// points each segment at its page directory; the second line holds `s d pt`
+// directory entries and the third `s d p f` page table entries. Negative
+// locations are disk blocks, as everywhere else.
+#[derive(Debug, Default)]
+pub struct ThreeLevelInitData {
+    pub st_entries: Vec<(u32, i32, i32)>,
+    pub pd_entries: Vec<(u32, u32, i32)>,
+    pub pt_entries: Vec<(u32, u32, u32, i32)>,
+    pub protections: Vec<(u32, Protection)>,
+}
+
+impl ThreeLevelInitData {
+    pub fn from_file<P: AsRef<Path>>(path: P, layout: &AddressLayout) -> Result<Self, String> {
+        let content = fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("Failed to read init file: {}", e))?;
+        Self::parse(&content, layout)
+    }
+
+    pub fn parse(content: &str, layout: &AddressLayout) -> Result<Self, String> {
+        let lines: Vec<&str> = content.lines().filter(|line| !is_comment_line(line)).map(strip_comment).collect();
+        if lines.is_empty() {
+            return Err("Init file is empty".to_string());
+        }
+
+        let (st_entries, protections, huge_segments) = InitData::parse_st_line(lines[0], layout)?;
+        if let Some(s) = huge_segments.first() {
+            return Err(format!("Segment {} cannot be mapped by a huge page in a three-level layout", s));
+        }
+        let mut pd_entries = Vec::new();
+        for chunk in Self::entry_tokens(lines.get(1), 3, "PD")?.chunks(3) {
+            let (s, d) = Self::parse_indices(chunk[0], chunk[1], layout)?;
+            let location: i32 = chunk[2].parse().map_err(|_| format!("Invalid frame/block: {}", chunk[2]))?;
+            pd_entries.push((s, d, location));
+        }
+        let mut pt_entries = Vec::new();
+        for chunk in Self::entry_tokens(lines.get(2), 4, "PT")?.chunks(4) {
+            let (s, d) = Self::parse_indices(chunk[0], chunk[1], layout)?;
+            let p: u32 = chunk[2].parse().map_err(|_| format!("Invalid page number: {}", chunk[2]))?;
+            if p >= layout.pt_size() as u32 {
+                return Err(format!("Page number {} exceeds max {}", p, layout.pt_size() - 1));
+            }
+            let f: i32 = chunk[3].parse().map_err(|_| format!("Invalid frame/block: {}", chunk[3]))?;
+            pt_entries.push((s, d, p, f));
+        }
+
+        Ok(ThreeLevelInitData { st_entries, pd_entries, pt_entries, protections })
+    }
+
+    fn entry_tokens<'a>(line: Option<&&'a str>, width: usize, what: &str) -> Result<Vec<&'a str>, String> {
+        let tokens: Vec<&str> = line.map(|line| line.split_whitespace().collect()).unwrap_or_default();
+        if !tokens.len().is_multiple_of(width) {
+            return Err(format!("{} line has {} tokens, expected multiple of {}", what, tokens.len(), width));
+        }
+        Ok(tokens)
+    }
+
+    fn parse_indices(s: &str, d: &str, layout: &AddressLayout) -> Result<(u32, u32), String> {
+        let s: u32 = s.parse().map_err(|_| format!("Invalid segment number: {}", s))?;
+        let d: u32 = d.parse().map_err(|_| format!("Invalid directory index: {}", d))?;
+        if s >= layout.max_segments() as u32 {
+            return Err(format!("Segment number {} exceeds max {}", s, layout.max_segments() - 1));
+        }
+        if d >= layout.directory_size() as u32 {
+            return Err(format!("Directory index {} exceeds max {}", d, layout.directory_size() - 1));
+        }
+        Ok((s, d))
+    }
+
+    pub fn protection(&self, segment: u32) -> Protection {
+        self.protections.iter().rev().find(|(s, _)| *s == segment).map(|&(_, p)| p).unwrap_or_default()
+    }
+
+    // Entries below a directory or page table that is absent are ignored.
+    pub fn apply_to(&self, pm: &mut PhysicalMemory, disk: &mut Disk, ffl: &mut FreeFrameList) {
+        for frame in 0..pm.layout().st_frames() as u32 {
+            ffl.mark_occupied(frame);
+        }
+        for &(segment, size, pd_location) in &self.st_entries {
+            pm.set_segment_entry(segment, size, pd_location, self.protection(segment));
+            Self::claim_table(pm, ffl, pd_location, segment);
+        }
+
+        for &(segment, d, pt_location) in &self.pd_entries {
+            let pd_location = pm.get_segment_pt_location(segment);
+            if pd_location == 0 {
+                continue;
+            }
+            pm.write_page_entry(disk, pd_location, d, pt_location);
+            Self::claim_table(pm, ffl, pt_location, segment);
+        }
+
+        let p_bits = pm.layout().p_bits;
+        for &(segment, d, page, frame_location) in &self.pt_entries {
+            let pd_location = pm.get_segment_pt_location(segment);
+            let pt_location = pm.read_page_entry(disk, pd_location, d);
+            if pt_location == 0 {
+                continue;
+            }
+            pm.write_page_entry(disk, pt_location, page, frame_location);
+            if frame_location > 0 && (frame_location as usize) < NUM_FRAMES {
+                let frame = frame_location as u32;
+                ffl.mark_occupied(frame);
+                if pm.who_owns(frame) == FrameOwner::Free {
+                    pm.frame_table_mut().assign(frame, segment, d << p_bits | page);
+                }
+            }
+        }
+    }
+
+    fn claim_table(pm: &mut PhysicalMemory, ffl: &mut FreeFrameList, location: i32, segment: u32) {
+        if location > 0 && (location as usize) < NUM_FRAMES {
+            ffl.mark_occupied(location as u32);
+            if pm.who_owns(location as u32) == FrameOwner::Free {
+                pm.frame_table_mut().set_owner(location as u32, FrameOwner::PageTable(segment));
+            }
+        }
+    }
+}
+
+pub fn parse_virtual_address(token: &str) -> Result<u32, String> {
+    let parsed = if let Some(hex) = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(binary) = token.strip_prefix("0b").or_else(|| token.strip_prefix("0B")) {
+        u32::from_str_radix(binary, 2)
+    } else {
+        token.parse()
+    };
+    parsed.map_err(|_| format!("Invalid virtual address: {}", token))
+}
+
+fn input_tokens(content: &str) -> impl Iterator<Item = &str> {
+    content.lines().flat_map(|line| strip_comment(line).split_whitespace())
+}
+
+// A token `x<n>` right after an address repeats that access n times in total.
+fn parse_repeat(token: &str) -> Option<Result<u64, String>> {
+    let count = token.strip_prefix('x').or_else(|| token.strip_prefix('X'))?;
+    if count.is_empty() || !count.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(match count.parse() {
+        Ok(0) | Err(_) => Err(format!("Invalid repeat count: {}", token)),
+        Ok(count) => Ok(count),
+    })
+}
+
+// The file argument that stands for stdin or stdout.
+pub const STDIO_PATH: &str = "-";
+
+fn read_error(e: std::io::Error) -> String {
+    format!("Failed to read input: {}", e)
+}
+
+fn output_error(e: std::io::Error) -> String {
+    format!("Failed to write output: {}", e)
+}
+
 pub fn read_virtual_addresses<P: AsRef<Path>>(path: P) -> Result<Vec<u32>, String> {
     let content = fs::read_to_string(path.as_ref())
         .map_err(|e| format!("Failed to read input file: {}", e))?;
-    let mut addresses = Vec::new();
-    for token in content.split_whitespace() {
-        let va: u32 = token.parse().map_err(|_| format!("Invalid virtual address: {}", token))?;
-        addresses.push(va);
+    parse_virtual_addresses(&content)
+}
+
+// read_virtual_addresses for input that is not a file, such as stdin.
+pub fn read_virtual_addresses_from<R: Read>(mut reader: R) -> Result<Vec<u32>, String> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content).map_err(read_error)?;
+    parse_virtual_addresses(&content)
+}
+
+fn parse_virtual_addresses(content: &str) -> Result<Vec<u32>, String> {
+    let mut runs: Vec<AccessRun> = Vec::new();
+    for token in input_tokens(content) {
+        match (parse_repeat(token), runs.last_mut()) {
+            (Some(count), Some(run)) if run.count == 1 => run.count = count?,
+            (Some(_), _) => return Err(format!("Repeat count {} does not follow an address", token)),
+            (None, _) => runs.push(AccessRun { access: Access::read(parse_virtual_address(token)?), count: 1 }),
+        }
     }
-    Ok(addresses)
+    Ok(expand_runs(&runs).map(|access| access.va).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessRun {
+    pub access: Access,
+    pub count: u64,
+}
+
+pub fn expand_runs(runs: &[AccessRun]) -> impl Iterator<Item = Access> + '_ {
+    runs.iter().flat_map(|run| std::iter::repeat_n(run.access, run.count as usize))
+}
+
+pub fn total_accesses(runs: &[AccessRun]) -> u64 {
+    runs.iter().map(|run| run.count).sum()
+}
+
+pub fn read_accesses<P: AsRef<Path>>(path: P) -> Result<Vec<Access>, String> {
+    let runs = read_access_runs(path)?;
+    let mut accesses = Vec::with_capacity(total_accesses(&runs) as usize);
+    accesses.extend(expand_runs(&runs));
+    Ok(accesses)
+}
+
+pub fn read_access_runs<P: AsRef<Path>>(path: P) -> Result<Vec<AccessRun>, String> {
+    read_trace(path)?.into_runs()
+}
+
+// Text or binary, going by the magic at the start of the file.
+pub fn read_trace<P: AsRef<Path>>(path: P) -> Result<Trace, String> {
+    let bytes = fs::read(path.as_ref()).map_err(|e| format!("Failed to read input file: {}", e))?;
+    trace_from_bytes(bytes)
+}
+
+pub fn read_trace_from<R: Read>(mut reader: R) -> Result<Trace, String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(read_error)?;
+    trace_from_bytes(bytes)
+}
+
+fn trace_from_bytes(bytes: Vec<u8>) -> Result<Trace, String> {
+    if bytes.starts_with(TRACE_MAGIC) {
+        return Trace::from_bytes(&bytes);
+    }
+    let content = String::from_utf8(bytes).map_err(|_| "Input file is neither text nor a binary trace".to_string())?;
+    parse_trace(&content)
+}
+
+pub fn parse_accesses(content: &str) -> Result<Vec<Access>, String> {
+    let runs = parse_access_runs(content)?;
+    let mut accesses = Vec::with_capacity(total_accesses(&runs) as usize);
+    accesses.extend(expand_runs(&runs));
+    Ok(accesses)
+}
+
+pub fn parse_access_runs(content: &str) -> Result<Vec<AccessRun>, String> {
+    parse_trace(content)?.into_runs()
+}
+
+// Changes to the address space made between accesses rather than by them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceDirective {
+    Resize { s: u32, size: u32 },
+    FreeSegment { s: u32 },
+}
+
+impl TraceDirective {
+    fn arity(name: &str) -> Option<usize> {
+        match name {
+            "G" | "g" => Some(2),
+            "F" | "f" => Some(1),
+            _ => None,
+        }
+    }
+
+    fn from_args(name: &str, args: &[u32]) -> Self {
+        match (name, args) {
+            ("G" | "g", &[s, size]) => TraceDirective::Resize { s, size },
+            (_, &[s, ..]) => TraceDirective::FreeSegment { s },
+            _ => unreachable!("directive arguments are checked against arity"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub runs: Vec<AccessRun>,
+    // Each directive with the number of accesses that precede it.
+    pub directives: Vec<(u64, TraceDirective)>,
+}
+
+impl Trace {
+    pub fn accesses(&self) -> Vec<Access> {
+        let mut accesses = Vec::with_capacity(total_accesses(&self.runs) as usize);
+        accesses.extend(expand_runs(&self.runs));
+        accesses
+    }
+
+    pub fn into_runs(self) -> Result<Vec<AccessRun>, String> {
+        if !self.directives.is_empty() {
+            return Err("Trace directives are not supported for this input".to_string());
+        }
+        Ok(self.runs)
+    }
+
+    // Plain reads, with consecutive repeats of an address folded into one run.
+    pub fn from_vas(vas: &[u32]) -> Self {
+        let mut runs: Vec<AccessRun> = Vec::new();
+        for &va in vas {
+            match runs.last_mut() {
+                Some(run) if run.access.va == va => run.count += 1,
+                _ => runs.push(AccessRun { access: Access::read(va), count: 1 }),
+            }
+        }
+        Trace { runs, directives: Vec::new() }
+    }
+
+    // Magic, version and record count, then one record per access run or
+    // directive in trace order. A record is a tag byte and a u32: the address
+    // of an access or the segment of a directive. Stores add their i32 value,
+    // resizes the new size, and runs repeated more than once set the high tag
+    // bit and add a u64 count, so a plain read costs five bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(12 + (self.runs.len() + self.directives.len()) * 5);
+        out.extend_from_slice(TRACE_MAGIC);
+        out.extend_from_slice(&TRACE_VERSION.to_le_bytes());
+        out.extend_from_slice(&((self.runs.len() + self.directives.len()) as u32).to_le_bytes());
+        let mut directives = self.directives.iter().peekable();
+        let mut seen = 0;
+        for run in self.runs.iter().map(Some).chain(std::iter::once(None)) {
+            while let Some((_, directive)) = directives.next_if(|&&(at, _)| at <= seen || run.is_none()) {
+                match *directive {
+                    TraceDirective::Resize { s, size } => {
+                        out.push(TAG_RESIZE);
+                        out.extend_from_slice(&s.to_le_bytes());
+                        out.extend_from_slice(&size.to_le_bytes());
+                    }
+                    TraceDirective::FreeSegment { s } => {
+                        out.push(TAG_FREE);
+                        out.extend_from_slice(&s.to_le_bytes());
+                    }
+                }
+            }
+            let Some(run) = run else { break };
+            let tag = match (run.access.kind, run.access.data) {
+                (_, DataOp::Store(_)) => TAG_STORE,
+                (_, DataOp::Load) => TAG_LOAD,
+                (AccessType::Read, DataOp::None) => TAG_READ,
+                (AccessType::Write, DataOp::None) => TAG_WRITE,
+                (AccessType::Execute, DataOp::None) => TAG_EXECUTE,
+            };
+            out.push(if run.count == 1 { tag } else { tag | TAG_REPEATED });
+            out.extend_from_slice(&run.access.va.to_le_bytes());
+            if let DataOp::Store(value) = run.access.data {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            if run.count != 1 {
+                out.extend_from_slice(&run.count.to_le_bytes());
+            }
+            seen += run.count;
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        check_header(bytes, TRACE_MAGIC, TRACE_VERSION, 12, "trace")?;
+        let count = read_u32(bytes, 8);
+        let mut trace = Trace::default();
+        let mut at = 12;
+        let truncated = || "Binary trace ends in the middle of a record".to_string();
+        for _ in 0..count {
+            let tag = *bytes.get(at).ok_or_else(truncated)?;
+            let width = match tag & !TAG_REPEATED {
+                TAG_STORE | TAG_RESIZE => 9,
+                _ => 5,
+            } + if tag & TAG_REPEATED != 0 { 8 } else { 0 };
+            if bytes.len() < at + width {
+                return Err(truncated());
+            }
+            let word = read_u32(bytes, at + 1);
+            let access = match tag & !TAG_REPEATED {
+                TAG_READ => Access::read(word),
+                TAG_WRITE => Access { kind: AccessType::Write, va: word, data: DataOp::None },
+                TAG_EXECUTE => Access { kind: AccessType::Execute, va: word, data: DataOp::None },
+                TAG_LOAD => Access::load(word),
+                TAG_STORE => Access::store(word, read_i32(bytes, at + 5)),
+                TAG_RESIZE | TAG_FREE if tag & TAG_REPEATED != 0 => {
+                    return Err(format!("Directive tag {} in binary trace cannot repeat", tag & !TAG_REPEATED))
+                }
+                TAG_RESIZE => {
+                    let size = read_u32(bytes, at + 5);
+                    trace.directives.push((total_accesses(&trace.runs), TraceDirective::Resize { s: word, size }));
+                    at += width;
+                    continue;
+                }
+                TAG_FREE => {
+                    trace.directives.push((total_accesses(&trace.runs), TraceDirective::FreeSegment { s: word }));
+                    at += width;
+                    continue;
+                }
+                other => return Err(format!("Invalid record tag {} in binary trace", other)),
+            };
+            let count = match tag & TAG_REPEATED {
+                0 => 1,
+                _ => read_u64(bytes, at + width - 8),
+            };
+            trace.runs.push(AccessRun { access, count });
+            at += width;
+        }
+        if at != bytes.len() {
+            return Err(format!("Binary trace has {} bytes after its last record", bytes.len() - at));
+        }
+        Ok(trace)
+    }
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        fs::write(path.as_ref(), self.to_bytes()).map_err(|e| format!("Failed to write input file: {}", e))
+    }
+}
+
+// Repeated accesses stay run-length encoded, so `x1000000` costs one entry.
+// `L <va>` loads the word at the address and `S <va> <value>` stores a value.
+// `G <s> <size>` grows or shrinks segment s to size words and `F <s>` frees
+// segment s with everything it maps.
+pub fn parse_trace(content: &str) -> Result<Trace, String> {
+    let mut runs: Vec<AccessRun> = Vec::new();
+    let mut directives = Vec::new();
+    let mut pending: Option<(AccessType, DataOp)> = None;
+    let mut store_at: Option<u32> = None;
+    let mut directive: Option<(&str, Vec<u32>)> = None;
+    let mut after_directive = false;
+    for token in input_tokens(content) {
+        if let Some(va) = store_at.take() {
+            let value = token.parse().map_err(|_| format!("Invalid store value: {}", token))?;
+            runs.push(AccessRun { access: Access::store(va, value), count: 1 });
+            after_directive = false;
+            continue;
+        }
+        if let Some((name, args)) = directive.as_mut() {
+            args.push(token.parse().map_err(|_| format!("Invalid {} argument: {}", name, token))?);
+            if Some(args.len()) == TraceDirective::arity(name) {
+                directives.push((total_accesses(&runs), TraceDirective::from_args(name, args)));
+                directive = None;
+                after_directive = true;
+            }
+            continue;
+        }
+        if let Some(count) = parse_repeat(token) {
+            match (pending, runs.last_mut()) {
+                (None, Some(run)) if run.count == 1 && !after_directive => run.count = count?,
+                _ => return Err(format!("Repeat count {} does not follow an address", token)),
+            }
+            continue;
+        }
+        if TraceDirective::arity(token).is_some() {
+            if pending.is_some() {
+                return Err(format!("Access type {} is not followed by an address", token));
+            }
+            directive = Some((token, Vec::new()));
+            continue;
+        }
+        let kind = match token {
+            "R" | "r" => Some((AccessType::Read, DataOp::None)),
+            "W" | "w" => Some((AccessType::Write, DataOp::None)),
+            "X" | "x" => Some((AccessType::Execute, DataOp::None)),
+            "L" | "l" => Some((AccessType::Read, DataOp::Load)),
+            "S" | "s" => Some((AccessType::Write, DataOp::Store(0))),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            if pending.is_some() {
+                return Err(format!("Access type {} is not followed by an address", token));
+            }
+            pending = Some(kind);
+            continue;
+        }
+        let va = parse_virtual_address(token)?;
+        match pending.take().unwrap_or((AccessType::Read, DataOp::None)) {
+            (_, DataOp::Store(_)) => store_at = Some(va),
+            (kind, data) => runs.push(AccessRun { access: Access { kind, va, data }, count: 1 }),
+        }
+        after_directive = false;
+    }
+    if pending.is_some() {
+        return Err("Input ends with an access type but no address".to_string());
+    }
+    if store_at.is_some() {
+        return Err("Input ends with a store but no value".to_string());
+    }
+    if let Some((name, _)) = directive {
+        return Err(format!("Input ends with an incomplete {} directive", name));
+    }
+    Ok(Trace { runs, directives })
 }
 
 pub fn write_results<P: AsRef<Path>>(path: P, results: &[i32]) -> Result<(), String> {
+    let file = File::create(path.as_ref()).map_err(|e| format!("Failed to write output file: {}", e))?;
+    write_results_to(BufWriter::new(file), results)
+}
+
+// write_results for output that is not a file, such as stdout.
+pub fn write_results_to<W: Write>(mut writer: W, results: &[i32]) -> Result<(), String> {
     let output: Vec<String> = results.iter().map(|r| r.to_string()).collect();
+    writer.write_all(output.join(" ").as_bytes()).map_err(output_error)?;
+    writer.flush().map_err(output_error)
+}
+
+// The trace as the (s, p) page reference string, optionally collapsing runs of
+// consecutive references to the same page into one.
+pub fn reference_string(vas: &[u32], layout: &AddressLayout, collapse_repeats: bool) -> Vec<(u32, u32)> {
+    let mut pages: Vec<(u32, u32)> = Vec::with_capacity(vas.len());
+    for &va in vas {
+        let va = VirtualAddress::decompose(layout, va);
+        if !(collapse_repeats && pages.last() == Some(&(va.s, va.p))) {
+            pages.push((va.s, va.p));
+        }
+    }
+    pages
+}
+
+// One `s p` pair per line.
+pub fn export_reference_string<P: AsRef<Path>>(
+    path: P,
+    vas: &[u32],
+    layout: &AddressLayout,
+    collapse_repeats: bool,
+) -> Result<usize, String> {
+    let pages = reference_string(vas, layout, collapse_repeats);
+    let content: String = pages.iter().map(|(s, p)| format!("{} {}\n", s, p)).collect();
+    fs::write(path.as_ref(), content).map_err(|e| format!("Failed to write reference string: {}", e))?;
+    Ok(pages.len())
+}
+
+pub fn write_trace<P: AsRef<Path>>(path: P, vas: &[u32]) -> Result<(), String> {
+    if is_binary_path(&path) {
+        return Trace::from_vas(vas).write_file(path);
+    }
+    let content: Vec<String> = vas.iter().map(|va| va.to_string()).collect();
+    fs::write(path.as_ref(), content.join(" ")).map_err(|e| format!("Failed to write input file: {}", e))
+}
+
+// A text trace, as write_trace writes one.
+pub fn write_trace_to<W: Write>(mut writer: W, vas: &[u32]) -> Result<(), String> {
+    let content: Vec<String> = vas.iter().map(|va| va.to_string()).collect();
+    writer.write_all(content.join(" ").as_bytes()).map_err(output_error)?;
+    writer.flush().map_err(output_error)
+}
+
+pub fn write_timed_results<P: AsRef<Path>>(path: P, results: &[(i32, u64)]) -> Result<(), String> {
+    let output: Vec<String> = results.iter().map(|(pa, cycles)| format!("{}:{}", pa, cycles)).collect();
     let content = output.join(" ");
     fs::write(path.as_ref(), content).map_err(|e| format!("Failed to write output file: {}", e))
 }
+
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    radix: Radix,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, String> {
+        writeln!(writer, "va,s,p,w,pa,error,pt_fault,page_fault,frame_allocated,st_fault").map_err(trace_error)?;
+        Ok(TraceWriter { writer, radix: Radix::Decimal })
+    }
+
+    pub fn set_radix(&mut self, radix: Radix) {
+        self.radix = radix;
+    }
+
+    // frame_allocated is the page frame filled by a page fault, or the PT frame
+    // when only the page table was faulted in.
+    pub fn write(
+        &mut self,
+        va: u32,
+        result: &TranslationResult,
+        faults: FaultFlags,
+        pm: &PhysicalMemory,
+    ) -> Result<(), String> {
+        let parts = VirtualAddress::decompose(pm.layout(), va);
+        let error = match result {
+            TranslationResult::Success(_) => "",
+            _ => result.kind(),
+        };
+        let frame_allocated = match (result, faults.page_fault || faults.zero_fault || faults.compressed_fault, faults.pt_fault) {
+            (TranslationResult::Success(pa), true, _) => (*pa as usize / pm.layout().page_size()).to_string(),
+            (_, _, true) => pm.pt_frame(pm.get_segment_pt_location(parts.s)).to_string(),
+            _ => String::new(),
+        };
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{},{},{}",
+            va,
+            parts.s,
+            parts.p,
+            parts.w,
+            self.radix.format(result.to_output()),
+            error,
+            faults.pt_fault as u8,
+            faults.page_fault as u8,
+            frame_allocated,
+            faults.st_fault as u8
+        )
+        .map_err(trace_error)
+    }
+
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(trace_error)
+    }
+}
+
+impl TraceWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = File::create(path.as_ref()).map_err(|e| format!("Failed to create trace file: {}", e))?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+fn trace_error(e: std::io::Error) -> String {
+    format!("Failed to write trace file: {}", e)
+}
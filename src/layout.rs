@@ -0,0 +1,153 @@
+use std::fmt;
+
+use crate::constants::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressLayout {
+    pub s_bits: u32,
+    pub d_bits: u32,
+    pub p_bits: u32,
+    pub w_bits: u32,
+}
+
+impl AddressLayout {
+    pub const DEFAULT: AddressLayout = AddressLayout { s_bits: S_BITS, d_bits: 0, p_bits: P_BITS, w_bits: W_BITS };
+
+    pub const MAX_W_BITS: u32 = 12;
+
+    pub fn new(s_bits: u32, p_bits: u32, w_bits: u32) -> Result<Self, String> {
+        let layout = AddressLayout { s_bits, d_bits: 0, p_bits, w_bits };
+        if s_bits == 0 || p_bits == 0 || w_bits == 0 {
+            return Err(format!("Address layout {} has an empty field", layout));
+        }
+        if s_bits + p_bits + w_bits > 32 {
+            return Err(format!("Address layout {} exceeds 32 bits", layout));
+        }
+        if w_bits > Self::MAX_W_BITS {
+            return Err(format!("Address layout {} has pages larger than 2^{} words", layout, Self::MAX_W_BITS));
+        }
+        if p_bits > w_bits {
+            return Err(format!("Address layout {} has page tables larger than a frame", layout));
+        }
+        if layout.st_frames() >= NUM_FRAMES {
+            return Err(format!("Address layout {} has a segment table larger than memory", layout));
+        }
+        Ok(layout)
+    }
+
+    // A three-level layout adds d_bits of page directory index between the
+    // segment and page numbers. Directories and page tables must each fit in a
+    // frame and in a disk block so both can be paged in.
+    pub fn three_level(s_bits: u32, d_bits: u32, p_bits: u32, w_bits: u32) -> Result<Self, String> {
+        let layout = AddressLayout { s_bits, d_bits, p_bits, w_bits };
+        if s_bits == 0 || d_bits == 0 || p_bits == 0 || w_bits == 0 {
+            return Err(format!("Address layout {} has an empty field", layout));
+        }
+        if s_bits + d_bits + p_bits + w_bits > 32 {
+            return Err(format!("Address layout {} exceeds 32 bits", layout));
+        }
+        if w_bits > Self::MAX_W_BITS {
+            return Err(format!("Address layout {} has pages larger than 2^{} words", layout, Self::MAX_W_BITS));
+        }
+        if d_bits > w_bits || p_bits > w_bits || 1 << d_bits > BLOCK_SIZE || 1 << p_bits > BLOCK_SIZE {
+            return Err(format!("Address layout {} has tables larger than a frame or disk block", layout));
+        }
+        if 1i64 << (d_bits + p_bits + w_bits) > SEGMENT_SIZE_MASK as i64 + 1 {
+            return Err(format!("Address layout {} has segments larger than the ST size field", layout));
+        }
+        if layout.st_frames() >= NUM_FRAMES {
+            return Err(format!("Address layout {} has a segment table larger than memory", layout));
+        }
+        Ok(layout)
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid address layout (expected s/p/w or s/d/p/w bit widths): {}", value);
+        let bits = value
+            .split('/')
+            .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<u32>, String>>()?;
+        match bits[..] {
+            [s_bits, p_bits, w_bits] => Self::new(s_bits, p_bits, w_bits),
+            [s_bits, d_bits, p_bits, w_bits] => Self::three_level(s_bits, d_bits, p_bits, w_bits),
+            _ => Err(invalid()),
+        }
+    }
+
+    #[inline]
+    pub fn is_three_level(&self) -> bool {
+        self.d_bits > 0
+    }
+
+    #[inline]
+    pub fn directory_size(&self) -> usize {
+        1 << self.d_bits
+    }
+
+    #[inline]
+    pub fn page_size(&self) -> usize {
+        1 << self.w_bits
+    }
+
+    #[inline]
+    pub fn pt_size(&self) -> usize {
+        1 << self.p_bits
+    }
+
+    #[inline]
+    pub fn max_segments(&self) -> usize {
+        1 << self.s_bits
+    }
+
+    pub fn st_size(&self) -> usize {
+        self.max_segments() * 2
+    }
+
+    pub fn st_frames(&self) -> usize {
+        self.st_size().div_ceil(self.page_size())
+    }
+
+    pub fn memory_size(&self) -> usize {
+        NUM_FRAMES * self.page_size()
+    }
+
+    // Splits a raw address into (s, p, w, pw); bits above s+p+w land in s and
+    // are rejected later as an invalid segment.
+    #[inline]
+    pub fn split(&self, raw: u32) -> (u32, u32, u32, u32) {
+        let w_mask = (1u32 << self.w_bits) - 1;
+        let p_mask = (1u32 << self.p_bits) - 1;
+        let pw_mask = (1u32 << (self.p_bits + self.w_bits)) - 1;
+        let s = raw >> (self.p_bits + self.w_bits);
+        let p = (raw >> self.w_bits) & p_mask;
+        (s, p, raw & w_mask, raw & pw_mask)
+    }
+
+    // Splits a raw address into (s, d, p, w, dpw) for a three-level layout.
+    #[inline]
+    pub fn split_three_level(&self, raw: u32) -> (u32, u32, u32, u32, u32) {
+        let w_mask = (1u32 << self.w_bits) - 1;
+        let p_mask = (1u32 << self.p_bits) - 1;
+        let d_mask = (1u32 << self.d_bits) - 1;
+        let dpw_mask = (1u32 << (self.d_bits + self.p_bits + self.w_bits)) - 1;
+        let s = raw >> (self.d_bits + self.p_bits + self.w_bits);
+        let d = (raw >> (self.p_bits + self.w_bits)) & d_mask;
+        let p = (raw >> self.w_bits) & p_mask;
+        (s, d, p, raw & w_mask, raw & dpw_mask)
+    }
+}
+
+impl Default for AddressLayout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl fmt::Display for AddressLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.d_bits {
+            0 => write!(f, "{}/{}/{}", self.s_bits, self.p_bits, self.w_bits),
+            d_bits => write!(f, "{}/{}/{}/{}", self.s_bits, d_bits, self.p_bits, self.w_bits),
+        }
+    }
+}
@@ -1,4 +1,43 @@
+pub mod access;
+pub mod allocation;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod builder;
+pub mod compaction;
+pub mod compression;
 pub mod constants;
+pub mod cost;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod generator;
+pub mod inspect;
+pub mod inverted;
 pub mod io;
+pub mod layout;
+pub mod manifest;
+pub mod prefetch;
 pub mod memory;
+pub mod parallel;
+pub mod process;
+pub mod provenance;
+pub mod purity;
+pub mod repl;
+pub mod replacement;
+pub mod residency;
+pub mod segment;
+pub mod sink;
+pub mod smp;
+pub mod snapshot;
+pub mod stats;
+pub mod stress;
+pub mod swap;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tlb;
 pub mod translation;
+pub mod vm_manager;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use vm_manager::VMManager;
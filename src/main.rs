@@ -1,19 +1,617 @@
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal};
+use std::path::Path;
 use std::process;
+use std::time::Duration;
 
-use rust_virtual_memory::io::{read_virtual_addresses, write_results, InitData};
-use rust_virtual_memory::memory::{Disk, PhysicalMemory};
-use rust_virtual_memory::translation::{translate_batch, translate_batch_with_demand_paging};
+use rust_virtual_memory::access::{handle_access, AccessOutcome, Walker};
+use rust_virtual_memory::allocation::{virtual_pages, AllocationPolicy, ProcessAllocator};
+use rust_virtual_memory::compaction::compact_page_tables;
+use rust_virtual_memory::constants::{DEFAULT_PT_SLOT_SIZE, DEFAULT_SYNC_INTERVAL, MAX_SEGMENTS, NUM_FRAMES, ST_FRAMES};
+use rust_virtual_memory::cost::{CostModel, LatencyBreakdown, TlbOutcome};
+use rust_virtual_memory::events::{Event, EventLog, Observer, VmEvent};
+use rust_virtual_memory::inverted::{translate_inverted, InvertedPageTable, TableKind};
+use rust_virtual_memory::generator::{Locality, WorkloadConfig};
+use rust_virtual_memory::inspect::{FrameMap, MemoryDump};
+use rust_virtual_memory::io::{
+    expand_runs, export_reference_string, parse_virtual_address, read_trace, read_trace_from, write_trace, write_trace_to, DuplicatePolicy, InitData,
+    ThreeLevelInitData, Trace, TraceDirective, TraceWriter, STDIO_PATH,
+};
+use rust_virtual_memory::layout::AddressLayout;
+use rust_virtual_memory::manifest::Manifest;
+use rust_virtual_memory::memory::{validate, Disk, FrameOwner, FreeFrameList, FreeListKind, PhysicalMemory, PteFormat};
+use rust_virtual_memory::parallel::translate_batch_parallel;
+use rust_virtual_memory::prefetch::Prefetcher;
+use rust_virtual_memory::provenance::{write_provenance, Provenance};
+use rust_virtual_memory::purity::{translate_pure, translate_with_demand_paging_checked};
+use rust_virtual_memory::repl::run_repl;
+use rust_virtual_memory::replacement::{parse_policy, PageId, Replacer};
+use rust_virtual_memory::residency::{translate_with_residency, ResidencyPolicy};
+use rust_virtual_memory::segment::{free_segment, resize_segment};
+use rust_virtual_memory::sink::{OutputFormat, Radix, ResultRecord, ResultSink, SyncedFileSink};
+use rust_virtual_memory::snapshot::{load_checkpoint, load_snapshot, save_checkpoint, save_snapshot};
+use rust_virtual_memory::compression::CompressedPool;
+use rust_virtual_memory::swap::SwapTiers;
+use rust_virtual_memory::stress::{minimize, parse_repro, replay, repro_to_string, run_stress, StressConfig};
+use rust_virtual_memory::stats::{
+    AccessHeatmap, FirstTouchMap, IdlePageReport, IdlePageTracker, LatencyHistogram, ProgressTracker, ThrashingDetector,
+    ThrashingEvent, TranslationStats, WorkingSetTracker,
+};
+use rust_virtual_memory::tlb::{IndexHash, SetAssociativeTlb, Tlb, TlbCache, TlbEviction};
+use rust_virtual_memory::VMManager;
+use rust_virtual_memory::translation::{
+    record_access, reverse_lookup, translate, translate_batch_grouped, translate_three_level_access, translate_with_stats, translate_with_demand_paging_faults, Access, AccessType,
+    DataOp, ErrorCodes, FaultFlags, TranslationResult, VirtualAddress,
+};
+
+struct Options {
+    init_file: String,
+    input_file: String,
+    output_file: String,
+    compact_pt: bool,
+    grouping: bool,
+    threads: usize,
+    latency: bool,
+    save_snapshot: Option<String>,
+    checkpoint: Option<String>,
+    idle_window: Option<usize>,
+    first_touch: Option<usize>,
+    working_set: Option<usize>,
+    thrashing: Option<(usize, f64)>,
+    residency: Vec<(u32, usize, usize)>,
+    replacement: Option<String>,
+    free_target: Option<usize>,
+    swap_tiers: Option<SwapTiers>,
+    compress_pool: Option<usize>,
+    allocation: Option<AllocationPolicy>,
+    prefetch: Option<usize>,
+    clear_refs: Option<usize>,
+    cost: CostModel,
+    latency_histogram: bool,
+    latency_breakdown: bool,
+    tlb_output: bool,
+    format: OutputFormat,
+    radix: Radix,
+    error_codes: ErrorCodes,
+    tlb_entries: Option<usize>,
+    tlb_eviction: TlbEviction,
+    tlb_ways: Option<usize>,
+    tlb_hash: Option<IndexHash>,
+    sync_every: Option<usize>,
+    resume: bool,
+    check_purity: bool,
+    verbose: bool,
+    progress: Option<Duration>,
+    validate: bool,
+    strict: bool,
+    duplicates: DuplicatePolicy,
+    free_list: FreeListKind,
+    frames: Option<usize>,
+    table: TableKind,
+    stats: bool,
+    stats_json: Option<String>,
+    provenance: Option<String>,
+    heatmap: Option<String>,
+    trace: Option<String>,
+    record: Option<String>,
+    ref_string: Option<String>,
+    ref_string_collapse: bool,
+    replay: Option<String>,
+    layout: Option<AddressLayout>,
+    pte_format: PteFormat,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut compact_pt = false;
+        let mut grouping = false;
+        let mut threads = 1;
+        let mut latency = false;
+        let mut save_snapshot = None;
+        let mut checkpoint = None;
+        let mut idle_window = None;
+        let mut first_touch = None;
+        let mut working_set = None;
+        let mut thrashing = None;
+        let mut residency = Vec::new();
+        let mut replacement = None;
+        let mut free_target = None;
+        let mut swap_tiers = None;
+        let mut compress_pool = None;
+        let mut allocation = None;
+        let mut prefetch = None;
+        let mut clear_refs = None;
+        let mut cost = CostModel::default();
+        let mut latency_histogram = false;
+        let mut latency_breakdown = false;
+        let mut tlb_output = false;
+        let mut format = OutputFormat::Text;
+        let mut radix = Radix::Decimal;
+        let mut error_codes = ErrorCodes::Collapsed;
+        let mut tlb_entries: Option<usize> = None;
+        let mut tlb_eviction = TlbEviction::Lru;
+        let mut tlb_ways: Option<usize> = None;
+        let mut tlb_hash = None;
+        let mut sync_every = None;
+        let mut resume = false;
+        let mut check_purity = false;
+        let mut verbose = false;
+        let mut progress = None;
+        let mut validate = false;
+        let mut strict = false;
+        let mut duplicates = DuplicatePolicy::default();
+        let mut free_list = FreeListKind::Sorted;
+        let mut frames = None;
+        let mut table = TableKind::Forward;
+        let mut stats = false;
+        let mut stats_json = None;
+        let mut provenance = None;
+        let mut heatmap = None;
+        let mut trace = None;
+        let mut record = None;
+        let mut ref_string = None;
+        let mut ref_string_collapse = false;
+        let mut replay = None;
+        let mut layout = None;
+        let mut pte_format = PteFormat::Packed;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--compact-pt" => compact_pt = true,
+                "--grouping" => grouping = true,
+                "--threads" => threads = parse_flag_value(&mut args, arg)?,
+                "--latency" => latency = true,
+                "--cost" => cost = CostModel::parse(&flag_value(&mut args, arg)?)?,
+                "--latency-histogram" => latency_histogram = true,
+                "--latency-breakdown" => latency_breakdown = true,
+                "--tlb" => tlb_entries = Some(parse_flag_value(&mut args, arg)?),
+                "--tlb-output" => tlb_output = true,
+                "--tlb-ways" => tlb_ways = Some(parse_flag_value(&mut args, arg)?),
+                "--tlb-hash" => tlb_hash = Some(IndexHash::parse(&flag_value(&mut args, arg)?)?),
+                "--tlb-policy" => tlb_eviction = TlbEviction::parse(&flag_value(&mut args, arg)?)?,
+                "--sync-every" => sync_every = Some(parse_flag_value(&mut args, arg)?),
+                "--resume" => resume = true,
+                "--check-purity" => check_purity = true,
+                "--verbose" => verbose = true,
+                "--progress" => progress = Some(parse_progress(&flag_value(&mut args, arg)?)?),
+                "--validate" => validate = true,
+                "--strict" => strict = true,
+                "--duplicates" => duplicates = DuplicatePolicy::parse(&flag_value(&mut args, arg)?)?,
+                "--free-list" => free_list = FreeListKind::parse(&flag_value(&mut args, arg)?)?,
+                "--frames" => frames = Some(parse_flag_value(&mut args, arg)?),
+                "--table" => table = TableKind::parse(&flag_value(&mut args, arg)?)?,
+                "--stats" => stats = true,
+                "--stats-json" => stats_json = Some(flag_value(&mut args, arg)?),
+                "--provenance" => provenance = Some(flag_value(&mut args, arg)?),
+                "--heatmap" => heatmap = Some(flag_value(&mut args, arg)?),
+                "--trace" => trace = Some(flag_value(&mut args, arg)?),
+                "--record" => record = Some(flag_value(&mut args, arg)?),
+                "--ref-string" => ref_string = Some(flag_value(&mut args, arg)?),
+                "--ref-string-collapse" => ref_string_collapse = true,
+                "--replay" => replay = Some(flag_value(&mut args, arg)?),
+                "--layout" => layout = Some(AddressLayout::parse(&flag_value(&mut args, arg)?)?),
+                "--pte-format" => pte_format = PteFormat::parse(&flag_value(&mut args, arg)?)?,
+                "--format" => format = OutputFormat::parse(&flag_value(&mut args, arg)?)?,
+                "--radix" => radix = Radix::parse(&flag_value(&mut args, arg)?)?,
+                "--error-codes" => error_codes = ErrorCodes::parse(&flag_value(&mut args, arg)?)?,
+                "--save-snapshot" => save_snapshot = Some(flag_value(&mut args, arg)?),
+                "--checkpoint" => checkpoint = Some(flag_value(&mut args, arg)?),
+                "--idle-pages" => idle_window = Some(parse_flag_value(&mut args, arg)?),
+                "--first-touch" => first_touch = Some(parse_flag_value(&mut args, arg)?),
+                "--working-set" => working_set = Some(parse_flag_value(&mut args, arg)?),
+                "--thrashing" => thrashing = Some(parse_thrashing(&flag_value(&mut args, arg)?)?),
+                "--replacement" => replacement = Some(flag_value(&mut args, arg)?),
+                "--free-target" => free_target = Some(parse_flag_value(&mut args, arg)?),
+                "--swap-tiers" => swap_tiers = Some(SwapTiers::parse(&flag_value(&mut args, arg)?)?),
+                "--compress-pool" => compress_pool = Some(parse_flag_value(&mut args, arg)?),
+                "--prefetch" => prefetch = Some(parse_flag_value(&mut args, arg)?),
+                "--clear-refs" => clear_refs = Some(parse_flag_value(&mut args, arg)?),
+                "--allocation" => allocation = Some(AllocationPolicy::parse(&flag_value(&mut args, arg)?)?),
+                "--residency" => residency.push(parse_residency(&flag_value(&mut args, arg)?)?),
+                flag if flag.starts_with("--") => return Err(format!("Unknown option: {}", flag)),
+                _ => positional.push(arg.clone()),
+            }
+        }
+
+        if (sync_every.is_some() || resume) && format != OutputFormat::Text {
+            return Err("--sync-every and --resume require text output".to_string());
+        }
+        if replacement.is_some() && !residency.is_empty() {
+            return Err("--replacement cannot be combined with --residency".to_string());
+        }
+        if radix == Radix::Hex && format == OutputFormat::Json {
+            return Err("--radix hex cannot be combined with --format json".to_string());
+        }
+        if ref_string_collapse && ref_string.is_none() {
+            return Err("--ref-string-collapse requires --ref-string".to_string());
+        }
+        if tlb_output && tlb_entries.is_none() {
+            return Err("--tlb-output requires --tlb".to_string());
+        }
+        if tlb_ways.is_some() && tlb_entries.is_none() {
+            return Err("--tlb-ways requires --tlb".to_string());
+        }
+        if tlb_hash.is_some() && tlb_ways.is_none() {
+            return Err("--tlb-hash requires --tlb-ways".to_string());
+        }
+        if tlb_ways.is_some() && tlb_eviction != TlbEviction::Lru {
+            return Err("A set-associative TLB replaces LRU within each set".to_string());
+        }
+        if let (Some(entries), Some(ways)) = (tlb_entries, tlb_ways) {
+            if ways == 0 || entries % ways != 0 || !(entries / ways).is_power_of_two() {
+                return Err(format!("--tlb {} must be a power-of-two number of sets of --tlb-ways {}", entries, ways));
+            }
+        }
+        if table == TableKind::Inverted && (replacement.is_some() || !residency.is_empty() || check_purity) {
+            return Err("--table inverted cannot be combined with --replacement, --residency or --check-purity".to_string());
+        }
+        if frames.is_some_and(|frames| frames <= ST_FRAMES || frames > NUM_FRAMES) {
+            return Err(format!("--frames must be between {} and {}", ST_FRAMES + 1, NUM_FRAMES));
+        }
+        if free_target.is_some() && replacement.is_none() {
+            return Err("--free-target requires --replacement".to_string());
+        }
+        if swap_tiers.is_some() && replacement.is_none() {
+            return Err("--swap-tiers requires --replacement".to_string());
+        }
+        if compress_pool.is_some() && replacement.is_none() {
+            return Err("--compress-pool requires --replacement".to_string());
+        }
+        if compress_pool == Some(0) {
+            return Err("--compress-pool must be at least 1 frame".to_string());
+        }
+        if prefetch.is_some() && (replacement.is_some() || !residency.is_empty()) {
+            return Err("--prefetch cannot be combined with --replacement or --residency".to_string());
+        }
+        if prefetch == Some(0) {
+            return Err("--prefetch depth must be at least 1".to_string());
+        }
+        if threads == 0 {
+            return Err("--threads must be at least 1".to_string());
+        }
+        if clear_refs == Some(0) {
+            return Err("--clear-refs interval must be at least 1".to_string());
+        }
+        if replacement.as_deref() == Some("nru") && clear_refs.is_none() {
+            return Err("--replacement nru requires --clear-refs".to_string());
+        }
+        if allocation.is_some() && replacement.is_none() {
+            return Err("--allocation requires --replacement".to_string());
+        }
+        if first_touch == Some(0) {
+            return Err("--first-touch window must be at least 1".to_string());
+        }
+        if working_set == Some(0) {
+            return Err("--working-set delta must be at least 1".to_string());
+        }
+        if layout.is_some() && (compact_pt || grouping || save_snapshot.is_some()) {
+            return Err("--layout cannot be combined with --compact-pt, --grouping or --save-snapshot".to_string());
+        }
+        if positional.len() != 3 {
+            return Err(format!("Expected 3 file arguments, got {}", positional.len()));
+        }
+        let mut positional = positional.into_iter();
+        let options = Options {
+            init_file: positional.next().unwrap(),
+            input_file: positional.next().unwrap(),
+            output_file: positional.next().unwrap(),
+            compact_pt,
+            grouping,
+            threads,
+            latency,
+            save_snapshot,
+            checkpoint,
+            idle_window,
+            first_touch,
+            working_set,
+            thrashing,
+            residency,
+            replacement,
+            free_target,
+            swap_tiers,
+            compress_pool,
+            allocation,
+            prefetch,
+            clear_refs,
+            cost,
+            latency_histogram,
+            latency_breakdown,
+            tlb_output,
+            format,
+            radix,
+            error_codes,
+            tlb_entries,
+            tlb_eviction,
+            tlb_ways,
+            tlb_hash,
+            sync_every,
+            resume,
+            check_purity,
+            verbose,
+            progress,
+            validate,
+            strict,
+            duplicates,
+            free_list,
+            frames,
+            table,
+            stats,
+            stats_json,
+            provenance,
+            heatmap,
+            trace,
+            record,
+            ref_string,
+            ref_string_collapse,
+            replay,
+            layout,
+            pte_format,
+        };
+        if (options.sync_every.is_some() || options.resume) && options.output_file == STDIO_PATH {
+            return Err("--sync-every and --resume require an output file, not stdout".to_string());
+        }
+        let three_level = options.layout.is_some_and(|layout| layout.is_three_level());
+        let checked = options.checkpoint.is_some() || options.validate || options.strict;
+        if three_level && (options.needs_per_access() || checked) {
+            return Err(
+                "A three-level --layout cannot be combined with per-access options, --checkpoint, --validate or --strict"
+                    .to_string(),
+            );
+        }
+        if three_level && (options.sync_every.is_some() || options.resume || options.ref_string.is_some()) {
+            return Err("A three-level --layout cannot be combined with --sync-every, --resume or --ref-string".to_string());
+        }
+        Ok(options)
+    }
+
+    fn needs_per_access(&self) -> bool {
+        self.latency
+            || self.latency_histogram
+            || self.latency_breakdown
+            || self.idle_window.is_some()
+            || self.first_touch.is_some()
+            || self.working_set.is_some()
+            || self.thrashing.is_some()
+            || !self.residency.is_empty()
+            || self.replacement.is_some()
+            || self.prefetch.is_some()
+            || self.clear_refs.is_some()
+            || self.tlb_entries.is_some()
+            || self.check_purity
+            || self.verbose
+            || self.progress.is_some()
+            || self.provenance.is_some()
+            || self.heatmap.is_some()
+            || self.trace.is_some()
+            || self.record.is_some()
+            || self.replay.is_some()
+            || self.table == TableKind::Inverted
+    }
+}
+
+fn parse_residency(value: &str) -> Result<(u32, usize, usize), String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    let invalid = || format!("Invalid residency bound (expected s:min:max): {}", value);
+    if parts.len() != 3 {
+        return Err(invalid());
+    }
+    let s = parts[0].parse().map_err(|_| invalid())?;
+    let min = parts[1].parse().map_err(|_| invalid())?;
+    let max = parts[2].parse().map_err(|_| invalid())?;
+    Ok((s, min, max))
+}
+
+fn parse_thrashing(value: &str) -> Result<(usize, f64), String> {
+    let invalid = || format!("Invalid thrashing threshold (expected window:rate with rate in 0..1): {}", value);
+    let (window, rate) = value.split_once(':').ok_or_else(invalid)?;
+    let window: usize = window.parse().map_err(|_| invalid())?;
+    let rate: f64 = rate.parse().map_err(|_| invalid())?;
+    if window == 0 || !(0.0..1.0).contains(&rate) {
+        return Err(invalid());
+    }
+    Ok((window, rate))
+}
+
+fn parse_progress(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid progress interval (expected a positive number of seconds): {}", value);
+    let seconds: f64 = value.parse().map_err(|_| invalid())?;
+    if !(seconds > 0.0 && seconds.is_finite()) {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn flag_value<'a>(args: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<String, String> {
+    args.next().cloned().ok_or_else(|| format!("Option {} requires a value", flag))
+}
+
+fn parse_flag_value<'a, T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<T, String> {
+    let value = flag_value(args, flag)?;
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+// How many pages the --verbose summary lists as the hottest.
+const HOTTEST_PAGES: usize = 10;
+
+const OPTIONS_HELP: &str = "\
+Options:
+  --compact-pt            pack the page tables of small segments into shared frames
+  --grouping              report how many consecutive accesses shared a page walk
+  --threads <n>           speculate demand-paging walks on n threads; results match a single-threaded run
+  --latency               write each result as pa:cycles using the cost model
+  --cost <costs>          cycle costs for the latency options and stats, e.g. tlb=1,memory=100,fault=1000,disk=100000
+  --latency-histogram     report the latency distribution with mean EAT and p50/p95/p99
+  --latency-breakdown     split simulated time into TLB, walk, fault, disk and data buckets
+  --tlb <entries>         consult a TLB with the given number of entries before each walk
+  --tlb-policy <policy>   TLB eviction policy: lru (default) or fifo
+  --tlb-ways <n>          make the TLB set-associative with n ways per set (LRU within a set)
+  --tlb-hash <hash>       set index from the sp value: low (low bits, default) or xor (xor-fold)
+  --tlb-output            prefix each result with h (TLB hit) or m (TLB miss)
+  --stats                 print a translation statistics summary and run manifest
+  --stats-json <file>     write translation statistics as JSON
+  --trace <file>          write a CSV row per translation with its fields, faults and allocated frame
+  --provenance <file>     record the ST words, PTE and faults behind each result
+  --heatmap <file>        write per-page access counts as a CSV segment x page matrix
+  --record <file>         log every fault, frame allocation, eviction and disk transfer in binary
+  --ref-string <file>     export the trace as its (s, p) page reference string, one pair per line
+  --ref-string-collapse   with --ref-string, merge consecutive references to the same page
+  --replay <file>         verify the run produces exactly the events logged by --record
+  --check-purity          verify translations of resident mappings never mutate state (slow)
+  --verbose               print every TLB lookup, fault, allocation, eviction, disk transfer and result,
+                          then per-segment totals and the hottest pages
+  --progress <seconds>    report percent done, translations/s and fault rate every so many seconds
+  --free-list <kind>      free frame list backing: sorted (default) or bitmap
+  --frames <n>            pretend physical memory has only n frames, so replacement kicks in sooner
+  --table <kind>          page table organization: forward (default) or inverted
+  --validate              check the initial ST, PTs, free list and frame table for conflicts
+  --strict                reject an init file whose entries conflict before applying it
+  --duplicates <policy>   when the init file defines a segment or page twice: last (default), first or error
+  --layout <s/p/w>        address bit widths for resident read-only runs (default 9/9/9);
+                          s/d/p/w selects a demand-paged three-level table (e.g. 7/7/6/12)
+  --pte-format <format>   PT entry words: packed (default) or legacy bare frame/block numbers
+  --format <format>       output format: text (default), json, csv or null
+  --radix <radix>         print physical addresses as dec (default) or hex in text, csv and trace output
+  --error-codes <codes>   failed translations as collapsed (-1, default) or detailed: -1 boundary
+                          violation, -2 invalid segment, -3 invalid page, -4 protection fault
+  --sync-every <n>        fsync the output and record a progress marker every n results
+  --resume                continue an interrupted --sync-every run from its progress marker
+  --save-snapshot <file>  save the final memory and disk state
+  --checkpoint <file>     resume from the file if it exists and save the final state to it
+  --idle-pages <k>        report faulted-in pages unused after first touch or in the final k accesses
+  --first-touch <k>       report the access that first touched each page, with counts per k accesses
+  --working-set <delta>   report working set sizes over a delta-access window and working-set faults
+  --thrashing <n:rate>    warn while more than rate of the last n accesses page faulted
+  --replacement <policy>  evict faulted-in pages when memory is full: fifo, lru, opt, nru or second-chance
+  --free-target <n>       with --replacement, evict ahead to keep n frames free for soft faults
+  --swap-tiers <n:hot>    with --replacement, blocks 1-n are fast swap; pages used hot times go there
+  --compress-pool <n>     with --replacement, keep evicted pages compressed in n frames' worth of memory
+  --prefetch <k>          on a page fault also load the next k pages that are on disk
+  --clear-refs <n>        clear every page's reference bit after each n accesses
+  --allocation <policy>   with --replacement, per-process frame quotas: global, equal or proportional
+  --residency <s:min:max> bound the resident pages of segment s (repeatable)";
+
+fn print_usage(program: &str) {
+    eprintln!("Usage: {} [translate] [options] <init_file> <input_file> <output_file>", program);
+    eprintln!("       {} snapshot-to-init <snapshot_file> <init_file>", program);
+    eprintln!("       {} repl <init_file>", program);
+    eprintln!("       {} inspect [--json] [--reverse <pa>] [--map] [--dot <file>] <init_file>", program);
+    eprintln!("       {} diff <init_file> <input_file> [options] --vs [options]", program);
+    eprintln!(
+        "       {} gen [--segments n] [--pages n] [--resident f] [--length n] [--locality l] [--seed n] <init_file> <input_file>",
+        program
+    );
+    eprintln!(
+        "       {} stress [--steps n] [--check-every k] [--seed n] [--tlb n] [--segments n] [--repro file] [--replay file] [init_file]",
+        program
+    );
+    eprintln!("       {} bench [--addresses n] [--segments n] [--pages n] [--locality d] [--tlb n] [--seed n]", program);
+    eprintln!("       {} help", program);
+    eprintln!("{}", OPTIONS_HELP);
+}
+
+// The first argument names a subcommand. Anything else is the original
+// `[options] <init_file> <input_file> <output_file>` form, which is `translate`.
+enum Command<'a> {
+    Translate(Box<Options>),
+    SnapshotToInit { snapshot_file: &'a str, init_file: &'a str },
+    Repl { init_file: &'a str },
+    Inspect { init_file: &'a str, json: bool, reverse: Option<u32>, map: bool, dot: Option<String> },
+    Gen(&'a [String]),
+    Bench(&'a [String]),
+    Diff(&'a [String]),
+    Stress(&'a [String]),
+    Help,
+}
+
+impl<'a> Command<'a> {
+    fn parse(args: &'a [String]) -> Result<Self, String> {
+        let rest = args.get(1..).unwrap_or_default();
+        match args.first().map(String::as_str) {
+            Some("translate") => Ok(Command::Translate(Box::new(Options::parse(rest)?))),
+            Some("snapshot-to-init") => match rest {
+                [snapshot_file, init_file] => Ok(Command::SnapshotToInit { snapshot_file, init_file }),
+                _ => Err("snapshot-to-init expects <snapshot_file> <init_file>".to_string()),
+            },
+            Some("repl") => match rest {
+                [init_file] => Ok(Command::Repl { init_file }),
+                _ => Err("repl expects <init_file>".to_string()),
+            },
+            Some("inspect") => Self::parse_inspect(rest),
+            Some("gen") => Ok(Command::Gen(rest)),
+            Some("bench") => Ok(Command::Bench(rest)),
+            Some("diff") => Ok(Command::Diff(rest)),
+            Some("stress") => Ok(Command::Stress(rest)),
+            Some("help" | "--help" | "-h") => Ok(Command::Help),
+            _ => Ok(Command::Translate(Box::new(Options::parse(args)?))),
+        }
+    }
+
+    fn parse_inspect(args: &'a [String]) -> Result<Self, String> {
+        let usage = || "inspect expects [--json] [--reverse <pa>] [--map] [--dot <file>] <init_file>".to_string();
+        let (mut json, mut reverse, mut map, mut dot, mut init_file) = (false, None, false, None, None);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--json" => json = true,
+                "--map" => map = true,
+                "--dot" => dot = Some(flag_value(&mut args, arg)?),
+                "--reverse" => {
+                    let pa = flag_value(&mut args, arg)?;
+                    reverse = Some(parse_virtual_address(&pa).map_err(|_| format!("Invalid physical address: {}", pa))?);
+                }
+                _ if init_file.is_none() => init_file = Some(arg.as_str()),
+                _ => return Err(usage()),
+            }
+        }
+        if map && (json || reverse.is_some()) {
+            return Err("--map cannot be combined with --json or --reverse".to_string());
+        }
+        if dot.is_some() && (json || map || reverse.is_some()) {
+            return Err("--dot cannot be combined with --json, --map or --reverse".to_string());
+        }
+        Ok(Command::Inspect { init_file: init_file.ok_or_else(usage)?, json, reverse, map, dot })
+    }
+
+    fn run(&self, program: &str) -> Result<(), String> {
+        match self {
+            Command::Translate(options) => run(options),
+            Command::SnapshotToInit { snapshot_file, init_file } => snapshot_to_init(snapshot_file, init_file),
+            Command::Repl { init_file } => repl(init_file),
+            Command::Inspect { init_file, dot: Some(dot_file), .. } => inspect_dot(init_file, dot_file),
+            Command::Inspect { init_file, map: true, .. } => inspect_map(init_file),
+            Command::Inspect { init_file, json, reverse: Some(pa), .. } => inspect_reverse(init_file, *pa, *json),
+            Command::Inspect { init_file, json, reverse: None, .. } => inspect(init_file, *json),
+            Command::Gen(args) => generate(args),
+            Command::Bench(args) => bench(args),
+            Command::Diff(args) => diff(args),
+            Command::Stress(args) => stress(args),
+            Command::Help => {
+                print_usage(program);
+                Ok(())
+            }
+        }
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 4 {
-        eprintln!("Usage: {} <init_file> <input_file> <output_file>", args[0]);
-        process::exit(1);
-    }
+    let command = match Command::parse(&args[1..]) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            print_usage(&args[0]);
+            process::exit(1);
+        }
+    };
 
-    if let Err(e) = run(&args[1], &args[2], &args[3]) {
+    if let Err(e) = command.run(&args[0]) {
         eprintln!("Error: {}", e);
         process::exit(1);
     }
@@ -29,20 +627,1271 @@ fn needs_demand_paging(init_data: &InitData) -> bool {
     false
 }
 
-fn run(init_file: &str, input_file: &str, output_file: &str) -> Result<(), String> {
+// Memory, disk and free list as a run starts, after init, validation and
+// compaction. Cloning it lets several configurations start from one load.
+#[derive(Clone)]
+struct LoadedState {
+    pm: PhysicalMemory,
+    disk: Disk,
+    ffl: FreeFrameList,
+    init_data: InitData,
+    resumed: bool,
+}
+
+// What a run produced, for writing out or comparing with another run.
+struct Simulation {
+    vas: Vec<u32>,
+    results: Vec<TranslationResult>,
+    stats: TranslationStats,
+    manifest: Manifest,
+    latencies: Vec<u64>,
+    tlb_hits: Vec<bool>,
+    provenance: Vec<Provenance>,
+    pm: PhysicalMemory,
+    disk: Disk,
+    ffl: FreeFrameList,
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    if let Some(layout) = options.layout.filter(AddressLayout::is_three_level) {
+        return run_three_level(options, layout);
+    }
+    let state = load_state(options)?;
+    let trace = read_input(&options.input_file)?;
+    let run = simulate(options, state, &trace)?;
+    report_stats(options, &run.stats, &run.manifest)?;
+    if let Some(path) = &options.provenance {
+        write_provenance(path, &run.provenance)?;
+    }
+    write_output(options, &run.vas, &run.results, &run.latencies, &run.tlb_hits)?;
+    save_final_state(options, &run.pm, &run.disk, &run.ffl)
+}
+
+fn load_state(options: &Options) -> Result<LoadedState, String> {
+    let restored = match &options.checkpoint {
+        Some(path) if Path::new(path).exists() => Some(load_checkpoint(path)?),
+        _ => None,
+    };
+    let resumed = restored.is_some();
+    let (mut pm, disk, mut ffl, init_data) = match restored {
+        Some((pm, disk, ffl)) => {
+            let init_data = InitData::from_memory(&pm, &disk);
+            (pm, disk, ffl.into_kind(options.free_list), init_data)
+        }
+        None => {
+            let layout = options.layout.unwrap_or_default();
+            let init_data = InitData::from_file_with_layout(&options.init_file, &layout)?;
+            if options.strict {
+                if let Err(errors) = init_data.validate_with_layout(&layout) {
+                    for error in &errors {
+                        eprintln!("Init conflict: {}", error);
+                    }
+                    return Err(format!("{} conflict(s) in {}", errors.len(), options.init_file));
+                }
+            }
+            let (init_data, overridden) = init_data.deduplicate(options.duplicates)?;
+            for entry in &overridden {
+                eprintln!("Init override: {}", entry);
+            }
+            let mut pm = PhysicalMemory::with_layout(layout);
+            pm.set_pte_format(options.pte_format);
+            let mut disk = Disk::new();
+            let mut ffl = free_frame_list(options);
+            init_data.apply_to(&mut pm, &mut disk, &mut ffl);
+            check_frame_budget(options, &pm)?;
+            (pm, disk, ffl, init_data)
+        }
+    };
+
+    if options.validate {
+        if let Err(errors) = validate(&pm, &ffl, &disk) {
+            for error in &errors {
+                eprintln!("Inconsistent state: {}", error);
+            }
+            return Err(format!("{} consistency violation(s) after init", errors.len()));
+        }
+    }
+
+    if options.compact_pt && !resumed {
+        let report = compact_page_tables(&mut pm, &mut ffl, DEFAULT_PT_SLOT_SIZE)?;
+        eprintln!(
+            "PT compaction: {} segments, {} -> {} PT frames ({} saved)",
+            report.segments_compacted,
+            report.pt_frames_before,
+            report.pt_frames_after,
+            report.frames_saved()
+        );
+    }
+    Ok(LoadedState { pm, disk, ffl, init_data, resumed })
+}
+
+fn simulate(options: &Options, state: LoadedState, trace: &Trace) -> Result<Simulation, String> {
+    let LoadedState { mut pm, mut disk, mut ffl, init_data, resumed } = state;
+    let layout = *pm.layout();
+    let accesses = trace.accesses();
+    let directives = &trace.directives;
+    if !directives.is_empty() && options.table == TableKind::Inverted {
+        return Err("G and F directives cannot be combined with --table inverted".to_string());
+    }
+    let vas: Vec<u32> = accesses.iter().map(|a| a.va).collect();
+    if let Some(path) = &options.ref_string {
+        let references = export_reference_string(path, &vas, &layout, options.ref_string_collapse)?;
+        eprintln!("Reference string: {} references from {} accesses", references, vas.len());
+    }
+    let tagged = accesses.iter().any(|a| a.kind != AccessType::Read || a.data != DataOp::None)
+        || !init_data.protections.is_empty()
+        || !directives.is_empty();
+    let demand_paging = needs_demand_paging(&init_data);
+    if options.layout.is_some() && (demand_paging || tagged || options.needs_per_access()) {
+        return Err("--layout supports only resident, read-only runs without per-access options".to_string());
+    }
+
+    let manifest = match options.stats || options.stats_json.is_some() {
+        true => build_manifest(options, &layout, demand_paging, resumed, ffl.free_count())?,
+        false => Manifest::default(),
+    };
+    let per_access = options.needs_per_access() || tagged;
+    if per_access && options.threads > 1 {
+        return Err("--threads supports only untagged traces without per-access options".to_string());
+    }
+    if per_access {
+        let run = simulate_per_access(options, &accesses, directives, demand_paging, &mut pm, &mut disk, &mut ffl)?;
+        let Recorder { results, stats, latencies, tlb_hits, provenance, .. } = run;
+        return Ok(Simulation { vas, results, stats, manifest, latencies, tlb_hits, provenance, pm, disk, ffl });
+    }
+    let mut stats = TranslationStats::new();
+    let results = if demand_paging {
+        let (results, report) = match options.threads {
+            1 => translate_batch_grouped(&vas, &mut pm, &disk, &mut ffl, Some(&mut stats)),
+            threads => translate_batch_parallel(&vas, &mut pm, &disk, &mut ffl, Some(&mut stats), threads),
+        };
+        if options.grouping {
+            eprintln!(
+                "Page grouping: {} accesses in {} groups (factor {:.2})",
+                report.accesses,
+                report.groups,
+                report.factor()
+            );
+        }
+        results
+    } else {
+        vas.iter()
+            .map(|&va| translate_with_stats(&VirtualAddress::decompose(&layout, va), &pm, Some(&mut stats)))
+            .collect()
+    };
+    stats.simulated_cycles = options.cost.simulated_cycles(&stats);
+    Ok(Simulation {
+        vas,
+        results,
+        stats,
+        manifest,
+        latencies: Vec::new(),
+        tlb_hits: Vec::new(),
+        provenance: Vec::new(),
+        pm,
+        disk,
+        ffl,
+    })
+}
+
+// Runs the trace one access at a time through the path VMManager uses, with the
+// run's pager servicing TLB misses and its directives applied between accesses.
+fn simulate_per_access(
+    options: &Options,
+    accesses: &[Access],
+    directives: &[(u64, TraceDirective)],
+    demand_paging: bool,
+    pm: &mut PhysicalMemory,
+    disk: &mut Disk,
+    ffl: &mut FreeFrameList,
+) -> Result<Recorder, String> {
+    let mut pager = Pager::new(options, accesses, demand_paging, pm, ffl)?;
+    let mut recorder = Recorder::new(options, accesses.len())?;
+    let mut tlb: Option<Box<dyn TlbCache>> = match (options.tlb_entries, options.tlb_ways) {
+        (Some(entries), Some(ways)) => {
+            let hash = options.tlb_hash.unwrap_or(IndexHash::LowBits);
+            Some(Box::new(SetAssociativeTlb::new(entries / ways, ways, hash)?))
+        }
+        (Some(entries), None) => Some(Box::new(Tlb::new(entries, options.tlb_eviction))),
+        (None, _) => None,
+    };
+    let mut pending_directives = directives.iter().peekable();
+    for access in accesses {
+        while let Some((_, directive)) = pending_directives.next_if(|(at, _)| *at as usize == recorder.results.len()) {
+            pager.apply_directive(directive, pm, disk, ffl, tlb.as_mut())?;
+        }
+        let va = VirtualAddress::decompose(pm.layout(), access.va);
+        let before = pager.replacement_counts();
+        let outcome = handle_access(access, &va, pm, disk, ffl, tlb.as_deref_mut(), &mut pager)?;
+        let after = pager.replacement_counts();
+        recorder.record(access, &va, &outcome, (after.0 - before.0, after.1 - before.1), pm)?;
+    }
+    for (_, directive) in pending_directives {
+        pager.apply_directive(directive, pm, disk, ffl, tlb.as_mut())?;
+    }
+    report_per_access(options, &mut recorder, &pager, tlb.as_deref(), pm)?;
+    Ok(recorder)
+}
+
+// Services TLB misses under the run's options: replacement (alone or under
+// per-process quotas), residency bounds, an inverted table, or plain demand
+// paging, with prefetching after each access.
+struct Pager {
+    demand_paging: bool,
+    check_purity: bool,
+    replacer: Option<Replacer>,
+    allocator: Option<ProcessAllocator>,
+    residency: ResidencyPolicy,
+    prefetcher: Option<Prefetcher>,
+    ipt: Option<InvertedPageTable>,
+}
+
+impl Pager {
+    fn new(options: &Options, accesses: &[Access], demand_paging: bool, pm: &PhysicalMemory, ffl: &FreeFrameList) -> Result<Self, String> {
+        let mut residency = ResidencyPolicy::new();
+        for &(s, min, max) in &options.residency {
+            residency.set_bounds(s, min, max)?;
+        }
+        residency.count_resident(pm);
+        let mut replacer = match &options.replacement {
+            Some(name) => {
+                let vas: Vec<u32> = accesses.iter().map(|a| a.va).collect();
+                Some(Replacer::new(parse_policy(name, &vas, pm.layout())?))
+            }
+            None => None,
+        };
+        if let (Some(replacer), Some(frames)) = (replacer.as_mut(), options.free_target) {
+            replacer.set_free_target(frames);
+        }
+        if let (Some(replacer), Some(tiers)) = (replacer.as_mut(), options.swap_tiers) {
+            replacer.set_tiers(tiers);
+        }
+        if let (Some(replacer), Some(frames)) = (replacer.as_mut(), options.compress_pool) {
+            replacer.set_pool(CompressedPool::new(frames * pm.layout().page_size()));
+        }
+        let mut allocator = options.allocation.map(|policy| ProcessAllocator::new(policy, ffl.free_count()));
+        if let Some(allocator) = allocator.as_mut() {
+            allocator.add_process(0, pm.st_frame(), virtual_pages(pm), replacer.take().unwrap());
+        }
+        Ok(Pager {
+            demand_paging,
+            check_purity: options.check_purity,
+            replacer,
+            allocator,
+            residency,
+            prefetcher: options.prefetch.map(Prefetcher::new),
+            ipt: (options.table == TableKind::Inverted).then(|| InvertedPageTable::from_memory(pm, 0)),
+        })
+    }
+
+    // The run's replacer, or process 0's under --allocation.
+    fn replacer(&self) -> Option<&Replacer> {
+        self.replacer.as_ref().or_else(|| self.allocator.as_ref().and_then(|allocator| allocator.replacer(0)))
+    }
+
+    fn replacer_mut(&mut self) -> Option<&mut Replacer> {
+        self.replacer.as_mut().or_else(|| self.allocator.as_mut().and_then(|allocator| allocator.replacer_mut(0)))
+    }
+
+    // Frames evicted and blocks written so far, for each access's replacement events.
+    fn replacement_counts(&self) -> (u64, u64) {
+        self.replacer().map_or((0, 0), |r| (r.evictions + r.pt_evictions, r.writebacks + r.pt_evictions))
+    }
+
+    // Applies a trace directive to the run's memory and drops whatever the TLB
+    // and the replacer still hold for the pages it unmaps.
+    fn apply_directive(
+        &mut self,
+        directive: &TraceDirective,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        ffl: &mut FreeFrameList,
+        tlb: Option<&mut Box<dyn TlbCache>>,
+    ) -> Result<(), String> {
+        let (s, freed) = match *directive {
+            TraceDirective::Resize { s, size } => (s, resize_segment(pm, disk, ffl, s, size)?),
+            TraceDirective::FreeSegment { s } => (s, free_segment(pm, disk, ffl, s)?),
+        };
+        if let Some(tlb) = tlb {
+            tlb.invalidate_segment(s);
+        }
+        if let Some(replacer) = self.replacer_mut() {
+            for page in freed.pages {
+                replacer.forget(page);
+            }
+            replacer.release_blocks(&freed.blocks);
+            if let TraceDirective::FreeSegment { s } = directive {
+                replacer.forget_page_table(*s);
+            }
+        }
+        if !self.residency.is_empty() {
+            self.residency.count_resident(pm);
+        }
+        Ok(())
+    }
+}
+
+impl Walker for Pager {
+    fn walk(
+        &mut self,
+        va: &VirtualAddress,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        ffl: &mut FreeFrameList,
+    ) -> Result<(TranslationResult, FaultFlags), String> {
+        let demand_paging = self.demand_paging;
+        Ok(if let Some(ipt) = self.ipt.as_mut() {
+            translate_inverted(va, pm, disk, ffl, ipt, 0)
+        } else if let (true, Some(allocator)) = (demand_paging, self.allocator.as_mut()) {
+            allocator.translate(0, va, pm, disk, ffl)?
+        } else if let (true, Some(replacer)) = (demand_paging, self.replacer.as_mut()) {
+            replacer.translate(va, pm, disk, ffl)
+        } else if demand_paging && !self.residency.is_empty() {
+            translate_with_residency(va, pm, disk, ffl, &mut self.residency)
+        } else if demand_paging && self.check_purity {
+            translate_with_demand_paging_checked(va, pm, disk, ffl)?
+        } else if demand_paging {
+            translate_with_demand_paging_faults(va, pm, disk, ffl)
+        } else if self.check_purity {
+            (translate_pure(va, pm)?, FaultFlags::default())
+        } else {
+            (translate(va, pm, None), FaultFlags::default())
+        })
+    }
+
+    fn take_evicted(&mut self) -> Vec<PageId> {
+        match (self.replacer.as_mut(), self.allocator.as_mut()) {
+            (Some(replacer), _) => replacer.take_evicted(),
+            (None, Some(allocator)) => allocator.take_evicted(0),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    fn on_hit(&mut self, va: &VirtualAddress) {
+        if let Some(replacer) = self.replacer_mut() {
+            replacer.record_hit(va);
+        }
+    }
+
+    fn after_translate(
+        &mut self,
+        va: &VirtualAddress,
+        result: &TranslationResult,
+        faults: FaultFlags,
+        pm: &mut PhysicalMemory,
+        disk: &Disk,
+        ffl: &mut FreeFrameList,
+    ) {
+        if let Some(prefetcher) = self.prefetcher.as_mut() {
+            prefetcher.after_access(va, result, faults, pm, disk, ffl);
+        }
+    }
+
+    fn after_copy(&mut self, va: &VirtualAddress, shared_frame: u32, private_frame: u32) {
+        if let Some(ipt) = self.ipt.as_mut() {
+            ipt.remove(shared_frame);
+            ipt.insert(private_frame, 0, va.s, va.p);
+        }
+    }
+}
+
+// What a per-access run observes: each access's events, trace line,
+// provenance and latency, and the trackers summarised once the trace has run.
+struct Recorder {
+    results: Vec<TranslationResult>,
+    stats: TranslationStats,
+    latencies: Vec<u64>,
+    tlb_hits: Vec<bool>,
+    provenance: Vec<Provenance>,
+    model: CostModel,
+    verbose: bool,
+    clear_refs: Option<usize>,
+    capture_provenance: bool,
+    recording: bool,
+    events: EventLog,
+    trace: Option<TraceWriter<BufWriter<File>>>,
+    idle: IdlePageTracker,
+    first_touch: FirstTouchMap,
+    heatmap: AccessHeatmap,
+    working_set: Option<WorkingSetTracker>,
+    thrashing: Option<ThrashingDetector>,
+    progress: Option<ProgressTracker>,
+    histogram: LatencyHistogram,
+    breakdown: LatencyBreakdown,
+    loads: u64,
+    stores: u64,
+    reference_clears: u64,
+}
+
+impl Recorder {
+    fn new(options: &Options, accesses: usize) -> Result<Self, String> {
+        let mut trace = match &options.trace {
+            Some(path) => Some(TraceWriter::create(path)?),
+            None => None,
+        };
+        if let Some(trace) = trace.as_mut() {
+            trace.set_radix(options.radix);
+        }
+        Ok(Recorder {
+            results: Vec::with_capacity(accesses),
+            stats: TranslationStats::new(),
+            latencies: Vec::new(),
+            tlb_hits: Vec::new(),
+            provenance: Vec::new(),
+            model: options.cost,
+            verbose: options.verbose,
+            clear_refs: options.clear_refs,
+            capture_provenance: options.provenance.is_some(),
+            recording: options.record.is_some() || options.replay.is_some(),
+            events: EventLog::new(),
+            trace,
+            idle: IdlePageTracker::new(),
+            first_touch: FirstTouchMap::new(),
+            heatmap: AccessHeatmap::new(),
+            working_set: options.working_set.map(WorkingSetTracker::new),
+            thrashing: options.thrashing.map(|(window, rate)| ThrashingDetector::new(window, rate)),
+            progress: options.progress.map(|interval| ProgressTracker::new(accesses, interval)),
+            histogram: LatencyHistogram::new(),
+            breakdown: LatencyBreakdown::default(),
+            loads: 0,
+            stores: 0,
+            reference_clears: 0,
+        })
+    }
+
+    // Emits the access's events, writes its trace and provenance, performs its
+    // data operation and feeds the trackers. replaced is the frames evicted and
+    // blocks written while handling it.
+    fn record(
+        &mut self,
+        access: &Access,
+        va: &VirtualAddress,
+        outcome: &AccessOutcome,
+        replaced: (u64, u64),
+        pm: &mut PhysicalMemory,
+    ) -> Result<(), String> {
+        let AccessOutcome { result, faults, tlb: tlb_outcome, page_frame } = *outcome;
+        let index = self.results.len() as u32;
+        let (stats, heatmap, events) = (&mut self.stats, &mut self.heatmap, &mut self.events);
+        let (recording, verbose) = (self.recording, self.verbose);
+        let mut observe = |event: VmEvent| {
+            stats.on_event(&event);
+            heatmap.on_event(&event);
+            if recording {
+                events.on_event(&event);
+            }
+            if verbose {
+                eprintln!("{}", event);
+            }
+        };
+        match tlb_outcome {
+            TlbOutcome::Hit => observe(VmEvent::TlbHit { access: index, va: access.va }),
+            TlbOutcome::Miss => observe(VmEvent::TlbMiss { access: index, va: access.va }),
+            TlbOutcome::NotPresent => {}
+        }
+        VmEvent::for_translation(index, va, &result, faults, page_frame, pm, &mut observe);
+        VmEvent::for_replacement(index, replaced.0 as u32, replaced.1 as u32, &mut observe);
+        observe(VmEvent::Translated { access: index, va: access.va, result, faults });
+        if self.capture_provenance {
+            self.provenance.push(Provenance::capture(va, pm, faults, tlb_outcome == TlbOutcome::Hit));
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            trace.write(access.va, &result, faults, pm)?;
+        }
+        record_access(access, &result, pm);
+        if self.clear_refs.is_some_and(|every| (self.results.len() + 1).is_multiple_of(every)) {
+            pm.clear_reference_bits();
+            self.reference_clears += 1;
+        }
+        let access_breakdown = self.model.breakdown(&result, faults, tlb_outcome);
+        let cycles = access_breakdown.total();
+        self.breakdown += access_breakdown;
+        self.idle.record(va, &result, faults);
+        self.first_touch.record(va, &result, faults);
+        if let Some(working_set) = self.working_set.as_mut() {
+            working_set.record(0, va, &result);
+        }
+        if let Some(event) = self.thrashing.as_mut().and_then(|detector| detector.record(faults)) {
+            report_thrashing(&event);
+        }
+        if let Some(report) = self.progress.as_mut().and_then(|progress| progress.record(faults)) {
+            eprintln!("Progress: {}", report);
+        }
+        self.histogram.record(cycles);
+        self.latencies.push(cycles);
+        self.tlb_hits.push(tlb_outcome == TlbOutcome::Hit);
+        if let TranslationResult::Success(_) = result {
+            match access.data {
+                DataOp::Load => self.loads += 1,
+                DataOp::Store(_) => self.stores += 1,
+                DataOp::None => {}
+            }
+        }
+        self.results.push(result);
+        Ok(())
+    }
+}
+
+// The summaries a per-access run prints once the trace has run, and the
+// files it saves: the event log, the heatmap and the trace's last lines.
+fn report_per_access(
+    options: &Options,
+    recorder: &mut Recorder,
+    pager: &Pager,
+    tlb: Option<&dyn TlbCache>,
+    pm: &PhysicalMemory,
+) -> Result<(), String> {
+    if let Some(trace) = recorder.trace.as_mut() {
+        trace.finish()?;
+    }
+    if let Some(progress) = recorder.progress.as_mut() {
+        eprintln!("Progress: {}", progress.finish());
+    }
+    recorder.stats.simulated_cycles = recorder.breakdown.total();
+    if let Some(tlb) = tlb {
+        report_tlb(tlb);
+    }
+    if let Some(prefetcher) = &pager.prefetcher {
+        recorder.stats.record_prefetches(prefetcher.issued, prefetcher.used);
+        eprintln!(
+            "Prefetch (depth {}): {} pages prefetched, {} used, {} wasted (accuracy {:.1}%)",
+            prefetcher.depth(),
+            prefetcher.issued,
+            prefetcher.used,
+            prefetcher.wasted(),
+            prefetcher.accuracy() * 100.0
+        );
+    }
+    if let Some(path) = &options.record {
+        recorder.events.save(path)?;
+    }
+    if let Some(path) = &options.replay {
+        let recorded = EventLog::load(path)?;
+        if let Some((index, expected, actual)) = recorded.first_divergence(&recorder.events) {
+            let describe = |event: Option<Event>| event.map_or_else(|| "end of log".to_string(), |event| event.to_string());
+            return Err(format!(
+                "Replay diverged at event {}: recorded {}, got {}",
+                index,
+                describe(expected),
+                describe(actual)
+            ));
+        }
+        eprintln!("Replay: {} events match", recorder.events.events().len());
+    }
+    if let Some(every) = options.clear_refs {
+        eprintln!(
+            "Reference bits: cleared every {} accesses ({} clears), {} frames referenced since the last clear",
+            every,
+            recorder.reference_clears,
+            pm.referenced_frames().len()
+        );
+    }
+    if recorder.loads + recorder.stores > 0 {
+        eprintln!("Data movement: {} loads, {} stores", recorder.loads, recorder.stores);
+    }
+    if let Some(ipt) = &pager.ipt {
+        eprintln!(
+            "Inverted page table: {} lookups, {} misses, {:.2} probes per lookup, longest chain {}",
+            ipt.lookups,
+            ipt.misses,
+            ipt.probes_per_lookup(),
+            ipt.longest_chain()
+        );
+    }
+    if options.latency_histogram {
+        print_latency_histogram(&recorder.histogram);
+    }
+    if options.latency_breakdown {
+        print_latency_breakdown(&recorder.breakdown);
+    }
+    if let Some(window) = options.idle_window {
+        print_idle_report(&recorder.idle.report(window), window);
+    }
+    if let Some(window) = options.first_touch {
+        print_first_touch(&recorder.first_touch, window);
+    }
+    if options.verbose {
+        print_heatmap_summary(&recorder.heatmap);
+    }
+    if let Some(path) = &options.heatmap {
+        fs::write(path, recorder.heatmap.to_csv()).map_err(|e| format!("Failed to write heatmap: {}", e))?;
+    }
+    if let Some(working_set) = &recorder.working_set {
+        print_working_set(working_set);
+    }
+    if let Some(detector) = recorder.thrashing.as_mut() {
+        if let Some(event) = detector.finish() {
+            report_thrashing(&event);
+        }
+        print_thrashing_summary(detector);
+    }
+    if let Some(allocator) = &pager.allocator {
+        for pid in allocator.pids() {
+            eprintln!(
+                "Allocation ({}): pid {} holds {} of a {}-frame quota",
+                allocator.policy().name(),
+                pid,
+                allocator.held(pid),
+                allocator.quota(pid).unwrap_or(0)
+            );
+        }
+    }
+    if let Some(replacer) = pager.replacer() {
+        report_replacement(replacer);
+    }
+    if !pager.residency.is_empty() {
+        eprintln!(
+            "Residency: {} faults denied at segment maximum, {} denied by minimum reservations",
+            pager.residency.denied_by_max, pager.residency.denied_by_reserve
+        );
+    }
+    Ok(())
+}
+
+fn report_tlb(tlb: &dyn TlbCache) {
+    eprintln!(
+        "TLB: {} hits, {} misses (hit rate {:.1}%)",
+        tlb.hits(),
+        tlb.misses(),
+        tlb.hit_rate() * 100.0
+    );
+    if tlb.invalidations() > 0 {
+        eprintln!("TLB invalidations: {}", tlb.invalidations());
+    }
+    let reach = tlb.reach();
+    if reach.huge_entries > 0 {
+        eprintln!(
+            "TLB reach: {} words in {} entries ({} huge); {} words as base pages, {} for a full TLB of base pages",
+            reach.words,
+            reach.entries,
+            reach.huge_entries,
+            reach.base_page_words(),
+            reach.full_base_page_words()
+        );
+    }
+    if let Some(classes) = tlb.miss_classes() {
+        eprintln!(
+            "TLB misses: {} compulsory, {} capacity, {} conflict",
+            classes.compulsory, classes.capacity, classes.conflict
+        );
+    }
+}
+
+fn report_replacement(replacer: &Replacer) {
+    eprintln!(
+        "Replacement ({}): {} page evictions, {} PT evictions, {} write-backs, {} soft faults",
+        replacer.policy_name(),
+        replacer.evictions,
+        replacer.pt_evictions,
+        replacer.writebacks,
+        replacer.soft_faults
+    );
+    let classes = replacer.classes();
+    if classes.samples > 0 {
+        eprintln!(
+            "NRU classes (R/D) at eviction: mean resident {:.1} / {:.1} / {:.1} / {:.1}, evicted {} / {} / {} / {}",
+            classes.mean_occupancy(0),
+            classes.mean_occupancy(1),
+            classes.mean_occupancy(2),
+            classes.mean_occupancy(3),
+            classes.evicted[0],
+            classes.evicted[1],
+            classes.evicted[2],
+            classes.evicted[3]
+        );
+    }
+    if let Some(swap) = replacer.swap() {
+        eprintln!("Swap: {}", swap.stats());
+    }
+    if let Some(tiers) = replacer.tiers() {
+        eprintln!(
+            "Swap tiers ({} fast blocks, hot at {} uses): {}",
+            tiers.fast_blocks,
+            tiers.hot_threshold,
+            replacer.tier_stats()
+        );
+    }
+    if let Some(pool) = replacer.pool() {
+        eprintln!("Compressed pool ({} of {} words in use): {}", pool.used(), pool.budget(), pool.stats());
+    }
+}
+
+// Three-level layouts have their own init format and walker and always demand
+// page; per-access options are rejected when the options are parsed.
+fn run_three_level(options: &Options, layout: AddressLayout) -> Result<(), String> {
+    let init_data = ThreeLevelInitData::from_file(&options.init_file, &layout)?;
+    let mut pm = PhysicalMemory::with_layout(layout);
+    pm.set_pte_format(options.pte_format);
+    let mut disk = Disk::new();
+    let mut ffl = free_frame_list(options);
+    init_data.apply_to(&mut pm, &mut disk, &mut ffl);
+    check_frame_budget(options, &pm)?;
+
+    let demand_paging = init_data.st_entries.iter().any(|&(_, _, f)| f < 0)
+        || init_data.pd_entries.iter().any(|&(_, _, f)| f < 0)
+        || init_data.pt_entries.iter().any(|&(_, _, _, f)| f < 0);
+    let manifest = match options.stats || options.stats_json.is_some() {
+        true => build_manifest(options, &layout, demand_paging, false, ffl.free_count())?,
+        false => Manifest::default(),
+    };
+    let runs = read_input(&options.input_file)?.into_runs()?;
+    let accesses: Vec<_> = expand_runs(&runs).collect();
+    let vas: Vec<u32> = accesses.iter().map(|a| a.va).collect();
+    let mut stats = TranslationStats::new();
+    let results: Vec<TranslationResult> = accesses
+        .iter()
+        .map(|access| {
+            let (result, faults) = translate_three_level_access(access, &mut pm, &disk, &mut ffl);
+            stats.record(&result, faults);
+            result
+        })
+        .collect();
+
+    report_stats(options, &stats, &manifest)?;
+    write_output(options, &vas, &results, &[], &[])
+}
+
+fn free_frame_list(options: &Options) -> FreeFrameList {
+    match options.frames {
+        Some(frames) => FreeFrameList::with_capacity(frames).into_kind(options.free_list),
+        None => FreeFrameList::with_kind(options.free_list),
+    }
+}
+
+// Frames past the budget do not exist, so the init file may not place anything there.
+fn check_frame_budget(options: &Options, pm: &PhysicalMemory) -> Result<(), String> {
+    let Some(frames) = options.frames else {
+        return Ok(());
+    };
+    match (frames as u32..NUM_FRAMES as u32).find(|&frame| pm.who_owns(frame) != FrameOwner::Free) {
+        Some(frame) => Err(format!("Init file uses frame {} but --frames limits memory to {} frames", frame, frames)),
+        None => Ok(()),
+    }
+}
+
+fn report_stats(options: &Options, stats: &TranslationStats, manifest: &Manifest) -> Result<(), String> {
+    if options.stats {
+        eprintln!("{}", stats);
+        eprintln!("{}", manifest);
+    }
+    if let Some(path) = &options.stats_json {
+        let mut json = stats.to_json();
+        json.pop();
+        json += &format!(", \"manifest\": {}}}\n", manifest.to_json());
+        fs::write(path, json).map_err(|e| format!("Failed to write stats file: {}", e))?;
+    }
+    Ok(())
+}
+
+// "-" reads the trace from stdin.
+fn read_input(path: &str) -> Result<Trace, String> {
+    match path {
+        STDIO_PATH => read_trace_from(io::stdin().lock()),
+        path => read_trace(path),
+    }
+}
+
+fn write_output(
+    options: &Options,
+    vas: &[u32],
+    results: &[TranslationResult],
+    latencies: &[u64],
+    tlb_hits: &[bool],
+) -> Result<(), String> {
+    let mut sink: Box<dyn ResultSink> = match (options.sync_every, options.resume) {
+        (Some(every), true) => Box::new(SyncedFileSink::resume(&options.output_file, every)?),
+        (Some(every), false) => Box::new(SyncedFileSink::create(&options.output_file, every)?),
+        (None, true) => Box::new(SyncedFileSink::resume(&options.output_file, DEFAULT_SYNC_INTERVAL)?),
+        (None, false) if options.output_file == STDIO_PATH => options.format.stdout_sink(),
+        (None, false) => options.format.create_sink(&options.output_file)?,
+    };
+    sink.set_radix(options.radix);
+    sink.set_error_codes(options.error_codes);
+    for (index, (&va, &result)) in vas.iter().zip(results).enumerate() {
+        let cycles = if options.latency { latencies.get(index).copied() } else { None };
+        let tlb_hit = if options.tlb_output { tlb_hits.get(index).copied() } else { None };
+        sink.write_result(&ResultRecord { index, va, result, cycles, tlb_hit })?;
+    }
+    sink.finish()
+}
+
+fn build_manifest(
+    options: &Options,
+    layout: &AddressLayout,
+    demand_paging: bool,
+    resumed: bool,
+    free_frames: usize,
+) -> Result<Manifest, String> {
+    let mut manifest = Manifest::new();
+    manifest.set_layout(layout);
+    match (&options.checkpoint, resumed) {
+        (Some(path), true) => manifest.set_file("checkpoint", path)?,
+        _ => manifest.set_file("init_file", &options.init_file)?,
+    }
+    match options.input_file.as_str() {
+        STDIO_PATH => manifest.set("input_file", STDIO_PATH),
+        path => manifest.set_file("input_file", path)?,
+    }
+    manifest.set("free_frames", free_frames);
+    manifest.set("mode", if demand_paging { "demand paging" } else { "resident" });
+    manifest.set("per_access", options.needs_per_access());
+    let compact_pt = if options.compact_pt && !resumed { DEFAULT_PT_SLOT_SIZE.to_string() } else { "off".to_string() };
+    manifest.set("compact_pt", compact_pt);
+    let tlb = match (options.tlb_entries, options.tlb_ways) {
+        (Some(entries), Some(ways)) => {
+            format!("{} {}-way {}", entries, ways, options.tlb_hash.unwrap_or(IndexHash::LowBits).name())
+        }
+        (Some(entries), None) => format!("{} {:?}", entries, options.tlb_eviction).to_lowercase(),
+        (None, _) => "off".to_string(),
+    };
+    manifest.set("tlb", tlb);
+    manifest.set("free_list", format!("{:?}", options.free_list).to_lowercase());
+    if options.pte_format == PteFormat::Legacy {
+        manifest.set("pte_format", options.pte_format.name());
+    }
+    if options.error_codes == ErrorCodes::Detailed {
+        manifest.set("error_codes", options.error_codes.name());
+    }
+    manifest.set("frames", options.frames.unwrap_or(NUM_FRAMES));
+    manifest.set("table", options.table.name());
+    manifest.set("replacement", options.replacement.as_deref().unwrap_or("off"));
+    if let Some(tiers) = options.swap_tiers {
+        manifest.set("swap_tiers", format!("{}:{}", tiers.fast_blocks, tiers.hot_threshold));
+    }
+    if let Some(frames) = options.compress_pool {
+        manifest.set("compress_pool", frames);
+    }
+    if let Some(frames) = options.free_target {
+        manifest.set("free_target", frames);
+    }
+    if let Some(depth) = options.prefetch {
+        manifest.set("prefetch", depth);
+    }
+    if let Some(every) = options.clear_refs {
+        manifest.set("clear_refs", every);
+    }
+    if let Some(policy) = options.allocation {
+        manifest.set("allocation", policy.name());
+    }
+    let residency: Vec<String> = options.residency.iter().map(|(s, min, max)| format!("{}:{}:{}", s, min, max)).collect();
+    manifest.set("residency", if residency.is_empty() { "off".to_string() } else { residency.join(",") });
+    manifest.set_cost_model(&options.cost);
+    manifest.set("format", format!("{:?}", options.format).to_lowercase());
+    Ok(manifest)
+}
+
+fn print_latency_histogram(histogram: &LatencyHistogram) {
+    eprintln!(
+        "Latency: mean EAT {:.2} cycles, p50 {}, p95 {}, p99 {} over {} accesses",
+        histogram.mean(),
+        histogram.percentile(50.0),
+        histogram.percentile(95.0),
+        histogram.percentile(99.0),
+        histogram.count()
+    );
+    for (cycles, count) in histogram.buckets() {
+        eprintln!("  {:>10} cycles: {}", cycles, count);
+    }
+}
+
+fn print_latency_breakdown(breakdown: &LatencyBreakdown) {
+    let total = breakdown.total();
+    let share = |cycles: u64| if total == 0 { 0.0 } else { cycles as f64 * 100.0 / total as f64 };
+    eprintln!("Latency breakdown: {} cycles total", total);
+    for (label, cycles) in [
+        ("TLB lookups", breakdown.tlb),
+        ("table walks", breakdown.table_walk),
+        ("fault servicing", breakdown.fault_service),
+        ("disk transfers", breakdown.disk),
+        ("data accesses", breakdown.data),
+    ] {
+        eprintln!("  {:<16} {:>12} cycles ({:.1}%)", label, cycles, share(cycles));
+    }
+}
+
+fn print_idle_report(report: &IdlePageReport, window: usize) {
+    eprintln!(
+        "Idle pages: {} faulted in, {} never touched again, {} unused in the final {} accesses",
+        report.faulted_pages,
+        report.one_touch.len(),
+        report.idle_in_window.len(),
+        window
+    );
+    for page in &report.one_touch {
+        eprintln!("  one-touch ({}, {}) faulted at access {}", page.s, page.p, page.faulted_at);
+    }
+    for page in &report.idle_in_window {
+        eprintln!("  idle ({}, {}) last used at access {}", page.s, page.p, page.last_access);
+    }
+}
+
+fn print_first_touch(map: &FirstTouchMap, window: usize) {
+    let entries = map.entries();
+    eprintln!("First touch: {} pages over {} accesses", entries.len(), map.accesses());
+    for (i, count) in map.per_window(window).iter().enumerate() {
+        eprintln!("  accesses {}..{}: {} new pages", i * window, ((i + 1) * window).min(map.accesses()), count);
+    }
+    for touch in &entries {
+        let how = if touch.faulted { "fault" } else { "resident" };
+        eprintln!("  ({}, {}) first touched at access {} ({})", touch.s, touch.p, touch.access, how);
+    }
+}
+
+fn print_heatmap_summary(heatmap: &AccessHeatmap) {
+    for segment in heatmap.segments() {
+        eprintln!(
+            "Segment {}: {} accesses, {} faults over {} pages",
+            segment.s, segment.accesses, segment.faults, segment.pages
+        );
+    }
+    eprintln!("Hottest pages:");
+    for page in heatmap.hottest(HOTTEST_PAGES) {
+        eprintln!("  ({}, {}): {} accesses, {} faults", page.s, page.p, page.accesses, page.faults);
+    }
+}
+
+fn print_working_set(tracker: &WorkingSetTracker) {
+    eprintln!(
+        "Working set (delta {}): {} faults under working-set allocation over {} accesses",
+        tracker.delta(),
+        tracker.faults(),
+        tracker.accesses()
+    );
+    for summary in tracker.summaries() {
+        eprintln!(
+            "  pid {}: {} references, mean size {:.2}, peak {}, {} faults",
+            summary.pid, summary.references, summary.mean, summary.peak, summary.faults
+        );
+    }
+    for window in tracker.windows() {
+        eprintln!("  accesses {}..{}: size {}, {} faults", window.start, window.end, window.size, window.faults);
+    }
+}
+
+fn report_thrashing(event: &ThrashingEvent) {
+    match event {
+        ThrashingEvent::Started { access, rate } => {
+            eprintln!("Warning: thrashing at access {}: page-fault rate {:.1}%", access, rate * 100.0)
+        }
+        ThrashingEvent::Ended(interval) => eprintln!(
+            "Warning: thrashing ended at access {}: {} page faults in accesses {}..{} (peak rate {:.1}%)",
+            interval.end,
+            interval.faults,
+            interval.start,
+            interval.end,
+            interval.peak_rate * 100.0
+        ),
+    }
+}
+
+fn print_thrashing_summary(detector: &ThrashingDetector) {
+    eprintln!(
+        "Thrashing (window {}, threshold {:.1}%): {} intervals covering {} accesses",
+        detector.window(),
+        detector.threshold() * 100.0,
+        detector.intervals().len(),
+        detector.thrashing_accesses()
+    );
+    for interval in detector.intervals() {
+        eprintln!(
+            "  accesses {}..{}: {} page faults, peak rate {:.1}%",
+            interval.start,
+            interval.end,
+            interval.faults,
+            interval.peak_rate * 100.0
+        );
+    }
+}
+
+fn save_final_state(options: &Options, pm: &PhysicalMemory, disk: &Disk, ffl: &FreeFrameList) -> Result<(), String> {
+    if let Some(path) = &options.save_snapshot {
+        save_snapshot(path, pm, disk)?;
+    }
+    if let Some(path) = &options.checkpoint {
+        save_checkpoint(path, pm, disk, ffl)?;
+    }
+    Ok(())
+}
+
+fn snapshot_to_init(snapshot_file: &str, init_file: &str) -> Result<(), String> {
+    let (pm, disk) = load_snapshot(snapshot_file)?;
+    InitData::from_memory(&pm, &disk).write_file(init_file)
+}
+
+// Runs one trace under two option sets and reports where the results part
+// ways, then how the stats differ. The init file is loaded once and cloned
+// when both sides would load it the same way.
+fn diff(args: &[String]) -> Result<(), String> {
+    let usage = || "diff expects <init_file> <input_file> [options] --vs [options]".to_string();
+    let [init_file, input_file, rest @ ..] = args else {
+        return Err(usage());
+    };
+    let split = rest.iter().position(|arg| arg == "--vs").ok_or_else(usage)?;
+    let a = diff_options(&rest[..split], init_file, input_file)?;
+    let b = diff_options(&rest[split + 1..], init_file, input_file)?;
+
+    let trace = read_input(input_file)?;
+    let state = load_state(&a)?;
+    let state_b = match same_init(&a, &b) {
+        true => state.clone(),
+        false => load_state(&b)?,
+    };
+    let run_a = simulate(&a, state, &trace)?;
+    let run_b = simulate(&b, state_b, &trace)?;
+
+    let mut divergences = 0;
+    for (index, (result_a, result_b)) in run_a.results.iter().zip(&run_b.results).enumerate() {
+        let (pa_a, pa_b) = (result_a.to_output_with(a.error_codes), result_b.to_output_with(b.error_codes));
+        if pa_a != pa_b {
+            divergences += 1;
+            println!("Access {} (va {}): A {}, B {}", index, run_a.vas[index], pa_a, pa_b);
+        }
+    }
+    println!("{} of {} accesses diverge", divergences, run_a.results.len());
+
+    let tlb_hits = |run: &Simulation| run.tlb_hits.iter().filter(|&&hit| hit).count() as u64;
+    let mut rows: Vec<(&str, u64, u64)> = run_a
+        .stats
+        .rows()
+        .iter()
+        .zip(run_b.stats.rows())
+        .map(|(&(label, value_a), (_, value_b))| (label, value_a, value_b))
+        .collect();
+    rows.push(("TLB hits:", tlb_hits(&run_a), tlb_hits(&run_b)));
+    rows.push(("Simulated cycles:", run_a.stats.simulated_cycles, run_b.stats.simulated_cycles));
+    println!("{:<26}{:>12}{:>12}{:>12}", "", "A", "B", "B - A");
+    for (label, value_a, value_b) in rows {
+        println!("{:<26}{:>12}{:>12}{:>+12}", label, value_a, value_b, value_b as i64 - value_a as i64);
+    }
+    Ok(())
+}
+
+// One side of a diff: its options plus the shared files. Results are never
+// written, and options that persist state between runs are refused.
+fn diff_options(args: &[String], init_file: &str, input_file: &str) -> Result<Options, String> {
+    let args: Vec<String> = args.iter().cloned().chain([init_file, input_file, STDIO_PATH].map(String::from)).collect();
+    let options = Options::parse(&args)?;
+    let unsupported = [
+        ("--checkpoint", options.checkpoint.is_some()),
+        ("--save-snapshot", options.save_snapshot.is_some()),
+        ("--sync-every", options.sync_every.is_some()),
+        ("--resume", options.resume),
+        ("--layout with three levels", options.layout.is_some_and(|layout| layout.is_three_level())),
+    ];
+    if let Some((option, _)) = unsupported.iter().find(|(_, used)| *used) {
+        return Err(format!("diff does not support {}", option));
+    }
+    Ok(options)
+}
+
+fn same_init(a: &Options, b: &Options) -> bool {
+    a.layout == b.layout
+        && a.strict == b.strict
+        && a.duplicates == b.duplicates
+        && a.pte_format == b.pte_format
+        && a.free_list == b.free_list
+        && a.frames == b.frames
+        && a.validate == b.validate
+        && a.compact_pt == b.compact_pt
+}
+
+#[cfg(feature = "bench")]
+fn bench(args: &[String]) -> Result<(), String> {
+    use rust_virtual_memory::bench::{run_benchmarks, BenchConfig};
+    use rust_virtual_memory::generator::Distribution;
+
+    let mut config = BenchConfig::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--addresses" => config.addresses = parse_flag_value(&mut args, arg)?,
+            "--segments" => config.segments = parse_flag_value(&mut args, arg)?,
+            "--pages" => config.pages = parse_flag_value(&mut args, arg)?,
+            "--locality" => config.locality = Distribution::parse(&flag_value(&mut args, arg)?)?,
+            "--tlb" => config.tlb_entries = parse_flag_value(&mut args, arg)?,
+            "--seed" => config.seed = parse_flag_value(&mut args, arg)?,
+            _ => return Err(format!("Unknown bench option: {}", arg)),
+        }
+    }
+    for result in run_benchmarks(&config)? {
+        println!("{}", result);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "bench"))]
+fn bench(_args: &[String]) -> Result<(), String> {
+    Err("bench requires a build with --features bench".to_string())
+}
+
+fn generate(args: &[String]) -> Result<(), String> {
+    let mut config = WorkloadConfig::default();
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--segments" => config.segments = parse_flag_value(&mut args, arg)?,
+            "--pages" => config.pages = parse_flag_value(&mut args, arg)?,
+            "--resident" => config.resident = parse_flag_value(&mut args, arg)?,
+            "--length" => config.length = parse_flag_value(&mut args, arg)?,
+            "--locality" => config.locality = Locality::parse(&flag_value(&mut args, arg)?)?,
+            "--seed" => config.seed = parse_flag_value(&mut args, arg)?,
+            flag if flag.starts_with("--") => return Err(format!("Unknown gen option: {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let [init_file, input_file] = positional.as_slice() else {
+        return Err(format!("gen expects <init_file> <input_file>, got {} file arguments", positional.len()));
+    };
+    let init_data = config.init_data()?;
+    let vas = config.trace();
+    init_data.write_file(init_file)?;
+    match input_file.as_str() {
+        STDIO_PATH => write_trace_to(io::stdout().lock(), &vas)?,
+        path => write_trace(path, &vas)?,
+    }
+    let on_disk = init_data.pt_entries.iter().filter(|&&(_, _, f)| f < 0).count();
+    eprintln!(
+        "Generated {} segments, {} pages ({} on disk) and {} accesses",
+        init_data.st_entries.len(),
+        init_data.pt_entries.len(),
+        on_disk,
+        vas.len()
+    );
+    Ok(())
+}
+
+// Random operations against one engine with the invariants checked as it
+// goes; the first violation is minimized into a file `--replay` runs again.
+fn stress(args: &[String]) -> Result<(), String> {
+    let mut config = StressConfig::default();
+    let mut repro = "stress-repro.txt".to_string();
+    let mut replay_file = None;
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => config.steps = parse_flag_value(&mut args, arg)?,
+            "--check-every" => config.check_every = parse_flag_value(&mut args, arg)?,
+            "--seed" => config.seed = parse_flag_value(&mut args, arg)?,
+            "--tlb" => config.tlb_entries = parse_flag_value(&mut args, arg)?,
+            "--segments" => config.segments = parse_flag_value(&mut args, arg)?,
+            "--repro" => repro = flag_value(&mut args, arg)?,
+            "--replay" => replay_file = Some(flag_value(&mut args, arg)?),
+            flag if flag.starts_with("--") => return Err(format!("Unknown stress option: {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    if config.check_every == 0 || config.segments == 0 || config.segments as usize > MAX_SEGMENTS {
+        return Err(format!("stress needs --check-every of at least 1 and 1 to {} --segments", MAX_SEGMENTS));
+    }
+
+    if let Some(path) = replay_file {
+        let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read reproduction file: {}", e))?;
+        let (init_data, tlb_entries, ops) = parse_repro(&content)?;
+        return match replay(&init_data, tlb_entries, &ops) {
+            Some(violation) => {
+                for error in &violation.errors {
+                    eprintln!("Invariant violated: {}", error);
+                }
+                Err(format!("{} fails at operation {} ({})", path, violation.step, ops[violation.step]))
+            }
+            None => {
+                println!("No violation in {} operations", ops.len());
+                Ok(())
+            }
+        };
+    }
+
+    let init_data = match positional.as_slice() {
+        [] => WorkloadConfig { seed: config.seed, ..WorkloadConfig::default() }.init_data()?,
+        [init_file] => InitData::from_file(init_file)?,
+        _ => return Err(format!("stress expects at most one init file, got {}", positional.len())),
+    };
+    let (violation, ops) = match run_stress(&init_data, &config) {
+        Ok(report) => {
+            println!("{}", report);
+            return Ok(());
+        }
+        Err(failure) => failure,
+    };
+    eprintln!("Invariant violated after operation {} ({}):", violation.step, ops[violation.step]);
+    for error in &violation.errors {
+        eprintln!("  {}", error);
+    }
+    let minimized = minimize(&init_data, config.tlb_entries, &ops);
+    let violation = replay(&init_data, config.tlb_entries, &minimized).unwrap_or(violation);
+    fs::write(&repro, repro_to_string(&init_data, config.tlb_entries, &minimized, &violation))
+        .map_err(|e| format!("Failed to write reproduction file: {}", e))?;
+    Err(format!("invariant violated; {} of {} operations reproduce it, written to {}", minimized.len(), ops.len(), repro))
+}
+
+fn inspect(init_file: &str, json: bool) -> Result<(), String> {
     let init_data = InitData::from_file(init_file)?;
     let mut pm = PhysicalMemory::new();
     let mut disk = Disk::new();
-    let mut ffl = init_data.apply(&mut pm, &mut disk);
+    let ffl = init_data.apply(&mut pm, &mut disk);
+    let dump = MemoryDump::capture(&pm, &disk, &ffl);
+    match json {
+        true => println!("{}", dump.to_json()),
+        false => println!("{}", dump),
+    }
+    Ok(())
+}
 
-    let vas = read_virtual_addresses(input_file)?;
+fn inspect_map(init_file: &str) -> Result<(), String> {
+    let init_data = InitData::from_file(init_file)?;
+    let mut pm = PhysicalMemory::new();
+    let mut disk = Disk::new();
+    init_data.apply(&mut pm, &mut disk);
+    println!("{}", FrameMap::capture(&pm));
+    Ok(())
+}
 
-    let results = if needs_demand_paging(&init_data) {
-        translate_batch_with_demand_paging(&vas, &mut pm, &disk, &mut ffl)
-    } else {
-        translate_batch(&vas, &pm)
-    };
+fn inspect_dot(init_file: &str, dot_file: &str) -> Result<(), String> {
+    let init_data = InitData::from_file(init_file)?;
+    let mut pm = PhysicalMemory::new();
+    let mut disk = Disk::new();
+    let ffl = init_data.apply(&mut pm, &mut disk);
+    let dot = MemoryDump::capture(&pm, &disk, &ffl).to_dot();
+    fs::write(dot_file, dot + "\n").map_err(|e| format!("Failed to write DOT graph: {}", e))
+}
 
-    write_results(output_file, &results)?;
+// Every virtual address mapping pa in the init file's state.
+fn inspect_reverse(init_file: &str, pa: u32, json: bool) -> Result<(), String> {
+    let init_data = InitData::from_file(init_file)?;
+    let mut pm = PhysicalMemory::new();
+    let mut disk = Disk::new();
+    init_data.apply(&mut pm, &mut disk);
+    if pa as usize >= pm.layout().memory_size() {
+        return Err(format!("Physical address {} is out of range", pa));
+    }
+    let vas = reverse_lookup(pa, &pm);
+    if json {
+        let entries: Vec<String> = vas
+            .iter()
+            .map(|va| format!("{{\"va\": {}, \"s\": {}, \"p\": {}, \"w\": {}}}", va.to_raw(), va.s, va.p, va.w))
+            .collect();
+        println!("{{\"pa\": {}, \"vas\": [{}]}}", pa, entries.join(", "));
+        return Ok(());
+    }
+    let page_size = pm.layout().page_size() as u32;
+    println!(
+        "Physical address {} (frame {}, offset {}): {} mapping(s)",
+        pa,
+        pa / page_size,
+        pa % page_size,
+        vas.len()
+    );
+    for va in &vas {
+        println!("  {} (s {}, p {}, w {})", va.to_raw(), va.s, va.p, va.w);
+    }
     Ok(())
 }
+
+fn repl(init_file: &str) -> Result<(), String> {
+    let mut vm = VMManager::from_init_file(init_file)?;
+    let stdin = io::stdin();
+    let prompt = stdin.is_terminal();
+    run_repl(&mut vm, stdin.lock(), io::stdout(), prompt)
+}
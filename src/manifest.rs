@@ -0,0 +1,96 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::constants::*;
+use crate::cost::CostModel;
+use crate::layout::AddressLayout;
+use crate::memory::fnv_hash_bytes;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: Vec<(String, String)>,
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        let mut manifest = Manifest::default();
+        manifest.set("version", env!("CARGO_PKG_VERSION"));
+        manifest
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        let value = value.to_string();
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key.to_string(), value)),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    pub fn set_layout(&mut self, layout: &AddressLayout) {
+        self.set("layout", layout);
+        self.set("frames", NUM_FRAMES);
+        self.set("page_size", layout.page_size());
+        self.set("disk_blocks", DISK_BLOCKS);
+    }
+
+    pub fn set_cost_model(&mut self, model: &CostModel) {
+        let fields =
+            [model.tlb_lookup, model.memory_access, model.fault_overhead, model.disk_transfer, model.slow_disk_transfer];
+        self.set(
+            "cost_model",
+            format!(
+                "tlb={} memory={} fault={} disk={} slow-disk={}",
+                model.tlb_lookup, model.memory_access, model.fault_overhead, model.disk_transfer, model.slow_disk_transfer
+            ),
+        );
+        self.set("cost_model_hash", format!("{:016x}", fnv_hash_bytes(fields.iter().flat_map(|f| f.to_le_bytes()))));
+    }
+
+    pub fn set_file<P: AsRef<Path>>(&mut self, key: &str, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("Failed to hash {}: {}", path.display(), e))?;
+        self.set(key, path.display());
+        self.set(&format!("{}_hash", key), format!("{:016x}", fnv_hash_bytes(bytes)));
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let fields: Vec<String> =
+            self.entries.iter().map(|(k, v)| format!("{}: {}", json_string(k), json_string(v))).collect();
+        format!("{{{}}}", fields.join(", "))
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Manifest:")?;
+        for (key, value) in &self.entries {
+            write!(f, "\n  {:<22}{}", format!("{}:", key), value)?;
+        }
+        Ok(())
+    }
+}
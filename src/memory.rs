@@ -1,14 +1,336 @@
+use std::fmt;
+use std::fs;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
 use crate::constants::*;
+use crate::layout::AddressLayout;
+use crate::translation::Protection;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv_hash<I: IntoIterator<Item = i32>>(words: I) -> u64 {
+    fnv_hash_bytes(words.into_iter().flat_map(i32::to_le_bytes))
+}
+
+pub(crate) fn fnv_hash_bytes<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    let mut hash = FNV_OFFSET;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn read_raw_words(path: &Path, capacity: usize, what: &str) -> Result<Vec<i32>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read raw {} image: {}", what, e))?;
+    if !bytes.len().is_multiple_of(4) {
+        return Err(format!("Raw {} image is {} bytes, not a whole number of words", what, bytes.len()));
+    }
+    if bytes.len() / 4 > capacity {
+        return Err(format!("Raw {} image has {} words, exceeds {}", what, bytes.len() / 4, capacity));
+    }
+    Ok(bytes.chunks_exact(4).map(|word| i32::from_le_bytes(word.try_into().unwrap())).collect())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameOwner {
+    #[default]
+    Free,
+    SegmentTable,
+    PageTable(u32),
+    Page(u32, u32),
+}
+
+impl fmt::Display for FrameOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameOwner::Free => write!(f, "free"),
+            FrameOwner::SegmentTable => write!(f, "segment table"),
+            FrameOwner::PageTable(s) => write!(f, "PT of segment {}", s),
+            FrameOwner::Page(s, p) => write!(f, "page ({}, {})", s, p),
+        }
+    }
+}
+
+// Per-frame metadata kept alongside memory: what each frame holds and the value
+// of a global use counter at its last successful translation. A frame shared by
+// several compact PTs is owned by the lowest segment among them.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameTable {
+    owners: Vec<FrameOwner>,
+    last_used: Vec<u64>,
+    clock: u64,
+}
+
+impl FrameTable {
+    pub fn new() -> Self {
+        FrameTable { owners: vec![FrameOwner::Free; NUM_FRAMES], last_used: vec![0; NUM_FRAMES], clock: 0 }
+    }
+
+    pub fn set_owner(&mut self, frame: u32, owner: FrameOwner) {
+        self.owners[frame as usize] = owner;
+    }
+
+    pub fn assign(&mut self, frame: u32, s: u32, p: u32) {
+        self.set_owner(frame, FrameOwner::Page(s, p));
+    }
+
+    pub fn release(&mut self, frame: u32) {
+        self.owners[frame as usize] = FrameOwner::Free;
+        self.last_used[frame as usize] = 0;
+    }
+
+    #[inline]
+    pub fn owner(&self, frame: u32) -> FrameOwner {
+        self.owners[frame as usize]
+    }
+
+    #[inline]
+    pub fn touch(&mut self, frame: u32) {
+        self.clock += 1;
+        self.last_used[frame as usize] = self.clock;
+    }
+
+    #[inline]
+    pub fn last_used(&self, frame: u32) -> u64 {
+        self.last_used[frame as usize]
+    }
+
+    pub fn pages(&self) -> impl Iterator<Item = (u32, (u32, u32))> + '_ {
+        self.owners.iter().enumerate().filter_map(|(frame, owner)| match *owner {
+            FrameOwner::Page(s, p) => Some((frame as u32, (s, p))),
+            _ => None,
+        })
+    }
+}
+
+impl Default for FrameTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A PT entry as stored in memory and on disk: flag bits below the frame of a
+// present page or the disk block of one that is not. A valid entry with
+// neither is a demand-zero page. Protection holds the denied accesses, as in
+// the ST, so a zero field leaves the page as open as its segment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageTableEntry(pub i32);
+
+impl PageTableEntry {
+    pub const VALID: i32 = 1;
+    pub const PRESENT: i32 = 1 << 1;
+    pub const DIRTY: i32 = 1 << 2;
+    pub const REFERENCED: i32 = 1 << 3;
+    pub const COW: i32 = 1 << 4;
+    pub const PROTECTION_SHIFT: u32 = 5;
+    pub const FIELD_SHIFT: u32 = 8;
+
+    pub fn resident(frame: u32) -> Self {
+        PageTableEntry((frame as i32) << Self::FIELD_SHIFT | Self::PRESENT | Self::VALID)
+    }
+
+    pub fn on_disk(block: usize) -> Self {
+        PageTableEntry((block as i32) << Self::FIELD_SHIFT | Self::VALID)
+    }
+
+    pub fn demand_zero() -> Self {
+        PageTableEntry(Self::VALID)
+    }
+
+    // Reads a bare legacy word: a frame (with PTE_COW for a shared one), a
+    // negated disk block, PTE_DEMAND_ZERO or 0.
+    pub fn from_legacy(word: i32) -> Self {
+        match word {
+            0 => PageTableEntry(0),
+            PTE_DEMAND_ZERO => Self::demand_zero(),
+            w if w < 0 => Self::on_disk((-w) as usize),
+            w => Self::resident((w & !PTE_COW) as u32).with_cow(w & PTE_COW != 0),
+        }
+    }
+
+    pub fn to_legacy(self) -> i32 {
+        match self.is_cow() {
+            true => self.location() | PTE_COW,
+            false => self.location(),
+        }
+    }
+
+    // The entry in the form the walkers use: a frame, a negated block,
+    // PTE_DEMAND_ZERO or 0.
+    pub fn location(self) -> i32 {
+        match (self.frame(), self.block()) {
+            (Some(frame), _) => frame as i32,
+            (None, Some(block)) => -(block as i32),
+            (None, None) if self.is_demand_zero() => PTE_DEMAND_ZERO,
+            (None, None) => 0,
+        }
+    }
+
+    #[inline]
+    pub fn is_valid(self) -> bool {
+        self.0 & Self::VALID != 0
+    }
+
+    #[inline]
+    pub fn is_present(self) -> bool {
+        self.is_valid() && self.0 & Self::PRESENT != 0
+    }
+
+    #[inline]
+    pub fn is_dirty(self) -> bool {
+        self.0 & Self::DIRTY != 0
+    }
+
+    #[inline]
+    pub fn is_referenced(self) -> bool {
+        self.0 & Self::REFERENCED != 0
+    }
+
+    #[inline]
+    pub fn is_cow(self) -> bool {
+        self.is_present() && self.0 & Self::COW != 0
+    }
+
+    #[inline]
+    pub fn is_demand_zero(self) -> bool {
+        self.is_valid() && !self.is_present() && self.field() == 0
+    }
+
+    pub fn protection(self) -> Protection {
+        Protection::from_denied_bits(self.0 >> Self::PROTECTION_SHIFT & 7)
+    }
+
+    #[inline]
+    pub fn frame(self) -> Option<u32> {
+        self.is_present().then(|| self.field() as u32)
+    }
+
+    #[inline]
+    pub fn block(self) -> Option<usize> {
+        (self.is_valid() && !self.is_present() && self.field() != 0).then(|| self.field() as usize)
+    }
+
+    #[inline]
+    fn field(self) -> i32 {
+        self.0 >> Self::FIELD_SHIFT
+    }
+
+    pub fn with_flag(self, flag: i32, on: bool) -> Self {
+        match on {
+            true => PageTableEntry(self.0 | flag),
+            false => PageTableEntry(self.0 & !flag),
+        }
+    }
+
+    pub fn with_dirty(self, dirty: bool) -> Self {
+        self.with_flag(Self::DIRTY, dirty)
+    }
+
+    pub fn with_referenced(self, referenced: bool) -> Self {
+        self.with_flag(Self::REFERENCED, referenced)
+    }
+
+    pub fn with_cow(self, cow: bool) -> Self {
+        self.with_flag(Self::COW, cow)
+    }
+
+    pub fn with_protection(self, protection: Protection) -> Self {
+        let cleared = self.0 & !(7 << Self::PROTECTION_SHIFT);
+        PageTableEntry(cleared | protection.denied_bits() << Self::PROTECTION_SHIFT)
+    }
+}
+
+// How PT words are laid out. Legacy keeps the bare frame/block words older
+// memory images and snapshots were written with; it has no room for the
+// dirty, referenced or protection bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PteFormat {
+    #[default]
+    Packed,
+    Legacy,
+}
+
+impl PteFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "packed" => Ok(PteFormat::Packed),
+            "legacy" => Ok(PteFormat::Legacy),
+            _ => Err(format!("Unknown PTE format: {} (expected packed or legacy)", value)),
+        }
+    }
 
+    pub fn name(&self) -> &'static str {
+        match self {
+            PteFormat::Packed => "packed",
+            PteFormat::Legacy => "legacy",
+        }
+    }
+}
+
+// The segment table is split into frame-sized slices. The root descriptor holds
+// each slice's location: a resident frame, or a disk block until it is faulted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StSlice {
+    Resident(u32),
+    OnDisk(usize),
+}
+
+#[derive(Clone)]
 pub struct PhysicalMemory {
-    data: Box<[i32; PM_SIZE]>,
+    data: Box<[i32]>,
+    dirty: Vec<bool>,
+    referenced: Vec<bool>,
+    frames: FrameTable,
+    st_slices: Vec<StSlice>,
+    layout: AddressLayout,
+    pte_format: PteFormat,
 }
 
 impl PhysicalMemory {
     pub fn new() -> Self {
-        let data = vec![0i32; PM_SIZE].into_boxed_slice();
-        let data: Box<[i32; PM_SIZE]> = data.try_into().unwrap();
-        PhysicalMemory { data }
+        Self::with_layout(AddressLayout::DEFAULT)
+    }
+
+    pub fn with_layout(layout: AddressLayout) -> Self {
+        let data = vec![0i32; layout.memory_size()].into_boxed_slice();
+        let mut frames = FrameTable::new();
+        for frame in 0..layout.st_frames() as u32 {
+            frames.set_owner(frame, FrameOwner::SegmentTable);
+        }
+        let st_slices = (0..layout.st_frames() as u32).map(StSlice::Resident).collect();
+        PhysicalMemory {
+            data,
+            dirty: vec![false; NUM_FRAMES],
+            referenced: vec![false; NUM_FRAMES],
+            frames,
+            st_slices,
+            layout,
+            pte_format: PteFormat::Packed,
+        }
+    }
+
+    // Raw images are bare words, so their PTs are read in the legacy format.
+    pub fn load_raw<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let mut pm = Self::new();
+        pm.pte_format = PteFormat::Legacy;
+        let words = read_raw_words(path.as_ref(), pm.data.len(), "memory")?;
+        pm.data[..words.len()].copy_from_slice(&words);
+        pm.rebuild_frame_table();
+        Ok(pm)
+    }
+
+    #[inline]
+    pub fn layout(&self) -> &AddressLayout {
+        &self.layout
     }
 
     #[inline]
@@ -21,36 +343,431 @@ impl PhysicalMemory {
         self.data[address] = value;
     }
 
+    // The frame of the first ST slice; only meaningful while the ST is contiguous.
+    #[inline]
+    pub fn st_frame(&self) -> u32 {
+        match self.st_slices[0] {
+            StSlice::Resident(frame) => frame,
+            StSlice::OnDisk(_) => 0,
+        }
+    }
+
+    pub fn set_st_frame(&mut self, frame: u32) {
+        for (i, slice) in self.st_slices.iter_mut().enumerate() {
+            *slice = StSlice::Resident(frame + i as u32);
+        }
+    }
+
+    pub fn st_slices(&self) -> &[StSlice] {
+        &self.st_slices
+    }
+
+    pub fn set_st_slice(&mut self, slice: usize, location: StSlice) {
+        self.st_slices[slice] = location;
+    }
+
+    #[inline]
+    pub fn st_slice_of(&self, segment: u32) -> usize {
+        2 * segment as usize / self.layout.page_size()
+    }
+
+    // True once any ST slice is on disk or away from its contiguous position.
+    pub fn st_is_paged(&self) -> bool {
+        let first = self.st_frame();
+        self.st_slices.iter().enumerate().any(|(i, &slice)| slice != StSlice::Resident(first + i as u32))
+    }
+
+    // The word address of a segment's ST entry, or None while its slice is on disk.
+    #[inline]
+    pub fn st_entry_address(&self, segment: u32) -> Option<usize> {
+        let word = 2 * segment as usize;
+        let page_size = self.layout.page_size();
+        match self.st_slices[word / page_size] {
+            StSlice::Resident(frame) => Some(frame as usize * page_size + word % page_size),
+            StSlice::OnDisk(_) => None,
+        }
+    }
+
+    // A segment whose ST slice is on disk reads as absent.
     #[inline]
     pub fn get_segment_size(&self, segment: u32) -> i32 {
-        self.data[2 * segment as usize]
+        self.st_entry_address(segment).map_or(0, |address| self.data[address] & SEGMENT_SIZE_MASK)
+    }
+
+    #[inline]
+    pub fn segment_protection(&self, segment: u32) -> Protection {
+        let denied = self.st_entry_address(segment).map_or(0, |address| self.data[address] >> PROTECTION_SHIFT);
+        Protection::from_denied_bits(denied)
+    }
+
+    #[inline]
+    pub fn is_huge_segment(&self, segment: u32) -> bool {
+        self.st_entry_address(segment).is_some_and(|address| self.data[address] & SEGMENT_HUGE != 0)
     }
 
     #[inline]
     pub fn get_segment_pt_location(&self, segment: u32) -> i32 {
-        self.data[2 * segment as usize + 1]
+        self.st_entry_address(segment).map_or(0, |address| self.data[address + 1])
     }
 
-    pub fn set_segment_entry(&mut self, segment: u32, size: i32, pt_location: i32) {
-        let base = 2 * segment as usize;
-        self.data[base] = size;
+    pub fn set_segment_entry(&mut self, segment: u32, size: i32, pt_location: i32, protection: Protection) {
+        let base = self.st_entry_address(segment).expect("segment table slice is not resident");
+        self.data[base] = (size & SEGMENT_SIZE_MASK) | protection.denied_bits() << PROTECTION_SHIFT;
         self.data[base + 1] = pt_location;
     }
 
+    // Reads a segment's size, PT location and protection wherever its ST slice
+    // currently lives, without faulting the slice in.
+    pub fn read_segment_entry(&self, disk: &Disk, segment: u32) -> (i32, i32, Protection) {
+        let word = 2 * segment as usize;
+        let page_size = self.layout.page_size();
+        let (size_word, pt_location) = match self.st_slices[word / page_size] {
+            StSlice::Resident(frame) => {
+                let address = frame as usize * page_size + word % page_size;
+                (self.data[address], self.data[address + 1])
+            }
+            StSlice::OnDisk(block) => (disk.read(block, word % page_size), disk.read(block, word % page_size + 1)),
+        };
+        (size_word & SEGMENT_SIZE_MASK, pt_location, Protection::from_denied_bits(size_word >> PROTECTION_SHIFT))
+    }
+
+    pub fn write_segment_entry(
+        &mut self,
+        disk: &mut Disk,
+        segment: u32,
+        size: i32,
+        pt_location: i32,
+        protection: Protection,
+    ) {
+        let word = 2 * segment as usize;
+        let page_size = self.layout.page_size();
+        match self.st_slices[word / page_size] {
+            StSlice::Resident(_) => self.set_segment_entry(segment, size, pt_location, protection),
+            StSlice::OnDisk(block) => {
+                disk.write(block, word % page_size, (size & SEGMENT_SIZE_MASK) | protection.denied_bits() << PROTECTION_SHIFT);
+                disk.write(block, word % page_size + 1, pt_location);
+            }
+        }
+    }
+
+    // Flags or unflags a segment as mapped by a huge page, after its entry is
+    // written. Its PT location is then the first frame of the huge page.
+    pub fn set_segment_huge(&mut self, disk: &mut Disk, segment: u32, huge: bool) {
+        let word = 2 * segment as usize;
+        let page_size = self.layout.page_size();
+        let flag = |size_word: i32| if huge { size_word | SEGMENT_HUGE } else { size_word & !SEGMENT_HUGE };
+        match self.st_slices[word / page_size] {
+            StSlice::Resident(frame) => {
+                let address = frame as usize * page_size + word % page_size;
+                self.data[address] = flag(self.data[address]);
+            }
+            StSlice::OnDisk(block) => disk.write(block, word % page_size, flag(disk.read(block, word % page_size))),
+        }
+    }
+
+    pub fn pte_format(&self) -> PteFormat {
+        self.pte_format
+    }
+
+    // Only switches how existing words are read; it does not convert them.
+    pub fn set_pte_format(&mut self, format: PteFormat) {
+        self.pte_format = format;
+    }
+
+    #[inline]
+    pub fn decode_pte(&self, word: i32) -> PageTableEntry {
+        match self.pte_format {
+            PteFormat::Packed => PageTableEntry(word),
+            PteFormat::Legacy => PageTableEntry::from_legacy(word),
+        }
+    }
+
+    #[inline]
+    pub fn encode_pte(&self, entry: PageTableEntry) -> i32 {
+        match self.pte_format {
+            PteFormat::Packed => entry.0,
+            PteFormat::Legacy => entry.to_legacy(),
+        }
+    }
+
+    #[inline]
+    pub fn page_entry(&self, pt_frame: i32, page: u32) -> PageTableEntry {
+        self.decode_pte(self.data[self.pt_base(pt_frame) + page as usize])
+    }
+
+    pub fn set_page_table_entry(&mut self, pt_frame: i32, page: u32, entry: PageTableEntry) {
+        let address = self.pt_base(pt_frame) + page as usize;
+        self.data[address] = self.encode_pte(entry);
+    }
+
+    // The entry's location, so every walker sees a plain frame number whatever
+    // flags the PTE carries.
     #[inline]
     pub fn get_page_frame(&self, pt_frame: i32, page: u32) -> i32 {
-        let pt_base = pt_frame as usize * PAGE_SIZE;
-        self.data[pt_base + page as usize]
+        self.page_entry(pt_frame, page).location()
+    }
+
+    pub fn is_cow(&self, pt_frame: i32, page: u32) -> bool {
+        self.page_entry(pt_frame, page).is_cow()
     }
 
+    pub fn set_cow(&mut self, pt_frame: i32, page: u32, cow: bool) {
+        let entry = self.page_entry(pt_frame, page);
+        if entry.is_present() {
+            self.set_page_table_entry(pt_frame, page, entry.with_cow(cow));
+        }
+    }
+
+    // Pages whose PT is not resident, and every page in the legacy format, are
+    // left to their segment's protection.
+    pub fn page_protection(&self, segment: u32, page: u32) -> Protection {
+        let pt_location = self.get_segment_pt_location(segment);
+        if pt_location <= 0 || self.is_huge_segment(segment) || page as usize >= self.layout.pt_size() {
+            return Protection::ALL;
+        }
+        self.page_entry(pt_location, page).protection()
+    }
+
+    pub fn set_page_protection(&mut self, pt_frame: i32, page: u32, protection: Protection) -> Result<(), String> {
+        if self.pte_format == PteFormat::Legacy {
+            return Err("Legacy PT entries have no protection bits".to_string());
+        }
+        let entry = self.page_entry(pt_frame, page);
+        self.set_page_table_entry(pt_frame, page, entry.with_protection(protection));
+        Ok(())
+    }
+
+    // Reads a PT entry's location wherever the PT currently lives.
+    pub fn read_page_entry(&self, disk: &Disk, pt_location: i32, page: u32) -> i32 {
+        match pt_location {
+            l if l > 0 => self.get_page_frame(l, page),
+            l if l < 0 => self.decode_pte(disk.read((-l) as usize, page as usize)).location(),
+            _ => 0,
+        }
+    }
+
+    pub fn write_page_entry(&mut self, disk: &mut Disk, pt_location: i32, page: u32, frame_location: i32) {
+        match pt_location {
+            l if l > 0 => self.set_page_entry(l, page, frame_location),
+            l if l < 0 => {
+                let block = (-l) as usize;
+                let old = self.decode_pte(disk.read(block, page as usize));
+                let entry = PageTableEntry::from_legacy(frame_location).with_protection(old.protection());
+                disk.write(block, page as usize, self.encode_pte(entry));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn copy_frame(&mut self, from: u32, to: u32) {
+        let page_size = self.layout.page_size();
+        let start = from as usize * page_size;
+        self.data.copy_within(start..start + page_size, to as usize * page_size);
+    }
+
+    // Takes a legacy-style location (with PTE_COW for a shared frame). A new
+    // frame's PTE picks up the frame's dirty and referenced bits; the page keeps
+    // its protection across remaps.
     pub fn set_page_entry(&mut self, pt_frame: i32, page: u32, frame_location: i32) {
-        let pt_base = pt_frame as usize * PAGE_SIZE;
-        self.data[pt_base + page as usize] = frame_location;
+        let old = self.page_entry(pt_frame, page);
+        let mut entry = PageTableEntry::from_legacy(frame_location).with_protection(old.protection());
+        if let Some(frame) = entry.frame().filter(|&frame| (frame as usize) < NUM_FRAMES) {
+            entry = entry.with_dirty(self.dirty[frame as usize]).with_referenced(self.referenced[frame as usize]);
+        }
+        self.set_page_table_entry(pt_frame, page, entry);
+    }
+
+    // Mirrors a frame's dirty or referenced bit into the PTE of the page that
+    // owns it, if that PT is resident and still maps the frame.
+    fn sync_pte_flag(&mut self, frame: u32, flag: i32, on: bool) {
+        let FrameOwner::Page(s, p) = self.frames.owner(frame) else { return };
+        if self.pte_format == PteFormat::Legacy || self.layout.is_three_level() || s as usize >= self.layout.max_segments() {
+            return;
+        }
+        let pt_location = self.get_segment_pt_location(s);
+        if pt_location <= 0 || self.is_huge_segment(s) || p as usize >= self.layout.pt_size() {
+            return;
+        }
+        let address = self.pt_base(pt_location) + p as usize;
+        let Some(&word) = self.data.get(address) else { return };
+        let entry = self.decode_pte(word);
+        if entry.frame() == Some(frame) {
+            self.data[address] = self.encode_pte(entry.with_flag(flag, on));
+        }
+    }
+
+    // A resident PT location is a frame number, or with PT_COMPACT set a word
+    // address (frame * page size + offset) into a shared compact PT frame.
+    #[inline]
+    pub fn pt_base(&self, pt_location: i32) -> usize {
+        match Self::is_compact_pt(pt_location) {
+            true => (pt_location & !PT_COMPACT) as usize,
+            false => pt_location as usize * self.layout.page_size(),
+        }
+    }
+
+    #[inline]
+    pub fn is_compact_pt(pt_location: i32) -> bool {
+        pt_location > 0 && pt_location & PT_COMPACT != 0
+    }
+
+    #[inline]
+    pub fn compact_pt_location(address: usize) -> i32 {
+        PT_COMPACT | address as i32
+    }
+
+    #[inline]
+    pub fn pt_frame(&self, pt_location: i32) -> u32 {
+        (self.pt_base(pt_location) / self.layout.page_size()) as u32
+    }
+
+    #[inline]
+    pub fn frame_to_address(&self, frame: i32) -> usize {
+        frame as usize * self.layout.page_size()
     }
 
     #[inline]
-    pub fn frame_to_address(frame: i32) -> usize {
-        frame as usize * PAGE_SIZE
+    pub fn is_dirty(&self, frame: u32) -> bool {
+        self.dirty[frame as usize]
+    }
+
+    #[inline]
+    pub fn mark_dirty(&mut self, frame: u32) {
+        self.dirty[frame as usize] = true;
+        self.sync_pte_flag(frame, PageTableEntry::DIRTY, true);
+    }
+
+    #[inline]
+    pub fn clear_dirty(&mut self, frame: u32) {
+        self.dirty[frame as usize] = false;
+        self.sync_pte_flag(frame, PageTableEntry::DIRTY, false);
+    }
+
+    // Reference bits are set by every successful access and only cleared by the
+    // periodic tick, so a set bit means "used since the last clear".
+    #[inline]
+    pub fn is_referenced(&self, frame: u32) -> bool {
+        self.referenced[frame as usize]
+    }
+
+    #[inline]
+    pub fn mark_referenced(&mut self, frame: u32) {
+        self.referenced[frame as usize] = true;
+        self.sync_pte_flag(frame, PageTableEntry::REFERENCED, true);
+    }
+
+    #[inline]
+    pub fn clear_referenced(&mut self, frame: u32) {
+        self.referenced[frame as usize] = false;
+        self.sync_pte_flag(frame, PageTableEntry::REFERENCED, false);
+    }
+
+    pub fn clear_reference_bits(&mut self) {
+        for frame in self.referenced_frames() {
+            self.clear_referenced(frame);
+        }
+    }
+
+    pub fn zero_frame(&mut self, frame: u32) {
+        let page_size = self.layout.page_size();
+        let start = frame as usize * page_size;
+        self.data[start..start + page_size].fill(0);
+    }
+
+    pub fn frame_table(&self) -> &FrameTable {
+        &self.frames
+    }
+
+    pub fn frame_table_mut(&mut self) -> &mut FrameTable {
+        &mut self.frames
+    }
+
+    pub fn who_owns(&self, frame: u32) -> FrameOwner {
+        self.frames.owner(frame)
+    }
+
+    // Ownership implied by the active segment table and the page tables it reaches.
+    pub fn derive_frame_owners(&self) -> Vec<FrameOwner> {
+        let mut owners = vec![FrameOwner::Free; NUM_FRAMES];
+        let page_size = self.layout.page_size();
+        for &slice in &self.st_slices {
+            if let StSlice::Resident(frame) = slice {
+                owners[frame as usize] = FrameOwner::SegmentTable;
+            }
+        }
+        for s in 0..self.layout.max_segments() as u32 {
+            let pt_location = self.get_segment_pt_location(s);
+            if pt_location <= 0 {
+                continue;
+            }
+            let pages = (self.get_segment_size(s).max(0) as usize).div_ceil(page_size).min(self.layout.pt_size());
+            if self.is_huge_segment(s) {
+                for p in 0..pages.min(HUGE_PAGE_FRAMES) {
+                    if let Some(owner) = owners.get_mut(pt_location as usize + p) {
+                        *owner = FrameOwner::Page(s, p as u32);
+                    }
+                }
+                continue;
+            }
+            let pt_frame = self.pt_base(pt_location) / page_size;
+            if pt_frame >= NUM_FRAMES || self.pt_base(pt_location) + pages > self.data.len() {
+                continue;
+            }
+            if owners[pt_frame] == FrameOwner::Free {
+                owners[pt_frame] = FrameOwner::PageTable(s);
+            }
+            for p in 0..pages as u32 {
+                let entry = self.get_page_frame(pt_location, p);
+                if entry > 0 && (entry as usize) < NUM_FRAMES && owners[entry as usize] == FrameOwner::Free {
+                    owners[entry as usize] = FrameOwner::Page(s, p);
+                }
+            }
+        }
+        owners
+    }
+
+    pub fn rebuild_frame_table(&mut self) {
+        for (frame, owner) in self.derive_frame_owners().into_iter().enumerate() {
+            self.frames.set_owner(frame as u32, owner);
+        }
+    }
+
+    // Lists every frame whose recorded owner disagrees with the tables in memory,
+    // and every free-listed frame the frame table or the tables still claim.
+    pub fn check_frame_table(&self, ffl: &FreeFrameList) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (frame, expected) in self.derive_frame_owners().into_iter().enumerate() {
+            let recorded = self.frames.owner(frame as u32);
+            if expected != FrameOwner::Free && recorded != expected {
+                problems.push(format!("frame {}: tables say {}, frame table says {}", frame, expected, recorded));
+            }
+            if expected != FrameOwner::Free && ffl.is_free(frame as u32) {
+                problems.push(format!("frame {}: {} but on the free list", frame, expected));
+            }
+        }
+        for frame in ffl.frames() {
+            let recorded = self.frames.owner(frame);
+            if recorded != FrameOwner::Free {
+                problems.push(format!("frame {}: free but frame table says {}", frame, recorded));
+            }
+        }
+        problems
+    }
+
+    pub fn dirty_frames(&self) -> Vec<u32> {
+        (0..NUM_FRAMES as u32).filter(|&f| self.dirty[f as usize]).collect()
+    }
+
+    pub fn referenced_frames(&self) -> Vec<u32> {
+        (0..NUM_FRAMES as u32).filter(|&f| self.referenced[f as usize]).collect()
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        fnv_hash(self.data.iter().copied())
+    }
+
+    pub fn freeze(&self) -> Arc<FrozenMemory> {
+        Arc::new(FrozenMemory { memory: self.clone() })
     }
 }
 
@@ -60,6 +777,22 @@ impl Default for PhysicalMemory {
     }
 }
 
+// An immutable copy of physical memory taken at a point in time. It derefs to
+// PhysicalMemory so the read-only translator and inspection helpers accept it,
+// and can be shared across threads while the owner keeps mutating the original.
+pub struct FrozenMemory {
+    memory: PhysicalMemory,
+}
+
+impl Deref for FrozenMemory {
+    type Target = PhysicalMemory;
+
+    fn deref(&self) -> &PhysicalMemory {
+        &self.memory
+    }
+}
+
+#[derive(Clone)]
 pub struct Disk {
     data: Box<[[i32; BLOCK_SIZE]; DISK_BLOCKS]>,
 }
@@ -71,6 +804,15 @@ impl Disk {
         Disk { data }
     }
 
+    pub fn load_raw<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let mut disk = Self::new();
+        let words = read_raw_words(path.as_ref(), DISK_BLOCKS * BLOCK_SIZE, "disk")?;
+        for (i, word) in words.into_iter().enumerate() {
+            disk.data[i / BLOCK_SIZE][i % BLOCK_SIZE] = word;
+        }
+        Ok(disk)
+    }
+
     #[inline]
     pub fn read(&self, block: usize, offset: usize) -> i32 {
         self.data[block][offset]
@@ -87,14 +829,62 @@ impl Disk {
         }
     }
 
+    // Frames follow the memory's layout and blocks are always BLOCK_SIZE words.
+    // A smaller frame fills the start of its block; a larger one has nowhere to
+    // go, so layouts with pages past BLOCK_SIZE words are resident only.
+    pub fn load_frame(&self, block: usize, frame: u32, pm: &mut PhysicalMemory) {
+        let page_size = Self::transfer_size(pm);
+        for i in 0..page_size {
+            pm.write(frame as usize * page_size + i, self.data[block][i]);
+        }
+    }
+
+    pub fn store_frame(&mut self, block: usize, frame: u32, pm: &PhysicalMemory) {
+        let page_size = Self::transfer_size(pm);
+        for i in 0..page_size {
+            self.data[block][i] = pm.read(frame as usize * page_size + i);
+        }
+    }
+
+    fn transfer_size(pm: &PhysicalMemory) -> usize {
+        let page_size = pm.layout().page_size();
+        assert!(page_size <= BLOCK_SIZE, "pages of {} words do not fit a {}-word disk block", page_size, BLOCK_SIZE);
+        page_size
+    }
+
     pub fn load_pt_from_disk(&self, disk_block: usize, frame: u32, pm: &mut PhysicalMemory) {
-        let pm_start = PhysicalMemory::frame_to_address(frame as i32);
-        self.read_block(disk_block, pm, pm_start);
+        self.load_frame(disk_block, frame, pm);
     }
 
     pub fn load_page_from_disk(&self, disk_block: usize, frame: u32, pm: &mut PhysicalMemory) {
-        let pm_start = PhysicalMemory::frame_to_address(frame as i32);
-        self.read_block(disk_block, pm, pm_start);
+        self.load_frame(disk_block, frame, pm);
+    }
+
+    pub fn write_block(&mut self, block: usize, pm: &PhysicalMemory, pm_start: usize) {
+        for i in 0..BLOCK_SIZE {
+            self.data[block][i] = pm.read(pm_start + i);
+        }
+    }
+
+    pub fn store_pt_to_disk(&mut self, disk_block: usize, frame: u32, pm: &PhysicalMemory) {
+        self.store_frame(disk_block, frame, pm);
+    }
+
+    pub fn store_page_to_disk(&mut self, disk_block: usize, frame: u32, pm: &mut PhysicalMemory) {
+        self.store_frame(disk_block, frame, pm);
+        pm.clear_dirty(frame);
+    }
+
+    pub fn write_back_if_dirty(&mut self, block: usize, frame: u32, pm: &mut PhysicalMemory) -> bool {
+        if !pm.is_dirty(frame) {
+            return false;
+        }
+        self.store_page_to_disk(block, frame, pm);
+        true
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        fnv_hash(self.data.iter().flat_map(|block| block.iter().copied()))
     }
 }
 
@@ -104,24 +894,234 @@ impl Default for Disk {
     }
 }
 
+// Free frames as a bitmap (bit set = free). `hint` is a lower bound on the first
+// word with a free bit, so lowest-first allocation skips the exhausted prefix.
+#[derive(Debug, Clone)]
+pub struct FrameBitmap {
+    words: Vec<u64>,
+    free: usize,
+    hint: usize,
+}
+
+impl FrameBitmap {
+    pub fn empty() -> Self {
+        FrameBitmap { words: vec![0; NUM_FRAMES.div_ceil(64)], free: 0, hint: 0 }
+    }
+
+    pub fn new() -> Self {
+        let mut bitmap = Self::empty();
+        for frame in ST_FRAMES as u32..NUM_FRAMES as u32 {
+            bitmap.release(frame);
+        }
+        bitmap
+    }
+
+    #[inline]
+    pub fn is_free(&self, frame: u32) -> bool {
+        self.words[frame as usize / 64] & (1 << (frame % 64)) != 0
+    }
+
+    #[inline]
+    pub fn take(&mut self, frame: u32) -> bool {
+        if frame as usize >= NUM_FRAMES || !self.is_free(frame) {
+            return false;
+        }
+        self.words[frame as usize / 64] &= !(1 << (frame % 64));
+        self.free -= 1;
+        true
+    }
+
+    #[inline]
+    pub fn release(&mut self, frame: u32) {
+        if frame as usize >= NUM_FRAMES || self.is_free(frame) {
+            return;
+        }
+        self.words[frame as usize / 64] |= 1 << (frame % 64);
+        self.free += 1;
+        self.hint = self.hint.min(frame as usize / 64);
+    }
+
+    pub fn allocate(&mut self) -> Option<u32> {
+        while self.hint < self.words.len() {
+            let word = self.words[self.hint];
+            if word != 0 {
+                let frame = (self.hint * 64) as u32 + word.trailing_zeros();
+                self.take(frame);
+                return Some(frame);
+            }
+            self.hint += 1;
+        }
+        None
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free
+    }
+
+    pub fn frames(&self) -> Vec<u32> {
+        (0..NUM_FRAMES as u32).rev().filter(|&frame| self.is_free(frame)).collect()
+    }
+}
+
+impl Default for FrameBitmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeListKind {
+    Sorted,
+    Bitmap,
+}
+
+impl FreeListKind {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "sorted" => Ok(FreeListKind::Sorted),
+            "bitmap" => Ok(FreeListKind::Bitmap),
+            _ => Err(format!("Unknown free list kind: {} (expected sorted or bitmap)", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum FrameStore {
+    Sorted(Vec<u32>),
+    Bitmap(FrameBitmap),
+}
+
+// Both backings hand out the lowest-numbered free frame first. The sorted Vec
+// (kept in descending order) pays O(n) for take/mark_occupied and release; the
+// bitmap makes those O(1).
+#[derive(Debug, Clone)]
 pub struct FreeFrameList {
-    free_frames: Vec<u32>,
+    store: FrameStore,
 }
 
 impl FreeFrameList {
     pub fn new() -> Self {
-        let free_frames: Vec<u32> = (ST_FRAMES as u32..NUM_FRAMES as u32).rev().collect();
-        FreeFrameList { free_frames }
+        Self::with_kind(FreeListKind::Sorted)
+    }
+
+    pub fn with_kind(kind: FreeListKind) -> Self {
+        match kind {
+            FreeListKind::Sorted => {
+                let free_frames: Vec<u32> = (ST_FRAMES as u32..NUM_FRAMES as u32).rev().collect();
+                FreeFrameList { store: FrameStore::Sorted(free_frames) }
+            }
+            FreeListKind::Bitmap => FreeFrameList { store: FrameStore::Bitmap(FrameBitmap::new()) },
+        }
+    }
+
+    // Restricted physical memory: only frames below `frames` are ever handed out,
+    // as if the machine had no more.
+    pub fn with_capacity(frames: usize) -> Self {
+        let limit = frames.clamp(ST_FRAMES, NUM_FRAMES) as u32;
+        FreeFrameList { store: FrameStore::Sorted((ST_FRAMES as u32..limit).rev().collect()) }
+    }
+
+    pub fn from_frames(mut frames: Vec<u32>) -> Self {
+        frames.sort_unstable_by(|a, b| b.cmp(a));
+        frames.dedup();
+        FreeFrameList { store: FrameStore::Sorted(frames) }
+    }
+
+    pub fn kind(&self) -> FreeListKind {
+        match self.store {
+            FrameStore::Sorted(_) => FreeListKind::Sorted,
+            FrameStore::Bitmap(_) => FreeListKind::Bitmap,
+        }
+    }
+
+    pub fn into_kind(self, kind: FreeListKind) -> Self {
+        match (kind, self.store) {
+            (FreeListKind::Bitmap, FrameStore::Sorted(frames)) => {
+                let mut bitmap = FrameBitmap::empty();
+                for frame in frames {
+                    bitmap.release(frame);
+                }
+                FreeFrameList { store: FrameStore::Bitmap(bitmap) }
+            }
+            (FreeListKind::Sorted, FrameStore::Bitmap(bitmap)) => Self::from_frames(bitmap.frames()),
+            (_, store) => FreeFrameList { store },
+        }
+    }
+
+    // Free frames in descending order, so the next frame allocated is the last.
+    pub fn frames(&self) -> Vec<u32> {
+        match &self.store {
+            FrameStore::Sorted(frames) => frames.clone(),
+            FrameStore::Bitmap(bitmap) => bitmap.frames(),
+        }
+    }
+
+    pub fn is_free(&self, frame: u32) -> bool {
+        match &self.store {
+            FrameStore::Sorted(frames) => frames.binary_search_by(|f| frame.cmp(f)).is_ok(),
+            FrameStore::Bitmap(bitmap) => (frame as usize) < NUM_FRAMES && bitmap.is_free(frame),
+        }
     }
 
     pub fn mark_occupied(&mut self, frame: u32) {
-        if let Some(pos) = self.free_frames.iter().position(|&f| f == frame) {
-            self.free_frames.remove(pos);
+        self.take(frame);
+    }
+
+    pub fn take(&mut self, frame: u32) -> bool {
+        match &mut self.store {
+            FrameStore::Sorted(frames) => match frames.iter().position(|&f| f == frame) {
+                Some(pos) => {
+                    frames.remove(pos);
+                    true
+                }
+                None => false,
+            },
+            FrameStore::Bitmap(bitmap) => bitmap.take(frame),
         }
     }
 
     pub fn allocate(&mut self) -> Option<u32> {
-        self.free_frames.pop()
+        match &mut self.store {
+            FrameStore::Sorted(frames) => frames.pop(),
+            FrameStore::Bitmap(bitmap) => bitmap.allocate(),
+        }
+    }
+
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<u32> {
+        if count == 0 {
+            return None;
+        }
+        let ascending: Vec<u32> = self.frames().into_iter().rev().collect();
+        let start = ascending
+            .windows(count)
+            .find(|run| run[count - 1] - run[0] == count as u32 - 1)
+            .map(|run| run[0])?;
+        for frame in start..start + count as u32 {
+            self.mark_occupied(frame);
+        }
+        Some(start)
+    }
+
+    pub fn release(&mut self, frame: u32) {
+        match &mut self.store {
+            FrameStore::Sorted(frames) => {
+                if let Err(pos) = frames.binary_search_by(|f| frame.cmp(f)) {
+                    frames.insert(pos, frame);
+                }
+            }
+            FrameStore::Bitmap(bitmap) => bitmap.release(frame),
+        }
+    }
+
+    pub fn free_count(&self) -> usize {
+        match &self.store {
+            FrameStore::Sorted(frames) => frames.len(),
+            FrameStore::Bitmap(bitmap) => bitmap.free_count(),
+        }
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        fnv_hash(self.frames().iter().map(|&f| f as i32))
     }
 }
 
@@ -130,3 +1130,230 @@ impl Default for FreeFrameList {
         Self::new()
     }
 }
+
+// Block 0 is never handed out: a disk location is stored negated in the ST or a
+// PT, and -0 cannot be told apart from an empty entry.
+pub struct DiskBlockAllocator {
+    free_blocks: Vec<usize>,
+}
+
+impl DiskBlockAllocator {
+    pub fn new() -> Self {
+        DiskBlockAllocator { free_blocks: (1..DISK_BLOCKS).rev().collect() }
+    }
+
+    // Marks every block referenced by the ST, the resident page tables, or the
+    // page tables stored on disk as in use.
+    pub fn from_tables(pm: &PhysicalMemory, disk: &Disk) -> Self {
+        let mut allocator = Self::new();
+        let layout = pm.layout();
+        for &slice in pm.st_slices() {
+            if let StSlice::OnDisk(block) = slice {
+                allocator.mark_used(block);
+            }
+        }
+        for s in 0..layout.max_segments() as u32 {
+            let (size, pt_location, _) = pm.read_segment_entry(disk, s);
+            let pages = (size.max(0) as usize)
+                .div_ceil(layout.page_size())
+                .min(layout.pt_size().min(BLOCK_SIZE));
+            if pt_location < 0 {
+                allocator.mark_used((-pt_location) as usize);
+            }
+            for p in 0..pages as u32 {
+                let entry = pm.read_page_entry(disk, pt_location, p);
+                if entry < 0 && entry != PTE_DEMAND_ZERO {
+                    allocator.mark_used((-entry) as usize);
+                }
+            }
+        }
+        allocator
+    }
+
+    pub fn mark_used(&mut self, block: usize) {
+        if let Ok(pos) = self.free_blocks.binary_search_by(|b| block.cmp(b)) {
+            self.free_blocks.remove(pos);
+        }
+    }
+
+    pub fn allocate(&mut self) -> Option<usize> {
+        self.free_blocks.pop()
+    }
+
+    pub fn release(&mut self, block: usize) {
+        if block == 0 || block >= DISK_BLOCKS {
+            return;
+        }
+        if let Err(pos) = self.free_blocks.binary_search_by(|b| block.cmp(b)) {
+            self.free_blocks.insert(pos, block);
+        }
+    }
+
+    pub fn is_free(&self, block: usize) -> bool {
+        self.free_blocks.binary_search_by(|b| block.cmp(b)).is_ok()
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free_blocks.len()
+    }
+}
+
+impl Default for DiskBlockAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsistencyError {
+    FrameOutOfRange { owner: FrameOwner, frame: i32 },
+    BlockOutOfRange { owner: FrameOwner, block: usize },
+    FrameConflict { frame: u32, first: FrameOwner, second: FrameOwner },
+    BlockConflict { block: usize, first: FrameOwner, second: FrameOwner },
+    FreeFrameInUse { frame: u32, owner: FrameOwner },
+    FrameTableMismatch { frame: u32, expected: FrameOwner, recorded: FrameOwner },
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsistencyError::FrameOutOfRange { owner, frame } => {
+                write!(f, "{} is mapped to frame {}, outside physical memory", owner, frame)
+            }
+            ConsistencyError::BlockOutOfRange { owner, block } => {
+                write!(f, "{} is mapped to disk block {}, outside the disk", owner, block)
+            }
+            ConsistencyError::FrameConflict { frame, first, second } => {
+                write!(f, "frame {} is used by both {} and {}", frame, first, second)
+            }
+            ConsistencyError::BlockConflict { block, first, second } => {
+                write!(f, "disk block {} is used by both {} and {}", block, first, second)
+            }
+            ConsistencyError::FreeFrameInUse { frame, owner } => {
+                write!(f, "frame {} is on the free list but holds {}", frame, owner)
+            }
+            ConsistencyError::FrameTableMismatch { frame, expected, recorded } => {
+                write!(f, "frame {} holds {} but the frame table records {}", frame, expected, recorded)
+            }
+        }
+    }
+}
+
+struct UsageMap {
+    frames: Vec<FrameOwner>,
+    blocks: Vec<FrameOwner>,
+    errors: Vec<ConsistencyError>,
+}
+
+impl UsageMap {
+    // Page tables may share a frame (compact PTs) and a PT may be reached from
+    // several segments; every other overlap is a conflict.
+    fn claim_frame(&mut self, frame: i32, owner: FrameOwner) {
+        if frame <= 0 || frame as usize >= NUM_FRAMES {
+            self.errors.push(ConsistencyError::FrameOutOfRange { owner, frame });
+            return;
+        }
+        let first = self.frames[frame as usize];
+        let shared_pt = matches!((first, owner), (FrameOwner::PageTable(_), FrameOwner::PageTable(_)));
+        match first {
+            FrameOwner::Free => self.frames[frame as usize] = owner,
+            _ if shared_pt => {}
+            _ => self.errors.push(ConsistencyError::FrameConflict { frame: frame as u32, first, second: owner }),
+        }
+    }
+
+    fn claim_block(&mut self, block: usize, owner: FrameOwner) {
+        if block >= DISK_BLOCKS {
+            self.errors.push(ConsistencyError::BlockOutOfRange { owner, block });
+            return;
+        }
+        match self.blocks[block] {
+            FrameOwner::Free => self.blocks[block] = owner,
+            first => self.errors.push(ConsistencyError::BlockConflict { block, first, second: owner }),
+        }
+    }
+
+    // Copy-on-write mappings may share their frame with other pages.
+    fn claim_cow_frame(&mut self, frame: i32, owner: FrameOwner) {
+        if !matches!(self.frames.get(frame as usize), Some(FrameOwner::Page(..))) {
+            self.claim_frame(frame, owner);
+        }
+    }
+
+    fn claim_entry(&mut self, entry: i32, owner: FrameOwner) {
+        match entry {
+            0 | PTE_DEMAND_ZERO => {}
+            e if e < 0 => self.claim_block((-e) as usize, owner),
+            e => self.claim_frame(e, owner),
+        }
+    }
+}
+
+// Checks the active segment table, every page table it reaches (resident or on
+// disk), the free frame list and the frame table against each other. A PT reached
+// from several segments is walked once, for the lowest of them.
+pub fn validate(pm: &PhysicalMemory, ffl: &FreeFrameList, disk: &Disk) -> Result<(), Vec<ConsistencyError>> {
+    let layout = pm.layout();
+    let mut usage = UsageMap {
+        frames: vec![FrameOwner::Free; NUM_FRAMES],
+        blocks: vec![FrameOwner::Free; DISK_BLOCKS],
+        errors: Vec::new(),
+    };
+    for &slice in pm.st_slices() {
+        match slice {
+            StSlice::Resident(frame) => usage.frames[frame as usize] = FrameOwner::SegmentTable,
+            StSlice::OnDisk(block) => usage.claim_block(block, FrameOwner::SegmentTable),
+        }
+    }
+    let mut walked = Vec::new();
+    for s in 0..layout.max_segments() as u32 {
+        let (size, pt_location, _) = pm.read_segment_entry(disk, s);
+        if pt_location != 0 && walked.contains(&pt_location) {
+            continue;
+        }
+        walked.push(pt_location);
+        let pages = (size.max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size());
+        if pt_location < 0 {
+            let block = (-pt_location) as usize;
+            usage.claim_block(block, FrameOwner::PageTable(s));
+            if block < DISK_BLOCKS {
+                for p in 0..pages.min(BLOCK_SIZE) {
+                    usage.claim_entry(pm.read_page_entry(disk, pt_location, p as u32), FrameOwner::Page(s, p as u32));
+                }
+            }
+        } else if pt_location > 0 {
+            let pt_address = pm.pt_base(pt_location);
+            if pt_address + pages > layout.memory_size() {
+                usage.errors.push(ConsistencyError::FrameOutOfRange {
+                    owner: FrameOwner::PageTable(s),
+                    frame: (pt_address / layout.page_size()) as i32,
+                });
+                continue;
+            }
+            usage.claim_frame((pt_address / layout.page_size()) as i32, FrameOwner::PageTable(s));
+            for p in 0..pages as u32 {
+                match pm.is_cow(pt_location, p) {
+                    true => usage.claim_cow_frame(pm.get_page_frame(pt_location, p), FrameOwner::Page(s, p)),
+                    false => usage.claim_entry(pm.get_page_frame(pt_location, p), FrameOwner::Page(s, p)),
+                }
+            }
+        }
+    }
+
+    for frame in ffl.frames() {
+        if let Some(&owner) = usage.frames.get(frame as usize).filter(|&&owner| owner != FrameOwner::Free) {
+            usage.errors.push(ConsistencyError::FreeFrameInUse { frame, owner });
+        }
+    }
+    for (frame, expected) in pm.derive_frame_owners().into_iter().enumerate() {
+        let recorded = pm.who_owns(frame as u32);
+        if expected != FrameOwner::Free && recorded != expected {
+            usage.errors.push(ConsistencyError::FrameTableMismatch { frame: frame as u32, expected, recorded });
+        }
+    }
+
+    match usage.errors.is_empty() {
+        true => Ok(()),
+        false => Err(usage.errors),
+    }
+}
@@ -0,0 +1,112 @@
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory, StSlice};
+use crate::stats::TranslationStats;
+use crate::translation::{translate_with_demand_paging_faults, FaultFlags, GroupingReport, TranslationResult, VirtualAddress};
+
+// The result a demand-paged walk would return without allocating anything, or
+// None if it would take a fault (or depends on a table a fault could rewrite).
+// Faults only ever replace on-disk and demand-zero entries, so a Some result
+// still holds after any faults taken earlier in the trace.
+pub fn speculate(va: &VirtualAddress, pm: &PhysicalMemory) -> Option<TranslationResult> {
+    if let StSlice::OnDisk(_) = pm.st_slices()[pm.st_slice_of(va.s)] {
+        return None;
+    }
+    let segment_size = pm.get_segment_size(va.s);
+    let pt_location = pm.get_segment_pt_location(va.s);
+    if segment_size == 0 && pt_location == 0 {
+        return Some(TranslationResult::InvalidSegment);
+    }
+    if va.pw >= segment_size as u32 {
+        return Some(TranslationResult::SegmentBoundaryViolation);
+    }
+    if pm.is_huge_segment(va.s) {
+        return Some(TranslationResult::Success(pt_location * pm.layout().page_size() as i32 + va.pw as i32));
+    }
+    if pt_location <= 0 {
+        return None;
+    }
+    match pm.get_page_frame(pt_location, va.p) {
+        0 => Some(TranslationResult::InvalidPage),
+        frame if frame < 0 => None,
+        frame => Some(TranslationResult::Success(frame * pm.layout().page_size() as i32 + va.w as i32)),
+    }
+}
+
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+// A drop-in for translate_batch_grouped that speculates every access across
+// `threads` workers against the initial memory, then commits in trace order,
+// walking for real only where speculation gave up. Faults are therefore taken
+// in exactly the sequential order, so results, frame allocation and the frame
+// table clock all match the single-threaded run.
+pub fn translate_batch_parallel(
+    vas: &[u32],
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    mut stats: Option<&mut TranslationStats>,
+    threads: usize,
+) -> (Vec<TranslationResult>, GroupingReport) {
+    let speculated = speculate_batch(vas, pm, threads.max(1));
+    let page_size = pm.layout().page_size() as i32;
+    let mut results = Vec::with_capacity(vas.len());
+    let mut report = GroupingReport::default();
+    let mut current_page: Option<(u32, u32)> = None;
+    let mut resolved_frame: Option<i32> = None;
+
+    for (&raw, speculated) in vas.iter().zip(speculated) {
+        let va = VirtualAddress::decompose(pm.layout(), raw);
+        report.accesses += 1;
+
+        if current_page != Some((va.s, va.p)) {
+            current_page = Some((va.s, va.p));
+            resolved_frame = None;
+            report.groups += 1;
+        }
+
+        let (result, faults) = match (resolved_frame, speculated) {
+            (Some(frame), _) => {
+                let result = if va.pw >= pm.get_segment_size(va.s) as u32 {
+                    TranslationResult::SegmentBoundaryViolation
+                } else {
+                    pm.frame_table_mut().touch(frame as u32);
+                    TranslationResult::Success(frame * page_size + va.w as i32)
+                };
+                (result, FaultFlags::default())
+            }
+            (None, Some(result)) => {
+                if let TranslationResult::Success(pa) = result {
+                    pm.frame_table_mut().touch((pa / page_size) as u32);
+                }
+                (result, FaultFlags::default())
+            }
+            (None, None) => translate_with_demand_paging_faults(&va, pm, disk, ffl),
+        };
+        if resolved_frame.is_none() {
+            if let TranslationResult::Success(pa) = result {
+                resolved_frame = Some(pa / page_size);
+            }
+        }
+        if let Some(stats) = stats.as_deref_mut() {
+            stats.record(&result, faults);
+        }
+        results.push(result);
+    }
+
+    (results, report)
+}
+
+fn speculate_batch(vas: &[u32], pm: &PhysicalMemory, threads: usize) -> Vec<Option<TranslationResult>> {
+    let chunk = vas.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let workers: Vec<_> = vas
+            .chunks(chunk)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(|&va| speculate(&VirtualAddress::decompose(pm.layout(), va), pm)).collect::<Vec<_>>()))
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().expect("speculation worker panicked")).collect()
+    })
+}
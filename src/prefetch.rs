@@ -0,0 +1,84 @@
+use std::collections::HashSet;
+
+use crate::constants::*;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::replacement::PageId;
+use crate::translation::{FaultFlags, TranslationResult, VirtualAddress};
+
+// Pre-paging: a page fault on (s, p) also loads (s, p+1) .. (s, p+depth) while
+// their PT entries point at disk blocks and free frames remain. A prefetched
+// page counts as used if it is accessed before anything faults it in again.
+#[derive(Debug, Default)]
+pub struct Prefetcher {
+    depth: usize,
+    pending: HashSet<PageId>,
+    pub issued: u64,
+    pub used: u64,
+}
+
+impl Prefetcher {
+    pub fn new(depth: usize) -> Self {
+        Prefetcher { depth, ..Self::default() }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn wasted(&self) -> u64 {
+        self.issued - self.used
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        if self.issued == 0 {
+            return 0.0;
+        }
+        self.used as f64 / self.issued as f64
+    }
+
+    // Call after every access; returns the number of pages prefetched for it.
+    pub fn after_access(
+        &mut self,
+        va: &VirtualAddress,
+        result: &TranslationResult,
+        faults: FaultFlags,
+        pm: &mut PhysicalMemory,
+        disk: &Disk,
+        ffl: &mut FreeFrameList,
+    ) -> usize {
+        if !matches!(result, TranslationResult::Success(_)) {
+            return 0;
+        }
+        let was_pending = self.pending.remove(&(va.s, va.p));
+        if !faults.page_fault {
+            self.used += was_pending as u64;
+            return 0;
+        }
+        self.prefetch(va, pm, disk, ffl)
+    }
+
+    fn prefetch(&mut self, va: &VirtualAddress, pm: &mut PhysicalMemory, disk: &Disk, ffl: &mut FreeFrameList) -> usize {
+        let pt_location = pm.get_segment_pt_location(va.s);
+        let layout = pm.layout();
+        let pages = (pm.get_segment_size(va.s).max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size()) as u32;
+        let last = (va.p as usize + self.depth).min(pages.saturating_sub(1) as usize) as u32;
+        let mut loaded = 0;
+        for p in va.p + 1..=last {
+            let entry = pm.get_page_frame(pt_location, p);
+            if entry >= 0 || entry == PTE_DEMAND_ZERO {
+                continue;
+            }
+            let Some(frame) = ffl.allocate() else {
+                break;
+            };
+            disk.load_page_from_disk((-entry) as usize, frame, pm);
+            pm.clear_dirty(frame);
+            pm.frame_table_mut().assign(frame, va.s, p);
+            pm.set_page_entry(pt_location, p, frame as i32);
+            self.pending.insert((va.s, p));
+            self.issued += 1;
+            loaded += 1;
+        }
+        loaded
+    }
+}
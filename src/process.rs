@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use crate::constants::*;
+use crate::memory::{Disk, FrameOwner, FreeFrameList, PhysicalMemory};
+use crate::stats::TlbSwitchComparison;
+use crate::tlb::{Tlb, TlbCache, TlbEviction};
+use crate::translation::{
+    check_protection, copy_on_write, record_access, translate, translate_with_demand_paging_faults, Access, AccessType, FaultFlags, TranslationResult,
+    VirtualAddress,
+};
+
+pub type Pid = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Permissions {
+    pub const READ_ONLY: Permissions = Permissions { read: true, write: false };
+    pub const READ_WRITE: Permissions = Permissions { read: true, write: true };
+    pub const NONE: Permissions = Permissions { read: false, write: false };
+
+    pub fn parse(flags: &str) -> Result<Self, String> {
+        let mut permissions = Permissions::NONE;
+        for c in flags.chars() {
+            match c {
+                'r' => permissions.read = true,
+                'w' => permissions.write = true,
+                '-' => {}
+                _ => return Err(format!("Invalid permission flags: {}", flags)),
+            }
+        }
+        Ok(permissions)
+    }
+
+    pub fn allows(&self, kind: AccessType) -> bool {
+        match kind {
+            AccessType::Read => self.read,
+            AccessType::Write => self.write,
+            AccessType::Execute => self.read,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Process {
+    pub pid: Pid,
+    pub st_frame: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessTable {
+    processes: Vec<Process>,
+    acl: HashMap<(Pid, u32), Permissions>,
+    current: Pid,
+}
+
+impl ProcessTable {
+    pub fn new() -> Self {
+        ProcessTable {
+            processes: vec![Process { pid: 0, st_frame: 0 }],
+            acl: HashMap::new(),
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> Pid {
+        self.current
+    }
+
+    pub fn processes(&self) -> &[Process] {
+        &self.processes
+    }
+
+    pub fn process(&self, pid: Pid) -> Result<Process, String> {
+        self.processes
+            .iter()
+            .find(|p| p.pid == pid)
+            .copied()
+            .ok_or_else(|| format!("No such process: {}", pid))
+    }
+
+    pub fn spawn(&mut self, pm: &mut PhysicalMemory, ffl: &mut FreeFrameList) -> Result<Pid, String> {
+        let st_frame = ffl
+            .allocate_contiguous(ST_FRAMES)
+            .ok_or_else(|| "No contiguous frames left for a segment table".to_string())?;
+        let start = pm.frame_to_address(st_frame as i32);
+        for i in 0..ST_SIZE {
+            pm.write(start + i, 0);
+        }
+        for frame in st_frame..st_frame + ST_FRAMES as u32 {
+            pm.frame_table_mut().set_owner(frame, FrameOwner::SegmentTable);
+        }
+        let pid = self.processes.iter().map(|p| p.pid).max().unwrap_or(0) + 1;
+        self.processes.push(Process { pid, st_frame });
+        Ok(pid)
+    }
+
+    pub fn switch_to(&mut self, pid: Pid, pm: &mut PhysicalMemory) -> Result<(), String> {
+        let process = self.process(pid)?;
+        pm.set_st_frame(process.st_frame);
+        self.current = pid;
+        Ok(())
+    }
+
+    // Without address-space tags every cached translation belongs to the process
+    // being switched away from, so the TLB is flushed on any actual switch.
+    pub fn switch_to_with_tlb(&mut self, pid: Pid, pm: &mut PhysicalMemory, tlb: &mut dyn TlbCache) -> Result<(), String> {
+        let previous = self.current;
+        self.switch_to(pid, pm)?;
+        if previous != pid {
+            tlb.flush_all();
+        }
+        Ok(())
+    }
+
+    // Tags the TLB with the new pid as its ASID instead, keeping every process's
+    // entries across the switch.
+    pub fn switch_to_with_asid(&mut self, pid: Pid, pm: &mut PhysicalMemory, tlb: &mut dyn TlbCache) -> Result<(), String> {
+        self.switch_to(pid, pm)?;
+        tlb.set_asid(pid);
+        Ok(())
+    }
+
+    // Runs an interleaved (pid, va) read trace through two LRU TLBs of the same
+    // size, one flushed on every switch and one ASID-tagged. Tables are walked
+    // without demand paging, and the running process is restored afterwards.
+    pub fn compare_tlb_switching(
+        &mut self,
+        trace: &[(Pid, u32)],
+        pm: &mut PhysicalMemory,
+        entries: usize,
+    ) -> Result<TlbSwitchComparison, String> {
+        let start = self.current;
+        let mut flushed = Tlb::new(entries, TlbEviction::Lru);
+        let mut tagged = Tlb::new(entries, TlbEviction::Lru);
+        let mut comparison = TlbSwitchComparison::default();
+        for &(pid, va) in trace {
+            if pid != self.current {
+                comparison.switches += 1;
+            }
+            self.switch_to_with_tlb(pid, pm, &mut flushed)?;
+            self.switch_to_with_asid(pid, pm, &mut tagged)?;
+            if self.check_access(&Access::read(va), pm).is_some() {
+                continue;
+            }
+            let va = VirtualAddress::decompose(pm.layout(), va);
+            translate(&va, pm, Some(&mut flushed));
+            translate(&va, pm, Some(&mut tagged));
+            comparison.accesses += 1;
+        }
+        self.switch_to(start, pm)?;
+        comparison.flush_misses = flushed.misses;
+        comparison.asid_misses = tagged.misses;
+        Ok(comparison)
+    }
+
+    pub fn set_permissions(&mut self, pid: Pid, segment: u32, permissions: Permissions) {
+        self.acl.insert((pid, segment), permissions);
+    }
+
+    pub fn permissions(&self, pid: Pid, segment: u32) -> Permissions {
+        self.acl.get(&(pid, segment)).copied().unwrap_or(Permissions::READ_WRITE)
+    }
+
+    pub fn share_segment(
+        &mut self,
+        pm: &mut PhysicalMemory,
+        owner: Pid,
+        segment: u32,
+        target: Pid,
+        target_segment: u32,
+        permissions: Permissions,
+    ) -> Result<(), String> {
+        if target_segment >= MAX_SEGMENTS as u32 {
+            return Err(format!("Segment number {} exceeds max {}", target_segment, MAX_SEGMENTS - 1));
+        }
+        let active = pm.st_frame();
+        pm.set_st_frame(self.process(owner)?.st_frame);
+        let size = pm.get_segment_size(segment);
+        let pt_location = pm.get_segment_pt_location(segment);
+        let protection = pm.segment_protection(segment);
+        let huge = pm.is_huge_segment(segment);
+        pm.set_st_frame(active);
+
+        if size <= 0 {
+            return Err(format!("Process {} has no segment {} to share", owner, segment));
+        }
+        if pt_location <= 0 {
+            return Err(format!("Segment {} of process {} must have a resident page table to be shared", segment, owner));
+        }
+        if huge {
+            return Err(format!("Segment {} of process {} is mapped by a huge page and cannot be shared", segment, owner));
+        }
+
+        pm.set_st_frame(self.process(target)?.st_frame);
+        pm.set_segment_entry(target_segment, size, pt_location, protection);
+        pm.set_st_frame(active);
+        self.set_permissions(target, target_segment, permissions);
+        Ok(())
+    }
+
+    fn check_access(&self, access: &Access, pm: &PhysicalMemory) -> Option<TranslationResult> {
+        let va = VirtualAddress::decompose(pm.layout(), access.va);
+        if pm.get_segment_size(va.s) == 0 && pm.get_segment_pt_location(va.s) == 0 {
+            return Some(TranslationResult::InvalidSegment);
+        }
+        if !self.permissions(self.current, va.s).allows(access.kind) {
+            return Some(TranslationResult::AccessDenied);
+        }
+        check_protection(access.kind, &va, pm)
+    }
+
+    pub fn translate(&self, access: &Access, pm: &mut PhysicalMemory) -> TranslationResult {
+        if let Some(denied) = self.check_access(access, pm) {
+            return denied;
+        }
+        let result = translate(&VirtualAddress::decompose(pm.layout(), access.va), pm, None);
+        record_access(access, &result, pm);
+        result
+    }
+
+    pub fn translate_with_demand_paging(
+        &self,
+        access: &Access,
+        pm: &mut PhysicalMemory,
+        disk: &Disk,
+        ffl: &mut FreeFrameList,
+    ) -> (TranslationResult, FaultFlags) {
+        if let Some(denied) = self.check_access(access, pm) {
+            return (denied, FaultFlags::default());
+        }
+        let va = VirtualAddress::decompose(pm.layout(), access.va);
+        let (result, mut faults) = translate_with_demand_paging_faults(&va, pm, disk, ffl);
+        let (result, cow_fault) = copy_on_write(access.kind, &va, result, pm, ffl);
+        faults.cow_fault = cow_fault;
+        record_access(access, &result, pm);
+        (result, faults)
+    }
+
+    // Creates a child with its own segment table and a private copy of every
+    // page table, sharing all resident pages copy-on-write with the parent.
+    // Pages still on disk are shared through their block until faulted in.
+    pub fn fork(&mut self, parent: Pid, pm: &mut PhysicalMemory, ffl: &mut FreeFrameList) -> Result<Pid, String> {
+        let parent_st = self.process(parent)?.st_frame;
+        let active = pm.st_frame();
+        pm.set_st_frame(parent_st);
+        let segments: Vec<(u32, i32, i32)> = (0..MAX_SEGMENTS as u32)
+            .map(|s| (s, pm.get_segment_size(s), pm.get_segment_pt_location(s)))
+            .filter(|&(_, size, pt_location)| size != 0 || pt_location != 0)
+            .collect();
+        let huge = segments.iter().find(|&&(s, _, _)| pm.is_huge_segment(s)).map(|&(s, _, _)| s);
+        pm.set_st_frame(active);
+        if let Some(s) = huge {
+            return Err(format!("Segment {} of process {} is mapped by a huge page and cannot be forked", s, parent));
+        }
+        if let Some(&(s, _, _)) = segments.iter().find(|&&(_, _, pt_location)| pt_location < 0) {
+            return Err(format!("Segment {} of process {} must have a resident page table to be forked", s, parent));
+        }
+        if ffl.free_count() < ST_FRAMES + segments.len() {
+            return Err(format!("Not enough free frames to fork process {}", parent));
+        }
+
+        let child = self.spawn(pm, ffl)?;
+        let child_st = self.process(child)?.st_frame;
+        for (s, size, pt_location) in segments {
+            pm.set_st_frame(parent_st);
+            let protection = pm.segment_protection(s);
+            pm.set_st_frame(child_st);
+            let child_pt = ffl.allocate().ok_or_else(|| format!("Not enough free frames to fork process {}", parent))?;
+            pm.frame_table_mut().set_owner(child_pt, FrameOwner::PageTable(s));
+            pm.set_segment_entry(s, size, child_pt as i32, protection);
+            let layout = *pm.layout();
+            for p in 0..layout.pt_size() as u32 {
+                pm.set_page_entry(child_pt as i32, p, 0);
+            }
+            let pages = (size.max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size()) as u32;
+            for p in 0..pages {
+                let entry = pm.get_page_frame(pt_location, p);
+                pm.set_page_entry(child_pt as i32, p, entry);
+                if entry > 0 {
+                    pm.set_cow(pt_location, p, true);
+                    pm.set_cow(child_pt as i32, p, true);
+                }
+            }
+        }
+        pm.set_st_frame(active);
+        let inherited: Vec<(u32, Permissions)> =
+            self.acl.iter().filter(|((pid, _), _)| *pid == parent).map(|(&(_, s), &p)| (s, p)).collect();
+        for (s, permissions) in inherited {
+            self.acl.insert((child, s), permissions);
+        }
+        Ok(child)
+    }
+}
+
+impl Default for ProcessTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
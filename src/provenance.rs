@@ -0,0 +1,88 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::memory::PhysicalMemory;
+use crate::translation::{FaultFlags, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Provenance {
+    pub st_address: Option<usize>,
+    pub segment_size: i32,
+    pub pt_location: i32,
+    pub pt_frame: Option<u32>,
+    pub pte_address: Option<usize>,
+    pub pte: Option<i32>,
+    pub faults: FaultFlags,
+    pub tlb_hit: bool,
+}
+
+impl Provenance {
+    pub fn capture(va: &VirtualAddress, pm: &PhysicalMemory, faults: FaultFlags, tlb_hit: bool) -> Self {
+        let segment_size = pm.get_segment_size(va.s);
+        let pt_location = pm.get_segment_pt_location(va.s);
+        let walked_pt = pt_location > 0 && va.pw < segment_size as u32 && !pm.is_huge_segment(va.s);
+        let pte_address = walked_pt.then(|| pm.pt_base(pt_location) + va.p as usize);
+        Provenance {
+            st_address: pm.st_entry_address(va.s),
+            segment_size,
+            pt_location,
+            pt_frame: walked_pt.then(|| pm.pt_frame(pt_location)),
+            pte_address,
+            pte: walked_pt.then(|| pm.get_page_frame(pt_location, va.p)),
+            faults,
+            tlb_hit,
+        }
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.st_address {
+            Some(address) => write!(f, "st[{}]={},{}", address, self.segment_size, self.pt_location)?,
+            None => write!(f, "st[disk]")?,
+        }
+        if let (Some(frame), Some(address), Some(pte)) = (self.pt_frame, self.pte_address, self.pte) {
+            write!(f, " pt={} pte[{}]={}", frame, address, pte)?;
+        }
+        let hard_faults: Vec<&str> = [
+            (self.faults.st_fault, "st"),
+            (self.faults.pd_fault, "pd"),
+            (self.faults.pt_fault, "pt"),
+            (self.faults.page_fault, "page"),
+        ]
+        .iter()
+        .filter(|(taken, _)| *taken)
+        .map(|&(_, kind)| kind)
+        .collect();
+        if !hard_faults.is_empty() {
+            write!(f, " fault={}", hard_faults.join("+"))?;
+        }
+        if self.faults.soft_fault {
+            write!(f, " fault=soft")?;
+        }
+        if self.faults.zero_fault {
+            write!(f, " fault=zero")?;
+        }
+        if self.faults.compressed_fault {
+            write!(f, " fault=compressed")?;
+        }
+        if self.faults.cow_fault {
+            write!(f, " cow")?;
+        }
+        if self.tlb_hit {
+            write!(f, " tlb")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn write_provenance<P: AsRef<Path>>(path: P, records: &[Provenance]) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("Failed to write provenance file: {}", e);
+    let mut writer = BufWriter::new(File::create(path.as_ref()).map_err(error)?);
+    for (index, record) in records.iter().enumerate() {
+        writeln!(writer, "{} {}", index, record).map_err(error)?;
+    }
+    writer.flush().map_err(error)
+}
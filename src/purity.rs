@@ -0,0 +1,60 @@
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::translation::{translate, translate_with_demand_paging_faults, FaultFlags, TranslationResult, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateHash {
+    pub pm: u64,
+    pub disk: u64,
+    pub ffl: u64,
+}
+
+impl StateHash {
+    pub fn capture(pm: &PhysicalMemory, disk: &Disk, ffl: &FreeFrameList) -> Self {
+        StateHash { pm: pm.state_hash(), disk: disk.state_hash(), ffl: ffl.state_hash() }
+    }
+}
+
+fn is_resident(va: &VirtualAddress, pm: &PhysicalMemory) -> bool {
+    let size = pm.get_segment_size(va.s);
+    let pt_location = pm.get_segment_pt_location(va.s);
+    va.pw < size.max(0) as u32 && pt_location > 0 && (pm.is_huge_segment(va.s) || pm.get_page_frame(pt_location, va.p) > 0)
+}
+
+pub fn translate_pure(va: &VirtualAddress, pm: &PhysicalMemory) -> Result<TranslationResult, String> {
+    let before = pm.state_hash();
+    let result = translate(va, pm, None);
+    if pm.state_hash() != before {
+        return Err(format!("Purity violation: translate mutated physical memory for ({}, {}, {})", va.s, va.p, va.w));
+    }
+    Ok(result)
+}
+
+pub fn translate_with_demand_paging_checked(
+    va: &VirtualAddress,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+) -> Result<(TranslationResult, FaultFlags), String> {
+    let resident = is_resident(va, pm);
+    let free_before = ffl.free_count();
+    let before = StateHash::capture(pm, disk, ffl);
+
+    let (result, faults) = translate_with_demand_paging_faults(va, pm, disk, ffl);
+
+    let after = StateHash::capture(pm, disk, ffl);
+    if after.disk != before.disk {
+        return Err(format!("Purity violation: translation of ({}, {}, {}) mutated the disk", va.s, va.p, va.w));
+    }
+    if resident && (ffl.free_count() != free_before || after.ffl != before.ffl) {
+        return Err(format!(
+            "Purity violation: resident mapping ({}, {}) allocated {} frame(s)",
+            va.s,
+            va.p,
+            free_before.saturating_sub(ffl.free_count())
+        ));
+    }
+    if resident && after.pm != before.pm {
+        return Err(format!("Purity violation: resident mapping ({}, {}) mutated physical memory", va.s, va.p));
+    }
+    Ok((result, faults))
+}
@@ -0,0 +1,167 @@
+use std::io::{self, BufRead, Write};
+
+use crate::constants::*;
+use crate::inspect::FrameMap;
+use crate::io::parse_virtual_address;
+use crate::memory::PageTableEntry;
+use crate::translation::{Access, Protection, TranslationResult};
+use crate::vm_manager::VMManager;
+
+const REPL_HELP: &str = "\
+Commands:
+  <va>              translate a virtual address (read)
+  r <va> | w <va>   translate a tagged read or write access
+  dump st           list the non-empty segment table entries
+  dump pt <s>       list the non-empty page table entries of segment s
+  owner <frame>     show what a physical frame holds
+  free              show the number of free frames
+  map               draw every frame as one character (see inspect --map)
+  map on | map off  redraw the map after each translation, marking new frames
+  stats             show translation statistics
+  help              show this message
+  quit              leave the REPL";
+
+fn output_error(e: io::Error) -> String {
+    format!("Failed to write output: {}", e)
+}
+
+
+fn describe(result: &TranslationResult) -> String {
+    match result {
+        TranslationResult::Success(pa) => pa.to_string(),
+        _ => format!("{} ({})", result.to_output(), result),
+    }
+}
+
+// A translation's result, followed by the frame map when it is being redrawn
+// after each one; `before` is the map from just before the translation.
+fn translated(result: TranslationResult, vm: &VMManager, before: Option<FrameMap>) -> Vec<String> {
+    let mut lines = vec![describe(&result)];
+    if let Some(before) = before {
+        lines.push(FrameMap::capture(vm.memory()).since(&before).to_string());
+    }
+    lines
+}
+
+fn dump_st(vm: &VMManager) -> Vec<String> {
+    let pm = vm.memory();
+    (0..MAX_SEGMENTS as u32)
+        .filter_map(|s| {
+            let size = pm.get_segment_size(s);
+            let pt_location = pm.get_segment_pt_location(s);
+            if size == 0 && pt_location == 0 {
+                return None;
+            }
+            if pm.is_huge_segment(s) {
+                return Some(format!("segment {}: size {}, huge page at frame {}", s, size, pt_location));
+            }
+            let location = if pt_location < 0 {
+                format!("disk block {}", -pt_location)
+            } else {
+                format!("frame {}", pm.pt_frame(pt_location))
+            };
+            Some(format!("segment {}: size {}, PT in {}", s, size, location))
+        })
+        .collect()
+}
+
+fn dump_pt(vm: &VMManager, s: u32) -> Result<Vec<String>, String> {
+    if s >= MAX_SEGMENTS as u32 {
+        return Err(format!("Segment number {} exceeds max {}", s, MAX_SEGMENTS - 1));
+    }
+    let pm = vm.memory();
+    let size = pm.get_segment_size(s);
+    let pt_location = pm.get_segment_pt_location(s);
+    if size == 0 && pt_location == 0 {
+        return Err(format!("Segment {} is not present", s));
+    }
+    if pm.is_huge_segment(s) {
+        return Err(format!("Segment {} is mapped by a huge page at frame {} and has no page table", s, pt_location));
+    }
+    let layout = pm.layout();
+    let pages = (size.max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size()) as u32;
+    Ok((0..pages)
+        .filter_map(|p| {
+            match pm.read_page_entry(vm.disk(), pt_location, p) {
+                0 => None,
+                PTE_DEMAND_ZERO => Some(format!("page {}: demand-zero", p)),
+                e if e < 0 => Some(format!("page {}: disk block {}", p, -e)),
+                e => Some(format!("page {}: frame {}{}", p, e, pte_flags(pm.page_entry(pt_location, p)))),
+            }
+        })
+        .collect())
+}
+
+fn pte_flags(entry: PageTableEntry) -> String {
+    let mut flags: Vec<String> = [(entry.is_dirty(), "dirty"), (entry.is_referenced(), "referenced"), (entry.is_cow(), "cow")]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|&(_, flag)| flag.to_string())
+        .collect();
+    if entry.protection() != Protection::ALL {
+        flags.push(entry.protection().to_string());
+    }
+    match flags.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", flags.join(", ")),
+    }
+}
+
+fn execute(vm: &mut VMManager, line: &str, show_map: &mut bool) -> Result<Option<Vec<String>>, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let before = show_map.then(|| FrameMap::capture(vm.memory()));
+    let lines = match tokens.as_slice() {
+        [] => Vec::new(),
+        ["quit"] | ["exit"] => return Ok(None),
+        ["help"] => vec![REPL_HELP.to_string()],
+        ["dump", "st"] => dump_st(vm),
+        ["dump", "pt", s] => dump_pt(vm, s.parse().map_err(|_| format!("Invalid segment number: {}", s))?)?,
+        ["owner", frame] => {
+            let frame: u32 = frame.parse().map_err(|_| format!("Invalid frame number: {}", frame))?;
+            if frame as usize >= NUM_FRAMES {
+                return Err(format!("Frame number {} exceeds max {}", frame, NUM_FRAMES - 1));
+            }
+            vec![format!("frame {}: {}", frame, vm.memory().who_owns(frame))]
+        }
+        ["free"] => vec![format!("{} free frames", vm.free_frames().free_count())],
+        ["stats"] => vec![vm.stats().to_string()],
+        ["map"] => vec![FrameMap::capture(vm.memory()).to_string()],
+        ["map", "on"] => {
+            *show_map = true;
+            vec!["map redrawn after each translation".to_string()]
+        }
+        ["map", "off"] => {
+            *show_map = false;
+            vec!["map off".to_string()]
+        }
+        ["r" | "R", va] => translated(vm.access(&Access::read(parse_virtual_address(va)?)), vm, before),
+        ["w" | "W", va] => translated(vm.access(&Access::write(parse_virtual_address(va)?)), vm, before),
+        [va] => translated(vm.translate(parse_virtual_address(va)?), vm, before),
+        _ => return Err(format!("Unknown command: {} (try help)", line.trim())),
+    };
+    Ok(Some(lines))
+}
+
+pub fn run_repl<R: BufRead, W: Write>(vm: &mut VMManager, input: R, mut output: W, prompt: bool) -> Result<(), String> {
+    let mut lines = input.lines();
+    let mut show_map = false;
+    loop {
+        if prompt {
+            write!(output, "vm> ").map_err(output_error)?;
+            output.flush().map_err(output_error)?;
+        }
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| format!("Failed to read input: {}", e))?,
+            None => return Ok(()),
+        };
+        match execute(vm, &line, &mut show_map) {
+            Ok(Some(reply)) => {
+                for text in reply {
+                    writeln!(output, "{}", text).map_err(output_error)?;
+                }
+            }
+            Ok(None) => return Ok(()),
+            Err(e) => writeln!(output, "Error: {}", e).map_err(output_error)?,
+        }
+    }
+}
@@ -0,0 +1,726 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::constants::*;
+use crate::memory::{Disk, FrameOwner, FreeFrameList, PhysicalMemory, StSlice};
+use crate::compression::CompressedPool;
+use crate::layout::AddressLayout;
+use crate::swap::{SwapAllocator, SwapTier, SwapTiers, TierStats};
+use crate::translation::{translate_with_frame_source, FaultFlags, FrameRequest, TranslationResult, VirtualAddress};
+
+pub type PageId = (u32, u32);
+
+pub trait ReplacementPolicy {
+    fn name(&self) -> &'static str;
+
+    fn loaded(&mut self, _page: PageId, _time: usize) {}
+
+    fn accessed(&mut self, _page: PageId, _time: usize) {}
+
+    fn evicted(&mut self, _page: PageId) {}
+
+    // Policies that read reference bits may also clear them, as second chance does.
+    fn victim(&mut self, resident: &[PageId], time: usize, pm: &mut PhysicalMemory) -> Option<PageId>;
+}
+
+#[derive(Debug, Default)]
+pub struct FifoReplacement {
+    loaded_at: HashMap<PageId, usize>,
+}
+
+impl FifoReplacement {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ReplacementPolicy for FifoReplacement {
+    fn name(&self) -> &'static str {
+        "fifo"
+    }
+
+    fn loaded(&mut self, page: PageId, time: usize) {
+        self.loaded_at.insert(page, time);
+    }
+
+    fn evicted(&mut self, page: PageId) {
+        self.loaded_at.remove(&page);
+    }
+
+    fn victim(&mut self, resident: &[PageId], _time: usize, _pm: &mut PhysicalMemory) -> Option<PageId> {
+        resident.iter().copied().min_by_key(|page| self.loaded_at.get(page).copied().unwrap_or(0))
+    }
+}
+
+// Evicts the page whose frame has the oldest last-used counter in the frame table.
+// Page table frames carry no page owner, so they are never candidates.
+#[derive(Debug, Default)]
+pub struct LruReplacement;
+
+impl LruReplacement {
+    pub fn new() -> Self {
+        LruReplacement
+    }
+}
+
+impl ReplacementPolicy for LruReplacement {
+    fn name(&self) -> &'static str {
+        "lru"
+    }
+
+    fn victim(&mut self, resident: &[PageId], _time: usize, pm: &mut PhysicalMemory) -> Option<PageId> {
+        let frames = pm.frame_table();
+        frames
+            .pages()
+            .filter(|(_, page)| resident.binary_search(page).is_ok())
+            .min_by_key(|&(frame, page)| (frames.last_used(frame), page))
+            .map(|(_, page)| page)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OptimalReplacement {
+    uses: HashMap<PageId, Vec<usize>>,
+}
+
+impl OptimalReplacement {
+    pub fn new(trace: &[u32], layout: &AddressLayout) -> Self {
+        let mut uses: HashMap<PageId, Vec<usize>> = HashMap::new();
+        for (time, &raw) in trace.iter().enumerate() {
+            let va = VirtualAddress::decompose(layout, raw);
+            uses.entry((va.s, va.p)).or_default().push(time);
+        }
+        OptimalReplacement { uses }
+    }
+
+    pub fn next_use(&self, page: PageId, time: usize) -> Option<usize> {
+        let uses = self.uses.get(&page)?;
+        uses.get(uses.partition_point(|&t| t <= time)).copied()
+    }
+}
+
+impl ReplacementPolicy for OptimalReplacement {
+    fn name(&self) -> &'static str {
+        "opt"
+    }
+
+    fn victim(&mut self, resident: &[PageId], time: usize, _pm: &mut PhysicalMemory) -> Option<PageId> {
+        resident
+            .iter()
+            .copied()
+            .max_by_key(|&page| (self.next_use(page, time).unwrap_or(usize::MAX), std::cmp::Reverse(page)))
+    }
+}
+
+// The NRU class of a resident page from its frame's reference and dirty bits:
+// 0 not referenced and clean, 1 not referenced but dirty, 2 referenced and
+// clean, 3 referenced and dirty.
+pub fn nru_class(pm: &PhysicalMemory, frame: u32) -> usize {
+    2 * pm.is_referenced(frame) as usize + pm.is_dirty(frame) as usize
+}
+
+// Not Recently Used: evicts a page from the lowest nonempty class, the lowest
+// page within it so runs stay deterministic. Only meaningful while something
+// clears the reference bits periodically, e.g. --clear-refs.
+#[derive(Debug, Default)]
+pub struct NruReplacement;
+
+impl NruReplacement {
+    pub fn new() -> Self {
+        NruReplacement
+    }
+}
+
+impl ReplacementPolicy for NruReplacement {
+    fn name(&self) -> &'static str {
+        "nru"
+    }
+
+    fn victim(&mut self, resident: &[PageId], _time: usize, pm: &mut PhysicalMemory) -> Option<PageId> {
+        pm.frame_table()
+            .pages()
+            .filter(|(_, page)| resident.binary_search(page).is_ok())
+            .min_by_key(|&(frame, page)| (nru_class(pm, frame), page))
+            .map(|(_, page)| page)
+    }
+}
+
+// FIFO that gives a referenced page at the head of the queue a second chance:
+// its reference bit is cleared and it moves to the tail.
+#[derive(Debug, Default)]
+pub struct SecondChanceReplacement {
+    queue: VecDeque<PageId>,
+}
+
+impl SecondChanceReplacement {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ReplacementPolicy for SecondChanceReplacement {
+    fn name(&self) -> &'static str {
+        "second-chance"
+    }
+
+    fn loaded(&mut self, page: PageId, _time: usize) {
+        self.queue.retain(|&p| p != page);
+        self.queue.push_back(page);
+    }
+
+    fn evicted(&mut self, page: PageId) {
+        self.queue.retain(|&p| p != page);
+    }
+
+    fn victim(&mut self, resident: &[PageId], _time: usize, pm: &mut PhysicalMemory) -> Option<PageId> {
+        let frames: HashMap<PageId, u32> = pm.frame_table().pages().map(|(frame, page)| (page, frame)).collect();
+        self.queue.retain(|page| resident.binary_search(page).is_ok());
+        // After one full pass every bit is clear, so the second pass always finds one.
+        for _ in 0..2 * self.queue.len() {
+            let page = *self.queue.front()?;
+            match frames.get(&page) {
+                Some(&frame) if pm.is_referenced(frame) => {
+                    pm.clear_referenced(frame);
+                    self.queue.rotate_left(1);
+                }
+                _ => return Some(page),
+            }
+        }
+        self.queue.front().copied()
+    }
+}
+
+pub fn parse_policy(name: &str, trace: &[u32], layout: &AddressLayout) -> Result<Box<dyn ReplacementPolicy>, String> {
+    match name {
+        "fifo" => Ok(Box::new(FifoReplacement::new())),
+        "lru" => Ok(Box::new(LruReplacement::new())),
+        "opt" => Ok(Box::new(OptimalReplacement::new(trace, layout))),
+        "nru" => Ok(Box::new(NruReplacement::new())),
+        "second-chance" => Ok(Box::new(SecondChanceReplacement::new())),
+        _ => Err(format!("Unknown replacement policy: {}", name)),
+    }
+}
+
+// How the resident pages were spread over the four NRU classes each time a
+// victim was chosen, and which class each victim came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NruClasses {
+    pub samples: u64,
+    pub occupancy: [u64; 4],
+    pub evicted: [u64; 4],
+}
+
+impl NruClasses {
+    pub fn mean_occupancy(&self, class: usize) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.occupancy[class] as f64 / self.samples as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResidentPage {
+    pub frame: u32,
+    pub block: usize,
+}
+
+// Only pages and page tables faulted in from a disk block are replaceable:
+// evicting one writes it back and points its PT or ST entry at that block again.
+// A page table is only evicted once it maps no resident page, and never while its
+// segment is being translated, so no resident page is left behind a PT on disk.
+// Such idle page tables are reclaimed before the policy is asked for a page.
+// Pages that were resident from the init file stay pinned.
+//
+// An evicted page keeps its contents in the freed frame, which goes on a standby
+// list. New allocations prefer frames that are not on standby, so a page that is
+// referenced again before its frame is reused is reclaimed as a soft fault.
+pub struct Replacer {
+    policy: Box<dyn ReplacementPolicy>,
+    resident: BTreeMap<PageId, ResidentPage>,
+    page_tables: BTreeMap<u32, ResidentPage>,
+    standby: VecDeque<(u32, PageId)>,
+    evicted: Vec<PageId>,
+    classes: NruClasses,
+    free_target: usize,
+    swap: Option<SwapAllocator>,
+    tiers: Option<SwapTiers>,
+    tier_stats: TierStats,
+    // Accesses to each resident page since it was loaded, when tiers are set.
+    uses: HashMap<PageId, u64>,
+    pool: Option<CompressedPool>,
+    time: usize,
+    pub evictions: u64,
+    pub pt_evictions: u64,
+    pub writebacks: u64,
+    pub soft_faults: u64,
+}
+
+impl Replacer {
+    pub fn new(policy: Box<dyn ReplacementPolicy>) -> Self {
+        Replacer {
+            policy,
+            resident: BTreeMap::new(),
+            page_tables: BTreeMap::new(),
+            standby: VecDeque::new(),
+            evicted: Vec::new(),
+            classes: NruClasses::default(),
+            free_target: 0,
+            swap: None,
+            tiers: None,
+            tier_stats: TierStats::default(),
+            uses: HashMap::new(),
+            pool: None,
+            time: 0,
+            evictions: 0,
+            pt_evictions: 0,
+            writebacks: 0,
+            soft_faults: 0,
+        }
+    }
+
+    pub fn set_free_target(&mut self, frames: usize) {
+        self.free_target = frames;
+    }
+
+    pub fn free_target(&self) -> usize {
+        self.free_target
+    }
+
+    pub fn policy_name(&self) -> &'static str {
+        self.policy.name()
+    }
+
+    pub fn classes(&self) -> &NruClasses {
+        &self.classes
+    }
+
+    // Set up the first time an evicted page needs a block of its own.
+    pub fn swap(&self) -> Option<&SwapAllocator> {
+        self.swap.as_ref()
+    }
+
+    pub fn set_tiers(&mut self, tiers: SwapTiers) {
+        self.tiers = Some(tiers);
+    }
+
+    pub fn tiers(&self) -> Option<&SwapTiers> {
+        self.tiers.as_ref()
+    }
+
+    pub fn tier_stats(&self) -> &TierStats {
+        &self.tier_stats
+    }
+
+    pub fn set_pool(&mut self, pool: CompressedPool) {
+        self.pool = Some(pool);
+    }
+
+    pub fn pool(&self) -> Option<&CompressedPool> {
+        self.pool.as_ref()
+    }
+
+    // Hands the disk blocks of freed pages back to swap.
+    pub fn release_blocks(&mut self, blocks: &[usize]) {
+        if let Some(swap) = self.swap.as_mut() {
+            for &block in blocks {
+                swap.release(block);
+            }
+        }
+    }
+
+    pub fn resident(&self) -> &BTreeMap<PageId, ResidentPage> {
+        &self.resident
+    }
+
+    pub fn page_tables(&self) -> &BTreeMap<u32, ResidentPage> {
+        &self.page_tables
+    }
+
+    // Stops tracking a page that was unmapped outside the replacer, e.g. by a
+    // segment shrink, so it is never chosen as a victim.
+    pub fn forget(&mut self, page: PageId) {
+        if self.resident.remove(&page).is_some() {
+            self.policy.evicted(page);
+        }
+        self.uses.remove(&page);
+        self.standby.retain(|&(_, p)| p != page);
+        if let Some(pool) = self.pool.as_mut() {
+            pool.remove(page);
+        }
+    }
+
+    pub fn forget_page_table(&mut self, s: u32) {
+        self.page_tables.remove(&s);
+    }
+
+    // Pages evicted since the last call, whose cached translations are now stale.
+    pub fn take_evicted(&mut self) -> Vec<PageId> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    pub fn translate(
+        &mut self,
+        va: &VirtualAddress,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        ffl: &mut FreeFrameList,
+    ) -> (TranslationResult, FaultFlags) {
+        let time = self.time;
+        self.time += 1;
+
+        let soft_fault = self.reclaim(va, pm, ffl);
+        if soft_fault {
+            self.policy.loaded((va.s, va.p), time);
+        }
+
+        let needed = frames_needed(va, pm, disk);
+        while ffl.free_count() < needed && self.evict(pm, disk, ffl, Some(va.s)) {}
+        let compressed_fault = self.decompress(va, pm, ffl);
+        if compressed_fault {
+            self.policy.loaded((va.s, va.p), time);
+        }
+
+        let mut loaded = None;
+        let mut loaded_pt = None;
+        let standby = &mut self.standby;
+        let (result, mut faults) = translate_with_frame_source(va, pm, disk, |pm, request| {
+            let frame = take_frame(ffl, standby)?;
+            match request {
+                // ST slices stay resident once faulted in.
+                FrameRequest::SegmentTable { .. } => {}
+                FrameRequest::PageTable { s } => {
+                    let block = (-pm.get_segment_pt_location(s)) as usize;
+                    loaded_pt = Some((s, ResidentPage { frame, block }));
+                }
+                FrameRequest::Page { s, p } => {
+                    let entry = pm.get_page_frame(pm.get_segment_pt_location(s), p);
+                    loaded = Some(((s, p), ResidentPage { frame, block: backing_block(entry) }));
+                }
+            }
+            Some(frame)
+        });
+
+        if let (Some((s, page_table)), true) = (loaded_pt, faults.pt_fault) {
+            self.page_tables.insert(s, page_table);
+        }
+        if let (Some((page, resident)), true) = (loaded, faults.page_fault || faults.zero_fault) {
+            if let (Some(tiers), true) = (self.tiers.as_ref(), faults.page_fault) {
+                faults.slow_tier = tiers.tier_of(resident.block) == SwapTier::Slow;
+                match faults.slow_tier {
+                    true => self.tier_stats.slow_reads += 1,
+                    false => self.tier_stats.fast_reads += 1,
+                }
+            }
+            if let (Some(pool), true) = (self.pool.as_mut(), faults.page_fault) {
+                pool.record_miss();
+            }
+            self.resident.insert(page, resident);
+            self.policy.loaded(page, time);
+        }
+        if let TranslationResult::Success(_) = result {
+            self.policy.accessed((va.s, va.p), time);
+            if self.tiers.is_some() {
+                *self.uses.entry((va.s, va.p)).or_default() += 1;
+            }
+        }
+        faults.soft_fault = soft_fault;
+        faults.compressed_fault = compressed_fault;
+
+        while ffl.free_count() < self.free_target && self.evict(pm, disk, ffl, Some(va.s)) {}
+        (result, faults)
+    }
+
+    // An access the TLB served without a walk still uses the page: it takes a
+    // time step, so OPT's position in the trace stays in step with the input,
+    // and counts as an access for the policy and the tiers.
+    pub fn record_hit(&mut self, va: &VirtualAddress) {
+        let time = self.time;
+        self.time += 1;
+        self.policy.accessed((va.s, va.p), time);
+        if self.tiers.is_some() {
+            *self.uses.entry((va.s, va.p)).or_default() += 1;
+        }
+    }
+
+    fn reclaim(&mut self, va: &VirtualAddress, pm: &mut PhysicalMemory, ffl: &mut FreeFrameList) -> bool {
+        let page = (va.s, va.p);
+        let position = match self.standby.iter().position(|&(_, p)| p == page) {
+            Some(position) => position,
+            None => return false,
+        };
+        let pt_location = pm.get_segment_pt_location(va.s);
+        if pt_location <= 0 || va.pw >= pm.get_segment_size(va.s) as u32 {
+            return false;
+        }
+        let entry = pm.get_page_frame(pt_location, va.p);
+        let (frame, _) = self.standby.remove(position).unwrap();
+        if entry >= 0 || !ffl.take(frame) {
+            return false;
+        }
+        pm.set_page_entry(pt_location, va.p, frame as i32);
+        pm.frame_table_mut().assign(frame, va.s, va.p);
+        self.resident.insert(page, ResidentPage { frame, block: backing_block(entry) });
+        if let Some(pool) = self.pool.as_mut() {
+            pool.remove(page);
+        }
+        self.soft_faults += 1;
+        true
+    }
+
+    // Faults a page in from the compressed pool instead of its disk block.
+    fn decompress(&mut self, va: &VirtualAddress, pm: &mut PhysicalMemory, ffl: &mut FreeFrameList) -> bool {
+        let page = (va.s, va.p);
+        let pool = match self.pool.as_mut() {
+            Some(pool) if pool.contains(page) => pool,
+            _ => return false,
+        };
+        let pt_location = pm.get_segment_pt_location(va.s);
+        if pt_location <= 0 || va.pw >= pm.get_segment_size(va.s) as u32 {
+            return false;
+        }
+        let entry = pm.get_page_frame(pt_location, va.p);
+        if entry >= 0 || entry == PTE_DEMAND_ZERO {
+            return false;
+        }
+        let Some(frame) = take_frame(ffl, &mut self.standby) else { return false };
+        let compressed = pool.load(page, frame, pm).unwrap();
+        pm.clear_referenced(frame);
+        match compressed.dirty {
+            true => pm.mark_dirty(frame),
+            false => pm.clear_dirty(frame),
+        }
+        pm.set_page_entry(pt_location, va.p, frame as i32);
+        pm.frame_table_mut().assign(frame, va.s, va.p);
+        self.resident.insert(page, ResidentPage { frame, block: compressed.block });
+        true
+    }
+
+    pub fn evict_one(&mut self, pm: &mut PhysicalMemory, disk: &mut Disk, ffl: &mut FreeFrameList) -> bool {
+        self.evict(pm, disk, ffl, None)
+    }
+
+    // Like evict_one, but never takes the page table of segment s.
+    pub fn evict_sparing(&mut self, s: u32, pm: &mut PhysicalMemory, disk: &mut Disk, ffl: &mut FreeFrameList) -> bool {
+        self.evict(pm, disk, ffl, Some(s))
+    }
+
+    fn evict(
+        &mut self,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        ffl: &mut FreeFrameList,
+        active: Option<u32>,
+    ) -> bool {
+        if self.evict_page_table(pm, disk, ffl, active) {
+            return true;
+        }
+        let candidates: Vec<PageId> = self.resident.keys().copied().collect();
+        for page in self.resident.values() {
+            self.classes.occupancy[nru_class(pm, page.frame)] += 1;
+        }
+        self.classes.samples += 1;
+        let victim = match self.policy.victim(&candidates, self.time.saturating_sub(1), pm) {
+            Some(victim) => victim,
+            None => return false,
+        };
+        // A demand-zero page that was never written goes back to being one; once
+        // written it needs a swap block like any other page.
+        let mut block = self.resident[&victim].block;
+        let frame = self.resident[&victim].frame;
+        let owner = FrameOwner::Page(victim.0, victim.1);
+        let uses = self.uses.remove(&victim).unwrap_or(0);
+        let target = self.tiers.map(|tiers| (tiers, tiers.placement(uses)));
+        if block == 0 && pm.is_dirty(frame) {
+            let blocks = target.map_or(1..DISK_BLOCKS, |(tiers, tier)| tiers.blocks(tier));
+            let swap = self.swap_allocator(pm, disk);
+            block = match swap.assign_in(owner, blocks).or_else(|| swap.assign(owner)) {
+                Some(block) => block,
+                None => return false,
+            };
+        } else if let Some((tiers, tier)) = target.filter(|&(tiers, tier)| block != 0 && tiers.tier_of(block) != tier) {
+            // Moving tiers rewrites the whole page to its new block.
+            match self.swap_allocator(pm, disk).assign_in(owner, tiers.blocks(tier)) {
+                Some(moved) => {
+                    disk.store_page_to_disk(moved, frame, pm);
+                    self.swap_allocator(pm, disk).release(block);
+                    block = moved;
+                    match tier {
+                        SwapTier::Fast => self.tier_stats.promotions += 1,
+                        SwapTier::Slow => self.tier_stats.demotions += 1,
+                    }
+                }
+                None => self.tier_stats.refused += 1,
+            }
+        }
+        let page = self.resident.remove(&victim).unwrap();
+        self.classes.evicted[nru_class(pm, page.frame)] += 1;
+        // A page kept in the compressed pool is only written back once the pool
+        // pushes it out.
+        let pooled = block != 0 && self.pool.as_mut().is_some_and(|pool| pool.store(victim, block, page.frame, pm, disk));
+        let entry = if block == 0 {
+            PTE_DEMAND_ZERO
+        } else {
+            if !pooled && disk.write_back_if_dirty(block, page.frame, pm) {
+                self.writebacks += 1;
+            }
+            -(block as i32)
+        };
+        let (s, p) = victim;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(s, p, frame = page.frame, block, pooled, policy = self.policy.name(), "page evicted");
+        pm.set_page_entry(pm.get_segment_pt_location(s), p, entry);
+        pm.clear_referenced(page.frame);
+        pm.frame_table_mut().release(page.frame);
+        ffl.release(page.frame);
+        self.standby.push_back((page.frame, victim));
+        self.evicted.push(victim);
+        self.policy.evicted(victim);
+        self.evictions += 1;
+        true
+    }
+
+    // Set up from the tables, plus the blocks behind whatever is resident, the
+    // first time it is needed.
+    fn swap_allocator(&mut self, pm: &PhysicalMemory, disk: &Disk) -> &mut SwapAllocator {
+        let (resident, page_tables) = (&self.resident, &self.page_tables);
+        self.swap.get_or_insert_with(|| {
+            let mut swap = SwapAllocator::from_tables(pm, disk);
+            for (&(s, p), page) in resident {
+                swap.reserve(page.block, FrameOwner::Page(s, p));
+            }
+            for (&s, page_table) in page_tables {
+                swap.reserve(page_table.block, FrameOwner::PageTable(s));
+            }
+            swap
+        })
+    }
+
+    fn evict_page_table(
+        &mut self,
+        pm: &mut PhysicalMemory,
+        disk: &mut Disk,
+        ffl: &mut FreeFrameList,
+        active: Option<u32>,
+    ) -> bool {
+        let victim = self
+            .page_tables
+            .keys()
+            .copied()
+            .find(|&s| Some(s) != active && !maps_resident_pages(pm, s));
+        let s = match victim {
+            Some(s) => s,
+            None => return false,
+        };
+        let page_table = self.page_tables.remove(&s).unwrap();
+        if let Some(pool) = self.pool.as_mut() {
+            pool.flush_segment(s, disk);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(s, frame = page_table.frame, block = page_table.block, "page table evicted");
+        disk.store_pt_to_disk(page_table.block, page_table.frame, pm);
+        pm.set_segment_entry(s, pm.get_segment_size(s), -(page_table.block as i32), pm.segment_protection(s));
+        pm.frame_table_mut().release(page_table.frame);
+        ffl.release(page_table.frame);
+        self.pt_evictions += 1;
+        true
+    }
+}
+
+fn backing_block(entry: i32) -> usize {
+    if entry == PTE_DEMAND_ZERO {
+        0
+    } else {
+        (-entry) as usize
+    }
+}
+
+fn take_frame(ffl: &mut FreeFrameList, standby: &mut VecDeque<(u32, PageId)>) -> Option<u32> {
+    let clean = ffl.frames().iter().rev().copied().find(|&f| standby.iter().all(|&(s, _)| s != f));
+    let frame = match clean {
+        Some(frame) => frame,
+        None => standby.pop_front()?.0,
+    };
+    standby.retain(|&(f, _)| f != frame);
+    ffl.take(frame).then_some(frame)
+}
+
+pub fn frames_needed(va: &VirtualAddress, pm: &PhysicalMemory, disk: &Disk) -> usize {
+    let st_slice = matches!(pm.st_slices()[pm.st_slice_of(va.s)], StSlice::OnDisk(_)) as usize;
+    let (segment_size, pt_location, _) = pm.read_segment_entry(disk, va.s);
+    if (segment_size == 0 && pt_location == 0) || va.pw >= segment_size as u32 || pm.is_huge_segment(va.s) {
+        return st_slice;
+    }
+    if pt_location < 0 {
+        st_slice + 1 + (pm.read_page_entry(disk, pt_location, va.p) < 0) as usize
+    } else {
+        st_slice + (pm.get_page_frame(pt_location, va.p) < 0) as usize
+    }
+}
+
+fn maps_resident_pages(pm: &PhysicalMemory, s: u32) -> bool {
+    let pt_location = pm.get_segment_pt_location(s);
+    let layout = pm.layout();
+    let pages = (pm.get_segment_size(s).max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size()) as u32;
+    pt_location > 0 && (0..pages).any(|p| pm.get_page_frame(pt_location, p) > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::InitData;
+
+    const SEGMENTS: u32 = 8;
+    const PAGES: u32 = 3;
+
+    // Every segment's PT and pages start out on disk, so with only a couple of
+    // frames the replacer has to keep evicting PTs as well as pages.
+    fn paged_out(layout: AddressLayout, frames: usize) -> (PhysicalMemory, Disk, FreeFrameList) {
+        let mut init = InitData::default();
+        for s in 0..SEGMENTS {
+            init.st_entries.push((s, (PAGES as usize * layout.page_size()) as i32, -(100 + s as i32)));
+            for p in 0..PAGES {
+                init.pt_entries.push((s, p, -(200 + (s * PAGES + p) as i32)));
+            }
+        }
+        let mut pm = PhysicalMemory::with_layout(layout);
+        let mut disk = Disk::new();
+        let mut ffl = FreeFrameList::with_capacity(layout.st_frames() + frames);
+        init.apply_to(&mut pm, &mut disk, &mut ffl);
+        (pm, disk, ffl)
+    }
+
+    fn run(layout: AddressLayout, policy: &str, frames: usize) {
+        let (mut pm, mut disk, mut ffl) = paged_out(layout, frames);
+        let mut replacer = Replacer::new(parse_policy(policy, &[], &layout).unwrap());
+        let mut seed = 0x2545_f491u32;
+        for step in 0..500 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let (s, p) = (seed % SEGMENTS, (seed >> 8) % PAGES);
+            let raw = s << (layout.p_bits + layout.w_bits) | p << layout.w_bits | ((seed >> 16) % layout.page_size() as u32);
+            let (result, _) = replacer.translate(&VirtualAddress::decompose(&layout, raw), &mut pm, &mut disk, &mut ffl);
+            assert!(matches!(result, TranslationResult::Success(_)), "{} step {}: {:?}", policy, step, result);
+            for &(s, p) in replacer.resident().keys() {
+                assert!(pm.get_segment_pt_location(s) > 0, "{} step {}: page {}.{} resident with its PT out", policy, step, s, p);
+            }
+        }
+    }
+
+    #[test]
+    fn page_tables_stay_while_their_pages_are_resident() {
+        for policy in ["fifo", "lru", "nru", "second-chance"] {
+            for frames in [2, 3] {
+                run(AddressLayout::DEFAULT, policy, frames);
+            }
+        }
+    }
+
+    #[test]
+    fn page_tables_stay_under_small_pages() {
+        let layout = AddressLayout::new(9, 7, 7).unwrap();
+        for frames in [2, 3] {
+            run(layout, "fifo", frames);
+        }
+    }
+}
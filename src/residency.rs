@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::constants::*;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::translation::{translate_with_frame_source, FaultFlags, FrameRequest, TranslationResult, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResidencyBounds {
+    pub min: usize,
+    pub max: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResidencyPolicy {
+    bounds: HashMap<u32, ResidencyBounds>,
+    resident: HashMap<u32, usize>,
+    pub denied_by_max: u64,
+    pub denied_by_reserve: u64,
+}
+
+impl ResidencyPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_bounds(&mut self, segment: u32, min: usize, max: usize) -> Result<(), String> {
+        if segment >= MAX_SEGMENTS as u32 {
+            return Err(format!("Segment number {} exceeds max {}", segment, MAX_SEGMENTS - 1));
+        }
+        if min > max {
+            return Err(format!("Segment {} minimum residency {} exceeds maximum {}", segment, min, max));
+        }
+        self.bounds.insert(segment, ResidencyBounds { min, max });
+        Ok(())
+    }
+
+    pub fn bounds(&self, segment: u32) -> Option<ResidencyBounds> {
+        self.bounds.get(&segment).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_empty()
+    }
+
+    pub fn count_resident(&mut self, pm: &PhysicalMemory) {
+        self.resident.clear();
+        let layout = pm.layout();
+        for s in 0..layout.max_segments() as u32 {
+            let size = pm.get_segment_size(s);
+            let pt_location = pm.get_segment_pt_location(s);
+            if size <= 0 || pt_location <= 0 {
+                continue;
+            }
+            let pages = (size as usize).div_ceil(layout.page_size()).min(layout.pt_size()) as u32;
+            let resident = match pm.is_huge_segment(s) {
+                true => pages as usize,
+                false => (0..pages).filter(|&p| pm.get_page_frame(pt_location, p) > 0).count(),
+            };
+            if resident > 0 {
+                self.resident.insert(s, resident);
+            }
+        }
+    }
+
+    pub fn resident_pages(&self, segment: u32) -> usize {
+        self.resident.get(&segment).copied().unwrap_or(0)
+    }
+
+    pub fn reserved_frames(&self, excluding: Option<u32>) -> usize {
+        self.bounds
+            .iter()
+            .filter(|(&s, _)| Some(s) != excluding)
+            .map(|(&s, b)| b.min.saturating_sub(self.resident_pages(s)))
+            .sum()
+    }
+
+    pub fn allocate(&mut self, request: FrameRequest, ffl: &mut FreeFrameList) -> Option<u32> {
+        let segment = match request {
+            FrameRequest::SegmentTable { .. } => None,
+            FrameRequest::PageTable { s } | FrameRequest::Page { s, .. } => Some(s),
+        };
+        if let FrameRequest::Page { s, .. } = request {
+            if let Some(bounds) = self.bounds(s) {
+                if self.resident_pages(s) >= bounds.max {
+                    self.denied_by_max += 1;
+                    return None;
+                }
+            }
+        }
+        let usable = ffl.free_count().saturating_sub(self.reserved_frames(segment));
+        if usable == 0 {
+            self.denied_by_reserve += 1;
+            return None;
+        }
+        let frame = ffl.allocate()?;
+        if let FrameRequest::Page { s, .. } = request {
+            *self.resident.entry(s).or_insert(0) += 1;
+        }
+        Some(frame)
+    }
+}
+
+pub fn translate_with_residency(
+    va: &VirtualAddress,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    policy: &mut ResidencyPolicy,
+) -> (TranslationResult, FaultFlags) {
+    translate_with_frame_source(va, pm, disk, |_, request| policy.allocate(request, ffl))
+}
@@ -0,0 +1,261 @@
+use std::ops::Range;
+
+use crate::constants::*;
+use crate::memory::{Disk, FrameOwner, FreeFrameList, PhysicalMemory};
+use crate::replacement::PageId;
+use crate::translation::Protection;
+
+pub const MAX_SEGMENT_SIZE: u32 = (PT_SIZE * PAGE_SIZE) as u32;
+
+// What an unmapping gave back: the pages whose cached translations are now
+// stale, the frames returned to the free list and the disk blocks the segment
+// no longer references (for a `DiskBlockAllocator` to reuse once no forked
+// copy of the tables refers to them either).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FreedMemory {
+    pub pages: Vec<PageId>,
+    pub frames: Vec<u32>,
+    pub blocks: Vec<usize>,
+}
+
+fn pages_in(pm: &PhysicalMemory, size: i32) -> u32 {
+    let layout = pm.layout();
+    (size.max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size()) as u32
+}
+
+// PT entries are read and written wherever the PT currently lives.
+fn page_entry(pm: &PhysicalMemory, disk: &Disk, pt_location: i32, p: u32) -> i32 {
+    pm.read_page_entry(disk, pt_location, p)
+}
+
+fn set_page_entry(pm: &mut PhysicalMemory, disk: &mut Disk, pt_location: i32, p: u32, value: i32) {
+    pm.write_page_entry(disk, pt_location, p, value);
+}
+
+// Clears one PT entry. A resident page's frame goes back to the free list
+// unless it is still shared copy-on-write with another process.
+fn unmap_page(
+    pm: &mut PhysicalMemory,
+    disk: &mut Disk,
+    ffl: &mut FreeFrameList,
+    s: u32,
+    pt_location: i32,
+    p: u32,
+    freed: &mut FreedMemory,
+) {
+    let entry = page_entry(pm, disk, pt_location, p);
+    if entry > 0 {
+        if !(pt_location > 0 && pm.is_cow(pt_location, p)) {
+            pm.frame_table_mut().release(entry as u32);
+            ffl.release(entry as u32);
+            freed.frames.push(entry as u32);
+        }
+        freed.pages.push((s, p));
+    } else if entry == PTE_DEMAND_ZERO {
+        freed.pages.push((s, p));
+    } else if entry < 0 {
+        freed.blocks.push((-entry) as usize);
+        freed.pages.push((s, p));
+    }
+    set_page_entry(pm, disk, pt_location, p, 0);
+}
+
+fn check_segment(pm: &PhysicalMemory, s: u32, new_size: u32) -> Result<(), String> {
+    let layout = pm.layout();
+    if s as usize >= layout.max_segments() {
+        return Err(format!("Segment {} is out of range", s));
+    }
+    if pm.st_entry_address(s).is_none() {
+        return Err(format!("The segment table slice of segment {} is on disk", s));
+    }
+    if pm.is_huge_segment(s) {
+        return Err(format!("Segment {} is mapped by a huge page and cannot be changed", s));
+    }
+    let max_size = (layout.pt_size() * layout.page_size()) as u32;
+    if new_size > max_size {
+        return Err(format!("Segment size {} exceeds the maximum of {}", new_size, max_size));
+    }
+    Ok(())
+}
+
+// Grows segment s to new_size words. A segment that does not exist yet gets a
+// zeroed PT from the free list; the entries of added pages are zeroed, so
+// they stay unmapped until something maps them.
+pub fn grow_segment(
+    pm: &mut PhysicalMemory,
+    disk: &mut Disk,
+    ffl: &mut FreeFrameList,
+    s: u32,
+    new_size: u32,
+) -> Result<(), String> {
+    check_segment(pm, s, new_size)?;
+    let size = pm.get_segment_size(s);
+    if new_size as i32 <= size {
+        return Err(format!("Cannot grow segment {} from {} to {} words", s, size, new_size));
+    }
+    let protection = pm.segment_protection(s);
+    let mut pt_location = pm.get_segment_pt_location(s);
+    if pt_location == 0 {
+        let frame = ffl.allocate().ok_or_else(|| format!("No free frame for the page table of segment {}", s))?;
+        pm.frame_table_mut().set_owner(frame, FrameOwner::PageTable(s));
+        pt_location = frame as i32;
+        for p in 0..pm.layout().pt_size() as u32 {
+            pm.set_page_entry(pt_location, p, 0);
+        }
+    } else if PhysicalMemory::is_compact_pt(pt_location) && pages_in(pm, new_size as i32) > pages_in(pm, size) {
+        return Err(format!("Segment {} has a compacted page table and cannot gain pages", s));
+    }
+    for p in pages_in(pm, size)..pages_in(pm, new_size as i32) {
+        set_page_entry(pm, disk, pt_location, p, 0);
+    }
+    pm.set_segment_entry(s, new_size as i32, pt_location, protection);
+    Ok(())
+}
+
+// Shrinks segment s to new_size words, unmapping the pages wholly past the new end.
+pub fn shrink_segment(
+    pm: &mut PhysicalMemory,
+    disk: &mut Disk,
+    ffl: &mut FreeFrameList,
+    s: u32,
+    new_size: u32,
+) -> Result<FreedMemory, String> {
+    check_segment(pm, s, new_size)?;
+    let size = pm.get_segment_size(s);
+    if new_size as i32 >= size {
+        return Err(format!("Cannot shrink segment {} from {} to {} words", s, size, new_size));
+    }
+    let protection = pm.segment_protection(s);
+    let pt_location = pm.get_segment_pt_location(s);
+    let mut freed = FreedMemory::default();
+    for p in pages_in(pm, new_size as i32)..pages_in(pm, size) {
+        unmap_page(pm, disk, ffl, s, pt_location, p, &mut freed);
+    }
+    pm.set_segment_entry(s, new_size as i32, pt_location, protection);
+    Ok(freed)
+}
+
+// Unmaps page p of segment s; the segment keeps its size.
+pub fn free_page(
+    pm: &mut PhysicalMemory,
+    disk: &mut Disk,
+    ffl: &mut FreeFrameList,
+    s: u32,
+    p: u32,
+) -> Result<FreedMemory, String> {
+    check_segment(pm, s, 0)?;
+    let pt_location = pm.get_segment_pt_location(s);
+    if pt_location == 0 || p >= pages_in(pm, pm.get_segment_size(s)) {
+        return Err(format!("Segment {} has no page {}", s, p));
+    }
+    let mut freed = FreedMemory::default();
+    unmap_page(pm, disk, ffl, s, pt_location, p, &mut freed);
+    Ok(freed)
+}
+
+// Unmaps every page of segment s, releases its page table and clears its ST
+// entry, as when the process owning the segment exits. A compacted PT frame
+// is released only once no other segment keeps its PT there.
+pub fn free_segment(pm: &mut PhysicalMemory, disk: &mut Disk, ffl: &mut FreeFrameList, s: u32) -> Result<FreedMemory, String> {
+    check_segment(pm, s, 0)?;
+    let size = pm.get_segment_size(s);
+    let pt_location = pm.get_segment_pt_location(s);
+    if size == 0 && pt_location == 0 {
+        return Err(format!("Segment {} does not exist", s));
+    }
+    let mut freed = FreedMemory::default();
+    for p in 0..pages_in(pm, size) {
+        unmap_page(pm, disk, ffl, s, pt_location, p, &mut freed);
+    }
+    if pt_location < 0 {
+        freed.blocks.push((-pt_location) as usize);
+    } else if pt_location > 0 {
+        let frame = pm.pt_frame(pt_location);
+        let shared = (0..pm.layout().max_segments() as u32).any(|other| {
+            let location = pm.get_segment_pt_location(other);
+            other != s && location > 0 && pm.pt_frame(location) == frame
+        });
+        if !shared {
+            pm.frame_table_mut().release(frame);
+            ffl.release(frame);
+            freed.frames.push(frame);
+        }
+    }
+    pm.set_segment_entry(s, 0, 0, Protection::default());
+    Ok(freed)
+}
+
+// Resizes segment s in whichever direction new_size calls for.
+pub fn resize_segment(
+    pm: &mut PhysicalMemory,
+    disk: &mut Disk,
+    ffl: &mut FreeFrameList,
+    s: u32,
+    new_size: u32,
+) -> Result<FreedMemory, String> {
+    check_segment(pm, s, new_size)?;
+    let size = pm.get_segment_size(s);
+    match (new_size as i32).cmp(&size) {
+        std::cmp::Ordering::Greater => grow_segment(pm, disk, ffl, s, new_size).map(|_| FreedMemory::default()),
+        std::cmp::Ordering::Less => shrink_segment(pm, disk, ffl, s, new_size),
+        std::cmp::Ordering::Equal => Ok(FreedMemory::default()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backing {
+    // Demand-zero pages, as for an anonymous mapping or a growing heap.
+    ZeroFill,
+    // Consecutive blocks starting at the given one, as for a mapped file.
+    DiskBlocks(usize),
+}
+
+// Maps len words of segment s starting at the page-aligned offset, growing (or
+// creating) the segment to cover them. Every mapped page starts out nonresident,
+// so the demand-paging walk faults each one in on first touch. The pages must
+// not be mapped already. Returns the range of pages mapped.
+pub fn map_region(
+    pm: &mut PhysicalMemory,
+    disk: &mut Disk,
+    ffl: &mut FreeFrameList,
+    s: u32,
+    offset: u32,
+    len: u32,
+    backing: Backing,
+) -> Result<Range<u32>, String> {
+    let end = offset.checked_add(len).ok_or_else(|| format!("Region {}+{} overflows", offset, len))?;
+    check_segment(pm, s, end)?;
+    let page_size = pm.layout().page_size();
+    if !(offset as usize).is_multiple_of(page_size) || len == 0 {
+        return Err(format!("Region offset {} must be page-aligned and its length nonzero", offset));
+    }
+    let pages = offset / page_size as u32..pages_in(pm, end as i32);
+    let size = pm.get_segment_size(s);
+    let pt_location = pm.get_segment_pt_location(s);
+    if pt_location != 0 {
+        let mapped = pages.clone().filter(|&p| p < pages_in(pm, size)).find(|&p| page_entry(pm, disk, pt_location, p) != 0);
+        if let Some(p) = mapped {
+            return Err(format!("Page {} of segment {} is already mapped", p, s));
+        }
+    }
+
+    let entries: Vec<i32> = match backing {
+        Backing::DiskBlocks(first) => {
+            let last = first + pages.len();
+            if first == 0 || last > DISK_BLOCKS {
+                return Err(format!("Disk blocks {}..{} are out of range", first, last));
+            }
+            (first..last).map(|block| -(block as i32)).collect()
+        }
+        Backing::ZeroFill => vec![PTE_DEMAND_ZERO; pages.len()],
+    };
+
+    if end as i32 > size {
+        grow_segment(pm, disk, ffl, s, end)?;
+    }
+    let pt_location = pm.get_segment_pt_location(s);
+    for (p, entry) in pages.clone().zip(entries) {
+        set_page_entry(pm, disk, pt_location, p, entry);
+    }
+    Ok(pages)
+}
@@ -0,0 +1,444 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::translation::{ErrorCodes, TranslationResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultRecord {
+    pub index: usize,
+    pub va: u32,
+    pub result: TranslationResult,
+    pub cycles: Option<u64>,
+    pub tlb_hit: Option<bool>,
+}
+
+pub fn tlb_prefix(hit: bool) -> &'static str {
+    if hit { "h" } else { "m" }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Radix {
+    #[default]
+    Decimal,
+    Hex,
+}
+
+impl Radix {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "dec" | "decimal" => Ok(Radix::Decimal),
+            "hex" => Ok(Radix::Hex),
+            _ => Err(format!("Unknown radix: {} (expected dec or hex)", name)),
+        }
+    }
+
+    // Error results keep their negative codes in either radix.
+    pub fn format(&self, value: i32) -> String {
+        match self {
+            Radix::Hex if value >= 0 => format!("{:#x}", value),
+            _ => value.to_string(),
+        }
+    }
+}
+
+pub trait ResultSink {
+    fn write_result(&mut self, record: &ResultRecord) -> Result<(), String>;
+    fn finish(&mut self) -> Result<(), String>;
+
+    // Sinks whose format has no place for non-decimal numbers ignore the radix.
+    fn set_radix(&mut self, _radix: Radix) {}
+
+    fn set_error_codes(&mut self, _codes: ErrorCodes) {}
+}
+
+fn write_error(e: io::Error) -> String {
+    format!("Failed to write output: {}", e)
+}
+
+fn create_file<P: AsRef<Path>>(path: P) -> Result<BufWriter<File>, String> {
+    File::create(path.as_ref())
+        .map(BufWriter::new)
+        .map_err(|e| format!("Failed to write output file: {}", e))
+}
+
+pub struct TextSink<W: Write> {
+    writer: W,
+    first: bool,
+    radix: Radix,
+    error_codes: ErrorCodes,
+}
+
+pub type FileSink = TextSink<BufWriter<File>>;
+pub type StdoutSink = TextSink<io::Stdout>;
+
+impl<W: Write> TextSink<W> {
+    pub fn new(writer: W) -> Self {
+        TextSink { writer, first: true, radix: Radix::Decimal, error_codes: ErrorCodes::Collapsed }
+    }
+}
+
+impl FileSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Ok(TextSink::new(create_file(path)?))
+    }
+}
+
+impl StdoutSink {
+    pub fn stdout() -> Self {
+        TextSink::new(io::stdout())
+    }
+}
+
+fn text_value(record: &ResultRecord, radix: Radix, codes: ErrorCodes) -> String {
+    let pa = radix.format(record.result.to_output_with(codes));
+    let value = match record.cycles {
+        Some(cycles) => format!("{}:{}", pa, cycles),
+        None => pa,
+    };
+    match record.tlb_hit {
+        Some(hit) => format!("{} {}", tlb_prefix(hit), value),
+        None => value,
+    }
+}
+
+impl<W: Write> ResultSink for TextSink<W> {
+    fn write_result(&mut self, record: &ResultRecord) -> Result<(), String> {
+        if !self.first {
+            self.writer.write_all(b" ").map_err(write_error)?;
+        }
+        self.first = false;
+        self.writer.write_all(text_value(record, self.radix, self.error_codes).as_bytes()).map_err(write_error)
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(write_error)
+    }
+
+    fn set_radix(&mut self, radix: Radix) {
+        self.radix = radix;
+    }
+
+    fn set_error_codes(&mut self, codes: ErrorCodes) {
+        self.error_codes = codes;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressMarker {
+    pub results: usize,
+    pub bytes: u64,
+}
+
+impl ProgressMarker {
+    pub fn path_for(output: &Path) -> PathBuf {
+        let mut name = output.as_os_str().to_os_string();
+        name.push(".progress");
+        PathBuf::from(name)
+    }
+
+    pub fn read(output: &Path) -> Result<Option<Self>, String> {
+        let content = match fs::read_to_string(Self::path_for(output)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read progress marker: {}", e)),
+        };
+        let mut results = None;
+        let mut bytes = None;
+        for token in content.split_whitespace() {
+            if let Some(value) = token.strip_prefix("results=") {
+                results = value.parse().ok();
+            } else if let Some(value) = token.strip_prefix("bytes=") {
+                bytes = value.parse().ok();
+            }
+        }
+        match (results, bytes) {
+            (Some(results), Some(bytes)) => Ok(Some(ProgressMarker { results, bytes })),
+            _ => Err(format!("Malformed progress marker: {}", content.trim())),
+        }
+    }
+
+    fn write(&self, output: &Path) -> Result<(), String> {
+        let path = Self::path_for(output);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let marker_error = |e: io::Error| format!("Failed to write progress marker: {}", e);
+        let mut file = File::create(&tmp).map_err(marker_error)?;
+        writeln!(file, "results={} bytes={}", self.results, self.bytes).map_err(marker_error)?;
+        file.sync_all().map_err(marker_error)?;
+        fs::rename(&tmp, &path).map_err(marker_error)
+    }
+}
+
+pub struct SyncedFileSink {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    sync_every: usize,
+    written: usize,
+    bytes: u64,
+    skip: usize,
+    radix: Radix,
+    error_codes: ErrorCodes,
+}
+
+impl SyncedFileSink {
+    pub fn create<P: AsRef<Path>>(path: P, sync_every: usize) -> Result<Self, String> {
+        Ok(SyncedFileSink {
+            writer: create_file(path.as_ref())?,
+            path: path.as_ref().to_path_buf(),
+            sync_every: sync_every.max(1),
+            written: 0,
+            bytes: 0,
+            skip: 0,
+            radix: Radix::Decimal,
+            error_codes: ErrorCodes::Collapsed,
+        })
+    }
+
+    pub fn resume<P: AsRef<Path>>(path: P, sync_every: usize) -> Result<Self, String> {
+        let path = path.as_ref();
+        let marker = match ProgressMarker::read(path)? {
+            Some(marker) => marker,
+            None => return Self::create(path, sync_every),
+        };
+        let file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("Failed to reopen output file: {}", e))?;
+        let length = file.metadata().map_err(write_error)?.len();
+        if marker.bytes > length {
+            return Err(format!("Progress marker points past the end of {}", path.display()));
+        }
+        file.set_len(marker.bytes).map_err(|e| format!("Failed to truncate output file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        writer.seek(SeekFrom::End(0)).map_err(write_error)?;
+        Ok(SyncedFileSink {
+            writer,
+            path: path.to_path_buf(),
+            sync_every: sync_every.max(1),
+            written: marker.results,
+            bytes: marker.bytes,
+            skip: marker.results,
+            radix: Radix::Decimal,
+            error_codes: ErrorCodes::Collapsed,
+        })
+    }
+
+    pub fn resume_point(&self) -> usize {
+        self.skip
+    }
+
+    fn sync(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(write_error)?;
+        self.writer.get_ref().sync_data().map_err(write_error)?;
+        ProgressMarker { results: self.written, bytes: self.bytes }.write(&self.path)
+    }
+}
+
+impl ResultSink for SyncedFileSink {
+    fn write_result(&mut self, record: &ResultRecord) -> Result<(), String> {
+        if record.index < self.skip {
+            return Ok(());
+        }
+        let mut value = text_value(record, self.radix, self.error_codes);
+        if self.written > 0 {
+            value.insert(0, ' ');
+        }
+        self.writer.write_all(value.as_bytes()).map_err(write_error)?;
+        self.bytes += value.len() as u64;
+        self.written += 1;
+        if self.written.is_multiple_of(self.sync_every) {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.writer.flush().map_err(write_error)?;
+        self.writer.get_ref().sync_all().map_err(write_error)?;
+        match fs::remove_file(ProgressMarker::path_for(&self.path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(format!("Failed to remove progress marker: {}", e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn set_radix(&mut self, radix: Radix) {
+        self.radix = radix;
+    }
+
+    fn set_error_codes(&mut self, codes: ErrorCodes) {
+        self.error_codes = codes;
+    }
+}
+
+pub struct JsonSink<W: Write> {
+    writer: W,
+    first: bool,
+    error_codes: ErrorCodes,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonSink { writer, first: true, error_codes: ErrorCodes::Collapsed }
+    }
+}
+
+impl JsonSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Ok(JsonSink::new(create_file(path)?))
+    }
+}
+
+impl<W: Write> ResultSink for JsonSink<W> {
+    fn write_result(&mut self, record: &ResultRecord) -> Result<(), String> {
+        let separator = if self.first { "[\n" } else { ",\n" };
+        self.first = false;
+        write!(
+            self.writer,
+            "{}  {{\"index\": {}, \"va\": {}, \"pa\": {}, \"result\": \"{}\"",
+            separator,
+            record.index,
+            record.va,
+            record.result.to_output_with(self.error_codes),
+            record.result.kind()
+        )
+        .map_err(write_error)?;
+        if let Some(cycles) = record.cycles {
+            write!(self.writer, ", \"cycles\": {}", cycles).map_err(write_error)?;
+        }
+        if let Some(hit) = record.tlb_hit {
+            write!(self.writer, ", \"tlb\": \"{}\"", if hit { "hit" } else { "miss" }).map_err(write_error)?;
+        }
+        self.writer.write_all(b"}").map_err(write_error)
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        let closing: &[u8] = if self.first { b"[]\n" } else { b"\n]\n" };
+        self.first = false;
+        self.writer.write_all(closing).map_err(write_error)?;
+        self.writer.flush().map_err(write_error)
+    }
+
+    fn set_error_codes(&mut self, codes: ErrorCodes) {
+        self.error_codes = codes;
+    }
+}
+
+pub struct CsvSink<W: Write> {
+    writer: W,
+    header_written: bool,
+    radix: Radix,
+    error_codes: ErrorCodes,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        CsvSink { writer, header_written: false, radix: Radix::Decimal, error_codes: ErrorCodes::Collapsed }
+    }
+
+    // The tlb column is only present when the records carry TLB hit annotations.
+    fn write_header(&mut self, tlb: bool) -> Result<(), String> {
+        if !self.header_written {
+            self.header_written = true;
+            let header: &[u8] = if tlb { b"index,va,pa,result,cycles,tlb\n" } else { b"index,va,pa,result,cycles\n" };
+            self.writer.write_all(header).map_err(write_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Ok(CsvSink::new(create_file(path)?))
+    }
+}
+
+impl<W: Write> ResultSink for CsvSink<W> {
+    fn write_result(&mut self, record: &ResultRecord) -> Result<(), String> {
+        self.write_header(record.tlb_hit.is_some())?;
+        let cycles = record.cycles.map(|c| c.to_string()).unwrap_or_default();
+        write!(
+            self.writer,
+            "{},{},{},{},{}",
+            record.index,
+            record.va,
+            self.radix.format(record.result.to_output_with(self.error_codes)),
+            record.result.kind(),
+            cycles
+        )
+        .map_err(write_error)?;
+        if let Some(hit) = record.tlb_hit {
+            write!(self.writer, ",{}", tlb_prefix(hit)).map_err(write_error)?;
+        }
+        self.writer.write_all(b"\n").map_err(write_error)
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.write_header(false)?;
+        self.writer.flush().map_err(write_error)
+    }
+
+    fn set_radix(&mut self, radix: Radix) {
+        self.radix = radix;
+    }
+
+    fn set_error_codes(&mut self, codes: ErrorCodes) {
+        self.error_codes = codes;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NullSink {
+    pub count: usize,
+}
+
+impl ResultSink for NullSink {
+    fn write_result(&mut self, _record: &ResultRecord) -> Result<(), String> {
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Null,
+}
+
+impl OutputFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "null" => Ok(OutputFormat::Null),
+            _ => Err(format!("Unknown output format: {}", name)),
+        }
+    }
+
+    // Writes the results to stdout rather than a file.
+    pub fn stdout_sink(&self) -> Box<dyn ResultSink> {
+        let stdout = BufWriter::new(io::stdout().lock());
+        match self {
+            OutputFormat::Text => Box::new(TextSink::new(stdout)),
+            OutputFormat::Json => Box::new(JsonSink::new(stdout)),
+            OutputFormat::Csv => Box::new(CsvSink::new(stdout)),
+            OutputFormat::Null => Box::new(NullSink::default()),
+        }
+    }
+
+    pub fn create_sink<P: AsRef<Path>>(&self, path: P) -> Result<Box<dyn ResultSink>, String> {
+        Ok(match self {
+            OutputFormat::Text => Box::new(FileSink::create(path)?),
+            OutputFormat::Json => Box::new(JsonSink::create(path)?),
+            OutputFormat::Csv => Box::new(CsvSink::create(path)?),
+            OutputFormat::Null => Box::new(NullSink::default()),
+        })
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use crate::io::InitData;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::replacement::{PageId, Replacer};
+use crate::segment::{self, FreedMemory};
+use crate::stats::TranslationStats;
+use crate::tlb::{Tlb, TlbCache, TlbEviction};
+use crate::translation::{
+    check_protection, copy_on_write, record_access, translate_with_demand_paging_faults, Access, FaultFlags, TranslationResult,
+    VirtualAddress,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShootdownStats {
+    // Eviction or remapping events broadcast to the other CPUs.
+    pub shootdowns: u64,
+    // Invalidation messages sent, one per other CPU per shootdown.
+    pub messages: u64,
+    // Entries those messages actually removed from other CPUs' TLBs.
+    pub entries_invalidated: u64,
+    // Later TLB misses on a CPU for a page whose entry it lost to a shootdown:
+    // without the shootdown these would have hit a stale entry (assuming the
+    // entry would not have been evicted from the TLB for capacity meanwhile).
+    pub stale_translations: u64,
+}
+
+// Several CPUs sharing one address space: memory, disk, free list and (if set)
+// a replacer are shared, while each CPU has its own TLB. Whenever one CPU
+// evicts, remaps or frees a page, the other CPUs' TLBs receive invalidations.
+pub struct Multiprocessor {
+    pm: PhysicalMemory,
+    disk: Disk,
+    ffl: FreeFrameList,
+    tlbs: Vec<Tlb>,
+    shot_down: Vec<HashSet<PageId>>,
+    replacer: Option<Replacer>,
+    stats: TranslationStats,
+    shootdown: ShootdownStats,
+}
+
+impl Multiprocessor {
+    pub fn new(init_data: &InitData, cpus: usize, tlb_entries: usize, eviction: TlbEviction) -> Result<Self, String> {
+        if cpus == 0 {
+            return Err("A multiprocessor needs at least one CPU".to_string());
+        }
+        let mut pm = PhysicalMemory::new();
+        let mut disk = Disk::new();
+        let ffl = init_data.apply(&mut pm, &mut disk);
+        Ok(Multiprocessor {
+            pm,
+            disk,
+            ffl,
+            tlbs: (0..cpus).map(|_| Tlb::new(tlb_entries, eviction)).collect(),
+            shot_down: vec![HashSet::new(); cpus],
+            replacer: None,
+            stats: TranslationStats::new(),
+            shootdown: ShootdownStats::default(),
+        })
+    }
+
+    pub fn set_replacer(&mut self, replacer: Replacer) {
+        self.replacer = Some(replacer);
+    }
+
+    pub fn cpus(&self) -> usize {
+        self.tlbs.len()
+    }
+
+    pub fn tlb(&self, cpu: usize) -> &Tlb {
+        &self.tlbs[cpu]
+    }
+
+    pub fn memory(&self) -> &PhysicalMemory {
+        &self.pm
+    }
+
+    pub fn stats(&self) -> &TranslationStats {
+        &self.stats
+    }
+
+    pub fn shootdown_stats(&self) -> &ShootdownStats {
+        &self.shootdown
+    }
+
+    pub fn translate(&mut self, cpu: usize, va: u32) -> TranslationResult {
+        self.access(cpu, &Access::read(va)).0
+    }
+
+    pub fn access(&mut self, cpu: usize, access: &Access) -> (TranslationResult, FaultFlags) {
+        let va = VirtualAddress::decompose(self.pm.layout(), access.va);
+        if let Some(violation) = check_protection(access.kind, &va, &self.pm) {
+            self.stats.record(&violation, FaultFlags::default());
+            return (violation, FaultFlags::default());
+        }
+        let cached = self.tlbs[cpu].lookup(&va);
+        let (result, mut faults) = match cached {
+            Some(result) => (result, FaultFlags::default()),
+            None => {
+                if self.shot_down[cpu].remove(&(va.s, va.p)) {
+                    self.shootdown.stale_translations += 1;
+                }
+                let (result, faults) = match self.replacer.as_mut() {
+                    Some(replacer) => replacer.translate(&va, &mut self.pm, &mut self.disk, &mut self.ffl),
+                    None => translate_with_demand_paging_faults(&va, &mut self.pm, &self.disk, &mut self.ffl),
+                };
+                let evicted = self.replacer.as_mut().map(Replacer::take_evicted).unwrap_or_default();
+                self.shoot_down(cpu, &evicted);
+                self.tlbs[cpu].fill(&va, &self.pm, &result);
+                (result, faults)
+            }
+        };
+        let (result, cow_fault) = copy_on_write(access.kind, &va, result, &mut self.pm, &mut self.ffl);
+        if cow_fault {
+            self.shoot_down(cpu, &[(va.s, va.p)]);
+            self.tlbs[cpu].fill(&va, &self.pm, &result);
+        }
+        faults.cow_fault = cow_fault;
+        record_access(access, &result, &mut self.pm);
+        self.stats.record(&result, faults);
+        (result, faults)
+    }
+
+    pub fn free_page(&mut self, cpu: usize, s: u32, p: u32) -> Result<FreedMemory, String> {
+        let freed = segment::free_page(&mut self.pm, &mut self.disk, &mut self.ffl, s, p)?;
+        self.forget(&freed);
+        self.shoot_down(cpu, &[(s, p)]);
+        Ok(freed)
+    }
+
+    pub fn free_segment(&mut self, cpu: usize, s: u32) -> Result<FreedMemory, String> {
+        let freed = segment::free_segment(&mut self.pm, &mut self.disk, &mut self.ffl, s)?;
+        self.forget(&freed);
+        if let Some(replacer) = self.replacer.as_mut() {
+            replacer.forget_page_table(s);
+        }
+        self.shoot_down(cpu, &freed.pages);
+        Ok(freed)
+    }
+
+    fn forget(&mut self, freed: &FreedMemory) {
+        if let Some(replacer) = self.replacer.as_mut() {
+            for &page in &freed.pages {
+                replacer.forget(page);
+            }
+            replacer.release_blocks(&freed.blocks);
+        }
+    }
+
+    // The initiating CPU drops its own entries directly; every other CPU is
+    // sent one invalidation message for the whole batch of pages.
+    fn shoot_down(&mut self, initiator: usize, pages: &[PageId]) {
+        if pages.is_empty() {
+            return;
+        }
+        for &(s, p) in pages {
+            self.tlbs[initiator].invalidate(s, p);
+        }
+        if self.tlbs.len() == 1 {
+            return;
+        }
+        self.shootdown.shootdowns += 1;
+        for cpu in (0..self.tlbs.len()).filter(|&cpu| cpu != initiator) {
+            self.shootdown.messages += 1;
+            for &(s, p) in pages {
+                if self.tlbs[cpu].invalidate(s, p) {
+                    self.shootdown.entries_invalidated += 1;
+                    self.shot_down[cpu].insert((s, p));
+                }
+            }
+        }
+    }
+}
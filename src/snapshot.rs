@@ -0,0 +1,330 @@
+use std::fs;
+use std::path::Path;
+
+use crate::constants::*;
+use crate::layout::AddressLayout;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory, PteFormat};
+
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"VMSN";
+pub const SNAPSHOT_VERSION: u32 = 1;
+pub const MEMORY_MAGIC: &[u8; 4] = b"VMPM";
+pub const DISK_MAGIC: &[u8; 4] = b"VMDK";
+pub const FREE_FRAMES_MAGIC: &[u8; 4] = b"VMFL";
+pub const CHECKPOINT_MAGIC: &[u8; 4] = b"VMCP";
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.pos + n > self.bytes.len() {
+            return Err("Snapshot is truncated".to_string());
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn header(&mut self, magic: &[u8; 4], what: &str) -> Result<(), String> {
+        if self.take(4)? != magic {
+            return Err(format!("Not a {} file (bad magic)", what));
+        }
+        let version = self.u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("Unsupported {} version {}", what, version));
+        }
+        Ok(())
+    }
+
+    fn pte_format(&mut self) -> Result<PteFormat, String> {
+        match self.u32()? {
+            0 => Ok(PteFormat::Packed),
+            1 => Ok(PteFormat::Legacy),
+            other => Err(format!("Unknown snapshot PTE format {}", other)),
+        }
+    }
+
+    fn finish(&self) -> Result<(), String> {
+        if self.pos != self.bytes.len() {
+            return Err(format!("Snapshot has {} trailing bytes", self.bytes.len() - self.pos));
+        }
+        Ok(())
+    }
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_i32(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_header(out: &mut Vec<u8>, magic: &[u8; 4]) {
+    out.extend_from_slice(magic);
+    push_u32(out, SNAPSHOT_VERSION);
+}
+
+fn encode_pte_format(out: &mut Vec<u8>, pm: &PhysicalMemory) {
+    push_u32(out, (pm.pte_format() == PteFormat::Legacy) as u32);
+}
+
+fn encode_frames(out: &mut Vec<u8>, pm: &PhysicalMemory, page_size: usize) {
+    let frames: Vec<usize> = (0..NUM_FRAMES)
+        .filter(|&f| (0..page_size).any(|i| pm.read(f * page_size + i) != 0))
+        .collect();
+    push_u32(out, frames.len() as u32);
+    for f in frames {
+        push_u32(out, f as u32);
+        for i in 0..page_size {
+            push_i32(out, pm.read(f * page_size + i));
+        }
+    }
+}
+
+fn decode_frames(reader: &mut Reader, pm: &mut PhysicalMemory, page_size: usize) -> Result<(), String> {
+    let frame_count = reader.u32()?;
+    for _ in 0..frame_count {
+        let f = reader.u32()? as usize;
+        if f >= NUM_FRAMES {
+            return Err(format!("Snapshot frame {} out of range", f));
+        }
+        for i in 0..page_size {
+            pm.write(f * page_size + i, reader.i32()?);
+        }
+    }
+    Ok(())
+}
+
+fn encode_blocks(out: &mut Vec<u8>, disk: &Disk) {
+    let blocks: Vec<usize> = (0..DISK_BLOCKS)
+        .filter(|&b| (0..BLOCK_SIZE).any(|i| disk.read(b, i) != 0))
+        .collect();
+    push_u32(out, blocks.len() as u32);
+    for b in blocks {
+        push_u32(out, b as u32);
+        for i in 0..BLOCK_SIZE {
+            push_i32(out, disk.read(b, i));
+        }
+    }
+}
+
+fn decode_blocks(reader: &mut Reader) -> Result<Disk, String> {
+    let mut disk = Disk::new();
+    let block_count = reader.u32()?;
+    for _ in 0..block_count {
+        let b = reader.u32()? as usize;
+        if b >= DISK_BLOCKS {
+            return Err(format!("Snapshot disk block {} out of range", b));
+        }
+        for i in 0..BLOCK_SIZE {
+            disk.write(b, i, reader.i32()?);
+        }
+    }
+    Ok(disk)
+}
+
+fn encode_memory(out: &mut Vec<u8>, pm: &PhysicalMemory) {
+    let layout = pm.layout();
+    push_u32(out, layout.s_bits);
+    push_u32(out, layout.p_bits);
+    push_u32(out, layout.w_bits);
+    push_u32(out, pm.st_frame());
+    encode_pte_format(out, pm);
+    encode_frames(out, pm, layout.page_size());
+    let dirty = pm.dirty_frames();
+    push_u32(out, dirty.len() as u32);
+    for frame in dirty {
+        push_u32(out, frame);
+    }
+}
+
+fn decode_memory(reader: &mut Reader) -> Result<PhysicalMemory, String> {
+    let layout = AddressLayout::new(reader.u32()?, reader.u32()?, reader.u32()?)?;
+    let mut pm = PhysicalMemory::with_layout(layout);
+    let st_frame = reader.u32()?;
+    if st_frame as usize >= NUM_FRAMES {
+        return Err(format!("Snapshot ST frame {} out of range", st_frame));
+    }
+    pm.set_st_frame(st_frame);
+    pm.set_pte_format(reader.pte_format()?);
+    decode_frames(reader, &mut pm, layout.page_size())?;
+    let dirty_count = reader.u32()?;
+    for _ in 0..dirty_count {
+        let frame = reader.u32()?;
+        if frame as usize >= NUM_FRAMES {
+            return Err(format!("Snapshot dirty frame {} out of range", frame));
+        }
+        pm.mark_dirty(frame);
+    }
+    pm.rebuild_frame_table();
+    Ok(pm)
+}
+
+fn encode_free_frames(out: &mut Vec<u8>, ffl: &FreeFrameList) {
+    push_u32(out, ffl.free_count() as u32);
+    for frame in ffl.frames() {
+        push_u32(out, frame);
+    }
+}
+
+fn decode_free_frames(reader: &mut Reader) -> Result<FreeFrameList, String> {
+    let count = reader.u32()?;
+    let mut frames = Vec::new();
+    for _ in 0..count {
+        let frame = reader.u32()?;
+        if frame as usize >= NUM_FRAMES {
+            return Err(format!("Snapshot free frame {} out of range", frame));
+        }
+        frames.push(frame);
+    }
+    Ok(FreeFrameList::from_frames(frames))
+}
+
+pub fn encode_snapshot(pm: &PhysicalMemory, disk: &Disk) -> Vec<u8> {
+    let mut out = Vec::new();
+    push_header(&mut out, SNAPSHOT_MAGIC);
+    encode_pte_format(&mut out, pm);
+    encode_frames(&mut out, pm, PAGE_SIZE);
+    encode_blocks(&mut out, disk);
+    out
+}
+
+pub fn decode_snapshot(bytes: &[u8]) -> Result<(PhysicalMemory, Disk), String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    reader.header(SNAPSHOT_MAGIC, "snapshot")?;
+    let mut pm = PhysicalMemory::new();
+    pm.set_pte_format(reader.pte_format()?);
+    decode_frames(&mut reader, &mut pm, PAGE_SIZE)?;
+    let disk = decode_blocks(&mut reader)?;
+    Ok((pm, disk))
+}
+
+pub fn encode_checkpoint(pm: &PhysicalMemory, disk: &Disk, ffl: &FreeFrameList) -> Vec<u8> {
+    let mut out = Vec::new();
+    push_header(&mut out, CHECKPOINT_MAGIC);
+    encode_memory(&mut out, pm);
+    encode_blocks(&mut out, disk);
+    encode_free_frames(&mut out, ffl);
+    out
+}
+
+pub fn decode_checkpoint(bytes: &[u8]) -> Result<(PhysicalMemory, Disk, FreeFrameList), String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    reader.header(CHECKPOINT_MAGIC, "checkpoint")?;
+    let pm = decode_memory(&mut reader)?;
+    let disk = decode_blocks(&mut reader)?;
+    let ffl = decode_free_frames(&mut reader)?;
+    reader.finish()?;
+    Ok((pm, disk, ffl))
+}
+
+// Snapshots record a single ST frame and a two-level layout, so a paged segment
+// table or a three-level layout cannot be saved.
+fn require_contiguous_st(pm: &PhysicalMemory) -> Result<(), String> {
+    if pm.st_is_paged() {
+        return Err("Cannot snapshot a paged segment table".to_string());
+    }
+    if pm.layout().is_three_level() {
+        return Err("Cannot snapshot a three-level layout".to_string());
+    }
+    Ok(())
+}
+
+fn write_bytes(path: &Path, bytes: Vec<u8>, what: &str) -> Result<(), String> {
+    fs::write(path, bytes).map_err(|e| format!("Failed to write {}: {}", what, e))
+}
+
+fn read_bytes(path: &Path, what: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("Failed to read {}: {}", what, e))
+}
+
+fn decode_section<T>(
+    bytes: &[u8],
+    magic: &[u8; 4],
+    what: &str,
+    decode: fn(&mut Reader) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    reader.header(magic, what)?;
+    let value = decode(&mut reader)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+impl PhysicalMemory {
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        require_contiguous_st(self)?;
+        let mut out = Vec::new();
+        push_header(&mut out, MEMORY_MAGIC);
+        encode_memory(&mut out, self);
+        write_bytes(path.as_ref(), out, "memory snapshot")
+    }
+
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let bytes = read_bytes(path.as_ref(), "memory snapshot")?;
+        decode_section(&bytes, MEMORY_MAGIC, "memory snapshot", decode_memory)
+    }
+}
+
+impl Disk {
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let mut out = Vec::new();
+        push_header(&mut out, DISK_MAGIC);
+        encode_blocks(&mut out, self);
+        write_bytes(path.as_ref(), out, "disk snapshot")
+    }
+
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let bytes = read_bytes(path.as_ref(), "disk snapshot")?;
+        decode_section(&bytes, DISK_MAGIC, "disk snapshot", decode_blocks)
+    }
+}
+
+impl FreeFrameList {
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let mut out = Vec::new();
+        push_header(&mut out, FREE_FRAMES_MAGIC);
+        encode_free_frames(&mut out, self);
+        write_bytes(path.as_ref(), out, "free frame snapshot")
+    }
+
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let bytes = read_bytes(path.as_ref(), "free frame snapshot")?;
+        decode_section(&bytes, FREE_FRAMES_MAGIC, "free frame snapshot", decode_free_frames)
+    }
+}
+
+pub fn save_checkpoint<P: AsRef<Path>>(
+    path: P,
+    pm: &PhysicalMemory,
+    disk: &Disk,
+    ffl: &FreeFrameList,
+) -> Result<(), String> {
+    require_contiguous_st(pm)?;
+    write_bytes(path.as_ref(), encode_checkpoint(pm, disk, ffl), "checkpoint")
+}
+
+pub fn load_checkpoint<P: AsRef<Path>>(path: P) -> Result<(PhysicalMemory, Disk, FreeFrameList), String> {
+    decode_checkpoint(&read_bytes(path.as_ref(), "checkpoint")?)
+}
+
+pub fn save_snapshot<P: AsRef<Path>>(path: P, pm: &PhysicalMemory, disk: &Disk) -> Result<(), String> {
+    require_contiguous_st(pm)?;
+    fs::write(path.as_ref(), encode_snapshot(pm, disk)).map_err(|e| format!("Failed to write snapshot: {}", e))
+}
+
+pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<(PhysicalMemory, Disk), String> {
+    let bytes = fs::read(path.as_ref()).map_err(|e| format!("Failed to read snapshot: {}", e))?;
+    decode_snapshot(&bytes)
+}
@@ -0,0 +1,778 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::events::{FaultKind, Observer, VmEvent};
+use crate::process::Pid;
+use crate::translation::{FaultFlags, TranslationResult, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TranslationStats {
+    pub translations: u64,
+    pub successes: u64,
+    pub boundary_violations: u64,
+    pub invalid_segments: u64,
+    pub invalid_pages: u64,
+    pub access_denied: u64,
+    pub protection_violations: u64,
+    pub st_faults: u64,
+    pub pd_faults: u64,
+    pub pt_faults: u64,
+    pub page_faults: u64,
+    pub soft_faults: u64,
+    pub zero_fill_faults: u64,
+    pub slow_tier_faults: u64,
+    pub compressed_faults: u64,
+    pub cow_faults: u64,
+    pub frames_allocated: u64,
+    pub disk_reads: u64,
+    pub prefetched_pages: u64,
+    pub prefetch_hits: u64,
+    pub simulated_cycles: u64,
+}
+
+impl TranslationStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, result: &TranslationResult, faults: FaultFlags) {
+        self.translations += 1;
+        match result {
+            TranslationResult::Success(_) => self.successes += 1,
+            TranslationResult::SegmentBoundaryViolation => self.boundary_violations += 1,
+            TranslationResult::InvalidSegment => self.invalid_segments += 1,
+            TranslationResult::InvalidPage => self.invalid_pages += 1,
+            TranslationResult::AccessDenied => self.access_denied += 1,
+            TranslationResult::ProtectionViolation => self.protection_violations += 1,
+        }
+        let faults_taken = faults.hard_faults();
+        self.st_faults += faults.st_fault as u64;
+        self.pd_faults += faults.pd_fault as u64;
+        self.pt_faults += faults.pt_fault as u64;
+        self.page_faults += faults.page_fault as u64;
+        self.soft_faults += faults.soft_fault as u64;
+        self.zero_fill_faults += faults.zero_fault as u64;
+        self.slow_tier_faults += faults.slow_tier as u64;
+        self.compressed_faults += faults.compressed_fault as u64;
+        self.cow_faults += faults.cow_fault as u64;
+        self.frames_allocated +=
+            faults_taken + faults.soft_fault as u64 + faults.zero_fault as u64 + faults.compressed_fault as u64 + faults.cow_fault as u64;
+        self.disk_reads += faults_taken;
+    }
+
+    // Prefetched pages each took a frame and a disk read outside any fault.
+    pub fn record_prefetches(&mut self, issued: u64, used: u64) {
+        self.prefetched_pages += issued;
+        self.prefetch_hits += used;
+        self.frames_allocated += issued;
+        self.disk_reads += issued;
+    }
+
+    // Mean simulated cycles per translation, once a cost model has filled in
+    // simulated_cycles.
+    pub fn effective_access_time(&self) -> f64 {
+        if self.translations == 0 {
+            return 0.0;
+        }
+        self.simulated_cycles as f64 / self.translations as f64
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.translations - self.successes
+    }
+
+    pub fn fault_rate(&self) -> f64 {
+        if self.translations == 0 {
+            return 0.0;
+        }
+        self.page_faults as f64 / self.translations as f64
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"translations\": {}, \"successes\": {}, \"boundary_violations\": {}, \"invalid_segments\": {}, \
+             \"invalid_pages\": {}, \"access_denied\": {}, \"protection_violations\": {}, \"st_faults\": {}, \
+             \"pd_faults\": {}, \"pt_faults\": {}, \"page_faults\": {}, \"soft_faults\": {}, \"zero_fill_faults\": {}, \
+             \"slow_tier_faults\": {}, \"compressed_faults\": {}, \"cow_faults\": {}, \"frames_allocated\": {}, \"disk_reads\": {}, \"prefetched_pages\": {}, \"prefetch_hits\": {}, \
+             \"simulated_cycles\": {}, \"effective_access_time\": {:.2}}}",
+            self.translations,
+            self.successes,
+            self.boundary_violations,
+            self.invalid_segments,
+            self.invalid_pages,
+            self.access_denied,
+            self.protection_violations,
+            self.st_faults,
+            self.pd_faults,
+            self.pt_faults,
+            self.page_faults,
+            self.soft_faults,
+            self.zero_fill_faults,
+            self.slow_tier_faults,
+            self.compressed_faults,
+            self.cow_faults,
+            self.frames_allocated,
+            self.disk_reads,
+            self.prefetched_pages,
+            self.prefetch_hits,
+            self.simulated_cycles,
+            self.effective_access_time()
+        )
+    }
+
+    // The counters as the text report labels them, in report order.
+    pub fn rows(&self) -> [(&'static str, u64); 20] {
+        [
+            ("Translations:", self.translations),
+            ("  successes:", self.successes),
+            ("  boundary violations:", self.boundary_violations),
+            ("  invalid segments:", self.invalid_segments),
+            ("  invalid pages:", self.invalid_pages),
+            ("  access denied:", self.access_denied),
+            ("  protection violations:", self.protection_violations),
+            ("ST faults:", self.st_faults),
+            ("Directory faults:", self.pd_faults),
+            ("PT faults:", self.pt_faults),
+            ("Page faults (hard):", self.page_faults),
+            ("Page faults (soft):", self.soft_faults),
+            ("Page faults (zero-fill):", self.zero_fill_faults),
+            ("  from slow swap:", self.slow_tier_faults),
+            ("Page faults (compressed):", self.compressed_faults),
+            ("COW copies:", self.cow_faults),
+            ("Frames allocated:", self.frames_allocated),
+            ("Disk reads:", self.disk_reads),
+            ("Pages prefetched:", self.prefetched_pages),
+            ("  used:", self.prefetch_hits),
+        ]
+    }
+}
+
+impl fmt::Display for TranslationStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (label, value)) in self.rows().iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:<26}{}", label, value)?;
+        }
+        if self.simulated_cycles > 0 {
+            write!(f, "\n{:<26}{}", "Simulated cycles:", self.simulated_cycles)?;
+            write!(f, "\n{:<26}{:.2} cycles", "Effective access time:", self.effective_access_time())?;
+        }
+        Ok(())
+    }
+}
+
+// Counts each access once, from its Translated event.
+impl Observer for TranslationStats {
+    fn on_event(&mut self, event: &VmEvent) {
+        if let VmEvent::Translated { result, faults, .. } = event {
+            self.record(result, *faults);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageHeat {
+    pub s: u32,
+    pub p: u32,
+    pub accesses: u64,
+    pub faults: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SegmentHeat {
+    pub s: u32,
+    pub accesses: u64,
+    pub faults: u64,
+    pub pages: usize,
+}
+
+// Access and fault counts per page, and summed per segment, for every access
+// whether it succeeded or not. An access adds each fault it took (ST, PT,
+// page, copy-on-write and so on) to its page's count.
+#[derive(Debug, Clone, Default)]
+pub struct AccessHeatmap {
+    pages: BTreeMap<(u32, u32), (u64, u64)>,
+}
+
+impl AccessHeatmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, va: &VirtualAddress, faults: FaultFlags) {
+        let (accesses, taken) = self.pages.entry((va.s, va.p)).or_default();
+        *accesses += 1;
+        *taken += FaultKind::taken(faults).count() as u64;
+    }
+
+    // Touched pages in (s, p) order.
+    pub fn pages(&self) -> Vec<PageHeat> {
+        self.pages.iter().map(|(&(s, p), &(accesses, faults))| PageHeat { s, p, accesses, faults }).collect()
+    }
+
+    pub fn segments(&self) -> Vec<SegmentHeat> {
+        let mut segments: Vec<SegmentHeat> = Vec::new();
+        for page in self.pages() {
+            match segments.last_mut().filter(|segment| segment.s == page.s) {
+                Some(segment) => {
+                    segment.accesses += page.accesses;
+                    segment.faults += page.faults;
+                    segment.pages += 1;
+                }
+                None => segments.push(SegmentHeat { s: page.s, accesses: page.accesses, faults: page.faults, pages: 1 }),
+            }
+        }
+        segments
+    }
+
+    // The n most accessed pages, ties broken by faults and then address.
+    pub fn hottest(&self, n: usize) -> Vec<PageHeat> {
+        let mut pages = self.pages();
+        pages.sort_by_key(|page| (std::cmp::Reverse((page.accesses, page.faults)), page.s, page.p));
+        pages.truncate(n);
+        pages
+    }
+
+    // Access counts as a segment x page matrix: a header of page numbers up
+    // to the highest touched, then one row per touched segment.
+    pub fn to_csv(&self) -> String {
+        let columns = self.pages.keys().map(|&(_, p)| p + 1).max().unwrap_or(0);
+        let mut csv = String::from("segment");
+        for p in 0..columns {
+            csv += &format!(",{}", p);
+        }
+        csv.push('\n');
+        for segment in self.segments() {
+            csv += &segment.s.to_string();
+            for p in 0..columns {
+                csv += &format!(",{}", self.pages.get(&(segment.s, p)).map_or(0, |&(accesses, _)| accesses));
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+impl Observer for AccessHeatmap {
+    fn on_event(&mut self, event: &VmEvent) {
+        if let VmEvent::Translated { va, faults, .. } = event {
+            self.record(&VirtualAddress::from_raw(*va), *faults);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageActivity {
+    pub s: u32,
+    pub p: u32,
+    pub faulted_at: usize,
+    pub last_access: usize,
+    pub touches: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdlePageReport {
+    pub accesses: usize,
+    pub faulted_pages: usize,
+    pub one_touch: Vec<PageActivity>,
+    pub idle_in_window: Vec<PageActivity>,
+}
+
+#[derive(Debug, Default)]
+pub struct IdlePageTracker {
+    pages: HashMap<(u32, u32), PageActivity>,
+    accesses: usize,
+}
+
+impl IdlePageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, va: &VirtualAddress, result: &TranslationResult, faults: FaultFlags) {
+        let index = self.accesses;
+        self.accesses += 1;
+        if !matches!(result, TranslationResult::Success(_)) {
+            return;
+        }
+        if faults.page_fault {
+            self.pages.insert(
+                (va.s, va.p),
+                PageActivity { s: va.s, p: va.p, faulted_at: index, last_access: index, touches: 1 },
+            );
+        } else if let Some(activity) = self.pages.get_mut(&(va.s, va.p)) {
+            activity.last_access = index;
+            activity.touches += 1;
+        }
+    }
+
+    pub fn report(&self, final_window: usize) -> IdlePageReport {
+        let window_start = self.accesses.saturating_sub(final_window);
+        let mut pages: Vec<PageActivity> = self.pages.values().copied().collect();
+        pages.sort_by_key(|a| a.faulted_at);
+
+        IdlePageReport {
+            accesses: self.accesses,
+            faulted_pages: pages.len(),
+            one_touch: pages.iter().filter(|a| a.touches == 1).copied().collect(),
+            idle_in_window: pages.iter().filter(|a| a.touches > 1 && a.last_access < window_start).copied().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FirstTouch {
+    pub s: u32,
+    pub p: u32,
+    pub access: usize,
+    pub faulted: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct FirstTouchMap {
+    pages: HashMap<(u32, u32), FirstTouch>,
+    accesses: usize,
+}
+
+impl FirstTouchMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, va: &VirtualAddress, result: &TranslationResult, faults: FaultFlags) {
+        let access = self.accesses;
+        self.accesses += 1;
+        if matches!(result, TranslationResult::Success(_)) {
+            self.pages
+                .entry((va.s, va.p))
+                .or_insert(FirstTouch { s: va.s, p: va.p, access, faulted: faults.page_fault });
+        }
+    }
+
+    pub fn accesses(&self) -> usize {
+        self.accesses
+    }
+
+    pub fn get(&self, s: u32, p: u32) -> Option<FirstTouch> {
+        self.pages.get(&(s, p)).copied()
+    }
+
+    pub fn entries(&self) -> Vec<FirstTouch> {
+        let mut entries: Vec<FirstTouch> = self.pages.values().copied().collect();
+        entries.sort_by_key(|t| (t.access, t.s, t.p));
+        entries
+    }
+
+    pub fn per_window(&self, window: usize) -> Vec<usize> {
+        let window = window.max(1);
+        let mut counts = vec![0; self.accesses.div_ceil(window)];
+        for touch in self.pages.values() {
+            counts[touch.access / window] += 1;
+        }
+        counts
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorkingSetSummary {
+    pub pid: Pid,
+    pub references: usize,
+    pub faults: usize,
+    pub peak: usize,
+    pub mean: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkingSetWindow {
+    pub start: usize,
+    pub end: usize,
+    pub size: usize,
+    pub faults: usize,
+}
+
+#[derive(Debug, Default)]
+struct ProcessWorkingSet {
+    time: usize,
+    recent: VecDeque<(usize, (u32, u32))>,
+    counts: HashMap<(u32, u32), usize>,
+    faults: usize,
+    peak: usize,
+    size_sum: usize,
+}
+
+// W(t, delta) is the set of pages a process referenced in its last delta
+// references, counted in that process's own virtual time. Under working-set
+// allocation a process holds exactly its working set, so a reference faults
+// whenever its page has dropped out of (or never entered) the set.
+#[derive(Debug)]
+pub struct WorkingSetTracker {
+    delta: usize,
+    processes: BTreeMap<Pid, ProcessWorkingSet>,
+    accesses: usize,
+    windows: Vec<WorkingSetWindow>,
+}
+
+impl WorkingSetTracker {
+    pub fn new(delta: usize) -> Self {
+        WorkingSetTracker { delta: delta.max(1), processes: BTreeMap::new(), accesses: 0, windows: Vec::new() }
+    }
+
+    pub fn delta(&self) -> usize {
+        self.delta
+    }
+
+    pub fn accesses(&self) -> usize {
+        self.accesses
+    }
+
+    pub fn record(&mut self, pid: Pid, va: &VirtualAddress, result: &TranslationResult) {
+        let index = self.accesses;
+        self.accesses += 1;
+        if index.is_multiple_of(self.delta) {
+            self.windows.push(WorkingSetWindow { start: index, end: index, size: 0, faults: 0 });
+        }
+        let window = self.windows.last_mut().unwrap();
+        window.end = index + 1;
+
+        if matches!(result, TranslationResult::Success(_)) {
+            let process = self.processes.entry(pid).or_default();
+            let page = (va.s, va.p);
+            let now = process.time;
+            process.time += 1;
+            while let Some(&(time, old)) = process.recent.front() {
+                if time + self.delta > now {
+                    break;
+                }
+                process.recent.pop_front();
+                let count = process.counts.get_mut(&old).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    process.counts.remove(&old);
+                }
+            }
+            if !process.counts.contains_key(&page) {
+                process.faults += 1;
+                window.faults += 1;
+            }
+            process.recent.push_back((now, page));
+            *process.counts.entry(page).or_insert(0) += 1;
+            process.peak = process.peak.max(process.counts.len());
+            process.size_sum += process.counts.len();
+        }
+        window.size = self.processes.values().map(|process| process.counts.len()).sum();
+    }
+
+    pub fn size(&self, pid: Pid) -> usize {
+        self.processes.get(&pid).map_or(0, |process| process.counts.len())
+    }
+
+    pub fn faults(&self) -> usize {
+        self.processes.values().map(|process| process.faults).sum()
+    }
+
+    pub fn summaries(&self) -> Vec<WorkingSetSummary> {
+        self.processes
+            .iter()
+            .map(|(&pid, process)| WorkingSetSummary {
+                pid,
+                references: process.time,
+                faults: process.faults,
+                peak: process.peak,
+                mean: if process.time == 0 { 0.0 } else { process.size_sum as f64 / process.time as f64 },
+            })
+            .collect()
+    }
+
+    // One entry per delta accesses: the combined working set size at the end
+    // of the window and the working-set faults taken within it.
+    pub fn windows(&self) -> &[WorkingSetWindow] {
+        &self.windows
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThrashingInterval {
+    pub start: usize,
+    pub end: usize,
+    pub faults: usize,
+    pub peak_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThrashingEvent {
+    Started { access: usize, rate: f64 },
+    Ended(ThrashingInterval),
+}
+
+// Watches the page-fault rate over the last `window` accesses. Thrashing starts
+// when a full window's rate exceeds the threshold; the interval begins at that
+// window's first access and ends at the first access whose window is back at or
+// below the threshold.
+#[derive(Debug)]
+pub struct ThrashingDetector {
+    window: usize,
+    threshold: f64,
+    recent: VecDeque<bool>,
+    window_faults: usize,
+    accesses: usize,
+    current: Option<ThrashingInterval>,
+    intervals: Vec<ThrashingInterval>,
+}
+
+impl ThrashingDetector {
+    pub fn new(window: usize, threshold: f64) -> Self {
+        let window = window.max(1);
+        ThrashingDetector {
+            window,
+            threshold,
+            recent: VecDeque::with_capacity(window),
+            window_faults: 0,
+            accesses: 0,
+            current: None,
+            intervals: Vec::new(),
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn record(&mut self, faults: FaultFlags) -> Option<ThrashingEvent> {
+        let index = self.accesses;
+        self.accesses += 1;
+        if self.recent.len() == self.window && self.recent.pop_front() == Some(true) {
+            self.window_faults -= 1;
+        }
+        self.recent.push_back(faults.page_fault);
+        self.window_faults += faults.page_fault as usize;
+        if self.recent.len() < self.window {
+            return None;
+        }
+
+        let rate = self.window_faults as f64 / self.window as f64;
+        match self.current.as_mut() {
+            None if rate > self.threshold => {
+                self.current = Some(ThrashingInterval {
+                    start: index + 1 - self.window,
+                    end: index + 1,
+                    faults: self.window_faults,
+                    peak_rate: rate,
+                });
+                Some(ThrashingEvent::Started { access: index, rate })
+            }
+            Some(interval) if rate > self.threshold => {
+                interval.end = index + 1;
+                interval.faults += faults.page_fault as usize;
+                interval.peak_rate = interval.peak_rate.max(rate);
+                None
+            }
+            Some(_) => self.finish(),
+            None => None,
+        }
+    }
+
+    // Closes an interval still open at the end of the trace.
+    pub fn finish(&mut self) -> Option<ThrashingEvent> {
+        let interval = self.current.take()?;
+        self.intervals.push(interval);
+        Some(ThrashingEvent::Ended(interval))
+    }
+
+    pub fn intervals(&self) -> &[ThrashingInterval] {
+        &self.intervals
+    }
+
+    pub fn thrashing_accesses(&self) -> usize {
+        self.intervals.iter().map(|interval| interval.end - interval.start).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgressReport {
+    pub done: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+    // Translations per second and the fraction of accesses that took any
+    // fault, both since the previous report.
+    pub rate: f64,
+    pub fault_rate: f64,
+}
+
+impl ProgressReport {
+    pub fn percent(&self) -> f64 {
+        match self.total {
+            0 => 100.0,
+            total => self.done as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+impl fmt::Display for ProgressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1}% ({} of {}) after {:.1}s, {:.0} translations/s, fault rate {:.1}%",
+            self.percent(),
+            self.done,
+            self.total,
+            self.elapsed.as_secs_f64(),
+            self.rate,
+            self.fault_rate * 100.0
+        )
+    }
+}
+
+// Reports how far a run of `total` accesses has got once every `interval` of
+// wall-clock time. The clock is only read every CLOCK_STRIDE accesses so long
+// runs do not pay for it on each one.
+#[derive(Debug)]
+pub struct ProgressTracker {
+    total: usize,
+    interval: Duration,
+    started: Instant,
+    last_report: Instant,
+    done: usize,
+    window_accesses: usize,
+    window_faults: usize,
+}
+
+impl ProgressTracker {
+    const CLOCK_STRIDE: usize = 1024;
+
+    pub fn new(total: usize, interval: Duration) -> Self {
+        let now = Instant::now();
+        ProgressTracker { total, interval, started: now, last_report: now, done: 0, window_accesses: 0, window_faults: 0 }
+    }
+
+    pub fn record(&mut self, faults: FaultFlags) -> Option<ProgressReport> {
+        self.done += 1;
+        self.window_accesses += 1;
+        self.window_faults += FaultKind::taken(faults).next().is_some() as usize;
+        if !self.done.is_multiple_of(Self::CLOCK_STRIDE) || self.last_report.elapsed() < self.interval {
+            return None;
+        }
+        Some(self.report())
+    }
+
+    // The final report, covering whatever ran since the last one.
+    pub fn finish(&mut self) -> ProgressReport {
+        self.report()
+    }
+
+    fn report(&mut self) -> ProgressReport {
+        let now = Instant::now();
+        let window = now.duration_since(self.last_report).as_secs_f64();
+        let report = ProgressReport {
+            done: self.done,
+            total: self.total,
+            elapsed: now.duration_since(self.started),
+            rate: if window > 0.0 { self.window_accesses as f64 / window } else { 0.0 },
+            fault_rate: match self.window_accesses {
+                0 => 0.0,
+                accesses => self.window_faults as f64 / accesses as f64,
+            },
+        };
+        self.last_report = now;
+        self.window_accesses = 0;
+        self.window_faults = 0;
+        report
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyHistogram {
+    buckets: BTreeMap<u64, u64>,
+    count: u64,
+    total: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, cycles: u64) {
+        *self.buckets.entry(cycles).or_insert(0) += 1;
+        self.count += 1;
+        self.total += cycles;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.total as f64 / self.count as f64
+    }
+
+    pub fn percentile(&self, pct: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((pct / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (&cycles, &n) in &self.buckets {
+            seen += n;
+            if seen >= rank {
+                return cycles;
+            }
+        }
+        self.buckets.keys().next_back().copied().unwrap_or(0)
+    }
+
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.buckets.iter().map(|(&cycles, &n)| (cycles, n))
+    }
+}
+
+// The same interleaved multi-process trace through two equal TLBs: one flushed
+// on every process switch and one whose entries are tagged with the pid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlbSwitchComparison {
+    pub accesses: u64,
+    pub switches: u64,
+    pub flush_misses: u64,
+    pub asid_misses: u64,
+}
+
+impl TlbSwitchComparison {
+    pub fn flush_miss_rate(&self) -> f64 {
+        rate(self.flush_misses, self.accesses)
+    }
+
+    pub fn asid_miss_rate(&self) -> f64 {
+        rate(self.asid_misses, self.accesses)
+    }
+}
+
+fn rate(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f64 / total as f64
+}
+
+impl fmt::Display for TlbSwitchComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<26}{}", "TLB lookups:", self.accesses)?;
+        writeln!(f, "{:<26}{}", "Process switches:", self.switches)?;
+        writeln!(f, "{:<26}{} ({:.1}%)", "Misses (flush on switch):", self.flush_misses, self.flush_miss_rate() * 100.0)?;
+        write!(f, "{:<26}{} ({:.1}%)", "Misses (ASID-tagged):", self.asid_misses, self.asid_miss_rate() * 100.0)
+    }
+}
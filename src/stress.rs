@@ -0,0 +1,325 @@
+use std::fmt;
+
+use crate::constants::*;
+use crate::generator::next_random;
+use crate::io::{parse_virtual_address, InitData};
+use crate::memory::validate;
+use crate::process::{Pid, ProcessTable};
+use crate::segment::{Backing, MAX_SEGMENT_SIZE};
+use crate::tlb::TlbEviction;
+use crate::translation::{compose, Access, AccessType};
+use crate::vm_manager::VMManager;
+
+// Forks stop once this many processes exist; later forks become switches.
+const MAX_PROCESSES: usize = 4;
+
+// One step of a stress run. Each is concrete, so a recorded sequence replays
+// the same way; steps the engine refuses (freeing a missing segment, growing
+// past the free frames) are part of the workload, not failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StressOp {
+    Access(Access),
+    Grow { s: u32, size: u32 },
+    Map { s: u32, offset: u32, len: u32 },
+    FreePage { s: u32, p: u32 },
+    Free(u32),
+    Fork(Pid),
+    Switch(Pid),
+}
+
+impl StressOp {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid stress operation: {}", line);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| fields.get(i).and_then(|field| parse_virtual_address(field).ok()).ok_or_else(invalid);
+        let op = match fields.first().copied() {
+            Some("r") => StressOp::Access(Access::read(number(1)?)),
+            Some("w") => StressOp::Access(Access::write(number(1)?)),
+            Some("grow") => StressOp::Grow { s: number(1)?, size: number(2)? },
+            Some("map") => StressOp::Map { s: number(1)?, offset: number(2)?, len: number(3)? },
+            Some("free-page") => StressOp::FreePage { s: number(1)?, p: number(2)? },
+            Some("free") => StressOp::Free(number(1)?),
+            Some("fork") => StressOp::Fork(number(1)?),
+            Some("switch") => StressOp::Switch(number(1)?),
+            _ => return Err(invalid()),
+        };
+        let arity = match op {
+            StressOp::Access(_) | StressOp::Free(_) | StressOp::Fork(_) | StressOp::Switch(_) => 2,
+            StressOp::Grow { .. } | StressOp::FreePage { .. } => 3,
+            StressOp::Map { .. } => 4,
+        };
+        match fields.len() == arity {
+            true => Ok(op),
+            false => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for StressOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StressOp::Access(access) if access.kind == AccessType::Write => write!(f, "w {}", access.va),
+            StressOp::Access(access) => write!(f, "r {}", access.va),
+            StressOp::Grow { s, size } => write!(f, "grow {} {}", s, size),
+            StressOp::Map { s, offset, len } => write!(f, "map {} {} {}", s, offset, len),
+            StressOp::FreePage { s, p } => write!(f, "free-page {} {}", s, p),
+            StressOp::Free(s) => write!(f, "free {}", s),
+            StressOp::Fork(pid) => write!(f, "fork {}", pid),
+            StressOp::Switch(pid) => write!(f, "switch {}", pid),
+        }
+    }
+}
+
+// `steps` random operations over segments 0..segments, with the invariants
+// checked after every `check_every` of them. tlb_entries of 0 runs without a TLB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StressConfig {
+    pub steps: usize,
+    pub check_every: usize,
+    pub seed: u64,
+    pub tlb_entries: usize,
+    pub segments: u32,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        StressConfig { steps: 10_000, check_every: 1, seed: 1, tlb_entries: 16, segments: 16 }
+    }
+}
+
+// The first failed check: after which operation, and what it found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub step: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StressReport {
+    pub operations: usize,
+    pub refused: usize,
+    pub checks: usize,
+    pub processes: usize,
+}
+
+impl fmt::Display for StressReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} operations ({} refused) across {} processes, {} checks passed",
+            self.operations, self.refused, self.processes, self.checks
+        )
+    }
+}
+
+// An engine under stress: a VMManager and the processes sharing it.
+pub struct StressRun {
+    vm: VMManager,
+    processes: ProcessTable,
+}
+
+impl StressRun {
+    pub fn new(init_data: &InitData, tlb_entries: usize) -> Self {
+        let mut vm = VMManager::from_init_data(init_data);
+        if tlb_entries > 0 {
+            vm.enable_tlb(tlb_entries, TlbEviction::Lru);
+        }
+        StressRun { vm, processes: ProcessTable::new() }
+    }
+
+    pub fn vm(&self) -> &VMManager {
+        &self.vm
+    }
+
+    pub fn processes(&self) -> &ProcessTable {
+        &self.processes
+    }
+
+    pub fn apply(&mut self, op: &StressOp) -> Result<(), String> {
+        match *op {
+            StressOp::Access(access) => {
+                self.vm.access(&access);
+                Ok(())
+            }
+            StressOp::Grow { s, size } => self.vm.grow_segment(s, size),
+            StressOp::Map { s, offset, len } => self.vm.map_region(s, offset, len, Backing::ZeroFill).map(|_| ()),
+            StressOp::FreePage { s, p } => self.vm.free_page(s, p).map(|_| ()),
+            StressOp::Free(s) => self.vm.free_segment(s).map(|_| ()),
+            StressOp::Fork(parent) => self.vm.fork_process(&mut self.processes, parent).map(|_| ()),
+            StressOp::Switch(pid) => self.vm.switch_process(&mut self.processes, pid),
+        }
+    }
+
+    // Validates every process's tables against the free list and frame table,
+    // and checks that each cached TLB entry still agrees with its process's
+    // page tables. Leaves the running process's segment table active.
+    pub fn check(&mut self) -> Result<(), Vec<String>> {
+        let running = self.vm.memory().st_frame();
+        let mut errors = Vec::new();
+        for process in self.processes.processes().to_vec() {
+            self.vm.memory_mut().set_st_frame(process.st_frame);
+            let (pm, disk, ffl) = (self.vm.memory(), self.vm.disk(), self.vm.free_frames());
+            if let Err(found) = validate(pm, ffl, disk) {
+                errors.extend(found.iter().map(|error| format!("process {}: {}", process.pid, error)));
+            }
+            let entries = self.vm.tlb().map(|tlb| tlb.entries()).unwrap_or_default();
+            for entry in entries.iter().filter(|entry| entry.asid == process.pid) {
+                let (size, pt_location) = (pm.get_segment_size(entry.s), pm.get_segment_pt_location(entry.s));
+                let frame = match entry.is_huge() {
+                    true => pt_location + (entry.p as usize * HUGE_PAGE_FRAMES) as i32,
+                    false if pt_location > 0 => pm.get_page_frame(pt_location, entry.p),
+                    false => 0,
+                };
+                if size != entry.segment_size || frame != entry.frame {
+                    errors.push(format!(
+                        "process {}: TLB maps page {} of segment {} to frame {} (size {}), tables say frame {} (size {})",
+                        process.pid, entry.p, entry.s, entry.frame, entry.segment_size, frame, size
+                    ));
+                }
+            }
+        }
+        self.vm.memory_mut().set_st_frame(running);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    // A random operation, weighted towards accesses inside the running
+    // process's segments; the rest change mappings or processes.
+    pub fn random_op(&self, rng: &mut u64, segments: u32) -> StressOp {
+        let below = |rng: &mut u64, n: u32| (next_random(rng) % n.max(1) as u64) as u32;
+        let pm = self.vm.memory();
+        let existing: Vec<u32> = (0..segments).filter(|&s| pm.get_segment_size(s) > 0).collect();
+        let pick_existing = |rng: &mut u64| match existing.is_empty() {
+            true => below(rng, segments),
+            false => existing[below(rng, existing.len() as u32) as usize],
+        };
+        let pids: Vec<Pid> = self.processes.processes().iter().map(|process| process.pid).collect();
+        let pick_pid = |rng: &mut u64| pids[below(rng, pids.len() as u32) as usize];
+
+        match below(rng, 100) {
+            roll @ 0..=69 => {
+                let va = match existing.is_empty() || roll < 7 {
+                    true => compose(below(rng, segments), 0, 0) | below(rng, MAX_SEGMENT_SIZE),
+                    false => {
+                        let s = pick_existing(rng);
+                        let pw = below(rng, pm.get_segment_size(s) as u32);
+                        compose(s, pw / PAGE_SIZE as u32, pw % PAGE_SIZE as u32)
+                    }
+                };
+                match roll % 2 {
+                    0 => StressOp::Access(Access::read(va)),
+                    _ => StressOp::Access(Access::write(va)),
+                }
+            }
+            70..=77 => {
+                let s = below(rng, segments);
+                let offset = (pm.get_segment_size(s).max(0) as u32).next_multiple_of(PAGE_SIZE as u32);
+                StressOp::Map { s, offset, len: (1 + below(rng, 2)) * PAGE_SIZE as u32 }
+            }
+            78..=81 => {
+                let s = below(rng, segments);
+                StressOp::Grow { s, size: pm.get_segment_size(s).max(0) as u32 + 1 + below(rng, 2 * PAGE_SIZE as u32) }
+            }
+            82..=86 => {
+                let s = pick_existing(rng);
+                let pages = (pm.get_segment_size(s).max(0) as u32).div_ceil(PAGE_SIZE as u32);
+                StressOp::FreePage { s, p: below(rng, pages) }
+            }
+            87..=88 => StressOp::Free(pick_existing(rng)),
+            89..=92 if pids.len() < MAX_PROCESSES => StressOp::Fork(pick_pid(rng)),
+            _ => StressOp::Switch(pick_pid(rng)),
+        }
+    }
+}
+
+// Runs config.steps random operations from init_data. On the first failed
+// check, returns the violation with every operation up to it.
+pub fn run_stress(init_data: &InitData, config: &StressConfig) -> Result<StressReport, (Violation, Vec<StressOp>)> {
+    let mut run = StressRun::new(init_data, config.tlb_entries);
+    let mut rng = config.seed;
+    let mut ops = Vec::with_capacity(config.steps);
+    let mut report = StressReport::default();
+    for step in 0..config.steps {
+        let op = run.random_op(&mut rng, config.segments);
+        ops.push(op);
+        report.operations += 1;
+        report.refused += run.apply(&op).is_err() as usize;
+        if (step + 1).is_multiple_of(config.check_every.max(1)) || step + 1 == config.steps {
+            if let Err(errors) = run.check() {
+                return Err((Violation { step, errors }, ops));
+            }
+            report.checks += 1;
+        }
+    }
+    report.processes = run.processes.processes().len();
+    Ok(report)
+}
+
+// Replays ops from init_data, checking after every one, and returns the first
+// violation.
+pub fn replay(init_data: &InitData, tlb_entries: usize, ops: &[StressOp]) -> Option<Violation> {
+    let mut run = StressRun::new(init_data, tlb_entries);
+    for (step, op) in ops.iter().enumerate() {
+        let _ = run.apply(op);
+        if let Err(errors) = run.check() {
+            return Some(Violation { step, errors });
+        }
+    }
+    None
+}
+
+// Shrinks a failing sequence: cuts it at its violation, then drops runs of
+// operations, halving their length down to single ones, for as long as the
+// replay still fails.
+pub fn minimize(init_data: &InitData, tlb_entries: usize, ops: &[StressOp]) -> Vec<StressOp> {
+    let Some(violation) = replay(init_data, tlb_entries, ops) else {
+        return ops.to_vec();
+    };
+    let mut ops = ops[..=violation.step].to_vec();
+    let mut chunk = ops.len().div_ceil(2);
+    while chunk > 0 {
+        let mut start = 0;
+        while start < ops.len() {
+            let candidate = [&ops[..start], &ops[(start + chunk).min(ops.len())..]].concat();
+            match replay(init_data, tlb_entries, &candidate) {
+                Some(violation) => ops = candidate[..=violation.step].to_vec(),
+                None => start += chunk,
+            }
+        }
+        chunk /= 2;
+    }
+    ops
+}
+
+// A reproduction file: the TLB size, the init file and the operations, as
+// `stress --replay` reads them back.
+pub fn repro_to_string(init_data: &InitData, tlb_entries: usize, ops: &[StressOp], violation: &Violation) -> String {
+    let mut out = String::new();
+    for error in &violation.errors {
+        out += &format!("# {}\n", error);
+    }
+    out += &format!("tlb {}\n[init]\n{}\n[ops]\n", tlb_entries, init_data.to_init_string().trim_end());
+    for op in ops {
+        out += &format!("{}\n", op);
+    }
+    out
+}
+
+pub fn parse_repro(content: &str) -> Result<(InitData, usize, Vec<StressOp>), String> {
+    let missing = || "Reproduction file needs [init] and [ops] sections".to_string();
+    let (header, rest) = content.split_once("[init]\n").ok_or_else(missing)?;
+    let (init, ops) = rest.split_once("[ops]\n").ok_or_else(missing)?;
+    let mut tlb_entries = 0;
+    for line in header.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        match line.split_once(' ') {
+            Some(("tlb", entries)) => {
+                tlb_entries = entries.trim().parse().map_err(|_| format!("Invalid TLB size: {}", entries))?
+            }
+            _ => return Err(format!("Unknown reproduction setting: {}", line)),
+        }
+    }
+    let ops = ops.lines().filter(|line| !line.trim().is_empty()).map(StressOp::parse).collect::<Result<_, _>>()?;
+    Ok((InitData::parse(init)?, tlb_entries, ops))
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::constants::*;
+use crate::memory::{Disk, DiskBlockAllocator, FrameOwner, PhysicalMemory, StSlice};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SwapStats {
+    pub capacity: usize,
+    pub used: usize,
+    pub peak_used: usize,
+    pub assigned: u64,
+    pub released: u64,
+    // Assignments refused because every block was in use.
+    pub failed: u64,
+}
+
+impl SwapStats {
+    pub fn occupancy(&self) -> f64 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+        self.used as f64 / self.capacity as f64
+    }
+}
+
+impl fmt::Display for SwapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} blocks in use ({:.1}%, peak {}), {} assigned, {} released, {} refused",
+            self.used,
+            self.capacity,
+            self.occupancy() * 100.0,
+            self.peak_used,
+            self.assigned,
+            self.released,
+            self.failed
+        )
+    }
+}
+
+// Tracks which disk blocks hold what, so blocks can be handed to evicted pages
+// that have none (demand-zero pages once written) and taken back when pages are
+// freed or a process exits, rather than only ever being assigned by init files.
+pub struct SwapAllocator {
+    owners: Vec<FrameOwner>,
+    free: DiskBlockAllocator,
+    stats: SwapStats,
+}
+
+impl SwapAllocator {
+    pub fn new() -> Self {
+        SwapAllocator {
+            owners: vec![FrameOwner::Free; DISK_BLOCKS],
+            free: DiskBlockAllocator::new(),
+            stats: SwapStats { capacity: DISK_BLOCKS - 1, ..SwapStats::default() },
+        }
+    }
+
+    // Reserves every block the ST and page tables point at. Blocks backing pages
+    // or tables that are resident now are not visible in the tables and must be
+    // reserved by the caller.
+    pub fn from_tables(pm: &PhysicalMemory, disk: &Disk) -> Self {
+        let mut swap = Self::new();
+        let layout = pm.layout();
+        for &slice in pm.st_slices() {
+            if let StSlice::OnDisk(block) = slice {
+                swap.reserve(block, FrameOwner::SegmentTable);
+            }
+        }
+        for s in 0..layout.max_segments() as u32 {
+            let (size, pt_location, _) = pm.read_segment_entry(disk, s);
+            if pm.is_huge_segment(s) {
+                continue;
+            }
+            let pages = (size.max(0) as usize).div_ceil(layout.page_size()).min(layout.pt_size().min(BLOCK_SIZE)) as u32;
+            if pt_location < 0 {
+                swap.reserve((-pt_location) as usize, FrameOwner::PageTable(s));
+            }
+            for p in 0..pages {
+                let entry = pm.read_page_entry(disk, pt_location, p);
+                if entry < 0 && entry != PTE_DEMAND_ZERO {
+                    swap.reserve((-entry) as usize, FrameOwner::Page(s, p));
+                }
+            }
+        }
+        swap
+    }
+
+    pub fn reserve(&mut self, block: usize, owner: FrameOwner) {
+        if block == 0 || block >= DISK_BLOCKS {
+            return;
+        }
+        if self.owners[block] == FrameOwner::Free {
+            self.free.mark_used(block);
+            self.stats.used += 1;
+            self.stats.peak_used = self.stats.peak_used.max(self.stats.used);
+        }
+        self.owners[block] = owner;
+    }
+
+    // The lowest free block, now owned by owner.
+    pub fn assign(&mut self, owner: FrameOwner) -> Option<usize> {
+        self.assign_in(owner, 1..DISK_BLOCKS)
+    }
+
+    pub fn assign_in(&mut self, owner: FrameOwner, blocks: Range<usize>) -> Option<usize> {
+        let blocks = blocks.start.max(1)..blocks.end.min(DISK_BLOCKS);
+        let Some(block) = blocks.into_iter().find(|&block| self.owners[block] == FrameOwner::Free) else {
+            self.stats.failed += 1;
+            return None;
+        };
+        self.free.mark_used(block);
+        self.owners[block] = owner;
+        self.stats.used += 1;
+        self.stats.peak_used = self.stats.peak_used.max(self.stats.used);
+        self.stats.assigned += 1;
+        Some(block)
+    }
+
+    pub fn release(&mut self, block: usize) -> FrameOwner {
+        if block == 0 || block >= DISK_BLOCKS || self.owners[block] == FrameOwner::Free {
+            return FrameOwner::Free;
+        }
+        self.free.release(block);
+        self.stats.used -= 1;
+        self.stats.released += 1;
+        std::mem::take(&mut self.owners[block])
+    }
+
+    // On process exit (or when a segment is freed): releases every block held by
+    // a page or the page table of segment s.
+    pub fn release_segment(&mut self, s: u32) -> Vec<usize> {
+        let blocks: Vec<usize> = (1..DISK_BLOCKS)
+            .filter(|&block| matches!(self.owners[block], FrameOwner::PageTable(o) | FrameOwner::Page(o, _) if o == s))
+            .collect();
+        for &block in &blocks {
+            self.release(block);
+        }
+        blocks
+    }
+
+    pub fn owner(&self, block: usize) -> FrameOwner {
+        self.owners.get(block).copied().unwrap_or_default()
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free.free_count()
+    }
+
+    pub fn stats(&self) -> &SwapStats {
+        &self.stats
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapTier {
+    Fast,
+    Slow,
+}
+
+// Two swap devices on one disk: blocks 1 through fast_blocks are the fast
+// device (an SSD, say) and the rest the slow one. A page used at least
+// hot_threshold times while resident is placed on the fast device when it is
+// evicted, and any other page on the slow one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapTiers {
+    pub fast_blocks: usize,
+    pub hot_threshold: u64,
+}
+
+impl SwapTiers {
+    pub fn new(fast_blocks: usize, hot_threshold: u64) -> Result<Self, String> {
+        if fast_blocks == 0 || fast_blocks >= DISK_BLOCKS - 1 {
+            return Err(format!("The fast swap tier must hold between 1 and {} blocks", DISK_BLOCKS - 2));
+        }
+        Ok(SwapTiers { fast_blocks, hot_threshold })
+    }
+
+    // `fast_blocks:hot_threshold`, e.g. `256:4`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (blocks, threshold) =
+            value.split_once(':').ok_or_else(|| format!("Invalid swap tiers (expected blocks:threshold): {}", value))?;
+        let blocks = blocks.parse().map_err(|_| format!("Invalid fast tier size: {}", blocks))?;
+        let threshold = threshold.parse().map_err(|_| format!("Invalid hot threshold: {}", threshold))?;
+        Self::new(blocks, threshold)
+    }
+
+    pub fn tier_of(&self, block: usize) -> SwapTier {
+        match block <= self.fast_blocks {
+            true => SwapTier::Fast,
+            false => SwapTier::Slow,
+        }
+    }
+
+    pub fn blocks(&self, tier: SwapTier) -> Range<usize> {
+        match tier {
+            SwapTier::Fast => 1..self.fast_blocks + 1,
+            SwapTier::Slow => self.fast_blocks + 1..DISK_BLOCKS,
+        }
+    }
+
+    pub fn placement(&self, accesses: u64) -> SwapTier {
+        match accesses >= self.hot_threshold {
+            true => SwapTier::Fast,
+            false => SwapTier::Slow,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TierStats {
+    pub fast_reads: u64,
+    pub slow_reads: u64,
+    pub promotions: u64,
+    pub demotions: u64,
+    // Moves skipped because the target tier was full.
+    pub refused: u64,
+}
+
+impl fmt::Display for TierStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} fast reads, {} slow reads, {} promotions, {} demotions, {} moves refused",
+            self.fast_reads, self.slow_reads, self.promotions, self.demotions, self.refused
+        )
+    }
+}
+
+impl Default for SwapAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,132 @@
+use crate::constants::*;
+use crate::generator::{next_random, unit_random};
+use crate::io::InitData;
+use crate::memory::{FrameOwner, PhysicalMemory};
+use crate::translation::{reverse_lookup, TranslationResult, VirtualAddress};
+
+// Generators and invariant checks for property tests and fuzz targets, built
+// with `--features testing`. Randomness comes from a u64 state advanced the
+// same way as `generator`'s, so a seed always yields the same init and
+// addresses.
+
+// Bounds for `random_init`: up to `max_segments` segments of up to
+// `max_pages` pages, each page resident with probability `resident` and
+// each page table with probability `pt_resident` (on disk otherwise).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InitParams {
+    pub max_segments: u32,
+    pub max_pages: u32,
+    pub resident: f64,
+    pub pt_resident: f64,
+}
+
+impl Default for InitParams {
+    fn default() -> Self {
+        InitParams { max_segments: 8, max_pages: 16, resident: 0.5, pt_resident: 0.75 }
+    }
+}
+
+fn below(rng: &mut u64, n: usize) -> usize {
+    (next_random(rng) % n as u64) as usize
+}
+
+// Removes and returns a random element, so frames and blocks are handed out
+// once each but in no particular order.
+fn take(rng: &mut u64, pool: &mut Vec<u32>) -> u32 {
+    let index = below(rng, pool.len());
+    pool.swap_remove(index)
+}
+
+// A consistent init: distinct segments with random sizes, every page inside
+// a segment's size mapped to its own frame or disk block, and PTs in frames
+// or on disk. Params that could run out of frames or blocks are rejected.
+pub fn random_init(rng: &mut u64, params: &InitParams) -> Result<InitData, String> {
+    let (segments, pages) = (params.max_segments as usize, params.max_pages as usize);
+    if segments == 0 || pages == 0 {
+        return Err("Random init needs at least one segment and page".to_string());
+    }
+    if segments > MAX_SEGMENTS || pages > PT_SIZE {
+        return Err(format!("At most {} segments of {} pages", MAX_SEGMENTS, PT_SIZE));
+    }
+    if segments * (pages + 1) > (NUM_FRAMES - ST_FRAMES).min(DISK_BLOCKS - 1) {
+        return Err(format!("{} segments of {} pages may not fit in memory or on disk", segments, pages));
+    }
+    for fraction in [params.resident, params.pt_resident] {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(format!("Resident fraction must be between 0 and 1, got {}", fraction));
+        }
+    }
+
+    let mut free_segments: Vec<u32> = (0..MAX_SEGMENTS as u32).collect();
+    let mut free_frames: Vec<u32> = (ST_FRAMES as u32..NUM_FRAMES as u32).collect();
+    let mut free_blocks: Vec<u32> = (1..DISK_BLOCKS as u32).collect();
+    let mut location = |rng: &mut u64, resident: f64| match unit_random(rng) < resident {
+        true => take(rng, &mut free_frames) as i32,
+        false => -(take(rng, &mut free_blocks) as i32),
+    };
+
+    let mut init_data = InitData::default();
+    for _ in 0..1 + below(rng, segments) {
+        let s = take(rng, &mut free_segments);
+        let max_size = (1 + below(rng, pages)) * PAGE_SIZE;
+        let size = 1 + below(rng, max_size);
+        init_data.st_entries.push((s, size as i32, location(rng, params.pt_resident)));
+        for p in 0..size.div_ceil(PAGE_SIZE) as u32 {
+            init_data.pt_entries.push((s, p, location(rng, params.resident)));
+        }
+    }
+    Ok(init_data)
+}
+
+// An address inside a segment's bounds whose page is mapped, so translating
+// it succeeds once any faults are served. Pages behind a PT on disk are taken
+// to be mapped, as `random_init` maps every page of a segment. None when no
+// mapped page exists.
+pub fn random_valid_va(pm: &PhysicalMemory, rng: &mut u64) -> Option<VirtualAddress> {
+    let layout = pm.layout();
+    let page_size = layout.page_size() as u32;
+    let segments: Vec<u32> = (0..layout.max_segments() as u32)
+        .filter(|&s| pm.get_segment_pt_location(s) != 0 && pm.get_segment_size(s) > 0)
+        .collect();
+    if segments.is_empty() {
+        return None;
+    }
+    let s = segments[below(rng, segments.len())];
+    let (size, pt_location) = (pm.get_segment_size(s) as u32, pm.get_segment_pt_location(s));
+    let mapped: Vec<u32> = (0..size.div_ceil(page_size).min(layout.pt_size() as u32))
+        .filter(|&p| pt_location < 0 || pm.is_huge_segment(s) || pm.get_page_frame(pt_location, p) != 0)
+        .collect();
+    if mapped.is_empty() {
+        return None;
+    }
+    let p = mapped[below(rng, mapped.len())];
+    let w = below(rng, (size - p * page_size).min(page_size) as usize) as u32;
+    Some(VirtualAddress { s, p, w, pw: p * page_size + w })
+}
+
+// Invariants every successful translation of va must keep: the physical
+// address lies in memory, at va's offset within a frame that holds a page
+// (not free, not the ST and not a PT), and a reverse lookup of it finds va.
+pub fn check_translation(pm: &PhysicalMemory, va: VirtualAddress, result: &TranslationResult) -> Result<(), String> {
+    let TranslationResult::Success(pa) = *result else {
+        return Ok(());
+    };
+    let pa = pa as u32;
+    let layout = pm.layout();
+    if pa as usize >= layout.memory_size() {
+        return Err(format!("va {} translated to {}, past the end of memory", va.to_raw(), pa));
+    }
+    let page_size = layout.page_size() as u32;
+    if pa % page_size != va.w {
+        return Err(format!("va {} translated to {}, at offset {} instead of {}", va.to_raw(), pa, pa % page_size, va.w));
+    }
+    let frame = pa / page_size;
+    let owner = pm.who_owns(frame);
+    if !matches!(owner, FrameOwner::Page(..)) {
+        return Err(format!("va {} translated to {}, in frame {} ({})", va.to_raw(), pa, frame, owner));
+    }
+    if !reverse_lookup(pa, pm).contains(&va) {
+        return Err(format!("va {} translated to {}, but no mapping leads back to it", va.to_raw(), pa));
+    }
+    Ok(())
+}
@@ -0,0 +1,612 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::constants::*;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::translation::{translate, translate_with_demand_paging, TranslationResult, VirtualAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TlbEviction {
+    Lru,
+    Fifo,
+}
+
+impl TlbEviction {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "lru" => Ok(TlbEviction::Lru),
+            "fifo" => Ok(TlbEviction::Fifo),
+            _ => Err(format!("Unknown TLB eviction policy: {}", name)),
+        }
+    }
+}
+
+// What the per-access loop needs from a TLB, whichever organization backs it.
+pub trait TlbCache {
+    fn lookup(&mut self, va: &VirtualAddress) -> Option<TranslationResult>;
+
+    // p is in units of page_size, and frame is the first frame of the page;
+    // page_size and frame_size are in words.
+    fn insert(&mut self, s: u32, p: u32, frame: i32, segment_size: i32, page_size: usize, frame_size: usize);
+
+    fn hits(&self) -> u64;
+
+    fn misses(&self) -> u64;
+
+    fn invalidate(&mut self, s: u32, p: u32) -> bool;
+
+    fn invalidate_segment(&mut self, s: u32) -> usize;
+
+    fn flush_all(&mut self);
+
+    fn invalidations(&self) -> u64;
+
+    fn set_asid(&mut self, asid: u32);
+
+    fn invalidate_asid(&mut self, asid: u32) -> usize;
+
+    fn reach(&self) -> TlbReach;
+
+    fn fill(&mut self, va: &VirtualAddress, pm: &PhysicalMemory, result: &TranslationResult) {
+        if let TranslationResult::Success(pa) = result {
+            let (p, frame, page_size) = entry_for(va, pm, *pa);
+            self.insert(va.s, p, frame, pm.get_segment_size(va.s), page_size, pm.layout().page_size());
+        }
+    }
+
+    fn hit_rate(&self) -> f64 {
+        let lookups = self.hits() + self.misses();
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits() as f64 / lookups as f64
+    }
+
+    fn miss_classes(&self) -> Option<MissClasses> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MissClasses {
+    pub compulsory: u64,
+    pub capacity: u64,
+    pub conflict: u64,
+}
+
+// An entry maps one page of page_size words: a base page, or the huge page of
+// a huge-page segment, whose p counts huge pages and covers HUGE_PAGE_FRAMES
+// base pages. frame_size is the memory's page size when the entry was filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlbEntry {
+    pub asid: u32,
+    pub s: u32,
+    pub p: u32,
+    pub frame: i32,
+    pub segment_size: i32,
+    pub page_size: usize,
+    pub frame_size: usize,
+    inserted: u64,
+    last_used: u64,
+}
+
+impl TlbEntry {
+    pub fn is_huge(&self) -> bool {
+        self.page_size > self.frame_size
+    }
+
+    fn frames(&self) -> usize {
+        self.page_size / self.frame_size
+    }
+
+    // Whether the entry covers base page p of segment s.
+    fn covers(&self, asid: u32, s: u32, p: u32) -> bool {
+        self.asid == asid && self.s == s && self.p == p / self.frames() as u32
+    }
+
+    fn translate(&self, va: &VirtualAddress) -> TranslationResult {
+        if va.pw >= self.segment_size as u32 {
+            return TranslationResult::SegmentBoundaryViolation;
+        }
+        TranslationResult::Success(self.frame * self.frame_size as i32 + (va.pw as usize % self.page_size) as i32)
+    }
+}
+
+// The address range the cached entries map, against what the same entries
+// would map as base pages and what a full TLB of base pages maps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TlbReach {
+    pub entries: usize,
+    pub huge_entries: usize,
+    pub capacity: usize,
+    pub words: usize,
+    pub frame_size: usize,
+}
+
+impl TlbReach {
+    fn of<'a>(entries: impl Iterator<Item = &'a TlbEntry>, capacity: usize) -> Self {
+        let mut reach = TlbReach { capacity, frame_size: PAGE_SIZE, ..TlbReach::default() };
+        for entry in entries {
+            reach.entries += 1;
+            reach.huge_entries += entry.is_huge() as usize;
+            reach.words += entry.page_size;
+            reach.frame_size = entry.frame_size;
+        }
+        reach
+    }
+
+    pub fn base_page_words(&self) -> usize {
+        self.entries * self.frame_size
+    }
+
+    pub fn full_base_page_words(&self) -> usize {
+        self.capacity * self.frame_size
+    }
+}
+
+// The TLB entry for a successful translation to pa: its page number, first
+// frame and page size.
+fn entry_for(va: &VirtualAddress, pm: &PhysicalMemory, pa: i32) -> (u32, i32, usize) {
+    let frame_size = pm.layout().page_size();
+    let page_size = match pm.is_huge_segment(va.s) {
+        true => HUGE_PAGE_FRAMES * frame_size,
+        false => frame_size,
+    };
+    let offset = va.pw as usize % page_size;
+    (va.pw / page_size as u32, ((pa as usize - offset) / frame_size) as i32, page_size)
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tlb {
+    entries: Vec<TlbEntry>,
+    capacity: usize,
+    eviction: TlbEviction,
+    asid: u32,
+    tick: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+}
+
+impl Tlb {
+    pub fn new(capacity: usize, eviction: TlbEviction) -> Self {
+        Tlb {
+            entries: Vec::with_capacity(capacity),
+            capacity,
+            eviction,
+            asid: 0,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+            invalidations: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn eviction(&self) -> TlbEviction {
+        self.eviction
+    }
+
+    pub fn entries(&self) -> &[TlbEntry] {
+        &self.entries
+    }
+
+    pub fn asid(&self) -> u32 {
+        self.asid
+    }
+
+    // Lookups, inserts and (s, p) invalidations only see entries tagged with the
+    // current ASID, so several address spaces can share the TLB without flushes.
+    pub fn set_asid(&mut self, asid: u32) {
+        self.asid = asid;
+    }
+
+    pub fn lookup(&mut self, va: &VirtualAddress) -> Option<TranslationResult> {
+        self.tick += 1;
+        let tick = self.tick;
+        let asid = self.asid;
+        match self.entries.iter_mut().find(|e| e.covers(asid, va.s, va.p)) {
+            Some(entry) => {
+                self.hits += 1;
+                entry.last_used = tick;
+                Some(entry.translate(va))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, s: u32, p: u32, frame: i32, segment_size: i32, page_size: usize, frame_size: usize) {
+        if self.capacity == 0 {
+            return;
+        }
+        let asid = self.asid;
+        let entry = TlbEntry { asid, s, p, frame, segment_size, page_size, frame_size, inserted: self.tick, last_used: self.tick };
+        if let Some(existing) = self.entries.iter_mut().find(|e| e.asid == asid && e.s == s && e.p == p && e.page_size == page_size) {
+            *existing = entry;
+            return;
+        }
+        if self.entries.len() < self.capacity {
+            self.entries.push(entry);
+            return;
+        }
+        let victim = match self.eviction {
+            TlbEviction::Lru => self.entries.iter().enumerate().min_by_key(|(_, e)| e.last_used),
+            TlbEviction::Fifo => self.entries.iter().enumerate().min_by_key(|(_, e)| e.inserted),
+        }
+        .map(|(i, _)| i)
+        .unwrap();
+        self.entries[victim] = entry;
+    }
+
+    // Drops the entry covering (s, p), if cached; call whenever its PT entry changes.
+    pub fn invalidate(&mut self, s: u32, p: u32) -> bool {
+        let asid = self.asid;
+        self.remove_where(|e| e.covers(asid, s, p)) > 0
+    }
+
+    pub fn reach(&self) -> TlbReach {
+        TlbReach::of(self.entries.iter(), self.capacity)
+    }
+
+    // Drops every entry of segment s, e.g. after its size or PT location changes.
+    pub fn invalidate_segment(&mut self, s: u32) -> usize {
+        let asid = self.asid;
+        self.remove_where(|e| e.asid == asid && e.s == s)
+    }
+
+    // Drops an address space's entries when its ASID is about to be reused.
+    pub fn invalidate_asid(&mut self, asid: u32) -> usize {
+        self.remove_where(|e| e.asid == asid)
+    }
+
+    pub fn flush_all(&mut self) {
+        self.remove_where(|_| true);
+    }
+
+    fn remove_where<F: Fn(&TlbEntry) -> bool>(&mut self, matches: F) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|e| !matches(e));
+        let removed = before - self.entries.len();
+        self.invalidations += removed as u64;
+        removed
+    }
+}
+
+impl TlbCache for Tlb {
+    fn lookup(&mut self, va: &VirtualAddress) -> Option<TranslationResult> {
+        Tlb::lookup(self, va)
+    }
+
+    fn insert(&mut self, s: u32, p: u32, frame: i32, segment_size: i32, page_size: usize, frame_size: usize) {
+        Tlb::insert(self, s, p, frame, segment_size, page_size, frame_size)
+    }
+
+    fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn invalidate(&mut self, s: u32, p: u32) -> bool {
+        Tlb::invalidate(self, s, p)
+    }
+
+    fn invalidate_segment(&mut self, s: u32) -> usize {
+        Tlb::invalidate_segment(self, s)
+    }
+
+    fn flush_all(&mut self) {
+        Tlb::flush_all(self)
+    }
+
+    fn invalidations(&self) -> u64 {
+        self.invalidations
+    }
+
+    fn set_asid(&mut self, asid: u32) {
+        Tlb::set_asid(self, asid)
+    }
+
+    fn invalidate_asid(&mut self, asid: u32) -> usize {
+        Tlb::invalidate_asid(self, asid)
+    }
+
+    fn reach(&self) -> TlbReach {
+        Tlb::reach(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IndexHash {
+    LowBits,
+    XorFold,
+}
+
+impl IndexHash {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "low" => Ok(IndexHash::LowBits),
+            "xor" => Ok(IndexHash::XorFold),
+            _ => Err(format!("Unknown TLB index hash: {} (expected low or xor)", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IndexHash::LowBits => "low",
+            IndexHash::XorFold => "xor",
+        }
+    }
+}
+
+// The sp value (segment and page numbers together) picks a set of `ways`
+// entries, replaced LRU within the set. Misses are split three ways against a
+// fully associative LRU shadow of the same total size: compulsory on first
+// reference, capacity if the shadow missed too, conflict if only the sets did.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetAssociativeTlb {
+    sets: Vec<Vec<TlbEntry>>,
+    ways: usize,
+    hash: IndexHash,
+    asid: u32,
+    tick: u64,
+    shadow: VecDeque<u64>,
+    seen: HashSet<u64>,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    pub classes: MissClasses,
+}
+
+impl SetAssociativeTlb {
+    pub fn new(sets: usize, ways: usize, hash: IndexHash) -> Result<Self, String> {
+        if sets == 0 || !sets.is_power_of_two() || ways == 0 {
+            return Err(format!("A set-associative TLB needs a power-of-two set count and at least one way, got {} sets of {} ways", sets, ways));
+        }
+        Ok(SetAssociativeTlb {
+            sets: vec![Vec::with_capacity(ways); sets],
+            ways,
+            hash,
+            asid: 0,
+            tick: 0,
+            shadow: VecDeque::with_capacity(sets * ways),
+            seen: HashSet::new(),
+            hits: 0,
+            misses: 0,
+            invalidations: 0,
+            classes: MissClasses::default(),
+        })
+    }
+
+    pub fn set_count(&self) -> usize {
+        self.sets.len()
+    }
+
+    pub fn ways(&self) -> usize {
+        self.ways
+    }
+
+    pub fn hash(&self) -> IndexHash {
+        self.hash
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.sets.len() * self.ways
+    }
+
+    fn set_index(&self, s: u32, p: u32) -> usize {
+        let sp = (s << P_BITS) | p;
+        let mask = self.sets.len() as u32 - 1;
+        match self.hash {
+            IndexHash::LowBits => (sp & mask) as usize,
+            IndexHash::XorFold => {
+                let bits = mask.count_ones();
+                let mut folded = 0;
+                let mut rest = sp;
+                while rest != 0 {
+                    folded ^= rest & mask;
+                    rest = rest.checked_shr(bits).unwrap_or(0);
+                }
+                folded as usize
+            }
+        }
+    }
+
+    // The miss that led to caching huge page p was tagged with a base page;
+    // from now on the shadow tracks the huge page as a whole.
+    fn retag_huge(&mut self, asid: u32, s: u32, p: u32) {
+        let tag = shadow_tag(asid, s, p, true);
+        self.seen.insert(tag);
+        if let Some(front) = self.shadow.front_mut() {
+            let (front_asid, front_s, front_p, huge) = split_tag(*front);
+            if !huge && (front_asid, front_s, front_p / HUGE_PAGE_FRAMES as u32) == (asid, s, p) {
+                *front = tag;
+            }
+        }
+        let mut first = true;
+        self.shadow.retain(|&t| t != tag || std::mem::take(&mut first));
+    }
+
+    // Updates the fully associative shadow and reports whether it hit.
+    fn shadow_lookup(&mut self, tag: u64) -> bool {
+        let hit = match self.shadow.iter().position(|&t| t == tag) {
+            Some(position) => {
+                self.shadow.remove(position);
+                true
+            }
+            None => false,
+        };
+        self.shadow.push_front(tag);
+        self.shadow.truncate(self.capacity());
+        hit
+    }
+
+    // Invalidated entries leave the shadow too, so a later miss on them is not
+    // mistaken for a conflict. matches gets an entry's ASID, segment, page
+    // number and page size in frames.
+    fn remove_where<F: Fn(u32, u32, u32, u32) -> bool>(&mut self, matches: F) -> usize {
+        let mut removed = 0;
+        for set in &mut self.sets {
+            let before = set.len();
+            set.retain(|e| !matches(e.asid, e.s, e.p, e.frames() as u32));
+            removed += before - set.len();
+        }
+        self.shadow.retain(|&tag| {
+            let (asid, s, p, huge) = split_tag(tag);
+            let frames = if huge { HUGE_PAGE_FRAMES as u32 } else { 1 };
+            !matches(asid, s, p, frames)
+        });
+        self.invalidations += removed as u64;
+        removed
+    }
+
+    pub fn reach(&self) -> TlbReach {
+        TlbReach::of(self.sets.iter().flatten(), self.capacity())
+    }
+}
+
+impl TlbCache for SetAssociativeTlb {
+    fn lookup(&mut self, va: &VirtualAddress) -> Option<TranslationResult> {
+        self.tick += 1;
+        let tick = self.tick;
+        let asid = self.asid;
+        // Once a page has been cached as part of a huge page, its misses count
+        // against the huge page.
+        let huge_tag = shadow_tag(asid, va.s, va.p / HUGE_PAGE_FRAMES as u32, true);
+        let tag = match self.seen.contains(&huge_tag) {
+            true => huge_tag,
+            false => shadow_tag(asid, va.s, va.p, false),
+        };
+        let shadow_hit = self.shadow_lookup(tag);
+        // Huge entries sit in the set of their huge page number, so both sets are probed.
+        let probes = [(va.p, false), (va.p / HUGE_PAGE_FRAMES as u32, true)];
+        let found = probes.iter().find_map(|&(key, huge)| {
+            let set = self.set_index(va.s, key);
+            let way = self.sets[set].iter().position(|e| e.is_huge() == huge && e.covers(asid, va.s, va.p))?;
+            Some((set, way))
+        });
+        match found {
+            Some((set, way)) => {
+                self.hits += 1;
+                let entry = &mut self.sets[set][way];
+                entry.last_used = tick;
+                Some(entry.translate(va))
+            }
+            None => {
+                self.misses += 1;
+                if self.seen.insert(tag) {
+                    self.classes.compulsory += 1;
+                } else if shadow_hit {
+                    self.classes.conflict += 1;
+                } else {
+                    self.classes.capacity += 1;
+                }
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, s: u32, p: u32, frame: i32, segment_size: i32, page_size: usize, frame_size: usize) {
+        let asid = self.asid;
+        let entry = TlbEntry { asid, s, p, frame, segment_size, page_size, frame_size, inserted: self.tick, last_used: self.tick };
+        if entry.is_huge() {
+            self.retag_huge(asid, s, p);
+        }
+        let ways = self.ways;
+        let set = self.set_index(s, p);
+        let set = &mut self.sets[set];
+        if let Some(existing) = set.iter_mut().find(|e| e.asid == asid && e.s == s && e.p == p && e.page_size == page_size) {
+            *existing = entry;
+        } else if set.len() < ways {
+            set.push(entry);
+        } else {
+            let victim = set.iter().enumerate().min_by_key(|(_, e)| e.last_used).map(|(i, _)| i).unwrap();
+            set[victim] = entry;
+        }
+    }
+
+    fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn invalidate(&mut self, s: u32, p: u32) -> bool {
+        let asid = self.asid;
+        self.remove_where(|ea, es, ep, frames| ea == asid && es == s && ep == p / frames) > 0
+    }
+
+    fn invalidate_segment(&mut self, s: u32) -> usize {
+        let asid = self.asid;
+        self.remove_where(|ea, es, _, _| ea == asid && es == s)
+    }
+
+    fn flush_all(&mut self) {
+        self.remove_where(|_, _, _, _| true);
+    }
+
+    fn invalidations(&self) -> u64 {
+        self.invalidations
+    }
+
+    fn set_asid(&mut self, asid: u32) {
+        self.asid = asid;
+    }
+
+    fn invalidate_asid(&mut self, asid: u32) -> usize {
+        self.remove_where(|ea, _, _, _| ea == asid)
+    }
+
+    fn reach(&self) -> TlbReach {
+        SetAssociativeTlb::reach(self)
+    }
+
+    fn miss_classes(&self) -> Option<MissClasses> {
+        Some(self.classes)
+    }
+}
+
+fn shadow_tag(asid: u32, s: u32, p: u32, huge: bool) -> u64 {
+    ((asid as u64) << 32) | (huge as u64) << 31 | ((s << P_BITS) | p) as u64
+}
+
+fn split_tag(tag: u64) -> (u32, u32, u32, bool) {
+    let sp = tag as u32 & !(1 << 31);
+    ((tag >> 32) as u32, sp >> P_BITS, sp & P_MASK, tag & 1 << 31 != 0)
+}
+
+// Batch forms of translate() and translate_with_demand_paging() that consult a
+// TLB before every walk.
+pub fn translate_batch_with_tlb(vas: &[u32], pm: &PhysicalMemory, tlb: &mut dyn TlbCache) -> Vec<i32> {
+    vas.iter()
+        .map(|&va| translate(&VirtualAddress::decompose(pm.layout(), va), pm, Some(&mut *tlb)).to_output())
+        .collect()
+}
+
+pub fn translate_batch_with_demand_paging_tlb(
+    vas: &[u32],
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    tlb: &mut dyn TlbCache,
+) -> Vec<i32> {
+    vas.iter()
+        .map(|&va| translate_with_demand_paging(&VirtualAddress::decompose(pm.layout(), va), pm, disk, ffl, Some(&mut *tlb)).to_output())
+        .collect()
+}
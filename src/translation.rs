@@ -1,7 +1,15 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::constants::*;
-use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::cost::CostModel;
+use crate::layout::AddressLayout;
+use crate::memory::{Disk, FrameOwner, FreeFrameList, PhysicalMemory, StSlice};
+use crate::stats::TranslationStats;
+use crate::tlb::TlbCache;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VirtualAddress {
     pub s: u32,
     pub p: u32,
@@ -17,14 +25,262 @@ impl VirtualAddress {
         let pw = va & PW_MASK;
         VirtualAddress { s, p, w, pw }
     }
+
+    pub fn decompose(layout: &AddressLayout, va: u32) -> Self {
+        let (s, p, w, pw) = layout.split(va);
+        VirtualAddress { s, p, w, pw }
+    }
+
+    pub fn try_from_components(s: u32, p: u32, w: u32) -> Result<Self, String> {
+        if s as usize >= MAX_SEGMENTS {
+            return Err(format!("Segment {} is out of range (0-{})", s, MAX_SEGMENTS - 1));
+        }
+        if p > P_MASK {
+            return Err(format!("Page {} is out of range (0-{})", p, P_MASK));
+        }
+        if w > W_MASK {
+            return Err(format!("Offset {} is out of range (0-{})", w, W_MASK));
+        }
+        Ok(VirtualAddress { s, p, w, pw: p << P_SHIFT | w })
+    }
+
+    pub fn to_raw(&self) -> u32 {
+        compose(self.s, self.p, self.w)
+    }
+}
+
+// The address with the given segment, page and offset under the default 9/9/9
+// layout. Components are not range checked; see try_from_components.
+pub fn compose(s: u32, p: u32, w: u32) -> u32 {
+    s << S_SHIFT | p << P_SHIFT | w
+}
+
+// Frame and offset are split at the default page size, as compose() does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhysicalAddress(pub u32);
+
+impl PhysicalAddress {
+    pub fn new(frame: u32, offset: u32) -> Self {
+        PhysicalAddress(frame * PAGE_SIZE as u32 + offset)
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.0 / PAGE_SIZE as u32
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.0 % PAGE_SIZE as u32
+    }
+}
+
+impl fmt::Display for PhysicalAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreeLevelAddress {
+    pub s: u32,
+    pub d: u32,
+    pub p: u32,
+    pub w: u32,
+    pub dpw: u32,
+}
+
+impl ThreeLevelAddress {
+    pub fn decompose(layout: &AddressLayout, va: u32) -> Self {
+        let (s, d, p, w, dpw) = layout.split_three_level(va);
+        ThreeLevelAddress { s, d, p, w, dpw }
+    }
+
+    // The page number within the segment, as recorded in the frame table.
+    pub fn page_index(&self, layout: &AddressLayout) -> u32 {
+        self.d << layout.p_bits | self.p
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccessType {
+    Read,
+    Write,
+    Execute,
+}
+
+// Segment protection is stored in the ST size word as the set of *denied*
+// accesses, so existing images with plain sizes remain rwx.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Protection {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Protection {
+    pub const ALL: Protection = Protection { read: true, write: true, execute: true };
+    pub const READ_ONLY: Protection = Protection { read: true, write: false, execute: false };
+    pub const READ_WRITE: Protection = Protection { read: true, write: true, execute: false };
+    pub const READ_EXECUTE: Protection = Protection { read: true, write: false, execute: true };
+
+    pub fn parse(flags: &str) -> Result<Self, String> {
+        let mut protection = Protection { read: false, write: false, execute: false };
+        for c in flags.chars() {
+            match c {
+                'r' => protection.read = true,
+                'w' => protection.write = true,
+                'x' => protection.execute = true,
+                '-' => {}
+                _ => return Err(format!("Invalid protection flags: {}", flags)),
+            }
+        }
+        Ok(protection)
+    }
+
+    pub fn allows(&self, kind: AccessType) -> bool {
+        match kind {
+            AccessType::Read => self.read,
+            AccessType::Write => self.write,
+            AccessType::Execute => self.execute,
+        }
+    }
+
+    pub fn denied_bits(&self) -> i32 {
+        (!self.read as i32) | (!self.write as i32) << 1 | (!self.execute as i32) << 2
+    }
+
+    pub fn from_denied_bits(bits: i32) -> Self {
+        Protection { read: bits & 1 == 0, write: bits & 2 == 0, execute: bits & 4 == 0 }
+    }
+}
+
+impl Default for Protection {
+    fn default() -> Self {
+        Protection::ALL
+    }
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |allowed: bool, c: char| if allowed { c } else { '-' };
+        write!(f, "{}{}{}", flag(self.read, 'r'), flag(self.write, 'w'), flag(self.execute, 'x'))
+    }
+}
+
+// Data movement carried by an access beyond its translation: a load reads the
+// word at the physical address, a store writes the value there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DataOp {
+    #[default]
+    None,
+    Load,
+    Store(i32),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Access {
+    pub kind: AccessType,
+    pub va: u32,
+    pub data: DataOp,
+}
+
+impl Access {
+    pub fn read(va: u32) -> Self {
+        Access { kind: AccessType::Read, va, data: DataOp::None }
+    }
+
+    pub fn write(va: u32) -> Self {
+        Access { kind: AccessType::Write, va, data: DataOp::None }
+    }
+
+    pub fn execute(va: u32) -> Self {
+        Access { kind: AccessType::Execute, va, data: DataOp::None }
+    }
+
+    pub fn load(va: u32) -> Self {
+        Access { kind: AccessType::Read, va, data: DataOp::Load }
+    }
+
+    pub fn store(va: u32, value: i32) -> Self {
+        Access { kind: AccessType::Write, va, data: DataOp::Store(value) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TranslationResult {
     Success(i32),
     SegmentBoundaryViolation,
     InvalidSegment,
     InvalidPage,
+    AccessDenied,
+    ProtectionViolation,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FaultFlags {
+    pub st_fault: bool,
+    pub pd_fault: bool,
+    pub pt_fault: bool,
+    pub page_fault: bool,
+    pub soft_fault: bool,
+    pub cow_fault: bool,
+    pub zero_fault: bool,
+    // The page was decompressed from the compressed pool rather than read from disk.
+    pub compressed_fault: bool,
+    // The page fault read from the slow swap tier.
+    pub slow_tier: bool,
+}
+
+impl FaultFlags {
+    // Faults that read a table or page from disk into a newly allocated frame.
+    pub fn hard_faults(&self) -> u64 {
+        self.st_fault as u64 + self.pd_fault as u64 + self.pt_fault as u64 + self.page_fault as u64
+    }
+}
+
+// What one demand-paged translation did, for callers that need exact fault
+// attribution rather than just the resulting address. frames_allocated lists
+// every frame taken from the free list, in the order the walk took them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranslationOutcome {
+    pub result: TranslationResult,
+    pub pt_fault: bool,
+    pub page_fault: bool,
+    pub frames_allocated: Vec<u32>,
+}
+
+// How failed translations are written: all as -1, or with a code per failure
+// kind so scripts can tell them apart. Protection violations and process
+// permission denials share a code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorCodes {
+    #[default]
+    Collapsed,
+    Detailed,
+}
+
+impl ErrorCodes {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "collapsed" => Ok(ErrorCodes::Collapsed),
+            "detailed" => Ok(ErrorCodes::Detailed),
+            _ => Err(format!("Unknown error codes: {} (expected collapsed or detailed)", value)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCodes::Collapsed => "collapsed",
+            ErrorCodes::Detailed => "detailed",
+        }
+    }
 }
 
 impl TranslationResult {
@@ -34,9 +290,93 @@ impl TranslationResult {
             _ => INVALID_ADDRESS,
         }
     }
+
+    pub fn to_output_with(&self, codes: ErrorCodes) -> i32 {
+        match (self, codes) {
+            (_, ErrorCodes::Collapsed) | (TranslationResult::Success(_), _) => self.to_output(),
+            (TranslationResult::SegmentBoundaryViolation, ErrorCodes::Detailed) => ERROR_BOUNDARY_VIOLATION,
+            (TranslationResult::InvalidSegment, ErrorCodes::Detailed) => ERROR_INVALID_SEGMENT,
+            (TranslationResult::InvalidPage, ErrorCodes::Detailed) => ERROR_INVALID_PAGE,
+            (TranslationResult::AccessDenied | TranslationResult::ProtectionViolation, ErrorCodes::Detailed) => ERROR_PROTECTION,
+        }
+    }
+
+    pub fn physical_address(&self) -> Option<PhysicalAddress> {
+        match self {
+            TranslationResult::Success(pa) => Some(PhysicalAddress(*pa as u32)),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TranslationResult::Success(_) => "success",
+            TranslationResult::SegmentBoundaryViolation => "boundary_violation",
+            TranslationResult::InvalidSegment => "invalid_segment",
+            TranslationResult::InvalidPage => "invalid_page",
+            TranslationResult::AccessDenied => "access_denied",
+            TranslationResult::ProtectionViolation => "protection_violation",
+        }
+    }
+}
+
+impl fmt::Display for TranslationResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranslationResult::Success(pa) => write!(f, "physical address {}", pa),
+            TranslationResult::SegmentBoundaryViolation => write!(f, "segment boundary violation"),
+            TranslationResult::InvalidSegment => write!(f, "invalid segment"),
+            TranslationResult::InvalidPage => write!(f, "invalid page"),
+            TranslationResult::AccessDenied => write!(f, "access denied"),
+            TranslationResult::ProtectionViolation => write!(f, "protection violation"),
+        }
+    }
 }
 
-pub fn translate(va: &VirtualAddress, pm: &PhysicalMemory) -> TranslationResult {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchError {
+    pub index: usize,
+    pub va: u32,
+    pub cause: TranslationResult,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "address {} (index {}): {}", self.va, self.index, self.cause)
+    }
+}
+
+fn collect_batch_errors<F>(vas: &[u32], layout: AddressLayout, mut translate_one: F) -> (Vec<i32>, Vec<BatchError>)
+where
+    F: FnMut(&VirtualAddress) -> TranslationResult,
+{
+    let mut results = Vec::with_capacity(vas.len());
+    let mut errors = Vec::new();
+    for (index, &va) in vas.iter().enumerate() {
+        let cause = translate_one(&VirtualAddress::decompose(&layout, va));
+        if !matches!(cause, TranslationResult::Success(_)) {
+            errors.push(BatchError { index, va, cause });
+        }
+        results.push(cause.to_output());
+    }
+    (results, errors)
+}
+
+// With a TLB, (s, p) is looked up before the walk and a successful walk fills
+// its entry; without one every call walks the tables.
+pub fn translate(va: &VirtualAddress, pm: &PhysicalMemory, tlb: Option<&mut dyn TlbCache>) -> TranslationResult {
+    let Some(tlb) = tlb else {
+        return walk(va, pm);
+    };
+    if let Some(result) = tlb.lookup(va) {
+        return result;
+    }
+    let result = walk(va, pm);
+    tlb.fill(va, pm, &result);
+    result
+}
+
+fn walk(va: &VirtualAddress, pm: &PhysicalMemory) -> TranslationResult {
     let segment_size = pm.get_segment_size(va.s);
     let pt_location = pm.get_segment_pt_location(va.s);
 
@@ -52,84 +392,712 @@ pub fn translate(va: &VirtualAddress, pm: &PhysicalMemory) -> TranslationResult
         return TranslationResult::InvalidSegment;
     }
 
+    if pm.is_huge_segment(va.s) {
+        return TranslationResult::Success(pt_location * pm.layout().page_size() as i32 + va.pw as i32);
+    }
+
     let page_frame = pm.get_page_frame(pt_location, va.p);
 
     if page_frame <= 0 {
         return TranslationResult::InvalidPage;
     }
 
-    let pa = page_frame * PAGE_SIZE as i32 + va.w as i32;
+    let pa = page_frame * pm.layout().page_size() as i32 + va.w as i32;
     TranslationResult::Success(pa)
 }
 
+// Every virtual address that translate() currently maps to pa: one per resident
+// page whose PTE holds pa's frame (several when the frame is shared, as COW
+// pages are), plus any huge-page segment covering pa. Segments whose ST slice
+// is on disk read as absent, as they do in translate().
+pub fn reverse_lookup(pa: u32, pm: &PhysicalMemory) -> Vec<VirtualAddress> {
+    let layout = pm.layout();
+    let page_size = layout.page_size() as u32;
+    let (frame, w) = ((pa / page_size) as i32, pa % page_size);
+    let mut vas = Vec::new();
+    for s in 0..layout.max_segments() as u32 {
+        let size = pm.get_segment_size(s) as u32;
+        let pt_location = pm.get_segment_pt_location(s);
+        if pt_location <= 0 {
+            continue;
+        }
+        if pm.is_huge_segment(s) {
+            let base = pt_location as u32 * page_size;
+            if (base..base + size).contains(&pa) {
+                let pw = pa - base;
+                vas.push(VirtualAddress { s, p: pw / page_size, w: pw % page_size, pw });
+            }
+            continue;
+        }
+        let pages = size.div_ceil(page_size).min(layout.pt_size() as u32);
+        for p in 0..pages {
+            let pw = p * page_size + w;
+            if pw < size && frame > 0 && pm.get_page_frame(pt_location, p) == frame {
+                vas.push(VirtualAddress { s, p, w, pw });
+            }
+        }
+    }
+    vas
+}
+
+// A TLB hit skips the walk that would have touched the frame, so the hit marks
+// the frame used and referenced itself for the replacement policies.
+pub fn record_tlb_hit(result: &TranslationResult, pm: &mut PhysicalMemory) {
+    if let TranslationResult::Success(pa) = result {
+        let frame = (*pa as usize / pm.layout().page_size()) as u32;
+        pm.frame_table_mut().touch(frame);
+        pm.mark_referenced(frame);
+    }
+}
+
+// Marks the frame of a successful write dirty and performs the access's store.
+pub fn record_access(access: &Access, result: &TranslationResult, pm: &mut PhysicalMemory) {
+    if let TranslationResult::Success(pa) = result {
+        pm.mark_referenced((*pa as usize / pm.layout().page_size()) as u32);
+    }
+    if let (AccessType::Write, TranslationResult::Success(pa)) = (access.kind, result) {
+        pm.mark_dirty((*pa as usize / pm.layout().page_size()) as u32);
+    }
+    if let (DataOp::Store(value), TranslationResult::Success(pa)) = (access.data, result) {
+        pm.write(*pa as usize, value);
+    }
+}
+
+// A write to a page whose PTE is marked copy-on-write gets a private copy: a new
+// frame receives the shared frame's contents and replaces it in the PTE, which
+// clears the flag. Other mappings of the shared frame keep their COW flag.
+pub fn copy_on_write(
+    kind: AccessType,
+    va: &VirtualAddress,
+    result: TranslationResult,
+    pm: &mut PhysicalMemory,
+    ffl: &mut FreeFrameList,
+) -> (TranslationResult, bool) {
+    if kind != AccessType::Write || !matches!(result, TranslationResult::Success(_)) {
+        return (result, false);
+    }
+    let pt_location = pm.get_segment_pt_location(va.s);
+    if pt_location <= 0 || pm.is_huge_segment(va.s) || !pm.is_cow(pt_location, va.p) {
+        return (result, false);
+    }
+    let new_frame = match ffl.allocate() {
+        Some(f) => f,
+        None => return (TranslationResult::InvalidPage, false),
+    };
+    #[cfg(feature = "tracing")]
+    tracing::debug!(s = va.s, p = va.p, shared = pm.get_page_frame(pt_location, va.p), frame = new_frame, "copy-on-write fault");
+    pm.copy_frame(pm.get_page_frame(pt_location, va.p) as u32, new_frame);
+    pm.set_page_entry(pt_location, va.p, new_frame as i32);
+    pm.frame_table_mut().assign(new_frame, va.s, va.p);
+    let pa = new_frame as i32 * pm.layout().page_size() as i32 + va.w as i32;
+    (TranslationResult::Success(pa), true)
+}
+
+pub fn check_protection(kind: AccessType, va: &VirtualAddress, pm: &PhysicalMemory) -> Option<TranslationResult> {
+    let present = pm.get_segment_size(va.s) != 0 || pm.get_segment_pt_location(va.s) != 0;
+    let allowed = pm.segment_protection(va.s).allows(kind) && pm.page_protection(va.s, va.p).allows(kind);
+    (present && !allowed).then_some(TranslationResult::ProtectionViolation)
+}
+
+pub fn translate_access(access: &Access, pm: &mut PhysicalMemory) -> TranslationResult {
+    let va = VirtualAddress::decompose(pm.layout(), access.va);
+    if let Some(violation) = check_protection(access.kind, &va, pm) {
+        return violation;
+    }
+    let result = translate(&va, pm, None);
+    record_access(access, &result, pm);
+    result
+}
+
+pub fn translate_with_stats(
+    va: &VirtualAddress,
+    pm: &PhysicalMemory,
+    stats: Option<&mut TranslationStats>,
+) -> TranslationResult {
+    let result = translate(va, pm, None);
+    if let Some(stats) = stats {
+        stats.record(&result, FaultFlags::default());
+    }
+    result
+}
+
 pub fn translate_batch(vas: &[u32], pm: &PhysicalMemory) -> Vec<i32> {
+    translate_batch_with_stats(vas, pm, None)
+}
+
+pub fn translate_batch_with_stats(
+    vas: &[u32],
+    pm: &PhysicalMemory,
+    stats: Option<&mut TranslationStats>,
+) -> Vec<i32> {
+    translate_batch_cancellable(vas, pm, stats, &AtomicBool::new(false))
+}
+
+// The cancellable batch loops check the flag before every access and return the
+// results translated so far; a result vector shorter than the input means the run
+// was cancelled, and any stats passed in cover exactly those results.
+pub fn translate_batch_cancellable(
+    vas: &[u32],
+    pm: &PhysicalMemory,
+    mut stats: Option<&mut TranslationStats>,
+    cancel: &AtomicBool,
+) -> Vec<i32> {
     vas.iter()
+        .take_while(|_| !cancel.load(Ordering::Relaxed))
         .map(|&va| {
-            let va = VirtualAddress::from_raw(va);
-            translate(&va, pm).to_output()
+            let va = VirtualAddress::decompose(pm.layout(), va);
+            translate_with_stats(&va, pm, stats.as_deref_mut()).to_output()
         })
         .collect()
 }
 
+pub fn translate_batch_checked(vas: &[u32], pm: &PhysicalMemory) -> (Vec<i32>, Vec<BatchError>) {
+    collect_batch_errors(vas, *pm.layout(), |va| translate(va, pm, None))
+}
+
+// As translate(), but a miss walks with demand paging. A hit touches the frame
+// as the walk would.
 pub fn translate_with_demand_paging(
     va: &VirtualAddress,
     pm: &mut PhysicalMemory,
     disk: &Disk,
     ffl: &mut FreeFrameList,
+    tlb: Option<&mut dyn TlbCache>,
+) -> TranslationResult {
+    let Some(tlb) = tlb else {
+        return translate_with_demand_paging_faults(va, pm, disk, ffl).0;
+    };
+    if let Some(result) = tlb.lookup(va) {
+        if let TranslationResult::Success(pa) = result {
+            let frame = (pa as usize / pm.layout().page_size()) as u32;
+            pm.frame_table_mut().touch(frame);
+        }
+        return result;
+    }
+    let (result, _) = translate_with_demand_paging_faults(va, pm, disk, ffl);
+    tlb.fill(va, pm, &result);
+    result
+}
+
+pub fn translate_with_demand_paging_stats(
+    va: &VirtualAddress,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    stats: Option<&mut TranslationStats>,
 ) -> TranslationResult {
+    let (result, faults) = translate_with_demand_paging_faults(va, pm, disk, ffl);
+    if let Some(stats) = stats {
+        stats.record(&result, faults);
+    }
+    result
+}
+
+pub fn translate_access_with_demand_paging(
+    access: &Access,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+) -> (TranslationResult, FaultFlags) {
+    let va = VirtualAddress::decompose(pm.layout(), access.va);
+    if let Some(violation) = check_protection(access.kind, &va, pm) {
+        return (violation, FaultFlags::default());
+    }
+    let (result, mut faults) = translate_with_demand_paging_faults(&va, pm, disk, ffl);
+    let (result, cow_fault) = copy_on_write(access.kind, &va, result, pm, ffl);
+    faults.cow_fault = cow_fault;
+    record_access(access, &result, pm);
+    (result, faults)
+}
+
+pub fn translate_with_demand_paging_faults(
+    va: &VirtualAddress,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+) -> (TranslationResult, FaultFlags) {
+    translate_with_frame_source(va, pm, disk, |_, _| ffl.allocate())
+}
+
+pub fn translate_with_detail(
+    va: &VirtualAddress,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+) -> TranslationOutcome {
+    let mut frames_allocated = Vec::new();
+    let (result, faults) = translate_with_frame_source(va, pm, disk, |_, _| {
+        let frame = ffl.allocate()?;
+        frames_allocated.push(frame);
+        Some(frame)
+    });
+    TranslationOutcome { result, pt_fault: faults.pt_fault, page_fault: faults.page_fault, frames_allocated }
+}
+
+// translate_with_detail over a whole trace, one outcome per address, for callers
+// that need the failure kinds and faults the i32 batch variants collapse to -1.
+pub fn translate_batch_detailed(
+    vas: &[u32],
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+) -> Vec<TranslationOutcome> {
+    translate_batch_detailed_iter(vas, pm, disk, ffl).collect()
+}
+
+// The same lazily, so long traces can be post-processed without holding every outcome.
+pub fn translate_batch_detailed_iter<'a>(
+    vas: &'a [u32],
+    pm: &'a mut PhysicalMemory,
+    disk: &'a Disk,
+    ffl: &'a mut FreeFrameList,
+) -> impl Iterator<Item = TranslationOutcome> + 'a {
+    vas.iter().map(move |&va| translate_with_detail(&VirtualAddress::decompose(pm.layout(), va), pm, disk, ffl))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRequest {
+    SegmentTable { slice: usize },
+    PageTable { s: u32 },
+    Page { s: u32, p: u32 },
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", name = "walk", skip_all, fields(va = va.to_raw(), s = va.s, p = va.p, w = va.w))
+)]
+pub fn translate_with_frame_source<F>(
+    va: &VirtualAddress,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    mut allocate: F,
+) -> (TranslationResult, FaultFlags)
+where
+    F: FnMut(&mut PhysicalMemory, FrameRequest) -> Option<u32>,
+{
+    let mut faults = FaultFlags::default();
+    let page_size = pm.layout().page_size() as i32;
+    let slice = pm.st_slice_of(va.s);
+    if let StSlice::OnDisk(disk_block) = pm.st_slices()[slice] {
+        let new_frame = match allocate(pm, FrameRequest::SegmentTable { slice }) {
+            Some(f) => f,
+            None => return (TranslationResult::InvalidSegment, faults),
+        };
+        disk.load_frame(disk_block, new_frame, pm);
+        pm.frame_table_mut().set_owner(new_frame, FrameOwner::SegmentTable);
+        pm.set_st_slice(slice, StSlice::Resident(new_frame));
+        faults.st_fault = true;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(slice, frame = new_frame, block = disk_block, "segment table fault");
+    }
+
     let segment_size = pm.get_segment_size(va.s);
     let mut pt_location = pm.get_segment_pt_location(va.s);
 
     if segment_size == 0 && pt_location == 0 {
-        return TranslationResult::InvalidSegment;
+        return (TranslationResult::InvalidSegment, faults);
     }
 
     if va.pw >= segment_size as u32 {
-        return TranslationResult::SegmentBoundaryViolation;
+        return (TranslationResult::SegmentBoundaryViolation, faults);
+    }
+
+    // A huge page is always resident, so its walk ends at the ST entry.
+    if pm.is_huge_segment(va.s) {
+        let pa = pt_location * page_size + va.pw as i32;
+        pm.frame_table_mut().touch((pa / page_size) as u32);
+        return (TranslationResult::Success(pa), faults);
     }
 
     if pt_location < 0 {
         let disk_block = (-pt_location) as usize;
-        let new_frame = match ffl.allocate() {
+        let new_frame = match allocate(pm, FrameRequest::PageTable { s: va.s }) {
             Some(f) => f,
-            None => return TranslationResult::InvalidSegment,
+            None => return (TranslationResult::InvalidSegment, faults),
         };
         disk.load_pt_from_disk(disk_block, new_frame, pm);
-        pm.set_segment_entry(va.s, segment_size, new_frame as i32);
+        pm.frame_table_mut().set_owner(new_frame, FrameOwner::PageTable(va.s));
+        pm.set_segment_entry(va.s, segment_size, new_frame as i32, pm.segment_protection(va.s));
         pt_location = new_frame as i32;
+        faults.pt_fault = true;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(s = va.s, frame = new_frame, block = disk_block, "page table fault");
     }
 
     let mut page_frame = pm.get_page_frame(pt_location, va.p);
 
-    if page_frame < 0 {
+    if page_frame == PTE_DEMAND_ZERO {
+        let new_frame = match allocate(pm, FrameRequest::Page { s: va.s, p: va.p }) {
+            Some(f) => f,
+            None => return (TranslationResult::InvalidPage, faults),
+        };
+        pm.zero_frame(new_frame);
+        pm.clear_dirty(new_frame);
+        pm.frame_table_mut().assign(new_frame, va.s, va.p);
+        pm.set_page_entry(pt_location, va.p, new_frame as i32);
+        page_frame = new_frame as i32;
+        faults.zero_fault = true;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(s = va.s, p = va.p, frame = new_frame, "zero-fill fault");
+    } else if page_frame < 0 {
         let disk_block = (-page_frame) as usize;
-        let new_frame = match ffl.allocate() {
+        let new_frame = match allocate(pm, FrameRequest::Page { s: va.s, p: va.p }) {
             Some(f) => f,
-            None => return TranslationResult::InvalidPage,
+            None => return (TranslationResult::InvalidPage, faults),
         };
         disk.load_page_from_disk(disk_block, new_frame, pm);
+        pm.clear_dirty(new_frame);
+        pm.frame_table_mut().assign(new_frame, va.s, va.p);
         pm.set_page_entry(pt_location, va.p, new_frame as i32);
         page_frame = new_frame as i32;
+        faults.page_fault = true;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(s = va.s, p = va.p, frame = new_frame, block = disk_block, "page fault");
     }
 
     if page_frame == 0 {
+        return (TranslationResult::InvalidPage, faults);
+    }
+
+    pm.frame_table_mut().touch(page_frame as u32);
+    let pa = page_frame * page_size + va.w as i32;
+    (TranslationResult::Success(pa), faults)
+}
+
+// Three-level walk: the ST entry locates the segment's page directory, whose
+// entries locate page tables using the same encoding as PT entries locate pages.
+pub fn translate_three_level(va: &ThreeLevelAddress, pm: &PhysicalMemory) -> TranslationResult {
+    let segment_size = pm.get_segment_size(va.s);
+    let pd_location = pm.get_segment_pt_location(va.s);
+
+    if segment_size == 0 && pd_location == 0 {
+        return TranslationResult::InvalidSegment;
+    }
+
+    if va.dpw >= segment_size as u32 {
+        return TranslationResult::SegmentBoundaryViolation;
+    }
+
+    if pd_location <= 0 {
+        return TranslationResult::InvalidSegment;
+    }
+
+    let pt_location = pm.get_page_frame(pd_location, va.d);
+    if pt_location <= 0 {
         return TranslationResult::InvalidPage;
     }
 
-    let pa = page_frame * PAGE_SIZE as i32 + va.w as i32;
+    let page_frame = pm.get_page_frame(pt_location, va.p);
+    if page_frame <= 0 {
+        return TranslationResult::InvalidPage;
+    }
+
+    let pa = page_frame * pm.layout().page_size() as i32 + va.w as i32;
     TranslationResult::Success(pa)
 }
 
+pub fn translate_three_level_with_demand_paging(
+    va: &ThreeLevelAddress,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+) -> (TranslationResult, FaultFlags) {
+    let mut faults = FaultFlags::default();
+    let segment_size = pm.get_segment_size(va.s);
+    let mut pd_location = pm.get_segment_pt_location(va.s);
+
+    if segment_size == 0 && pd_location == 0 {
+        return (TranslationResult::InvalidSegment, faults);
+    }
+
+    if va.dpw >= segment_size as u32 {
+        return (TranslationResult::SegmentBoundaryViolation, faults);
+    }
+
+    if pd_location < 0 {
+        let new_frame = match ffl.allocate() {
+            Some(f) => f,
+            None => return (TranslationResult::InvalidSegment, faults),
+        };
+        disk.load_frame((-pd_location) as usize, new_frame, pm);
+        pm.frame_table_mut().set_owner(new_frame, FrameOwner::PageTable(va.s));
+        pm.set_segment_entry(va.s, segment_size, new_frame as i32, pm.segment_protection(va.s));
+        pd_location = new_frame as i32;
+        faults.pd_fault = true;
+    }
+
+    let mut pt_location = pm.get_page_frame(pd_location, va.d);
+
+    if pt_location < 0 {
+        let new_frame = match ffl.allocate() {
+            Some(f) => f,
+            None => return (TranslationResult::InvalidPage, faults),
+        };
+        disk.load_frame((-pt_location) as usize, new_frame, pm);
+        pm.frame_table_mut().set_owner(new_frame, FrameOwner::PageTable(va.s));
+        pm.set_page_entry(pd_location, va.d, new_frame as i32);
+        pt_location = new_frame as i32;
+        faults.pt_fault = true;
+    }
+
+    if pt_location == 0 {
+        return (TranslationResult::InvalidPage, faults);
+    }
+
+    let mut page_frame = pm.get_page_frame(pt_location, va.p);
+
+    if page_frame < 0 {
+        let new_frame = match ffl.allocate() {
+            Some(f) => f,
+            None => return (TranslationResult::InvalidPage, faults),
+        };
+        disk.load_frame((-page_frame) as usize, new_frame, pm);
+        pm.clear_dirty(new_frame);
+        let page = va.page_index(pm.layout());
+        pm.frame_table_mut().assign(new_frame, va.s, page);
+        pm.set_page_entry(pt_location, va.p, new_frame as i32);
+        page_frame = new_frame as i32;
+        faults.page_fault = true;
+    }
+
+    if page_frame == 0 {
+        return (TranslationResult::InvalidPage, faults);
+    }
+
+    pm.frame_table_mut().touch(page_frame as u32);
+    let pa = page_frame * pm.layout().page_size() as i32 + va.w as i32;
+    (TranslationResult::Success(pa), faults)
+}
+
+pub fn translate_three_level_access(
+    access: &Access,
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+) -> (TranslationResult, FaultFlags) {
+    let va = ThreeLevelAddress::decompose(pm.layout(), access.va);
+    let present = pm.get_segment_size(va.s) != 0 || pm.get_segment_pt_location(va.s) != 0;
+    if present && !pm.segment_protection(va.s).allows(access.kind) {
+        return (TranslationResult::ProtectionViolation, FaultFlags::default());
+    }
+    let (result, faults) = translate_three_level_with_demand_paging(&va, pm, disk, ffl);
+    record_access(access, &result, pm);
+    (result, faults)
+}
+
+pub fn translate_batch_checked_with_demand_paging(
+    vas: &[u32],
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+) -> (Vec<i32>, Vec<BatchError>) {
+    collect_batch_errors(vas, *pm.layout(), |va| translate_with_demand_paging(va, pm, disk, ffl, None))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupingReport {
+    pub accesses: usize,
+    pub groups: usize,
+}
+
+impl GroupingReport {
+    pub fn factor(&self) -> f64 {
+        if self.groups == 0 {
+            return 0.0;
+        }
+        self.accesses as f64 / self.groups as f64
+    }
+}
+
 pub fn translate_batch_with_demand_paging(
     vas: &[u32],
     pm: &mut PhysicalMemory,
     disk: &Disk,
     ffl: &mut FreeFrameList,
 ) -> Vec<i32> {
+    translate_batch_with_demand_paging_stats(vas, pm, disk, ffl, None)
+}
+
+pub fn translate_batch_with_demand_paging_stats(
+    vas: &[u32],
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    stats: Option<&mut TranslationStats>,
+) -> Vec<i32> {
+    translate_batch_grouped(vas, pm, disk, ffl, stats).0.iter().map(TranslationResult::to_output).collect()
+}
+
+pub fn translate_batch_grouped(
+    vas: &[u32],
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    stats: Option<&mut TranslationStats>,
+) -> (Vec<TranslationResult>, GroupingReport) {
+    translate_batch_grouped_cancellable(vas, pm, disk, ffl, stats, &AtomicBool::new(false))
+}
+
+pub fn translate_batch_grouped_cancellable(
+    vas: &[u32],
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    mut stats: Option<&mut TranslationStats>,
+    cancel: &AtomicBool,
+) -> (Vec<TranslationResult>, GroupingReport) {
+    let mut results = Vec::with_capacity(vas.len());
+    let mut report = GroupingReport::default();
+    let mut current_page: Option<(u32, u32)> = None;
+    let mut resolved_frame: Option<i32> = None;
+    let page_size = pm.layout().page_size() as i32;
+
+    for &raw in vas {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let va = VirtualAddress::decompose(pm.layout(), raw);
+        report.accesses += 1;
+
+        if current_page != Some((va.s, va.p)) {
+            current_page = Some((va.s, va.p));
+            resolved_frame = None;
+            report.groups += 1;
+        }
+
+        let (result, faults) = match resolved_frame {
+            Some(frame) => {
+                let result = if va.pw >= pm.get_segment_size(va.s) as u32 {
+                    TranslationResult::SegmentBoundaryViolation
+                } else {
+                    // Still a use of the frame, as it would be after a full walk.
+                    pm.frame_table_mut().touch(frame as u32);
+                    TranslationResult::Success(frame * page_size + va.w as i32)
+                };
+                (result, FaultFlags::default())
+            }
+            None => {
+                let (result, faults) = translate_with_demand_paging_faults(&va, pm, disk, ffl);
+                if let TranslationResult::Success(pa) = result {
+                    resolved_frame = Some(pa / page_size);
+                }
+                (result, faults)
+            }
+        };
+        if let Some(stats) = stats.as_deref_mut() {
+            stats.record(&result, faults);
+        }
+        results.push(result);
+    }
+
+    (results, report)
+}
+
+pub fn translate_batch_timed(vas: &[u32], pm: &PhysicalMemory, model: &CostModel) -> Vec<(i32, u64)> {
+    vas.iter()
+        .map(|&va| {
+            let result = translate(&VirtualAddress::decompose(pm.layout(), va), pm, None);
+            (result.to_output(), model.access_latency(&result, FaultFlags::default()))
+        })
+        .collect()
+}
+
+pub fn translate_batch_timed_with_demand_paging(
+    vas: &[u32],
+    pm: &mut PhysicalMemory,
+    disk: &Disk,
+    ffl: &mut FreeFrameList,
+    model: &CostModel,
+) -> Vec<(i32, u64)> {
     vas.iter()
         .map(|&va| {
-            let va = VirtualAddress::from_raw(va);
-            translate_with_demand_paging(&va, pm, disk, ffl).to_output()
+            let va = VirtualAddress::decompose(pm.layout(), va);
+            let (result, faults) = translate_with_demand_paging_faults(&va, pm, disk, ffl);
+            (result.to_output(), model.access_latency(&result, faults))
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::InitData;
+    use crate::parallel::translate_batch_parallel;
+    use crate::tlb::{Tlb, TlbEviction};
+
+    // Segment 0 has its PT and two pages on disk and an unmapped third page;
+    // segment 1 has a resident PT and ends halfway through its second page.
+    fn memory(layout: &AddressLayout) -> (PhysicalMemory, Disk, FreeFrameList) {
+        let page = layout.page_size();
+        let content = format!("0 {} -100 1 {} 20\n0 0 -200 0 1 -201 0 2 0 1 0 30 1 1 -202", 3 * page, page + page / 2);
+        let init = InitData::parse_with_layout(&content, layout).unwrap();
+        let mut pm = PhysicalMemory::with_layout(*layout);
+        let mut disk = Disk::new();
+        let ffl = init.apply(&mut pm, &mut disk);
+        (pm, disk, ffl)
+    }
+
+    // Runs on one page, with a boundary violation partway through a run and
+    // returns to pages the batch has already resolved.
+    fn trace(layout: &AddressLayout) -> Vec<u32> {
+        let half = layout.page_size() as u32 / 2;
+        [(0, 0, 5), (0, 0, 9), (0, 1, 3), (0, 1, half), (0, 2, 1), (1, 1, 1), (1, 1, half + 1), (1, 1, 2), (1, 0, 7), (0, 1, 4), (0, 0, 5)]
+            .iter()
+            .map(|&(s, p, w)| s << (layout.p_bits + layout.w_bits) | p << layout.w_bits | w)
+            .collect()
+    }
+
+    fn last_used(pm: &PhysicalMemory) -> Vec<u64> {
+        (0..NUM_FRAMES as u32).map(|frame| pm.frame_table().last_used(frame)).collect()
+    }
+
+    #[test]
+    fn grouped_batch_matches_per_access_walks() {
+        for layout in [AddressLayout::DEFAULT, AddressLayout::new(9, 7, 7).unwrap()] {
+            let vas = trace(&layout);
+            let (mut pm, disk, mut ffl) = memory(&layout);
+            let walked: Vec<TranslationResult> = vas
+                .iter()
+                .map(|&va| translate_with_demand_paging(&VirtualAddress::decompose(&layout, va), &mut pm, &disk, &mut ffl, None))
+                .collect();
+
+            let (mut grouped_pm, disk, mut ffl) = memory(&layout);
+            let (grouped, report) = translate_batch_grouped(&vas, &mut grouped_pm, &disk, &mut ffl, None);
+            assert_eq!(grouped, walked, "layout {}", layout);
+            assert!(report.groups < report.accesses);
+            assert_eq!(last_used(&grouped_pm), last_used(&pm), "layout {}", layout);
+
+            let (mut parallel_pm, disk, mut ffl) = memory(&layout);
+            let (parallel, _) = translate_batch_parallel(&vas, &mut parallel_pm, &disk, &mut ffl, None, 2);
+            assert_eq!(parallel, walked, "layout {}", layout);
+            assert_eq!(last_used(&parallel_pm), last_used(&pm), "layout {}", layout);
+        }
+    }
+
+    #[test]
+    fn tlb_hits_match_walks() {
+        let layout = AddressLayout::DEFAULT;
+        let vas = trace(&layout);
+        let (mut pm, disk, mut ffl) = memory(&layout);
+        let walked: Vec<TranslationResult> = vas
+            .iter()
+            .map(|&va| translate_with_demand_paging(&VirtualAddress::decompose(&layout, va), &mut pm, &disk, &mut ffl, None))
+            .collect();
+
+        let (mut cached_pm, disk, mut ffl) = memory(&layout);
+        let mut tlb = Tlb::new(4, TlbEviction::Lru);
+        let cached: Vec<TranslationResult> = vas
+            .iter()
+            .map(|&va| {
+                translate_with_demand_paging(&VirtualAddress::decompose(&layout, va), &mut cached_pm, &disk, &mut ffl, Some(&mut tlb))
+            })
+            .collect();
+        assert_eq!(cached, walked);
+        assert!(tlb.hits > 0);
+        assert_eq!(last_used(&cached_pm), last_used(&pm));
+
+        let resident: Vec<TranslationResult> =
+            vas.iter().map(|&va| translate(&VirtualAddress::decompose(&layout, va), &cached_pm, Some(&mut tlb))).collect();
+        assert_eq!(resident, walked);
+    }
+}
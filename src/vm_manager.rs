@@ -0,0 +1,325 @@
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::access::{handle_access, AccessOutcome, DemandPaging};
+use crate::cost::TlbOutcome;
+use crate::events::{Observer, Observers, VmEvent};
+use crate::io::InitData;
+use crate::memory::{Disk, FreeFrameList, PhysicalMemory};
+use crate::process::{Pid, ProcessTable};
+use crate::segment::{self, Backing, FreedMemory};
+use crate::sink::tlb_prefix;
+use crate::stats::TranslationStats;
+use crate::tlb::{Tlb, TlbEviction};
+use crate::translation::{record_access, Access, ErrorCodes, FaultFlags, TranslationResult, VirtualAddress};
+
+pub struct VMManager {
+    pm: PhysicalMemory,
+    disk: Disk,
+    ffl: FreeFrameList,
+    tlb: Option<Tlb>,
+    stats: TranslationStats,
+    observers: Observers,
+    accesses: u32,
+    error_codes: ErrorCodes,
+    reference_interval: Option<usize>,
+    since_reference_clear: usize,
+}
+
+impl VMManager {
+    pub fn new() -> Self {
+        VMManager {
+            pm: PhysicalMemory::new(),
+            disk: Disk::new(),
+            ffl: FreeFrameList::new(),
+            tlb: None,
+            stats: TranslationStats::new(),
+            observers: Observers::new(),
+            accesses: 0,
+            error_codes: ErrorCodes::Collapsed,
+            reference_interval: None,
+            since_reference_clear: 0,
+        }
+    }
+
+    pub fn from_init_data(init_data: &InitData) -> Self {
+        let mut manager = Self::new();
+        manager.ffl = init_data.apply(&mut manager.pm, &mut manager.disk);
+        manager
+    }
+
+    pub fn from_init_str(content: &str) -> Result<Self, String> {
+        Ok(Self::from_init_data(&InitData::parse(content)?))
+    }
+
+    pub fn from_init_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        Ok(Self::from_init_data(&InitData::from_file(path)?))
+    }
+
+    pub fn init_from_str(&mut self, content: &str) -> Result<(), String> {
+        self.init_from_data(&InitData::parse(content)?);
+        Ok(())
+    }
+
+    // Replaces memory, disk and statistics but keeps the TLB configuration,
+    // reference clearing interval, error codes and observers.
+    pub fn init_from_data(&mut self, init_data: &InitData) {
+        let tlb = self.tlb.as_ref().map(|tlb| Tlb::new(tlb.capacity(), tlb.eviction()));
+        let reference_interval = self.reference_interval;
+        let error_codes = self.error_codes;
+        let observers = std::mem::take(&mut self.observers);
+        *self = Self::from_init_data(init_data);
+        self.tlb = tlb;
+        self.reference_interval = reference_interval;
+        self.error_codes = error_codes;
+        self.observers = observers;
+    }
+
+    // How the batch methods and output() encode failed translations.
+    pub fn set_error_codes(&mut self, codes: ErrorCodes) {
+        self.error_codes = codes;
+    }
+
+    pub fn error_codes(&self) -> ErrorCodes {
+        self.error_codes
+    }
+
+    // A result as the output files write it, under the configured error codes.
+    pub fn output(&self, result: &TranslationResult) -> i32 {
+        result.to_output_with(self.error_codes)
+    }
+
+    // Registers an observer for every VmEvent from later accesses; the
+    // returned id removes it again.
+    pub fn add_observer<O: Observer + Send + 'static>(&mut self, observer: O) -> usize {
+        self.observers.add(observer)
+    }
+
+    pub fn remove_observer(&mut self, id: usize) -> bool {
+        self.observers.remove(id)
+    }
+
+    fn emit(&mut self, event: VmEvent) {
+        self.stats.on_event(&event);
+        self.observers.emit(&event);
+    }
+
+    pub fn enable_tlb(&mut self, entries: usize, eviction: TlbEviction) {
+        self.tlb = Some(Tlb::new(entries, eviction));
+    }
+
+    pub fn disable_tlb(&mut self) {
+        self.tlb = None;
+    }
+
+    pub fn tlb(&self) -> Option<&Tlb> {
+        self.tlb.as_ref()
+    }
+
+    // Clears every reference bit after each `every` accesses; None leaves them
+    // set until clear_reference_bits is called.
+    pub fn set_reference_clear_interval(&mut self, every: Option<usize>) {
+        self.reference_interval = every.filter(|&every| every > 0);
+        self.since_reference_clear = 0;
+    }
+
+    pub fn clear_reference_bits(&mut self) {
+        self.pm.clear_reference_bits();
+        self.since_reference_clear = 0;
+    }
+
+    // Each of these drops the stale TLB entries; resizes drop the whole
+    // segment's, since every entry carries the segment size it was filled with.
+    pub fn grow_segment(&mut self, s: u32, new_size: u32) -> Result<(), String> {
+        segment::grow_segment(&mut self.pm, &mut self.disk, &mut self.ffl, s, new_size)?;
+        if let Some(tlb) = self.tlb.as_mut() {
+            tlb.invalidate_segment(s);
+        }
+        Ok(())
+    }
+
+    pub fn shrink_segment(&mut self, s: u32, new_size: u32) -> Result<FreedMemory, String> {
+        let freed = segment::shrink_segment(&mut self.pm, &mut self.disk, &mut self.ffl, s, new_size)?;
+        if let Some(tlb) = self.tlb.as_mut() {
+            tlb.invalidate_segment(s);
+        }
+        Ok(freed)
+    }
+
+    pub fn map_region(&mut self, s: u32, offset: u32, len: u32, backing: Backing) -> Result<Range<u32>, String> {
+        let pages = segment::map_region(&mut self.pm, &mut self.disk, &mut self.ffl, s, offset, len, backing)?;
+        if let Some(tlb) = self.tlb.as_mut() {
+            tlb.invalidate_segment(s);
+        }
+        Ok(pages)
+    }
+
+    pub fn free_segment(&mut self, s: u32) -> Result<FreedMemory, String> {
+        let freed = segment::free_segment(&mut self.pm, &mut self.disk, &mut self.ffl, s)?;
+        if let Some(tlb) = self.tlb.as_mut() {
+            tlb.invalidate_segment(s);
+        }
+        Ok(freed)
+    }
+
+    pub fn free_page(&mut self, s: u32, p: u32) -> Result<FreedMemory, String> {
+        let freed = segment::free_page(&mut self.pm, &mut self.disk, &mut self.ffl, s, p)?;
+        if let Some(tlb) = self.tlb.as_mut() {
+            tlb.invalidate(s, p);
+        }
+        Ok(freed)
+    }
+
+    // Runs pid's address space from here on, with the TLB tagged by pid so
+    // every process keeps its entries across switches.
+    pub fn switch_process(&mut self, processes: &mut ProcessTable, pid: Pid) -> Result<(), String> {
+        match self.tlb.as_mut() {
+            Some(tlb) => processes.switch_to_with_asid(pid, &mut self.pm, tlb),
+            None => processes.switch_to(pid, &mut self.pm),
+        }
+    }
+
+    pub fn fork_process(&mut self, processes: &mut ProcessTable, parent: Pid) -> Result<Pid, String> {
+        processes.fork(parent, &mut self.pm, &mut self.ffl)
+    }
+
+    pub fn translate(&mut self, va: u32) -> TranslationResult {
+        self.access(&Access::read(va))
+    }
+
+    pub fn access(&mut self, access: &Access) -> TranslationResult {
+        self.access_with_faults(access).0
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", name = "access", skip_all, fields(va = access.va, kind = ?access.kind))
+    )]
+    pub fn access_with_faults(&mut self, access: &Access) -> (TranslationResult, FaultFlags) {
+        let va = VirtualAddress::decompose(self.pm.layout(), access.va);
+        let index = self.accesses;
+        self.accesses += 1;
+        let AccessOutcome { result, faults, tlb: tlb_outcome, page_frame } =
+            handle_access(access, &va, &mut self.pm, &mut self.disk, &mut self.ffl, self.tlb.as_mut(), &mut DemandPaging)
+                .expect("demand paging walks do not fail");
+        match tlb_outcome {
+            TlbOutcome::Hit => self.emit(VmEvent::TlbHit { access: index, va: access.va }),
+            TlbOutcome::Miss => self.emit(VmEvent::TlbMiss { access: index, va: access.va }),
+            TlbOutcome::NotPresent => {}
+        }
+        record_access(access, &result, &mut self.pm);
+        let (pm, stats, observers) = (&self.pm, &mut self.stats, &mut self.observers);
+        VmEvent::for_translation(index, &va, &result, faults, page_frame, pm, |event| {
+            stats.on_event(&event);
+            observers.emit(&event);
+        });
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            pa = result.to_output(),
+            result = result.kind(),
+            faults = crate::events::FaultKind::taken(faults).map(|kind| kind.name()).collect::<Vec<_>>().join(","),
+            "translated"
+        );
+        self.emit(VmEvent::Translated { access: index, va: access.va, result, faults });
+        self.tick_reference_clear();
+        (result, faults)
+    }
+
+    fn tick_reference_clear(&mut self) {
+        if let Some(every) = self.reference_interval {
+            self.since_reference_clear += 1;
+            if self.since_reference_clear >= every {
+                self.clear_reference_bits();
+            }
+        }
+    }
+
+    // Translates va as a read and returns the word stored at its physical address.
+    pub fn load(&mut self, va: u32) -> Result<i32, TranslationResult> {
+        match self.access(&Access::load(va)) {
+            TranslationResult::Success(pa) => Ok(self.pm.read(pa as usize)),
+            failure => Err(failure),
+        }
+    }
+
+    // Translates va as a write (dirtying the frame, copying a COW page) and stores value there.
+    pub fn store(&mut self, va: u32, value: i32) -> Result<(), TranslationResult> {
+        match self.access(&Access::store(va, value)) {
+            TranslationResult::Success(_) => Ok(()),
+            failure => Err(failure),
+        }
+    }
+
+    pub fn translate_batch(&mut self, vas: &[u32]) -> Vec<i32> {
+        vas.iter()
+            .map(|&va| {
+                let result = self.translate(va);
+                self.output(&result)
+            })
+            .collect()
+    }
+
+    pub fn translate_batch_cancellable(&mut self, vas: &[u32], cancel: &AtomicBool) -> Vec<i32> {
+        vas.iter()
+            .take_while(|_| !cancel.load(Ordering::Relaxed))
+            .map(|&va| {
+                let result = self.translate(va);
+                self.output(&result)
+            })
+            .collect()
+    }
+
+    // With tlb_output set and a TLB enabled, each result is prefixed with h or m.
+    pub fn translate_batch_output(&mut self, vas: &[u32], tlb_output: bool) -> Vec<String> {
+        vas.iter()
+            .map(|&va| {
+                let hits = self.tlb.as_ref().map(|tlb| tlb.hits);
+                let result = self.translate(va);
+                let pa = self.output(&result);
+                match (tlb_output, hits, self.tlb.as_ref()) {
+                    (true, Some(hits), Some(tlb)) => format!("{} {}", tlb_prefix(tlb.hits > hits), pa),
+                    _ => pa.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> &TranslationStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = TranslationStats::new();
+    }
+
+    pub fn memory(&self) -> &PhysicalMemory {
+        &self.pm
+    }
+
+    pub fn memory_mut(&mut self) -> &mut PhysicalMemory {
+        &mut self.pm
+    }
+
+    pub fn disk(&self) -> &Disk {
+        &self.disk
+    }
+
+    pub fn disk_mut(&mut self) -> &mut Disk {
+        &mut self.disk
+    }
+
+    pub fn free_frames(&self) -> &FreeFrameList {
+        &self.ffl
+    }
+
+    pub fn free_frames_mut(&mut self) -> &mut FreeFrameList {
+        &mut self.ffl
+    }
+}
+
+impl Default for VMManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,154 @@
+use js_sys::JSON;
+use wasm_bindgen::prelude::*;
+
+use crate::events::FaultKind;
+use crate::inspect::MemoryDump;
+use crate::io::{parse_accesses, parse_virtual_address, InitData};
+use crate::tlb::TlbEviction;
+use crate::translation::{Access, ErrorCodes, TranslationResult};
+use crate::vm_manager::VMManager;
+
+fn js_error(message: String) -> JsValue {
+    JsError::new(&message).into()
+}
+
+// Stats and dumps already have JSON writers, so JS gets them as parsed objects.
+fn js_object(json: &str) -> Result<JsValue, JsValue> {
+    JSON::parse(json)
+}
+
+fn result_name(result: &TranslationResult) -> &'static str {
+    match result {
+        TranslationResult::Success(_) => "success",
+        TranslationResult::SegmentBoundaryViolation => "segment boundary violation",
+        TranslationResult::InvalidSegment => "invalid segment",
+        TranslationResult::InvalidPage => "invalid page",
+        TranslationResult::AccessDenied => "access denied",
+        TranslationResult::ProtectionViolation => "protection violation",
+    }
+}
+
+// VMManager for JavaScript, built with `--features wasm` for wasm32 targets.
+// Init files and traces are passed as strings (or init bytes in the binary
+// format), addresses as numbers, and failures come back as -1 like the CLI.
+#[wasm_bindgen(js_name = VMManager)]
+pub struct WasmVm {
+    vm: VMManager,
+}
+
+#[wasm_bindgen(js_class = VMManager)]
+impl WasmVm {
+    #[wasm_bindgen(constructor)]
+    pub fn new(init: &str) -> Result<WasmVm, JsValue> {
+        Ok(WasmVm { vm: VMManager::from_init_str(init).map_err(js_error)? })
+    }
+
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmVm, JsValue> {
+        Ok(WasmVm { vm: VMManager::from_init_data(&InitData::from_bytes(bytes).map_err(js_error)?) })
+    }
+
+    // Reloads memory from an init file, keeping the TLB configuration.
+    #[wasm_bindgen(js_name = initFromString)]
+    pub fn init_from_string(&mut self, init: &str) -> Result<(), JsValue> {
+        self.vm.init_from_str(init).map_err(js_error)
+    }
+
+    pub fn translate(&mut self, va: u32) -> i32 {
+        let result = self.vm.translate(va);
+        self.vm.output(&result)
+    }
+
+    // codes is "collapsed" (every failure -1) or "detailed" (-1 to -4 by kind).
+    #[wasm_bindgen(js_name = setErrorCodes)]
+    pub fn set_error_codes(&mut self, codes: &str) -> Result<(), JsValue> {
+        self.vm.set_error_codes(ErrorCodes::parse(codes).map_err(js_error)?);
+        Ok(())
+    }
+
+    // Accepts the input file's address forms: decimal, 0x hex or 0b binary.
+    #[wasm_bindgen(js_name = translateAddress)]
+    pub fn translate_address(&mut self, va: &str) -> Result<i32, JsValue> {
+        Ok(self.translate(parse_virtual_address(va).map_err(js_error)?))
+    }
+
+    #[wasm_bindgen(js_name = translateBatch)]
+    pub fn translate_batch(&mut self, vas: &[u32]) -> Vec<i32> {
+        self.vm.translate_batch(vas)
+    }
+
+    // Runs an input file's accesses, tagged or not, and returns one result each.
+    #[wasm_bindgen(js_name = translateTrace)]
+    pub fn translate_trace(&mut self, input: &str) -> Result<Vec<i32>, JsValue> {
+        let accesses = parse_accesses(input).map_err(js_error)?;
+        Ok(accesses
+            .iter()
+            .map(|access| {
+                let result = self.vm.access(access);
+                self.vm.output(&result)
+            })
+            .collect())
+    }
+
+    // One access of kind "r", "w" or "x", described for display:
+    // `{ pa, result, faults }` with faults named as in the event log.
+    pub fn access(&mut self, kind: &str, va: u32) -> Result<JsValue, JsValue> {
+        let access = match kind {
+            "r" | "R" => Access::read(va),
+            "w" | "W" => Access::write(va),
+            "x" | "X" => Access::execute(va),
+            _ => return Err(js_error(format!("Unknown access type: {} (expected r, w or x)", kind))),
+        };
+        let (result, faults) = self.vm.access_with_faults(&access);
+        let faults: Vec<String> = FaultKind::taken(faults).map(|kind| format!("\"{}\"", kind.name())).collect();
+        js_object(&format!(
+            "{{\"pa\": {}, \"result\": \"{}\", \"faults\": [{}]}}",
+            self.vm.output(&result),
+            result_name(&result),
+            faults.join(", ")
+        ))
+    }
+
+    pub fn load(&mut self, va: u32) -> Result<i32, JsValue> {
+        self.vm.load(va).map_err(|failure| js_error(failure.to_string()))
+    }
+
+    pub fn store(&mut self, va: u32, value: i32) -> Result<(), JsValue> {
+        self.vm.store(va, value).map_err(|failure| js_error(failure.to_string()))
+    }
+
+    // eviction is "lru" or "fifo".
+    #[wasm_bindgen(js_name = enableTlb)]
+    pub fn enable_tlb(&mut self, entries: usize, eviction: &str) -> Result<(), JsValue> {
+        self.vm.enable_tlb(entries, TlbEviction::parse(eviction).map_err(js_error)?);
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = disableTlb)]
+    pub fn disable_tlb(&mut self) {
+        self.vm.disable_tlb();
+    }
+
+    pub fn stats(&self) -> Result<JsValue, JsValue> {
+        js_object(&self.vm.stats().to_json())
+    }
+
+    #[wasm_bindgen(js_name = resetStats)]
+    pub fn reset_stats(&mut self) {
+        self.vm.reset_stats();
+    }
+
+    // The segment table, page tables and free frames, as `inspect --json` prints them.
+    #[wasm_bindgen(js_name = memoryDump)]
+    pub fn memory_dump(&self) -> Result<JsValue, JsValue> {
+        js_object(&MemoryDump::capture(self.vm.memory(), self.vm.disk(), self.vm.free_frames()).to_json())
+    }
+
+    #[wasm_bindgen(js_name = readWord)]
+    pub fn read_word(&self, address: usize) -> Result<i32, JsValue> {
+        if address >= self.vm.memory().layout().memory_size() {
+            return Err(js_error(format!("Physical address {} is out of range", address)));
+        }
+        Ok(self.vm.memory().read(address))
+    }
+}